@@ -1,4 +1,4 @@
-use crate::jme::builtin_functions::BuiltinFunctions;
+use crate::jme::builtin_functions::{BuiltinFunctions, ParameterType, Signature};
 use std::convert::TryInto;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -103,6 +103,12 @@ impl Ident {
     pub fn is_builtin_funtion(&self) -> bool {
         BuiltinFunctions::get(&self.name[..]).is_some()
     }
+    pub fn name(&self) -> &str {
+        &self.name[..]
+    }
+    pub fn annotations(&self) -> &[String] {
+        &self.annotations[..]
+    }
 }
 
 impl std::fmt::Display for Ident {
@@ -188,8 +194,8 @@ pub enum Expr {
     Prefix(PrefixOperator, Box<Expr>),
     /// Matches a faculty expression
     Faculty(Box<Expr>),
-    /// Matches an indexation expression
-    Indexation(Box<Expr>),
+    /// Matches an indexation expression, e.g. `list[2]`
+    Indexation(Box<Expr>, Box<Expr>),
     /// Matches a cast expression
     Cast(Box<Expr>, Box<Expr>),
     /// Matches a sequence expression
@@ -202,6 +208,83 @@ pub enum ExprValidationError {
     UnknownVariable(Ident),
 }
 
+impl ExprValidationError {
+    /// The name of the unknown function or variable
+    pub fn name(&self) -> &str {
+        match self {
+            ExprValidationError::UnknownFunction(i) => i.name(),
+            ExprValidationError::UnknownVariable(i) => i.name(),
+        }
+    }
+}
+
+impl std::fmt::Display for ExprValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExprValidationError::UnknownFunction(i) => write!(f, "Unknown function {}", i),
+            ExprValidationError::UnknownVariable(i) => write!(f, "Unknown variable {}", i),
+        }
+    }
+}
+
+/// A call of a builtin function that doesn't match any of its signatures
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FunctionCallError {
+    /// The function is called with an amount of arguments that it doesn't accept
+    WrongNumberOfArguments {
+        function: Ident,
+        count: usize,
+        signatures: Vec<Signature>,
+    },
+    /// An argument has a type that the function doesn't accept at that position
+    InvalidArgumentType {
+        function: Ident,
+        /// The position of the argument, starting at zero
+        position: usize,
+        expected: ParameterType,
+        found: &'static str,
+    },
+}
+
+impl std::fmt::Display for FunctionCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FunctionCallError::WrongNumberOfArguments {
+                function,
+                count,
+                signatures,
+            } => write!(
+                f,
+                "{} is called with {} argument{}, but expects {}",
+                function,
+                count,
+                if *count == 1 { "" } else { "s" },
+                signatures
+                    .iter()
+                    .map(|s| format!("{}{}", function, s))
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ),
+            FunctionCallError::InvalidArgumentType {
+                function,
+                position,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Argument {} of {} should be a {}, but is a {}",
+                position + 1,
+                function,
+                expected,
+                found
+            ),
+        }
+    }
+}
+
+/// Variables that are always defined
+const BUILTIN_VARIABLES: [&str; 1] = ["nothing"];
+
 impl Expr {
     pub fn validate(&self) -> Vec<ExprValidationError> {
         match self {
@@ -219,7 +302,7 @@ impl Expr {
                 .into_iter()
                 .chain(e2.validate().into_iter())
                 .collect(),
-            Expr::Ident(_) => vec![], // TODO: check if part of variable list
+            Expr::Ident(_) => vec![], // Depends on the context, see unknown_identifiers
             Expr::Constant(_) => vec![],
             Expr::Relation(_, e1, e2) => e1
                 .validate()
@@ -251,7 +334,11 @@ impl Expr {
             }
             Expr::Prefix(_, e1) => e1.validate(),
             Expr::Faculty(e1) => e1.validate(),
-            Expr::Indexation(e1) => e1.validate(),
+            Expr::Indexation(e1, e2) => e1
+                .validate()
+                .into_iter()
+                .chain(e2.validate().into_iter())
+                .collect(),
             Expr::Cast(e1, e2) => e1
                 .validate()
                 .into_iter()
@@ -264,6 +351,332 @@ impl Expr {
                 .collect(),
         }
     }
+
+    /// The variables and functions that are used in this expression, but that are not builtin
+    /// and not bound within the expression itself (e.g. by `map` or `let`).
+    ///
+    /// Whether these are really unknown depends on the context of the expression, e.g. the
+    /// variables and functions of a question.
+    pub fn unknown_identifiers(&self) -> Vec<ExprValidationError> {
+        let mut result = Vec::new();
+        self.collect_unknown_identifiers(&mut Vec::new(), &mut result);
+        result
+    }
+
+    fn collect_unknown_identifiers(
+        &self,
+        bound: &mut Vec<String>,
+        result: &mut Vec<ExprValidationError>,
+    ) {
+        let mut add = |error: ExprValidationError| {
+            if !result.contains(&error) {
+                result.push(error)
+            }
+        };
+        match self {
+            Expr::Str(_) | Expr::Int(_) | Expr::Float(_, _) | Expr::Bool(_) | Expr::Constant(_) => {
+            }
+            Expr::Ident(ident) => {
+                let name = ident.name.to_lowercase();
+                if !bound.contains(&name) && !BUILTIN_VARIABLES.contains(&&name[..]) {
+                    add(ExprValidationError::UnknownVariable(ident.clone()))
+                }
+            }
+            Expr::FunctionApplication(ident, args) => {
+                if !ident.is_builtin_funtion() {
+                    add(ExprValidationError::UnknownFunction(ident.clone()));
+                }
+                collect_unknown_identifiers_in_arguments(
+                    &ident.name.to_lowercase()[..],
+                    args,
+                    bound,
+                    result,
+                )
+            }
+            Expr::List(es) => {
+                for e in es.iter() {
+                    e.collect_unknown_identifiers(bound, result)
+                }
+            }
+            Expr::Dictionary(es) => {
+                for (k, v) in es.iter() {
+                    k.collect_unknown_identifiers(bound, result);
+                    v.collect_unknown_identifiers(bound, result);
+                }
+            }
+            Expr::Prefix(_, e1) | Expr::Faculty(e1) => {
+                e1.collect_unknown_identifiers(bound, result)
+            }
+            Expr::Range(_, e1, e2)
+            | Expr::Arithmetic(_, e1, e2)
+            | Expr::Relation(_, e1, e2)
+            | Expr::Logic(_, e1, e2)
+            | Expr::Indexation(e1, e2)
+            | Expr::Cast(e1, e2)
+            | Expr::Sequence(e1, e2) => {
+                e1.collect_unknown_identifiers(bound, result);
+                e2.collect_unknown_identifiers(bound, result);
+            }
+        }
+    }
+
+    /// The data type of this expression if it is a literal value, e.g. `"number"` for `-5`
+    pub fn literal_type(&self) -> Option<&'static str> {
+        match self {
+            Expr::Str(_) => Some("string"),
+            Expr::Int(_) | Expr::Float(_, _) => Some("number"),
+            Expr::Prefix(PrefixOperator::Minus, e) => match **e {
+                Expr::Int(_) | Expr::Float(_, _) => Some("number"),
+                _ => None,
+            },
+            Expr::Bool(_) => Some("boolean"),
+            Expr::List(_) => Some("list"),
+            Expr::Dictionary(_) => Some("dict"),
+            Expr::Range(_, _, _) => Some("range"),
+            _ => None,
+        }
+    }
+
+    /// The calls of builtin functions with arguments that don't match any of the signatures of
+    /// the function
+    ///
+    /// Only the types of literal arguments are checked.
+    pub fn invalid_function_calls(&self) -> Vec<FunctionCallError> {
+        let mut result = Vec::new();
+        self.collect_invalid_function_calls(&mut result);
+        result
+    }
+
+    fn collect_invalid_function_calls(&self, result: &mut Vec<FunctionCallError>) {
+        match self {
+            Expr::Str(_)
+            | Expr::Int(_)
+            | Expr::Float(_, _)
+            | Expr::Bool(_)
+            | Expr::Ident(_)
+            | Expr::Constant(_) => (),
+            Expr::FunctionApplication(ident, args) => {
+                let argument_types: Vec<_> = args.iter().map(|a| a.literal_type()).collect();
+                if let Some(error) = check_function_call(ident, &argument_types) {
+                    result.push(error);
+                }
+                for arg in args.iter() {
+                    arg.collect_invalid_function_calls(result);
+                }
+            }
+            Expr::List(es) => {
+                for e in es.iter() {
+                    e.collect_invalid_function_calls(result)
+                }
+            }
+            Expr::Dictionary(es) => {
+                for (k, v) in es.iter() {
+                    k.collect_invalid_function_calls(result);
+                    v.collect_invalid_function_calls(result);
+                }
+            }
+            Expr::Prefix(_, e1) | Expr::Faculty(e1) => e1.collect_invalid_function_calls(result),
+            Expr::Range(_, e1, e2)
+            | Expr::Arithmetic(_, e1, e2)
+            | Expr::Relation(_, e1, e2)
+            | Expr::Logic(_, e1, e2)
+            | Expr::Indexation(e1, e2)
+            | Expr::Cast(e1, e2)
+            | Expr::Sequence(e1, e2) => {
+                e1.collect_invalid_function_calls(result);
+                e2.collect_invalid_function_calls(result);
+            }
+        }
+    }
+
+    /// The names that are bound by an argument like the `x` in `map(x^2, x, list)`
+    ///
+    /// These are either a single name or a list of names.
+    pub(crate) fn bound_names(&self) -> Option<Vec<String>> {
+        match self {
+            Expr::Ident(ident) => Some(vec![ident.name.to_lowercase()]),
+            Expr::List(items) => items
+                .iter()
+                .map(|i| match i {
+                    Expr::Ident(ident) => Some(ident.name.to_lowercase()),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// Check the arguments of a call of a builtin function against its signatures
+///
+/// The types of the arguments are given as data type names, None if the type is not known.
+pub(crate) fn check_function_call(
+    ident: &Ident,
+    argument_types: &[Option<&'static str>],
+) -> Option<FunctionCallError> {
+    let function = BuiltinFunctions::get(ident.name())
+        .or_else(|| BuiltinFunctions::get(&ident.name().to_lowercase()[..]))?;
+    check_signatures(ident, function.signatures(), argument_types)
+}
+
+/// Check the amount and (known) types of the arguments of a call against the signatures of the
+/// called function
+pub(crate) fn check_signatures(
+    ident: &Ident,
+    signatures: Vec<Signature>,
+    argument_types: &[Option<&'static str>],
+) -> Option<FunctionCallError> {
+    let candidates: Vec<_> = signatures
+        .iter()
+        .filter(|s| s.accepts_count(argument_types.len()))
+        .collect();
+    if candidates.is_empty() {
+        return Some(FunctionCallError::WrongNumberOfArguments {
+            function: ident.clone(),
+            count: argument_types.len(),
+            signatures: signatures.clone(),
+        });
+    }
+    let mismatch = |signature: &Signature| {
+        argument_types
+            .iter()
+            .enumerate()
+            .find_map(|(position, found)| {
+                let found = (*found)?;
+                let expected = signature.parameter_type(position)?;
+                if expected.accepts(found) {
+                    None
+                } else {
+                    Some(FunctionCallError::InvalidArgumentType {
+                        function: ident.clone(),
+                        position,
+                        expected,
+                        found,
+                    })
+                }
+            })
+    };
+    let mismatches: Vec<_> = candidates.iter().map(|s| mismatch(s)).collect();
+    if mismatches.iter().any(|m| m.is_none()) {
+        None
+    } else {
+        mismatches.into_iter().next().flatten()
+    }
+}
+
+/// Collect the unknown identifiers in the arguments of a function application
+///
+/// Some builtin functions (e.g. `map` and `let`) bind names that can be used in (some of) their
+/// other arguments.
+fn collect_unknown_identifiers_in_arguments(
+    function: &str,
+    args: &[Expr],
+    bound: &mut Vec<String>,
+    result: &mut Vec<ExprValidationError>,
+) {
+    // The index of the argument with the names and the indices of the arguments that can use them
+    let binding: Option<(usize, &[usize])> = match (function, args.len()) {
+        ("map", 3) | ("filter", 3) | ("iterate", 4) => Some((1, &[0])),
+        ("take", 4) => Some((2, &[1])),
+        ("iterate_until", 4) | ("iterate_until", 5) => Some((1, &[0, 3])),
+        ("try", 3) => Some((1, &[2])),
+        ("satisfy", 3) | ("satisfy", 4) => Some((0, &[1, 2])),
+        _ => None,
+    };
+    match (function, binding) {
+        ("foldl", _) if args.len() == 5 => {
+            let names = args[1].bound_names().zip(args[2].bound_names());
+            for (i, arg) in args.iter().enumerate() {
+                match (&names, i) {
+                    (Some((acc, item)), 0) => {
+                        let length = bound.len();
+                        bound.extend(acc.iter().chain(item.iter()).cloned());
+                        arg.collect_unknown_identifiers(bound, result);
+                        bound.truncate(length);
+                    }
+                    (Some(_), 1) | (Some(_), 2) => (),
+                    _ => arg.collect_unknown_identifiers(bound, result),
+                }
+            }
+        }
+        ("let", _) => collect_unknown_identifiers_in_let(args, bound, result),
+        ("isset", _) | ("unset", _) => {
+            // The first argument contains names, not values
+            for arg in args.iter().skip(1) {
+                arg.collect_unknown_identifiers(bound, result);
+            }
+        }
+        (_, Some((names_index, scoped))) => match args[names_index].bound_names() {
+            Some(names) => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i == names_index {
+                        continue;
+                    }
+                    if scoped.contains(&i) {
+                        let length = bound.len();
+                        bound.extend(names.iter().cloned());
+                        arg.collect_unknown_identifiers(bound, result);
+                        bound.truncate(length);
+                    } else {
+                        arg.collect_unknown_identifiers(bound, result);
+                    }
+                }
+            }
+            None => {
+                for arg in args.iter() {
+                    arg.collect_unknown_identifiers(bound, result);
+                }
+            }
+        },
+        _ => {
+            for arg in args.iter() {
+                arg.collect_unknown_identifiers(bound, result);
+            }
+        }
+    }
+}
+
+/// `let` is either called as `let(name1, value1, name2, value2, ..., expression)` or as
+/// `let(dictionary, expression)`
+fn collect_unknown_identifiers_in_let(
+    args: &[Expr],
+    bound: &mut Vec<String>,
+    result: &mut Vec<ExprValidationError>,
+) {
+    let length = bound.len();
+    if let Some((expression, definitions)) = args.split_last() {
+        match definitions {
+            [Expr::Dictionary(items)] => {
+                for (key, value) in items.iter() {
+                    value.collect_unknown_identifiers(bound, result);
+                    if let Expr::Str(name) = key {
+                        bound.push(name.to_lowercase());
+                    }
+                }
+                expression.collect_unknown_identifiers(bound, result);
+            }
+            [dictionary] if dictionary.bound_names().is_none() => {
+                // The names are only known when the expression is evaluated
+                dictionary.collect_unknown_identifiers(bound, result);
+            }
+            _ => {
+                for definition in definitions.chunks(2) {
+                    match definition {
+                        [names, value] => {
+                            value.collect_unknown_identifiers(bound, result);
+                            if let Some(names) = names.bound_names() {
+                                bound.extend(names);
+                            }
+                        }
+                        [value] => value.collect_unknown_identifiers(bound, result),
+                        _ => unreachable!(),
+                    }
+                }
+                expression.collect_unknown_identifiers(bound, result);
+            }
+        }
+    }
+    bound.truncate(length);
 }
 
 #[cfg(test)]
@@ -419,6 +832,36 @@ mod test {
         assert_eq!(ast.validate(), vec![]);
     }
 
+    #[test]
+    fn ast_unknown_identifiers() {
+        let ident = |name: &str| Ident {
+            name: name.to_string(),
+            annotations: vec![],
+        };
+        for (input, expected) in vec![
+            (
+                "a + f(b) + a",
+                vec![
+                    UnknownVariable(ident("a")),
+                    UnknownFunction(ident("f")),
+                    UnknownVariable(ident("b")),
+                ],
+            ),
+            ("map(x^2 + a, x, 1..3)", vec![UnknownVariable(ident("a"))]),
+            ("map(x + y, [x, y], l)", vec![UnknownVariable(ident("l"))]),
+            (
+                "let(b, a + 1, c, b, b * c)",
+                vec![UnknownVariable(ident("a"))],
+            ),
+            ("let([\"b\": 1], b)", vec![]),
+            ("foldl(acc + x, acc, x, 0, [1, 2])", vec![]),
+            ("isset(q) and [key: nothing] = [key: nothing]", vec![]),
+        ] {
+            let ast = consume_one_expression(parse_as_jme(input).unwrap()).unwrap();
+            assert_eq!(ast.unknown_identifiers(), expected, "{}", input);
+        }
+    }
+
     #[test]
     fn ast_superscript() {
         let to_compare = vec![
@@ -440,6 +883,51 @@ mod test {
         }
     }
 
+    #[test]
+    fn invalid_function_calls() {
+        let errors = |input: &str| {
+            consume_one_expression(parse_as_jme(input).unwrap())
+                .unwrap()
+                .invalid_function_calls()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("precround(x)"),
+            vec!["precround is called with 1 argument, but expects precround(anything, number)"]
+        );
+        assert_eq!(
+            errors("1 + sqrt(\"4\")"),
+            vec!["Argument 1 of sqrt should be a number, but is a string"]
+        );
+        assert_eq!(
+            errors("log(1, 2, 3)"),
+            vec!["log is called with 3 arguments, but expects log(number, [number])"]
+        );
+        assert_eq!(
+            errors("max()"),
+            vec!["max is called with 0 arguments, but expects max(list) or max(number, number, number...)"]
+        );
+        assert_eq!(
+            errors("max(1, [2])"),
+            vec!["Argument 2 of max should be a number, but is a list"]
+        );
+        for valid in vec![
+            "max([1, 2])",
+            "max(1, 2, 3, x)",
+            "min(1..5)",
+            "precround(x, 2)",
+            "vector(1, 2, 3)",
+            "matrix([1, 2], [3, 4])",
+            "f(1, 2, 3)",
+            "random(1 .. 5)",
+            "dict()",
+        ] {
+            assert_eq!(errors(valid), Vec::<String>::new(), "{}", valid);
+        }
+    }
+
     #[derive(Serialize, Deserialize)]
     struct DocTest {
         name: String,
@@ -582,3 +582,339 @@ impl BuiltinFunctions {
         serde_plain::from_str(s).ok()
     }
 }
+
+/// The type of a parameter of a builtin function
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParameterType {
+    Number,
+    String,
+    Boolean,
+    /// A list, or a value that Numbas converts to a list (a range, a set, a vector or a matrix)
+    List,
+    Dict,
+    Range,
+    /// A vector, or a value that Numbas converts to a vector (a list of numbers or a matrix)
+    Vector,
+    /// A matrix, or a value that Numbas converts to a matrix (a list of lists or a vector)
+    Matrix,
+    /// HTML or a string
+    Html,
+    Anything,
+}
+
+impl ParameterType {
+    /// Whether a value with the given data type (see the `isa` operator) can be used for this
+    /// parameter
+    pub fn accepts(&self, type_name: &str) -> bool {
+        match self {
+            ParameterType::Number => {
+                matches!(type_name, "number" | "integer" | "rational" | "decimal")
+            }
+            ParameterType::String => type_name == "string",
+            ParameterType::Boolean => type_name == "boolean",
+            ParameterType::List => {
+                matches!(type_name, "list" | "range" | "set" | "vector" | "matrix")
+            }
+            ParameterType::Dict => type_name == "dict",
+            ParameterType::Range => type_name == "range",
+            ParameterType::Vector => matches!(type_name, "vector" | "list" | "matrix"),
+            ParameterType::Matrix => matches!(type_name, "matrix" | "list" | "vector"),
+            ParameterType::Html => matches!(type_name, "html" | "string"),
+            ParameterType::Anything => true,
+        }
+    }
+}
+
+impl std::fmt::Display for ParameterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ParameterType::Number => "number",
+            ParameterType::String => "string",
+            ParameterType::Boolean => "boolean",
+            ParameterType::List => "list",
+            ParameterType::Dict => "dict",
+            ParameterType::Range => "range",
+            ParameterType::Vector => "vector",
+            ParameterType::Matrix => "matrix",
+            ParameterType::Html => "html",
+            ParameterType::Anything => "anything",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A way to call a builtin function
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Signature {
+    /// The parameters that always have to be given
+    pub parameters: &'static [ParameterType],
+    /// The parameters that can be left out, from left to right
+    pub optional: &'static [ParameterType],
+    /// The type of any further arguments, for functions that take any amount of arguments
+    pub variadic: Option<ParameterType>,
+}
+
+impl Signature {
+    pub(crate) const fn new(parameters: &'static [ParameterType]) -> Self {
+        Signature {
+            parameters,
+            optional: &[],
+            variadic: None,
+        }
+    }
+    pub(crate) const fn optional(
+        parameters: &'static [ParameterType],
+        optional: &'static [ParameterType],
+    ) -> Self {
+        Signature {
+            parameters,
+            optional,
+            variadic: None,
+        }
+    }
+    pub(crate) const fn variadic(
+        parameters: &'static [ParameterType],
+        variadic: ParameterType,
+    ) -> Self {
+        Signature {
+            parameters,
+            optional: &[],
+            variadic: Some(variadic),
+        }
+    }
+
+    /// Whether the function can be called with the given amount of arguments
+    pub fn accepts_count(&self, count: usize) -> bool {
+        count >= self.parameters.len()
+            && (self.variadic.is_some() || count <= self.parameters.len() + self.optional.len())
+    }
+
+    /// The type of the parameter at the given position
+    pub fn parameter_type(&self, index: usize) -> Option<ParameterType> {
+        self.parameters
+            .iter()
+            .chain(self.optional.iter())
+            .nth(index)
+            .copied()
+            .or(self.variadic)
+    }
+}
+
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut parameters: Vec<_> = self.parameters.iter().map(|p| p.to_string()).collect();
+        parameters.extend(self.optional.iter().map(|p| format!("[{}]", p)));
+        if let Some(variadic) = self.variadic {
+            parameters.push(format!("{}...", variadic));
+        }
+        write!(f, "({})", parameters.join(", "))
+    }
+}
+
+impl BuiltinFunctions {
+    /// The ways in which this function can be called
+    pub fn signatures(&self) -> Vec<Signature> {
+        use ParameterType as T;
+        const ANY: T = T::Anything;
+        const N: T = T::Number;
+        const S: T = T::String;
+        const B: T = T::Boolean;
+        const L: T = T::List;
+        const D: T = T::Dict;
+        match self {
+            Self::Exp
+            | Self::Rational
+            | Self::Int
+            | Self::Arg
+            | Self::Re
+            | Self::Im
+            | Self::Conj
+            | Self::IsInt
+            | Self::IsZero
+            | Self::Sqrt
+            | Self::Ln
+            | Self::Degrees
+            | Self::Radians
+            | Self::Sign
+            | Self::ScientificNumberLaTeX
+            | Self::ScientificNumberHTML
+            | Self::ToBinary
+            | Self::ToOctal
+            | Self::ToHexadecimal
+            | Self::IsNan
+            | Self::Sin
+            | Self::Cos
+            | Self::Tan
+            | Self::Cosec
+            | Self::Sec
+            | Self::Cot
+            | Self::ArcSin
+            | Self::ArcCos
+            | Self::ArcTan
+            | Self::Sinh
+            | Self::Cosh
+            | Self::Tanh
+            | Self::Cosech
+            | Self::Sech
+            | Self::Coth
+            | Self::ArcSinh
+            | Self::ArcCosh
+            | Self::ArcTanh
+            | Self::Fact
+            | Self::Factorise
+            | Self::Divisors
+            | Self::ProperDivisors
+            | Self::Gamma
+            | Self::Fract
+            | Self::Id
+            | Self::LetterOrdinal
+            | Self::Deal => vec![Signature::new(&[N])],
+            Self::Root
+            | Self::ATan2
+            | Self::ToNearest
+            | Self::ToBase
+            | Self::Mod
+            | Self::Perm
+            | Self::Comb
+            | Self::GCDWithoutPIorI
+            | Self::CoPrime => vec![Signature::new(&[N, N])],
+            Self::Clamp | Self::WithIntolerance => vec![Signature::new(&[N, N, N])],
+            Self::Log => vec![Signature::optional(&[N], &[N])],
+            Self::Ceil | Self::Floor | Self::Trunc => vec![
+                Signature::new(&[N]),
+                Signature::new(&[T::Vector]),
+                Signature::new(&[T::Matrix]),
+            ],
+            Self::Decimal
+            | Self::Abs
+            | Self::CountDP
+            | Self::CountSigFigs
+            | Self::Round
+            | Self::Transpose
+            | Self::Sum
+            | Self::Prod
+            | Self::LaTeX
+            | Self::IsBool
+            | Self::List
+            | Self::JsonEncode
+            | Self::Args
+            | Self::Type
+            | Self::FindVars
+            | Self::InferVariableTypes
+            | Self::InferType
+            | Self::IsSet => vec![Signature::new(&[ANY])],
+            Self::Max | Self::Min | Self::GCD | Self::LCM => {
+                vec![Signature::new(&[L]), Signature::variadic(&[N, N], N)]
+            }
+            Self::PrecRound | Self::SigRound => vec![Signature::new(&[ANY, N])],
+            Self::DPFormat | Self::SigFormat => vec![Signature::optional(&[N, N], &[S])],
+            Self::ToGivenPrecision => vec![Signature::new(&[S, S, N, B])],
+            Self::FormatNumber => vec![Signature::new(&[N, S])],
+            Self::CleanNumber => vec![Signature::optional(&[S], &[L])],
+            Self::MatchNumber => vec![Signature::new(&[S, L])],
+            Self::ParseNumber
+            | Self::ParseNumberOrFraction
+            | Self::ParseDecimal
+            | Self::ParseDecimalOrFraction => vec![Signature::optional(&[S], &[ANY])],
+            Self::FromBinary
+            | Self::FromOctal
+            | Self::FromHexadecimal
+            | Self::Safe
+            | Self::Capitalise
+            | Self::Upper
+            | Self::Lower
+            | Self::Trim
+            | Self::UnPercent
+            | Self::JsonDecode
+            | Self::Parse
+            | Self::Name
+            | Self::Op
+            | Self::Function => vec![Signature::new(&[S])],
+            Self::FromBase => vec![Signature::new(&[S, N])],
+            Self::RationalApproximation => vec![Signature::optional(&[N], &[N])],
+            Self::Vector | Self::RowVector => {
+                vec![Signature::new(&[L]), Signature::variadic(&[N], N)]
+            }
+            Self::Matrix => vec![Signature::variadic(&[L], L)],
+            Self::NumRows | Self::NumColumns | Self::Det | Self::SumCells => {
+                vec![Signature::new(&[T::Matrix])]
+            }
+            Self::Dot => vec![Signature::new(&[ANY, ANY])],
+            Self::Cross | Self::Angle => vec![Signature::new(&[T::Vector, T::Vector])],
+            Self::IsZeroVector => vec![Signature::new(&[T::Vector])],
+            Self::IsScalarMultiple => vec![Signature::optional(&[T::Vector, T::Vector], &[N, N])],
+            Self::Augment | Self::Stack | Self::CombineDiagonally | Self::Product => {
+                vec![Signature::variadic(&[ANY], ANY)]
+            }
+            Self::String => vec![Signature::optional(&[ANY], &[ANY])],
+            Self::Render => vec![Signature::optional(&[S], &[D])],
+            Self::Pluralise => vec![Signature::new(&[N, S, S])],
+            Self::Join => vec![Signature::new(&[L, S])],
+            Self::Split => vec![Signature::new(&[S, S])],
+            Self::MatchRegex | Self::SplitRegex => vec![Signature::optional(&[S, S], &[S])],
+            Self::ReplaceRegex => vec![Signature::optional(&[S, S, S], &[S])],
+            Self::Currency => vec![Signature::new(&[N, S, S])],
+            Self::SeparateThousands => vec![Signature::new(&[N, S])],
+            Self::LPad | Self::RPad => vec![Signature::new(&[S, N, S])],
+            Self::FormatString => vec![Signature::new(&[S, L])],
+            Self::Translate => vec![Signature::optional(&[S], &[D])],
+            Self::IsClose => vec![Signature::optional(&[N, N], &[N, N])],
+            Self::ResultsEqual => vec![Signature::new(&[ANY, ANY, S, N])],
+            Self::Repeat => vec![Signature::new(&[ANY, N])],
+            Self::All
+            | Self::Some
+            | Self::Flatten
+            | Self::Sort
+            | Self::SortDestinations
+            | Self::Reverse
+            | Self::Distinct
+            | Self::Frequencies
+            | Self::Enumerate
+            | Self::WeightedRandom
+            | Self::Shuffle
+            | Self::ShuffleTogether => vec![Signature::new(&[L])],
+            Self::Map | Self::Filter | Self::Try => vec![Signature::new(&[ANY, ANY, ANY])],
+            Self::FoldL => vec![Signature::new(&[ANY, ANY, ANY, ANY, ANY])],
+            Self::Iterate => vec![Signature::new(&[ANY, ANY, ANY, N])],
+            Self::IterateUntil => vec![Signature::optional(&[ANY, ANY, ANY, ANY], &[N])],
+            Self::Take => vec![Signature::new(&[N, ANY, ANY, ANY])],
+            Self::Let => vec![Signature::variadic(&[ANY, ANY], ANY)],
+            Self::SortBy | Self::GroupBy => vec![Signature::new(&[ANY, L])],
+            Self::Indices => vec![Signature::new(&[L, ANY])],
+            Self::MakeVariables => vec![Signature::optional(&[D], &[ANY])],
+            Self::Satisfy => vec![Signature::optional(&[ANY, ANY, ANY], &[N])],
+            Self::Zip => vec![Signature::variadic(&[L], L)],
+            Self::Combinations | Self::CombinationsWithReplacement | Self::Permutations => {
+                vec![Signature::new(&[L, N])]
+            }
+            Self::Get => vec![Signature::new(&[D, S, ANY])],
+            Self::Dict | Self::Set => vec![Signature::variadic(&[], ANY)],
+            Self::Keys => vec![Signature::new(&[D])],
+            Self::Values => vec![Signature::optional(&[D], &[L])],
+            Self::Items => vec![Signature::new(&[D])],
+            Self::Union | Self::Intersection => vec![Signature::new(&[ANY, ANY])],
+            Self::Random => vec![Signature::variadic(&[ANY], ANY)],
+            Self::Reorder => vec![Signature::new(&[L, L])],
+            Self::Award => vec![Signature::new(&[N, B])],
+            Self::If => vec![Signature::new(&[B, ANY, ANY])],
+            Self::Switch => vec![Signature::variadic(&[ANY, ANY], ANY)],
+            Self::Assert => vec![Signature::new(&[B, ANY])],
+            Self::HTML | Self::IsNonEmptyHTML => vec![Signature::new(&[T::Html])],
+            Self::Table => vec![Signature::optional(&[L], &[L])],
+            Self::Image => vec![Signature::optional(&[S], &[N, N])],
+            Self::MaxWidth | Self::MaxHeight => vec![Signature::new(&[N, ANY])],
+            Self::Eval => vec![Signature::optional(&[ANY], &[D])],
+            Self::Exec => vec![Signature::new(&[ANY, L])],
+            Self::Substitute => vec![Signature::new(&[D, ANY])],
+            Self::Simplify => vec![Signature::new(&[ANY, ANY])],
+            Self::ExpandJuxtapositions => vec![Signature::optional(&[ANY], &[D])],
+            Self::CanonicalCompare | Self::NumericalCompare => vec![Signature::new(&[ANY, ANY])],
+            Self::ScopeCaseSensitive => vec![Signature::optional(&[ANY], &[B])],
+            Self::Diff => vec![Signature::optional(&[ANY, ANY], &[N])],
+            Self::Match | Self::Matches => vec![Signature::optional(&[ANY, S], &[S])],
+            Self::Replace => vec![Signature::optional(&[S, S, ANY], &[S])],
+            Self::DefinedVariables => vec![Signature::new(&[])],
+            Self::Unset => vec![Signature::new(&[ANY, ANY])],
+        }
+    }
+}
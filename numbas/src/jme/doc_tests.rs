@@ -0,0 +1,164 @@
+//! Runs the examples of the Numbas JME documentation against the parser and the evaluator
+//!
+//! Run `cargo test -p numbas doc_test_conformance -- --nocapture` to see the conformance of
+//! each builtin function.
+
+use crate::jme::ast::Expr;
+use crate::jme::evaluate::{Environment, EvaluationError, Value};
+use crate::jme::parser::{consume_one_expression, parse_as_jme};
+use crate::jme::types::TypeEnvironment;
+use std::collections::BTreeMap;
+
+/// The amount of examples that are known to evaluate to their expected output
+///
+/// Increase this when the evaluator supports more functions.
+const PASSING_EXAMPLES: usize = 228;
+
+/// The seed that is used to evaluate the examples
+const SEED: u64 = 0;
+
+#[derive(Debug)]
+enum Outcome {
+    /// The input evaluates to the expected output
+    Passed,
+    /// The input can't be parsed
+    ParseError,
+    /// The input uses a function that the evaluator doesn't know or support
+    Unsupported(String),
+    /// The evaluation of the input failed
+    EvaluationError(EvaluationError),
+    /// The input evaluates to an other value than expected
+    Mismatch { expected: String, got: String },
+}
+
+#[derive(Default)]
+struct FunctionConformance {
+    passed: usize,
+    failures: Vec<(String, Outcome)>,
+}
+
+fn parse(input: &str) -> Option<Expr> {
+    consume_one_expression(parse_as_jme(input).ok()?).ok()
+}
+
+/// Compare two values, numbers are compared with a relative tolerance
+fn values_equal(a: &Value, b: &Value) -> bool {
+    let numbers_equal =
+        |a: f64, b: f64| a == b || (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0);
+    let all_equal = |a: &[Value], b: &[Value]| {
+        a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_equal(a, b))
+    };
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => numbers_equal(*a, *b),
+        (Value::List(a), Value::List(b)) | (Value::Set(a), Value::Set(b)) => all_equal(a, b),
+        (Value::Vector(a), Value::Vector(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| numbers_equal(*a, *b))
+        }
+        (Value::Matrix(a), Value::Matrix(b)) => {
+            a.len() == b.len()
+                && a.iter().zip(b.iter()).all(|(a, b)| {
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| numbers_equal(*a, *b))
+                })
+        }
+        (Value::Dictionary(a), Value::Dictionary(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|((ka, a), (kb, b))| ka == kb && values_equal(a, b))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn run_example(input: &str, expected: &str) -> Outcome {
+    let ast = match parse(input) {
+        Some(ast) => ast,
+        None => return Outcome::ParseError,
+    };
+    // The documented examples are valid calls
+    assert_eq!(ast.invalid_function_calls(), vec![], "{}", input);
+    assert_eq!(
+        ast.type_errors(&TypeEnvironment::new()),
+        vec![],
+        "{}",
+        input
+    );
+    let got = match Environment::new(SEED).evaluate(&ast) {
+        Ok(value) => value,
+        Err(EvaluationError::UnsupportedFunction(name))
+        | Err(EvaluationError::UnknownFunction(name)) => return Outcome::Unsupported(name),
+        Err(e) => return Outcome::EvaluationError(e),
+    };
+    // The expected output is usually a JME expression, otherwise the printed values are compared
+    let expected_value = parse(expected).and_then(|e| Environment::new(SEED).evaluate(&e).ok());
+    let passed = match expected_value {
+        Some(expected_value) => values_equal(&got, &expected_value),
+        None => got.to_string() == expected || got.to_plain_string() == expected,
+    };
+    if passed {
+        Outcome::Passed
+    } else {
+        Outcome::Mismatch {
+            expected: expected.to_string(),
+            got: got.to_string(),
+        }
+    }
+}
+
+#[test]
+fn doc_test_conformance() {
+    let doc_tests: serde_json::Value =
+        serde_json::from_str(include_str!("numbas-jme-doc-tests.json")).unwrap();
+    let mut conformance: BTreeMap<String, FunctionConformance> = BTreeMap::new();
+    for test in doc_tests.as_array().unwrap().iter() {
+        for r#fn in test["fns"].as_array().unwrap().iter() {
+            let name = format!(
+                "{}.{}",
+                test["name"].as_str().unwrap(),
+                r#fn["name"].as_str().unwrap()
+            );
+            let function = conformance.entry(name).or_default();
+            for example in r#fn["examples"].as_array().unwrap().iter() {
+                let input = example["in"].as_str().unwrap();
+                let expected = example["out"].as_str().unwrap();
+                match run_example(input, expected) {
+                    Outcome::Passed => function.passed += 1,
+                    outcome => function.failures.push((input.to_string(), outcome)),
+                }
+            }
+        }
+    }
+
+    let mut passed = 0;
+    let mut total = 0;
+    let mut parse_errors = Vec::new();
+    for (name, function) in conformance.iter() {
+        let examples = function.passed + function.failures.len();
+        if examples == 0 {
+            continue;
+        }
+        passed += function.passed;
+        total += examples;
+        println!("{:>3}/{:<3} {}", function.passed, examples, name);
+        for (input, outcome) in function.failures.iter() {
+            match outcome {
+                Outcome::ParseError => parse_errors.push(input.clone()),
+                Outcome::Unsupported(f) => println!("        {}: {} is not supported", input, f),
+                Outcome::EvaluationError(e) => println!("        {}: {}", input, e),
+                Outcome::Mismatch { expected, got } => {
+                    println!("        {}: expected {}, got {}", input, expected, got)
+                }
+                Outcome::Passed => unreachable!(),
+            }
+        }
+    }
+    println!("{}/{} examples pass", passed, total);
+
+    assert_eq!(parse_errors, Vec::<String>::new());
+    assert!(
+        passed >= PASSING_EXAMPLES,
+        "only {} of the {} examples that used to pass, pass",
+        passed,
+        PASSING_EXAMPLES
+    );
+}
@@ -0,0 +1,2912 @@
+//! Evaluation of JME expressions without the javascript runtime of Numbas.
+//!
+//! Not every builtin function is supported (e.g. the ones that work on sub-expressions or HTML),
+//! calling them results in an [EvaluationError::UnsupportedFunction].
+use crate::jme::ast::{
+    ArithmeticOperator, Expr, Ident, LogicalOperator, PrefixOperator, RangeOperator,
+    RelationalOperator,
+};
+use crate::jme::builtin_functions::BuiltinFunctions;
+use std::collections::{BTreeMap, HashMap};
+
+/// The maximal amount of items that are created when converting a range to a list or by `deal`
+const MAX_RANGE_LENGTH: f64 = 1e7;
+/// The maximal amount of iterations of `repeat`, `iterate` and `iterate_until`
+const MAX_ITERATIONS: i64 = 1_000_000;
+/// The maximal amount of items that are created by `product`, `combinations` and `permutations`
+const MAX_COMBINATIONS: f64 = 1e6;
+/// The maximal amount of decimal places or significant figures for rounding and formatting
+const MAX_PRECISION: i64 = 100;
+/// The largest number whose divisors are calculated by `divisors` and `proper_divisors`
+const MAX_DIVISORS_NUMBER: i64 = 1_000_000_000_000;
+/// The largest number that is factorised by `factorise`
+const MAX_FACTORISE_NUMBER: i64 = 1_000_000;
+/// The largest integer that a number can represent exactly, integers outside this range are rejected
+const MAX_INTEGER: f64 = 9_007_199_254_740_991.0;
+/// The maximal depth of nested calls of custom functions
+const MAX_CALL_DEPTH: usize = 100;
+/// The maximal amount of rows (and columns) of the identity matrices created by `id`
+const MAX_IDENTITY_SIZE: i64 = 1000;
+/// The maximal exponent of the power of a matrix
+const MAX_MATRIX_POWER: f64 = 1e4;
+
+/// A value that is the result of evaluating a JME expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    List(Vec<Value>),
+    Dictionary(BTreeMap<String, Value>),
+    /// A range with a start, end and step size. A step size of zero means a continuous range.
+    Range(f64, f64, f64),
+    Set(Vec<Value>),
+    Vector(Vec<f64>),
+    /// A matrix, as a list of rows
+    Matrix(Vec<Vec<f64>>),
+    Nothing,
+}
+
+impl Value {
+    /// The name of the data type, as used by the `isa` operator
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Dictionary(_) => "dict",
+            Value::Range(_, _, _) => "range",
+            Value::Set(_) => "set",
+            Value::Vector(_) => "vector",
+            Value::Matrix(_) => "matrix",
+            Value::Nothing => "nothing",
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(&s[..]),
+            _ => None,
+        }
+    }
+
+    /// The items of a collection, None if this value is not a collection
+    pub fn to_list(&self) -> Option<Vec<Value>> {
+        match self {
+            Value::List(l) | Value::Set(l) => Some(l.clone()),
+            Value::Range(start, end, step) => range_values(*start, *end, *step)
+                .ok()
+                .map(|v| v.into_iter().map(Value::Number).collect()),
+            Value::Vector(v) => Some(v.iter().map(|n| Value::Number(*n)).collect()),
+            Value::Matrix(m) => Some(m.iter().map(|r| Value::Vector(r.clone())).collect()),
+            Value::Dictionary(d) => Some(d.keys().map(|k| Value::String(k.clone())).collect()),
+            _ => None,
+        }
+    }
+
+    /// The string that is used when this value is substituted into a string
+    pub fn to_plain_string(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    /// Writes the value as a JME expression
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn join<T: std::fmt::Display>(items: &[T]) -> String {
+            items
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        fn join_numbers(items: &[f64]) -> String {
+            items
+                .iter()
+                .map(|i| format_number(*i))
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        match self {
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+            Value::List(l) => write!(f, "[{}]", join(l)),
+            Value::Dictionary(d) => write!(
+                f,
+                "[{}]",
+                d.iter()
+                    .map(|(k, v)| format!("{}: {}", Value::String(k.clone()), v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Range(start, end, step) => write!(
+                f,
+                "{}..{}#{}",
+                format_number(*start),
+                format_number(*end),
+                format_number(*step)
+            ),
+            Value::Set(s) => write!(f, "set({})", join(s)),
+            Value::Vector(v) => write!(f, "vector({})", join_numbers(v)),
+            Value::Matrix(m) => write!(
+                f,
+                "matrix({})",
+                m.iter()
+                    .map(|r| format!("[{}]", join_numbers(r)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Value::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+/// Format a number the way it would be written in a JME expression
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "infinity" } else { "-infinity" }.to_string()
+    } else if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else if n.abs() < 1e-10 || n.abs() >= 1e15 {
+        format!("{}", n)
+    } else {
+        let s = format!("{:.12}", n);
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        if s == "-0" {
+            "0".to_string()
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvaluationError {
+    UnknownVariable(String),
+    UnknownFunction(String),
+    /// The function exists in Numbas, but can't be evaluated by rumbas
+    UnsupportedFunction(String),
+    /// The function was called with invalid arguments
+    InvalidArguments(String, String),
+    /// The operation is not defined for the given values
+    InvalidOperation(String),
+    CircularDefinition(Vec<String>),
+    /// An error that occured while evaluating the definition of a variable
+    InVariable(String, Box<EvaluationError>),
+}
+
+impl std::fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EvaluationError::UnknownVariable(n) => write!(f, "Unknown variable {}", n),
+            EvaluationError::UnknownFunction(n) => write!(f, "Unknown function {}", n),
+            EvaluationError::UnsupportedFunction(n) => {
+                write!(f, "The function {} can't be evaluated by rumbas", n)
+            }
+            EvaluationError::InvalidArguments(n, m) => {
+                write!(f, "Invalid arguments for {}: {}", n, m)
+            }
+            EvaluationError::InvalidOperation(m) => write!(f, "Invalid operation: {}", m),
+            EvaluationError::CircularDefinition(names) => {
+                write!(f, "Circular definition: {}", names.join(" -> "))
+            }
+            EvaluationError::InVariable(n, e) => {
+                write!(f, "Error while evaluating variable {}: {}", n, e)
+            }
+        }
+    }
+}
+
+impl EvaluationError {
+    /// Whether the error is caused by a function that rumbas can't evaluate, instead of by the
+    /// values that are generated
+    pub fn is_unsupported(&self) -> bool {
+        match self {
+            EvaluationError::UnknownFunction(_) | EvaluationError::UnsupportedFunction(_) => true,
+            EvaluationError::InVariable(_, e) => e.is_unsupported(),
+            _ => false,
+        }
+    }
+}
+
+pub type EvaluationResult<T> = Result<T, EvaluationError>;
+
+/// A custom function that is defined in JME
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function {
+    pub parameters: Vec<String>,
+    pub definition: Expr,
+}
+
+/// A small seedable pseudo random number generator (splitmix64)
+///
+/// The same seed always gives the same values, which makes evaluations reproducible.
+#[derive(Clone, Debug)]
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// A random number in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// A random number in [0, n)
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+/// The environment in which JME expressions are evaluated
+///
+/// It contains the (question) variables, the custom functions and the random number generator.
+#[derive(Clone, Debug)]
+pub struct Environment {
+    variables: BTreeMap<String, Value>,
+    /// Definitions of variables that are evaluated when they are needed
+    definitions: BTreeMap<String, Expr>,
+    /// The variables that are currently being evaluated, used to detect circular definitions
+    evaluating: Vec<String>,
+    functions: HashMap<String, Function>,
+    /// Temporary variables, e.g. of `map` and `let`
+    scopes: Vec<HashMap<String, Value>>,
+    /// The amount of custom functions that are currently being called
+    depth: usize,
+    random: Random,
+}
+
+impl Environment {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            variables: BTreeMap::new(),
+            definitions: BTreeMap::new(),
+            evaluating: Vec::new(),
+            functions: HashMap::new(),
+            scopes: Vec::new(),
+            depth: 0,
+            random: Random::new(seed),
+        }
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_lowercase(), value);
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(&name.to_lowercase())
+    }
+
+    /// All variables that have been evaluated
+    pub fn variables(&self) -> &BTreeMap<String, Value> {
+        &self.variables
+    }
+
+    pub fn add_function(&mut self, name: &str, function: Function) {
+        self.functions.insert(name.to_lowercase(), function);
+    }
+
+    /// Add the definition of a variable, it is evaluated when it is first needed
+    pub fn add_definition(&mut self, name: &str, definition: Expr) {
+        self.definitions.insert(name.to_lowercase(), definition);
+    }
+
+    /// Evaluate all definitions that have not yet been evaluated
+    pub fn evaluate_definitions(&mut self) -> EvaluationResult<()> {
+        let names = self.definitions.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            self.lookup(&name)?;
+        }
+        Ok(())
+    }
+
+    fn lookup(&mut self, name: &str) -> EvaluationResult<Value> {
+        let name = name.to_lowercase();
+        for scope in self.scopes.iter().rev() {
+            if let Some(v) = scope.get(&name) {
+                return Ok(v.clone());
+            }
+        }
+        if let Some(v) = self.variables.get(&name) {
+            return Ok(v.clone());
+        }
+        if let Some(position) = self.evaluating.iter().position(|n| n == &name) {
+            let mut cycle = self.evaluating[position..].to_vec();
+            cycle.push(name);
+            return Err(EvaluationError::CircularDefinition(cycle));
+        }
+        if let Some(definition) = self.definitions.get(&name).cloned() {
+            // Definitions of variables can't see the temporary variables of the place they are used
+            let scopes = std::mem::take(&mut self.scopes);
+            self.evaluating.push(name.clone());
+            let result = self.evaluate(&definition);
+            self.evaluating.pop();
+            self.scopes = scopes;
+            return match result {
+                Ok(v) => {
+                    self.variables.insert(name, v.clone());
+                    Ok(v)
+                }
+                Err(e @ EvaluationError::InVariable(_, _))
+                | Err(e @ EvaluationError::CircularDefinition(_)) => Err(e),
+                Err(e) => Err(EvaluationError::InVariable(name, Box::new(e))),
+            };
+        }
+        Err(EvaluationError::UnknownVariable(name))
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.scopes.iter().any(|s| s.contains_key(&name))
+            || self.variables.contains_key(&name)
+            || self.definitions.contains_key(&name)
+    }
+
+    fn with_scope<T>(
+        &mut self,
+        scope: HashMap<String, Value>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.scopes.push(scope);
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    /// Evaluate the given expression
+    pub fn evaluate(&mut self, expr: &Expr) -> EvaluationResult<Value> {
+        match expr {
+            Expr::Str(s) => Ok(Value::String(s.clone())),
+            Expr::Int(i) => Ok(Value::Number(*i as f64)),
+            Expr::Float(i, broken) => format!("{}.{}", i, broken)
+                .parse()
+                .map(Value::Number)
+                .map_err(|_| EvaluationError::InvalidOperation(format!("{}.{}", i, broken))),
+            Expr::Bool(b) => Ok(Value::Boolean(*b)),
+            Expr::Range(RangeOperator::Create, start, end) => {
+                let start = self.evaluate(start)?;
+                let end = self.evaluate(end)?;
+                match (start, end) {
+                    (Value::Number(s), Value::Number(e)) => Ok(Value::Range(s, e, 1.0)),
+                    (s, e) => Err(invalid_operation("..", &s, &e)),
+                }
+            }
+            Expr::Range(RangeOperator::Step, range, step) => {
+                let range = self.evaluate(range)?;
+                let step = self.evaluate(step)?;
+                match (range, step) {
+                    (Value::Range(s, e, _), Value::Number(step)) => Ok(Value::Range(s, e, step)),
+                    (r, s) => Err(invalid_operation("#", &r, &s)),
+                }
+            }
+            Expr::Arithmetic(op, e1, e2) => {
+                let v1 = self.evaluate(e1)?;
+                let v2 = self.evaluate(e2)?;
+                arithmetic(*op, v1, v2)
+            }
+            Expr::Ident(ident) => self.lookup(ident.name()),
+            Expr::Constant(ident) => constant(ident),
+            Expr::Relation(op, e1, e2) => {
+                let v1 = self.evaluate(e1)?;
+                let v2 = self.evaluate(e2)?;
+                relation(*op, v1, v2)
+            }
+            Expr::Logic(op, e1, e2) => self.logic(*op, e1, e2),
+            Expr::List(es) => Ok(Value::List(
+                es.iter()
+                    .map(|e| self.evaluate(e))
+                    .collect::<EvaluationResult<_>>()?,
+            )),
+            Expr::Dictionary(items) => {
+                let mut dict = BTreeMap::new();
+                for (k, v) in items.iter() {
+                    let key = self.evaluate(k)?;
+                    let value = self.evaluate(v)?;
+                    match key {
+                        Value::String(s) => dict.insert(s, value),
+                        k => {
+                            return Err(EvaluationError::InvalidOperation(format!(
+                                "{} can't be used as a dictionary key",
+                                k
+                            )))
+                        }
+                    };
+                }
+                Ok(Value::Dictionary(dict))
+            }
+            Expr::FunctionApplication(ident, args) => self.call(ident, args),
+            Expr::Prefix(PrefixOperator::Minus, e) => negate(self.evaluate(e)?),
+            Expr::Prefix(PrefixOperator::Not, e) => match self.evaluate(e)? {
+                Value::Boolean(b) => Ok(Value::Boolean(!b)),
+                v => Err(EvaluationError::InvalidOperation(format!("not {}", v))),
+            },
+            Expr::Faculty(e) => {
+                let v = self.evaluate(e)?;
+                call_builtin("fact", &BuiltinFunctions::Fact, vec![v], &mut self.random)
+            }
+            Expr::Indexation(e, index) => {
+                let v = self.evaluate(e)?;
+                let index = self.evaluate(index)?;
+                indexation(v, index)
+            }
+            Expr::Cast(e, t) => {
+                let v = self.evaluate(e)?;
+                match self.evaluate(t)? {
+                    Value::String(t) => cast(v, &t[..]),
+                    t => Err(invalid_operation("as", &v, &t)),
+                }
+            }
+            Expr::Sequence(e1, e2) => {
+                self.evaluate(e1)?;
+                self.evaluate(e2)
+            }
+        }
+    }
+
+    fn logic(&mut self, op: LogicalOperator, e1: &Expr, e2: &Expr) -> EvaluationResult<Value> {
+        let v1 = self.evaluate(e1)?;
+        match (op, v1) {
+            (LogicalOperator::And, Value::Boolean(false)) => Ok(Value::Boolean(false)),
+            (LogicalOperator::Or, Value::Boolean(true)) => Ok(Value::Boolean(true)),
+            (LogicalOperator::Implies, Value::Boolean(false)) => Ok(Value::Boolean(true)),
+            (op, v1) => {
+                let v2 = self.evaluate(e2)?;
+                match (op, v1, v2) {
+                    (LogicalOperator::Xor, Value::Boolean(a), Value::Boolean(b)) => {
+                        Ok(Value::Boolean(a != b))
+                    }
+                    (_, Value::Boolean(_), Value::Boolean(b)) => Ok(Value::Boolean(b)),
+                    (LogicalOperator::And, Value::Set(a), Value::Set(b)) => Ok(Value::Set(
+                        a.into_iter().filter(|x| b.contains(x)).collect(),
+                    )),
+                    (LogicalOperator::Or, Value::Set(a), Value::Set(b)) => {
+                        Ok(Value::Set(distinct(a.into_iter().chain(b))))
+                    }
+                    (op, v1, v2) => Err(invalid_operation(&format!("{:?}", op), &v1, &v2)),
+                }
+            }
+        }
+    }
+
+    /// Call a builtin or custom function
+    fn call(&mut self, ident: &Ident, args: &[Expr]) -> EvaluationResult<Value> {
+        let name = ident.name().to_lowercase();
+        if let Some(function) = self.functions.get(&name).cloned() {
+            if function.parameters.len() != args.len() {
+                return Err(EvaluationError::InvalidArguments(
+                    name,
+                    format!(
+                        "expected {} arguments but got {}",
+                        function.parameters.len(),
+                        args.len()
+                    ),
+                ));
+            }
+            if self.depth >= MAX_CALL_DEPTH {
+                return Err(EvaluationError::InvalidArguments(
+                    name,
+                    "too many nested calls of custom functions".to_string(),
+                ));
+            }
+            let mut scope = HashMap::new();
+            for (parameter, arg) in function.parameters.iter().zip(args.iter()) {
+                scope.insert(parameter.to_lowercase(), self.evaluate(arg)?);
+            }
+            self.depth += 1;
+            let result = self.with_scope(scope, |env| env.evaluate(&function.definition));
+            self.depth -= 1;
+            return result;
+        }
+        let builtin = BuiltinFunctions::get(ident.name())
+            .or_else(|| BuiltinFunctions::get(&name[..]))
+            .ok_or_else(|| EvaluationError::UnknownFunction(name.clone()))?;
+        if let Some(result) = self.call_lazy(&name, &builtin, args) {
+            return result;
+        }
+        let values = args
+            .iter()
+            .map(|a| self.evaluate(a))
+            .collect::<EvaluationResult<Vec<_>>>()?;
+        call_builtin(&name, &builtin, values, &mut self.random)
+    }
+
+    /// Call the builtin functions that don't evaluate all of their arguments beforehand
+    ///
+    /// Returns None if the function is not lazy
+    fn call_lazy(
+        &mut self,
+        name: &str,
+        function: &BuiltinFunctions,
+        args: &[Expr],
+    ) -> Option<EvaluationResult<Value>> {
+        let invalid = |m: &str| {
+            Err(EvaluationError::InvalidArguments(
+                name.to_string(),
+                m.into(),
+            ))
+        };
+        Some(match function {
+            BuiltinFunctions::If => match args {
+                [condition, a, b] => match self.evaluate(condition) {
+                    Ok(Value::Boolean(true)) => self.evaluate(a),
+                    Ok(Value::Boolean(false)) => self.evaluate(b),
+                    Ok(_) => invalid("the condition should be a boolean"),
+                    Err(e) => Err(e),
+                },
+                _ => invalid("expected three arguments"),
+            },
+            BuiltinFunctions::Switch => {
+                for pair in args.chunks(2) {
+                    match pair {
+                        [condition, value] => match self.evaluate(condition) {
+                            Ok(Value::Boolean(true)) => return Some(self.evaluate(value)),
+                            Ok(Value::Boolean(false)) => (),
+                            Ok(_) => return Some(invalid("the conditions should be booleans")),
+                            Err(e) => return Some(Err(e)),
+                        },
+                        [default] => return Some(self.evaluate(default)),
+                        _ => unreachable!(),
+                    }
+                }
+                invalid("no condition is true and there is no default value")
+            }
+            BuiltinFunctions::Let => self.call_let(name, args),
+            BuiltinFunctions::Map => match args {
+                [expr, names, collection] => self.evaluate(collection).and_then(|collection| {
+                    let names = binding_names(name, names)?;
+                    let items = collection_items(name, &collection)?;
+                    let results = items
+                        .into_iter()
+                        .map(|item| {
+                            let scope = bind(name, &names, item)?;
+                            self.with_scope(scope, |env| env.evaluate(expr))
+                        })
+                        .collect::<EvaluationResult<Vec<_>>>()?;
+                    match collection {
+                        Value::Vector(_) => numbers_of(name, &results).map(Value::Vector),
+                        _ => Ok(Value::List(results)),
+                    }
+                }),
+                _ => invalid("expected three arguments"),
+            },
+            BuiltinFunctions::Filter => match args {
+                [expr, names, collection] => self.evaluate(collection).and_then(|collection| {
+                    let names = binding_names(name, names)?;
+                    let mut results = Vec::new();
+                    for item in collection_items(name, &collection)? {
+                        let scope = bind(name, &names, item.clone())?;
+                        match self.with_scope(scope, |env| env.evaluate(expr))? {
+                            Value::Boolean(true) => results.push(item),
+                            Value::Boolean(false) => (),
+                            _ => return invalid("the condition should give a boolean"),
+                        }
+                    }
+                    Ok(Value::List(results))
+                }),
+                _ => invalid("expected three arguments"),
+            },
+            BuiltinFunctions::Take => match args {
+                [n, expr, names, collection] => (|| {
+                    let n = expect_integer(name, &self.evaluate(n)?)?;
+                    let collection = self.evaluate(collection)?;
+                    let names = binding_names(name, names)?;
+                    let mut results = Vec::new();
+                    for item in collection_items(name, &collection)? {
+                        if results.len() as i64 >= n {
+                            break;
+                        }
+                        let scope = bind(name, &names, item.clone())?;
+                        if expect_bool(name, &self.with_scope(scope, |env| env.evaluate(expr))?)? {
+                            results.push(item);
+                        }
+                    }
+                    Ok(Value::List(results))
+                })(),
+                _ => invalid("expected four arguments"),
+            },
+            BuiltinFunctions::FoldL => match args {
+                [expr, accumulator, item_name, first, collection] => (|| {
+                    let accumulator = binding_names(name, accumulator)?;
+                    let item_name = binding_names(name, item_name)?;
+                    let mut value = self.evaluate(first)?;
+                    let collection = self.evaluate(collection)?;
+                    for item in collection_items(name, &collection)? {
+                        let mut scope = bind(name, &accumulator, value)?;
+                        scope.extend(bind(name, &item_name, item)?);
+                        value = self.with_scope(scope, |env| env.evaluate(expr))?;
+                    }
+                    Ok(value)
+                })(),
+                _ => invalid("expected five arguments"),
+            },
+            BuiltinFunctions::Iterate => match args {
+                [expr, names, initial, times] => (|| {
+                    let names = binding_names(name, names)?;
+                    let mut value = self.evaluate(initial)?;
+                    let times = expect_integer(name, &self.evaluate(times)?)?;
+                    if times > MAX_ITERATIONS {
+                        return invalid("the amount of iterations is too large");
+                    }
+                    let mut results = vec![value.clone()];
+                    for _ in 0..times {
+                        let scope = bind(name, &names, value)?;
+                        value = self.with_scope(scope, |env| env.evaluate(expr))?;
+                        results.push(value.clone());
+                    }
+                    Ok(Value::List(results))
+                })(),
+                _ => invalid("expected four arguments"),
+            },
+            BuiltinFunctions::IterateUntil => match args {
+                [expr, names, initial, condition] | [expr, names, initial, condition, _] => {
+                    (|| {
+                        let names = binding_names(name, names)?;
+                        let max_iterations = match args.get(4) {
+                            Some(m) => expect_integer(name, &self.evaluate(m)?)?,
+                            None => 100,
+                        };
+                        if max_iterations > MAX_ITERATIONS {
+                            return invalid("the maximal amount of iterations is too large");
+                        }
+                        let mut value = self.evaluate(initial)?;
+                        let mut results = vec![value.clone()];
+                        for _ in 0..max_iterations {
+                            let scope = bind(name, &names, value.clone())?;
+                            let stop =
+                                self.with_scope(scope.clone(), |env| env.evaluate(condition))?;
+                            if expect_bool(name, &stop)? {
+                                break;
+                            }
+                            value = self.with_scope(scope, |env| env.evaluate(expr))?;
+                            results.push(value.clone());
+                        }
+                        Ok(Value::List(results))
+                    })()
+                }
+                _ => invalid("expected four or five arguments"),
+            },
+            BuiltinFunctions::Repeat => match args {
+                [expr, n] => (|| {
+                    let n = expect_integer(name, &self.evaluate(n)?)?;
+                    if n > MAX_ITERATIONS {
+                        return invalid("the amount of repetitions is too large");
+                    }
+                    Ok(Value::List(
+                        (0..n)
+                            .map(|_| self.evaluate(expr))
+                            .collect::<EvaluationResult<_>>()?,
+                    ))
+                })(),
+                _ => invalid("expected two arguments"),
+            },
+            BuiltinFunctions::Assert => match args {
+                [condition, value] => match self.evaluate(condition) {
+                    Ok(Value::Boolean(true)) => Ok(Value::Boolean(true)),
+                    Ok(Value::Boolean(false)) => self.evaluate(value),
+                    Ok(_) => invalid("the condition should be a boolean"),
+                    Err(e) => Err(e),
+                },
+                _ => invalid("expected two arguments"),
+            },
+            BuiltinFunctions::Try => match args {
+                [expr, names, except] => match self.evaluate(expr) {
+                    Ok(v) => Ok(v),
+                    Err(e) => binding_names(name, names).and_then(|names| {
+                        let scope = bind(name, &names, Value::String(e.to_string()))?;
+                        self.with_scope(scope, |env| env.evaluate(except))
+                    }),
+                },
+                _ => invalid("expected three arguments"),
+            },
+            BuiltinFunctions::IsSet => match args {
+                [Expr::Ident(i)] => Ok(Value::Boolean(self.is_set(i.name()))),
+                [e] => self
+                    .evaluate(e)
+                    .and_then(|v| expect_string(name, &v))
+                    .map(|n| Value::Boolean(self.is_set(&n[..]))),
+                _ => invalid("expected one argument"),
+            },
+            BuiltinFunctions::DefinedVariables => Ok(Value::List(
+                self.scopes
+                    .iter()
+                    .flat_map(|s| s.keys().cloned())
+                    .chain(self.variables.keys().cloned())
+                    .chain(self.definitions.keys().cloned())
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .into_iter()
+                    .map(Value::String)
+                    .collect(),
+            )),
+            _ => return None,
+        })
+    }
+
+    fn call_let(&mut self, name: &str, args: &[Expr]) -> EvaluationResult<Value> {
+        let (expr, definitions) = match args.split_last() {
+            Some(s) => s,
+            None => {
+                return Err(EvaluationError::InvalidArguments(
+                    name.to_string(),
+                    "expected at least two arguments".to_string(),
+                ))
+            }
+        };
+        let mut scope = HashMap::new();
+        if let [dict] = definitions {
+            let dict = self.evaluate(dict)?;
+            match dict {
+                Value::Dictionary(d) => {
+                    scope.extend(d.into_iter().map(|(k, v)| (k.to_lowercase(), v)));
+                }
+                _ => {
+                    return Err(EvaluationError::InvalidArguments(
+                        name.to_string(),
+                        "expected a dictionary".to_string(),
+                    ))
+                }
+            }
+        } else {
+            for definition in definitions.chunks(2) {
+                match definition {
+                    [names, value] => {
+                        let names = binding_names(name, names)?;
+                        let value = self.with_scope(scope.clone(), |env| env.evaluate(value))?;
+                        scope.extend(bind(name, &names, value)?);
+                    }
+                    _ => {
+                        return Err(EvaluationError::InvalidArguments(
+                            name.to_string(),
+                            "each name needs a value".to_string(),
+                        ))
+                    }
+                }
+            }
+        }
+        self.with_scope(scope, |env| env.evaluate(expr))
+    }
+}
+
+/// The names that are bound by functions like `map`, either one name or a list of names
+fn binding_names(function: &str, expr: &Expr) -> EvaluationResult<Vec<String>> {
+    match expr {
+        Expr::Ident(i) => Ok(vec![i.name().to_lowercase()]),
+        Expr::List(items) => items
+            .iter()
+            .map(|i| match i {
+                Expr::Ident(i) => Ok(i.name().to_lowercase()),
+                _ => Err(EvaluationError::InvalidArguments(
+                    function.to_string(),
+                    "expected a name".to_string(),
+                )),
+            })
+            .collect(),
+        _ => Err(EvaluationError::InvalidArguments(
+            function.to_string(),
+            "expected a name or a list of names".to_string(),
+        )),
+    }
+}
+
+fn bind(
+    function: &str,
+    names: &[String],
+    value: Value,
+) -> EvaluationResult<HashMap<String, Value>> {
+    if let [name] = names {
+        return Ok(vec![(name.clone(), value)].into_iter().collect());
+    }
+    let values = collection_items(function, &value)?;
+    if values.len() != names.len() {
+        return Err(EvaluationError::InvalidArguments(
+            function.to_string(),
+            format!("can't assign {} to {} names", value, names.len()),
+        ));
+    }
+    Ok(names.iter().cloned().zip(values).collect())
+}
+
+fn constant(ident: &Ident) -> EvaluationResult<Value> {
+    match ident.name() {
+        "pi" | "π" => Ok(Value::Number(std::f64::consts::PI)),
+        "e" => Ok(Value::Number(std::f64::consts::E)),
+        "infinity" | "infty" | "∞" => Ok(Value::Number(f64::INFINITY)),
+        "nan" => Ok(Value::Number(f64::NAN)),
+        n => Err(EvaluationError::InvalidOperation(format!(
+            "the constant {} is not supported",
+            n
+        ))),
+    }
+}
+
+fn invalid_operation(op: &str, v1: &Value, v2: &Value) -> EvaluationError {
+    EvaluationError::InvalidOperation(format!(
+        "{} is not defined for {} and {}",
+        op,
+        v1.type_name(),
+        v2.type_name()
+    ))
+}
+
+fn is_integer(n: f64) -> bool {
+    n.is_finite() && n.fract() == 0.0
+}
+
+/// The values in a range, an error for continuous ranges
+fn range_values(start: f64, end: f64, step: f64) -> EvaluationResult<Vec<f64>> {
+    if step == 0.0 {
+        return Err(EvaluationError::InvalidOperation(
+            "a continuous range can't be converted to a list".to_string(),
+        ));
+    }
+    let amount = ((end - start) / step + 1e-10).floor();
+    if amount > MAX_RANGE_LENGTH {
+        return Err(EvaluationError::InvalidOperation(
+            "the range is too large".to_string(),
+        ));
+    }
+    if amount < 0.0 {
+        return Ok(vec![]);
+    }
+    Ok((0..=(amount as usize))
+        .map(|i| start + (i as f64) * step)
+        .collect())
+}
+
+fn distinct(items: impl Iterator<Item = Value>) -> Vec<Value> {
+    let mut result: Vec<Value> = Vec::new();
+    for item in items {
+        if !result.iter().any(|r| values_equal(r, &item)) {
+            result.push(item);
+        }
+    }
+    result
+}
+
+fn values_equal(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::Set(a), Value::Set(b)) => {
+            a.len() == b.len() && a.iter().all(|x| b.iter().any(|y| values_equal(x, y)))
+        }
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| values_equal(x, y))
+        }
+        (Value::Dictionary(a), Value::Dictionary(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, x)| b.get(k).map(|y| values_equal(x, y)).unwrap_or(false))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn negate(v: Value) -> EvaluationResult<Value> {
+    match v {
+        Value::Number(n) => Ok(Value::Number(-n)),
+        Value::Vector(v) => Ok(Value::Vector(v.into_iter().map(|n| -n).collect())),
+        Value::Matrix(m) => Ok(Value::Matrix(
+            m.into_iter()
+                .map(|r| r.into_iter().map(|n| -n).collect())
+                .collect(),
+        )),
+        v => Err(EvaluationError::InvalidOperation(format!(
+            "- is not defined for {}",
+            v.type_name()
+        ))),
+    }
+}
+
+fn elementwise(
+    op: &str,
+    a: Vec<f64>,
+    b: Vec<f64>,
+    f: impl Fn(f64, f64) -> f64,
+) -> EvaluationResult<Vec<f64>> {
+    if a.len() != b.len() {
+        return Err(EvaluationError::InvalidOperation(format!(
+            "{} is not defined for vectors of different length",
+            op
+        )));
+    }
+    Ok(a.into_iter().zip(b).map(|(x, y)| f(x, y)).collect())
+}
+
+fn elementwise_matrix(
+    op: &str,
+    a: Vec<Vec<f64>>,
+    b: Vec<Vec<f64>>,
+    f: impl Fn(f64, f64) -> f64 + Copy,
+) -> EvaluationResult<Vec<Vec<f64>>> {
+    if a.len() != b.len() {
+        return Err(EvaluationError::InvalidOperation(format!(
+            "{} is not defined for matrices of different size",
+            op
+        )));
+    }
+    a.into_iter()
+        .zip(b)
+        .map(|(x, y)| elementwise(op, x, y, f))
+        .collect()
+}
+
+fn matrix_multiply(a: &[Vec<f64>], b: &[Vec<f64>]) -> EvaluationResult<Vec<Vec<f64>>> {
+    let columns = b.first().map(|r| r.len()).unwrap_or(0);
+    if a.iter().any(|r| r.len() != b.len()) {
+        return Err(EvaluationError::InvalidOperation(
+            "matrices of incompatible sizes can't be multiplied".to_string(),
+        ));
+    }
+    Ok(a.iter()
+        .map(|row| {
+            (0..columns)
+                .map(|j| row.iter().zip(b.iter()).map(|(x, r)| x * r[j]).sum())
+                .collect()
+        })
+        .collect())
+}
+
+fn arithmetic(op: ArithmeticOperator, v1: Value, v2: Value) -> EvaluationResult<Value> {
+    use Value::*;
+    match op {
+        ArithmeticOperator::Add => match (v1, v2) {
+            (Number(a), Number(b)) => Ok(Number(a + b)),
+            (String(a), b) => Ok(String(a + &b.to_plain_string())),
+            (a, String(b)) => Ok(String(a.to_plain_string() + &b)),
+            (List(mut a), List(b)) => {
+                a.extend(b);
+                Ok(List(a))
+            }
+            (List(mut a), b) => {
+                a.push(b);
+                Ok(List(a))
+            }
+            (Dictionary(mut a), Dictionary(b)) => {
+                a.extend(b);
+                Ok(Dictionary(a))
+            }
+            (Vector(a), Vector(b)) => elementwise("+", a, b, |x, y| x + y).map(Vector),
+            (Matrix(a), Matrix(b)) => elementwise_matrix("+", a, b, |x, y| x + y).map(Matrix),
+            (a, b) => Err(invalid_operation("+", &a, &b)),
+        },
+        ArithmeticOperator::Subtract => match (v1, v2) {
+            (Number(a), Number(b)) => Ok(Number(a - b)),
+            (Vector(a), Vector(b)) => elementwise("-", a, b, |x, y| x - y).map(Vector),
+            (Matrix(a), Matrix(b)) => elementwise_matrix("-", a, b, |x, y| x - y).map(Matrix),
+            (Set(a), Set(b)) => Ok(Set(a
+                .into_iter()
+                .filter(|x| !b.iter().any(|y| values_equal(x, y)))
+                .collect())),
+            (a, b) => Err(invalid_operation("-", &a, &b)),
+        },
+        ArithmeticOperator::Multiply => match (v1, v2) {
+            (Number(a), Number(b)) => Ok(Number(a * b)),
+            (Number(a), Vector(v)) | (Vector(v), Number(a)) => {
+                Ok(Vector(v.into_iter().map(|x| a * x).collect()))
+            }
+            (Number(a), Matrix(m)) | (Matrix(m), Number(a)) => Ok(Matrix(
+                m.into_iter()
+                    .map(|r| r.into_iter().map(|x| a * x).collect())
+                    .collect(),
+            )),
+            (Matrix(m), Vector(v)) => {
+                let column = v.into_iter().map(|x| vec![x]).collect::<Vec<_>>();
+                matrix_multiply(&m, &column)
+                    .map(|r| Vector(r.into_iter().map(|row| row[0]).collect()))
+            }
+            (Matrix(a), Matrix(b)) => matrix_multiply(&a, &b).map(Matrix),
+            (a, b) => Err(invalid_operation("*", &a, &b)),
+        },
+        ArithmeticOperator::Divide => match (v1, v2) {
+            (Number(a), Number(b)) => Ok(Number(a / b)),
+            (Vector(v), Number(a)) => Ok(Vector(v.into_iter().map(|x| x / a).collect())),
+            (Matrix(m), Number(a)) => Ok(Matrix(
+                m.into_iter()
+                    .map(|r| r.into_iter().map(|x| x / a).collect())
+                    .collect(),
+            )),
+            (a, b) => Err(invalid_operation("/", &a, &b)),
+        },
+        ArithmeticOperator::Power => match (v1, v2) {
+            (Number(a), Number(b)) => Ok(Number(a.powf(b))),
+            (Matrix(_), Number(n)) if n > MAX_MATRIX_POWER => {
+                Err(EvaluationError::InvalidOperation(
+                    "the power of the matrix is too large".to_string(),
+                ))
+            }
+            (Matrix(m), Number(n)) if is_integer(n) && n >= 0.0 => {
+                let size = m.len();
+                let mut result = identity(size);
+                for _ in 0..(n as usize) {
+                    result = matrix_multiply(&result, &m)?;
+                }
+                Ok(Matrix(result))
+            }
+            (a, b) => Err(invalid_operation("^", &a, &b)),
+        },
+        ArithmeticOperator::Except => match (v1, v2) {
+            (collection, List(b)) => {
+                let items = collection_items("except", &collection)?;
+                Ok(List(
+                    items
+                        .into_iter()
+                        .filter(|x| !b.iter().any(|y| values_equal(x, y)))
+                        .collect(),
+                ))
+            }
+            (collection, b) => {
+                let items = collection_items("except", &collection)?;
+                Ok(List(
+                    items.into_iter().filter(|x| !values_equal(x, &b)).collect(),
+                ))
+            }
+        },
+    }
+}
+
+fn relation(op: RelationalOperator, v1: Value, v2: Value) -> EvaluationResult<Value> {
+    let compare = |f: fn(f64, f64) -> bool| match (&v1, &v2) {
+        (Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(f(*a, *b))),
+        (a, b) => Err(invalid_operation(&format!("{:?}", op), a, b)),
+    };
+    match op {
+        RelationalOperator::LessThan => compare(|a, b| a < b),
+        RelationalOperator::LessThanOrEqual => compare(|a, b| a <= b),
+        RelationalOperator::GreaterThan => compare(|a, b| a > b),
+        RelationalOperator::GreaterThanOrEqual => compare(|a, b| a >= b),
+        RelationalOperator::Equals => Ok(Value::Boolean(values_equal(&v1, &v2))),
+        RelationalOperator::NotEquals => Ok(Value::Boolean(!values_equal(&v1, &v2))),
+        RelationalOperator::Divides => match (&v1, &v2) {
+            (Value::Number(a), Value::Number(b)) => {
+                Ok(Value::Boolean(*a != 0.0 && (b / a).fract() == 0.0))
+            }
+            (a, b) => Err(invalid_operation("|", a, b)),
+        },
+        RelationalOperator::In => match (&v1, &v2) {
+            (Value::String(a), Value::String(b)) => Ok(Value::Boolean(b.contains(&a[..]))),
+            (Value::String(a), Value::Dictionary(d)) => Ok(Value::Boolean(d.contains_key(a))),
+            (Value::Number(n), Value::Range(start, end, 0.0)) => {
+                Ok(Value::Boolean(start <= n && n <= end))
+            }
+            (v, collection) => match collection.to_list() {
+                Some(items) => Ok(Value::Boolean(items.iter().any(|i| values_equal(i, v)))),
+                None => Err(invalid_operation("in", &v1, &v2)),
+            },
+        },
+        RelationalOperator::IsA => match &v2 {
+            Value::String(t) => Ok(Value::Boolean(match (&t[..], &v1) {
+                ("integer", Value::Number(n)) => is_integer(*n),
+                ("decimal", Value::Number(_)) | ("rational", Value::Number(_)) => true,
+                (t, v) => t == v.type_name(),
+            })),
+            _ => Err(invalid_operation("isa", &v1, &v2)),
+        },
+    }
+}
+
+fn indexation(v: Value, index: Value) -> EvaluationResult<Value> {
+    fn position(length: usize, index: f64) -> EvaluationResult<usize> {
+        let length = length as i64;
+        let index = index as i64;
+        let index = if index < 0 { length + index } else { index };
+        if index < 0 || index >= length {
+            Err(EvaluationError::InvalidOperation(format!(
+                "index {} is out of bounds",
+                index
+            )))
+        } else {
+            Ok(index as usize)
+        }
+    }
+    fn positions(length: usize, start: f64, end: f64, step: f64) -> EvaluationResult<Vec<usize>> {
+        let length = length as f64;
+        let start = if start < 0.0 { length + start } else { start };
+        let end = if end < 0.0 {
+            length + end
+        } else {
+            end.min(length)
+        };
+        // The end of a slice is exclusive
+        Ok(range_values(start, end, step)?
+            .into_iter()
+            .filter(|i| *i >= 0.0 && *i < length && *i < end)
+            .map(|i| i as usize)
+            .collect())
+    }
+    match (v, index) {
+        (Value::Dictionary(d), Value::String(k)) => d.get(&k).cloned().ok_or_else(|| {
+            EvaluationError::InvalidOperation(format!("the dictionary has no key {}", k))
+        }),
+        (Value::String(s), Value::Number(i)) => {
+            let chars = s.chars().collect::<Vec<_>>();
+            Ok(Value::String(chars[position(chars.len(), i)?].to_string()))
+        }
+        (Value::String(s), Value::Range(start, end, step)) => {
+            let chars = s.chars().collect::<Vec<_>>();
+            Ok(Value::String(
+                positions(chars.len(), start, end, step)?
+                    .into_iter()
+                    .map(|i| chars[i])
+                    .collect(),
+            ))
+        }
+        (Value::Vector(v), Value::Number(i)) => Ok(Value::Number(v[position(v.len(), i)?])),
+        (Value::Vector(v), Value::Range(start, end, step)) => Ok(Value::Vector(
+            positions(v.len(), start, end, step)?
+                .into_iter()
+                .map(|i| v[i])
+                .collect(),
+        )),
+        (Value::Matrix(m), Value::Number(i)) => Ok(Value::Vector(m[position(m.len(), i)?].clone())),
+        (Value::Matrix(m), Value::Range(start, end, step)) => Ok(Value::Matrix(
+            positions(m.len(), start, end, step)?
+                .into_iter()
+                .map(|i| m[i].clone())
+                .collect(),
+        )),
+        (collection, index) => match (collection.to_list(), index) {
+            (Some(items), Value::Number(i)) => Ok(items[position(items.len(), i)?].clone()),
+            (Some(items), Value::Range(start, end, step)) => Ok(Value::List(
+                positions(items.len(), start, end, step)?
+                    .into_iter()
+                    .map(|i| items[i].clone())
+                    .collect(),
+            )),
+            (_, index) => Err(EvaluationError::InvalidOperation(format!(
+                "{} can't be indexed by {}",
+                collection.type_name(),
+                index.type_name()
+            ))),
+        },
+    }
+}
+
+fn cast(v: Value, t: &str) -> EvaluationResult<Value> {
+    match (t, v) {
+        ("string", v) => Ok(Value::String(v.to_plain_string())),
+        ("number", Value::Number(n))
+        | ("decimal", Value::Number(n))
+        | ("rational", Value::Number(n)) => Ok(Value::Number(n)),
+        ("integer", Value::Number(n)) => Ok(Value::Number(n.round())),
+        ("number", Value::String(s)) | ("decimal", Value::String(s)) => s
+            .trim()
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| EvaluationError::InvalidOperation(format!("{} is not a number", s))),
+        ("list", v) => v.to_list().map(Value::List).ok_or_else(|| {
+            EvaluationError::InvalidOperation(format!("{} can't be cast to a list", v))
+        }),
+        ("set", v) => v
+            .to_list()
+            .map(|l| Value::Set(distinct(l.into_iter())))
+            .ok_or_else(|| {
+                EvaluationError::InvalidOperation(format!("{} can't be cast to a set", v))
+            }),
+        ("vector", v) => v
+            .to_list()
+            .ok_or(())
+            .and_then(|l| numbers_of("vector", &l).map_err(|_| ()))
+            .map(Value::Vector)
+            .map_err(|_| {
+                EvaluationError::InvalidOperation(format!("{} can't be cast to a vector", v))
+            }),
+        (t, v) => {
+            if v.type_name() == t {
+                Ok(v)
+            } else {
+                Err(EvaluationError::InvalidOperation(format!(
+                    "{} can't be cast to {}",
+                    v, t
+                )))
+            }
+        }
+    }
+}
+
+fn identity(size: usize) -> Vec<Vec<f64>> {
+    (0..size)
+        .map(|i| (0..size).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+fn invalid_arguments<T>(function: &str, message: &str) -> EvaluationResult<T> {
+    Err(EvaluationError::InvalidArguments(
+        function.to_string(),
+        message.to_string(),
+    ))
+}
+
+fn expect_number(function: &str, v: &Value) -> EvaluationResult<f64> {
+    v.as_number().ok_or_else(|| {
+        EvaluationError::InvalidArguments(
+            function.to_string(),
+            format!("expected a number but got {}", v.type_name()),
+        )
+    })
+}
+
+fn expect_integer(function: &str, v: &Value) -> EvaluationResult<i64> {
+    let n = expect_number(function, v)?;
+    if is_integer(n) && n.abs() <= MAX_INTEGER {
+        Ok(n as i64)
+    } else if is_integer(n) {
+        invalid_arguments(function, &format!("the integer {} is too large", v))
+    } else {
+        invalid_arguments(function, &format!("expected an integer but got {}", v))
+    }
+}
+
+fn expect_bool(function: &str, v: &Value) -> EvaluationResult<bool> {
+    v.as_bool().ok_or_else(|| {
+        EvaluationError::InvalidArguments(
+            function.to_string(),
+            format!("expected a boolean but got {}", v.type_name()),
+        )
+    })
+}
+
+fn expect_string(function: &str, v: &Value) -> EvaluationResult<String> {
+    v.as_str().map(|s| s.to_string()).ok_or_else(|| {
+        EvaluationError::InvalidArguments(
+            function.to_string(),
+            format!("expected a string but got {}", v.type_name()),
+        )
+    })
+}
+
+fn collection_items(function: &str, v: &Value) -> EvaluationResult<Vec<Value>> {
+    v.to_list().ok_or_else(|| {
+        EvaluationError::InvalidArguments(
+            function.to_string(),
+            format!("expected a collection but got {}", v.type_name()),
+        )
+    })
+}
+
+fn numbers_of(function: &str, values: &[Value]) -> EvaluationResult<Vec<f64>> {
+    values.iter().map(|v| expect_number(function, v)).collect()
+}
+
+/// The numbers in a vector, a single row or column matrix or a list of numbers
+fn expect_vector(function: &str, v: &Value) -> EvaluationResult<Vec<f64>> {
+    match v {
+        Value::Vector(v) => Ok(v.clone()),
+        Value::Matrix(m) if m.len() == 1 => Ok(m[0].clone()),
+        Value::Matrix(m) if m.iter().all(|r| r.len() == 1) => Ok(m.iter().map(|r| r[0]).collect()),
+        Value::List(l) => numbers_of(function, l),
+        v => invalid_arguments(
+            function,
+            &format!("expected a vector but got {}", v.type_name()),
+        ),
+    }
+}
+
+fn expect_matrix(function: &str, v: &Value) -> EvaluationResult<Vec<Vec<f64>>> {
+    match v {
+        Value::Matrix(m) => rectangular(function, m.clone()),
+        Value::Vector(v) => Ok(v.iter().map(|x| vec![*x]).collect()),
+        Value::List(rows) => rectangular(
+            function,
+            rows.iter()
+                .map(|r| expect_vector(function, r))
+                .collect::<EvaluationResult<_>>()?,
+        ),
+        v => invalid_arguments(
+            function,
+            &format!("expected a matrix but got {}", v.type_name()),
+        ),
+    }
+}
+
+/// Check that all rows of a matrix have the same length
+fn rectangular(function: &str, m: Vec<Vec<f64>>) -> EvaluationResult<Vec<Vec<f64>>> {
+    match m.first() {
+        Some(first) if m.iter().any(|r| r.len() != first.len()) => {
+            invalid_arguments(function, "all rows of a matrix should have the same length")
+        }
+        _ => Ok(m),
+    }
+}
+
+/// The arguments of functions like `max` which are either given separately or as one list
+fn spread_arguments(args: Vec<Value>) -> Vec<Value> {
+    match &args[..] {
+        [single] => single.to_list().unwrap_or(args),
+        _ => args,
+    }
+}
+
+/// Apply a function to a number, or to each element of a vector or matrix
+fn map_numbers(function: &str, v: &Value, f: impl Fn(f64) -> f64) -> EvaluationResult<Value> {
+    match v {
+        Value::Number(n) => Ok(Value::Number(f(*n))),
+        Value::Vector(v) => Ok(Value::Vector(v.iter().map(|x| f(*x)).collect())),
+        Value::Matrix(m) => Ok(Value::Matrix(
+            m.iter()
+                .map(|r| r.iter().map(|x| f(*x)).collect())
+                .collect(),
+        )),
+        v => invalid_arguments(
+            function,
+            &format!("expected a number but got {}", v.type_name()),
+        ),
+    }
+}
+
+fn precround(n: f64, dp: i64) -> f64 {
+    let factor = 10f64.powi(dp as i32);
+    let rounded = (n * factor).round() / factor;
+    // Remove floating point noise like 0.30000000000000004
+    format!("{:.*}", dp.max(0) as usize, rounded)
+        .parse()
+        .unwrap_or(rounded)
+}
+
+fn siground(n: f64, sf: i64) -> f64 {
+    if n == 0.0 || !n.is_finite() {
+        return n;
+    }
+    let dp = sf - 1 - n.abs().log10().floor() as i64;
+    precround(n, dp)
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+/// Lanczos approximation of the gamma function
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x))
+    } else {
+        let x = x - 1.0;
+        let t = x + G + 0.5;
+        let sum = COEFFICIENTS[1..]
+            .iter()
+            .enumerate()
+            .fold(COEFFICIENTS[0], |acc, (i, c)| {
+                acc + c / (x + i as f64 + 1.0)
+            });
+        (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+    }
+}
+
+fn factorial(function: &str, n: f64) -> EvaluationResult<f64> {
+    // 171! is too large for a number
+    if n > 170.0 {
+        Ok(f64::INFINITY)
+    } else if is_integer(n) && n >= 0.0 {
+        Ok((1..=(n as u64)).fold(1.0, |acc, i| acc * i as f64))
+    } else if n > -1.0 {
+        Ok(gamma(n + 1.0))
+    } else {
+        invalid_arguments(function, "expected a positive number")
+    }
+}
+
+fn divisors(function: &str, n: i64) -> EvaluationResult<Vec<f64>> {
+    let n = n.abs();
+    if n > MAX_DIVISORS_NUMBER {
+        return invalid_arguments(function, "the number is too large");
+    }
+    let (mut small, mut large) = (Vec::new(), Vec::new());
+    for d in (1..).take_while(|d| d * d <= n) {
+        if n % d == 0 {
+            small.push(d as f64);
+            if d * d != n {
+                large.push((n / d) as f64);
+            }
+        }
+    }
+    small.extend(large.into_iter().rev());
+    Ok(small)
+}
+
+/// The product of `amount` factors, stopping as soon as it is infinite
+fn product_of(amount: f64, factor: impl Fn(f64) -> f64) -> f64 {
+    let mut result = 1.0f64;
+    let mut i = 0.0;
+    while i < amount && result.is_finite() {
+        result *= factor(i);
+        i += 1.0;
+    }
+    result
+}
+
+/// The amount of ways to choose `k` out of `n` items, as a number because it can be very large
+fn binomial(n: f64, k: f64) -> f64 {
+    let k = k.min(n - k);
+    if k < 0.0 {
+        return 0.0;
+    }
+    product_of(k, |i| (n - i) / (i + 1.0)).round()
+}
+
+/// The amount of ordered ways to choose `k` out of `n` items
+fn permutation_count(n: f64, k: f64) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    product_of(k, |i| n - i)
+}
+
+fn determinant(mut m: Vec<Vec<f64>>) -> f64 {
+    let size = m.len();
+    let mut det = 1.0;
+    for i in 0..size {
+        let pivot = (i..size)
+            .max_by(|a, b| m[*a][i].abs().total_cmp(&m[*b][i].abs()))
+            .unwrap();
+        if m[pivot][i] == 0.0 {
+            return 0.0;
+        }
+        if pivot != i {
+            m.swap(pivot, i);
+            det = -det;
+        }
+        det *= m[i][i];
+        let pivot_row = m[i].clone();
+        for row in m.iter_mut().skip(i + 1) {
+            let factor = row[i] / pivot_row[i];
+            for (cell, pivot_cell) in row.iter_mut().zip(pivot_row.iter()).skip(i) {
+                *cell -= factor * pivot_cell;
+            }
+        }
+    }
+    det
+}
+
+fn transpose(m: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let columns = m.first().map(|r| r.len()).unwrap_or(0);
+    (0..columns)
+        .map(|j| m.iter().map(|r| r[j]).collect())
+        .collect()
+}
+
+fn combinations(items: &[Value], r: usize, with_replacement: bool) -> Vec<Vec<Value>> {
+    let n = items.len();
+    if r > 0 && (n == 0 || (!with_replacement && r > n)) {
+        return vec![];
+    }
+    let mut indices: Vec<usize> = if with_replacement {
+        vec![0; r]
+    } else {
+        (0..r).collect()
+    };
+    let mut result = Vec::new();
+    loop {
+        result.push(indices.iter().map(|i| items[*i].clone()).collect());
+        let last = |i: usize| if with_replacement { n - 1 } else { i + n - r };
+        match (0..r).rev().find(|i| indices[*i] != last(*i)) {
+            Some(i) => {
+                indices[i] += 1;
+                for j in i + 1..r {
+                    indices[j] = if with_replacement {
+                        indices[i]
+                    } else {
+                        indices[j - 1] + 1
+                    };
+                }
+            }
+            None => break,
+        }
+    }
+    result
+}
+
+fn permutations(items: &[Value], r: usize) -> Vec<Vec<Value>> {
+    if r == 0 {
+        return vec![vec![]];
+    }
+    if r > items.len() {
+        return vec![];
+    }
+    let mut result = Vec::new();
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let item = rest.remove(i);
+        for mut permutation in permutations(&rest, r - 1) {
+            permutation.insert(0, item.clone());
+            result.push(permutation);
+        }
+    }
+    result
+}
+
+fn compare_values(function: &str, a: &Value, b: &Value) -> EvaluationResult<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => {
+            Ok(x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal))
+        }
+        (Value::String(x), Value::String(y)) => Ok(x.cmp(y)),
+        (a, b) => invalid_arguments(
+            function,
+            &format!("can't compare {} and {}", a.type_name(), b.type_name()),
+        ),
+    }
+}
+
+fn sort_values(function: &str, mut items: Vec<Value>) -> EvaluationResult<Vec<Value>> {
+    let mut error = None;
+    items.sort_by(|a, b| {
+        compare_values(function, a, b).unwrap_or_else(|e| {
+            error = Some(e);
+            std::cmp::Ordering::Equal
+        })
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(items),
+    }
+}
+
+fn json_to_value(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Nothing,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        serde_json::Value::Array(a) => Value::List(a.iter().map(json_to_value).collect()),
+        serde_json::Value::Object(o) => Value::Dictionary(
+            o.iter()
+                .map(|(k, v)| (k.clone(), json_to_value(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn value_to_json(v: &Value) -> serde_json::Value {
+    match v {
+        Value::Nothing => serde_json::Value::Null,
+        Value::Boolean(b) => serde_json::Value::Bool(*b),
+        Value::Number(n) => serde_json::Number::from_f64(*n)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Value::String(s) => serde_json::Value::String(s.clone()),
+        Value::Dictionary(d) => serde_json::Value::Object(
+            d.iter()
+                .map(|(k, v)| (k.clone(), value_to_json(v)))
+                .collect(),
+        ),
+        v => serde_json::Value::Array(
+            v.to_list()
+                .unwrap_or_default()
+                .iter()
+                .map(value_to_json)
+                .collect(),
+        ),
+    }
+}
+
+fn letter_ordinal(n: i64) -> String {
+    let mut n = n;
+    let mut result = String::new();
+    loop {
+        result.insert(0, (b'a' + (n % 26) as u8) as char);
+        n = n / 26 - 1;
+        if n < 0 {
+            break;
+        }
+    }
+    result
+}
+
+fn count_significant_figures(s: &str) -> usize {
+    let s = s.trim().trim_start_matches('-');
+    let digits = s.trim_start_matches(['0', '.']);
+    if s.contains('.') {
+        digits.chars().filter(|c| c.is_ascii_digit()).count()
+    } else {
+        digits.trim_end_matches('0').len()
+    }
+}
+
+/// Call a builtin function with evaluated arguments
+fn call_builtin(
+    name: &str,
+    function: &BuiltinFunctions,
+    args: Vec<Value>,
+    random: &mut Random,
+) -> EvaluationResult<Value> {
+    use BuiltinFunctions as B;
+    let number = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| {
+                EvaluationError::InvalidArguments(
+                    name.to_string(),
+                    format!("missing argument {}", i + 1),
+                )
+            })
+            .and_then(|v| expect_number(name, v))
+    };
+    let integer = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| {
+                EvaluationError::InvalidArguments(
+                    name.to_string(),
+                    format!("missing argument {}", i + 1),
+                )
+            })
+            .and_then(|v| expect_integer(name, v))
+    };
+    let string = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| {
+                EvaluationError::InvalidArguments(
+                    name.to_string(),
+                    format!("missing argument {}", i + 1),
+                )
+            })
+            .and_then(|v| expect_string(name, v))
+    };
+    let collection = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| {
+                EvaluationError::InvalidArguments(
+                    name.to_string(),
+                    format!("missing argument {}", i + 1),
+                )
+            })
+            .and_then(|v| collection_items(name, v))
+    };
+    let argument = |i: usize| {
+        args.get(i).cloned().ok_or_else(|| {
+            EvaluationError::InvalidArguments(
+                name.to_string(),
+                format!("missing argument {}", i + 1),
+            )
+        })
+    };
+    let unary = |f: fn(f64) -> f64| argument(0).and_then(|v| map_numbers(name, &v, f));
+    let num = |n: f64| Ok(Value::Number(n));
+    let boolean = |b: bool| Ok(Value::Boolean(b));
+    let string_value = |s: String| Ok(Value::String(s));
+    match function {
+        // Numbers
+        B::Exp => unary(f64::exp),
+        B::Decimal | B::Rational => match argument(0)? {
+            Value::String(s) => cast(Value::String(s), "number"),
+            v => map_numbers(name, &v, |x| x),
+        },
+        B::Int => unary(f64::round),
+        B::Abs => match argument(0)? {
+            Value::Number(n) => num(n.abs()),
+            Value::String(s) => num(s.chars().count() as f64),
+            Value::List(l) | Value::Set(l) => num(l.len() as f64),
+            Value::Dictionary(d) => num(d.len() as f64),
+            Value::Range(start, end, step) => num(range_values(start, end, step)?.len() as f64),
+            Value::Vector(v) => num(v.iter().map(|x| x * x).sum::<f64>().sqrt()),
+            v => invalid_arguments(name, &format!("can't take the absolute value of {}", v)),
+        },
+        B::Arg => num(if number(0)? < 0.0 {
+            std::f64::consts::PI
+        } else {
+            0.0
+        }),
+        B::Re | B::Conj => num(number(0)?),
+        B::Im => num(0.0),
+        B::IsInt => boolean(is_integer(number(0)?)),
+        B::IsZero => boolean(number(0)? == 0.0),
+        B::Sqrt => unary(f64::sqrt),
+        B::Root => num(number(0)?.powf(1.0 / number(1)?)),
+        B::Ln => unary(f64::ln),
+        B::Log => match args.len() {
+            1 => unary(f64::log10),
+            _ => num(number(0)?.log(number(1)?)),
+        },
+        B::Degrees => unary(f64::to_degrees),
+        B::Radians => unary(f64::to_radians),
+        B::Sign => unary(|x| if x == 0.0 { 0.0 } else { x.signum() }),
+        B::Max | B::Min => {
+            let numbers = numbers_of(name, &spread_arguments(args.clone()))?;
+            if numbers.is_empty() {
+                return invalid_arguments(name, "expected at least one number");
+            }
+            num(if matches!(function, B::Max) {
+                numbers.into_iter().fold(f64::NEG_INFINITY, f64::max)
+            } else {
+                numbers.into_iter().fold(f64::INFINITY, f64::min)
+            })
+        }
+        B::Clamp => num(number(0)?.min(number(2)?).max(number(1)?)),
+        B::PrecRound => {
+            let dp = integer(1)?;
+            if dp.abs() > MAX_PRECISION {
+                return invalid_arguments(name, "the amount of decimal places is too large");
+            }
+            map_numbers(name, &argument(0)?, |x| precround(x, dp))
+        }
+        B::SigRound => {
+            let sf = integer(1)?;
+            if sf.abs() > MAX_PRECISION {
+                return invalid_arguments(name, "the amount of significant figures is too large");
+            }
+            map_numbers(name, &argument(0)?, |x| siground(x, sf))
+        }
+        B::WithIntolerance => {
+            let (a, b, t) = (number(0)?, number(1)?, number(2)?);
+            boolean(b - t <= a && a <= b + t)
+        }
+        B::DPFormat => {
+            let dp = integer(1)?;
+            if dp > MAX_PRECISION {
+                return invalid_arguments(name, "the amount of decimal places is too large");
+            }
+            let dp = dp.max(0) as usize;
+            string_value(format!("{:.*}", dp, precround(number(0)?, dp as i64)))
+        }
+        B::SigFormat => {
+            let n = number(0)?;
+            let sf = integer(1)?;
+            if sf.abs() > MAX_PRECISION {
+                return invalid_arguments(name, "the amount of significant figures is too large");
+            }
+            let rounded = siground(n, sf);
+            let dp = if n == 0.0 {
+                sf - 1
+            } else {
+                sf - 1 - n.abs().log10().floor() as i64
+            };
+            string_value(format!("{:.*}", dp.max(0) as usize, rounded))
+        }
+        B::CountDP => {
+            let s = string(0)?;
+            num(s.trim().split('.').nth(1).map(|d| d.len()).unwrap_or(0) as f64)
+        }
+        B::CountSigFigs => num(count_significant_figures(&string(0)?) as f64),
+        B::ToNearest => {
+            let (a, b) = (number(0)?, number(1)?);
+            num((a / b).round() * b)
+        }
+        B::CleanNumber => string_value(string(0)?.chars().filter(|c| !c.is_whitespace()).collect()),
+        B::ParseNumber | B::ParseDecimal => {
+            let s = string(0)?.replace([',', ' '], "");
+            num(s.parse().unwrap_or(f64::NAN))
+        }
+        B::ParseNumberOrFraction | B::ParseDecimalOrFraction => {
+            let s = string(0)?.replace(' ', "");
+            match s.split_once('/') {
+                Some((a, b)) => match (a.parse::<f64>(), b.parse::<f64>()) {
+                    (Ok(a), Ok(b)) => num(a / b),
+                    _ => num(f64::NAN),
+                },
+                None => num(s.parse().unwrap_or(f64::NAN)),
+            }
+        }
+        B::ToBinary | B::ToOctal | B::ToHexadecimal | B::ToBase => {
+            let n = integer(0)?;
+            let base = match function {
+                B::ToBinary => 2,
+                B::ToOctal => 8,
+                B::ToHexadecimal => 16,
+                _ => integer(1)?,
+            };
+            if !(2..=36).contains(&base) {
+                return invalid_arguments(name, "the base should be between 2 and 36");
+            }
+            let mut digits = String::new();
+            let mut rest = n.abs();
+            loop {
+                digits.insert(
+                    0,
+                    std::char::from_digit((rest % base) as u32, base as u32).unwrap(),
+                );
+                rest /= base;
+                if rest == 0 {
+                    break;
+                }
+            }
+            if n < 0 {
+                digits.insert(0, '-');
+            }
+            string_value(digits)
+        }
+        B::FromBinary | B::FromOctal | B::FromHexadecimal | B::FromBase => {
+            let s = string(0)?;
+            let base = match function {
+                B::FromBinary => 2,
+                B::FromOctal => 8,
+                B::FromHexadecimal => 16,
+                _ => integer(1)?,
+            };
+            if !(2..=36).contains(&base) {
+                return invalid_arguments(name, "the base should be between 2 and 36");
+            }
+            num(i64::from_str_radix(s.trim(), base as u32)
+                .map(|n| n as f64)
+                .unwrap_or(f64::NAN))
+        }
+        B::IsNan => boolean(number(0)?.is_nan()),
+
+        // Trigonometry
+        B::Sin => unary(f64::sin),
+        B::Cos => unary(f64::cos),
+        B::Tan => unary(f64::tan),
+        B::Cosec => unary(|x| 1.0 / x.sin()),
+        B::Sec => unary(|x| 1.0 / x.cos()),
+        B::Cot => unary(|x| 1.0 / x.tan()),
+        B::ArcSin => unary(f64::asin),
+        B::ArcCos => unary(f64::acos),
+        B::ArcTan => unary(f64::atan),
+        B::ATan2 => num(number(0)?.atan2(number(1)?)),
+        B::Sinh => unary(f64::sinh),
+        B::Cosh => unary(f64::cosh),
+        B::Tanh => unary(f64::tanh),
+        B::Cosech => unary(|x| 1.0 / x.sinh()),
+        B::Sech => unary(|x| 1.0 / x.cosh()),
+        B::Coth => unary(|x| 1.0 / x.tanh()),
+        B::ArcSinh => unary(f64::asinh),
+        B::ArcCosh => unary(f64::acosh),
+        B::ArcTanh => unary(f64::atanh),
+
+        // Number theory
+        B::Fact => num(factorial(name, number(0)?)?),
+        B::Factorise => {
+            let mut n = integer(0)?.abs();
+            if n > MAX_FACTORISE_NUMBER {
+                return invalid_arguments(name, "the number is too large");
+            }
+            let mut exponents = Vec::new();
+            let mut prime = 2;
+            while n > 1 {
+                let is_prime = (2..prime)
+                    .take_while(|d| d * d <= prime)
+                    .all(|d| prime % d != 0);
+                if is_prime {
+                    let mut exponent = 0;
+                    while n % prime == 0 {
+                        n /= prime;
+                        exponent += 1;
+                    }
+                    exponents.push(Value::Number(exponent as f64));
+                }
+                prime += 1;
+            }
+            Ok(Value::List(exponents))
+        }
+        B::Divisors => Ok(Value::List(
+            divisors(name, integer(0)?)?
+                .into_iter()
+                .map(Value::Number)
+                .collect(),
+        )),
+        B::ProperDivisors => {
+            let n = integer(0)?.abs();
+            Ok(Value::List(
+                divisors(name, n)?
+                    .into_iter()
+                    .filter(|d| *d != n as f64)
+                    .map(Value::Number)
+                    .collect(),
+            ))
+        }
+        B::Gamma => unary(gamma),
+        B::Ceil => unary(f64::ceil),
+        B::Floor => unary(f64::floor),
+        B::Round => unary(f64::round),
+        B::Trunc => unary(f64::trunc),
+        B::Fract => unary(f64::fract),
+        B::Mod => {
+            let (a, b) = (number(0)?, number(1)?);
+            num(((a % b) + b) % b)
+        }
+        B::Perm => {
+            let (n, k) = (number(0)?, number(1)?);
+            if is_integer(n) && is_integer(k) && 0.0 <= k && k <= n {
+                return num(permutation_count(n, k));
+            }
+            num(factorial(name, n)? / factorial(name, n - k)?)
+        }
+        B::Comb => {
+            let (n, k) = (number(0)?, number(1)?);
+            if is_integer(n) && is_integer(k) && 0.0 <= k && k <= n {
+                return num(binomial(n, k));
+            }
+            num((factorial(name, n)? / (factorial(name, k)? * factorial(name, n - k)?)).round())
+        }
+        B::GCD | B::GCDWithoutPIorI => num(gcd(integer(0)?, integer(1)?) as f64),
+        B::CoPrime => boolean(gcd(integer(0)?, integer(1)?) == 1),
+        B::LCM => {
+            let numbers = numbers_of(name, &spread_arguments(args.clone()))?;
+            let mut result = 1i64;
+            for n in numbers {
+                if !is_integer(n) {
+                    return invalid_arguments(name, "expected integers");
+                }
+                let n = n as i64;
+                if n == 0 {
+                    return num(0.0);
+                }
+                result = match result.checked_mul(n / gcd(result, n)) {
+                    Some(r) => r.abs(),
+                    None => return invalid_arguments(name, "the result is too large"),
+                };
+            }
+            num(result as f64)
+        }
+
+        // Vectors and matrices
+        B::Vector => match &args[..] {
+            [Value::List(l)] => numbers_of(name, l).map(Value::Vector),
+            _ => numbers_of(name, &args).map(Value::Vector),
+        },
+        B::Matrix => match &args[..] {
+            [Value::List(rows)] if rows.iter().all(|r| matches!(r, Value::List(_))) => {
+                expect_matrix(name, &args[0]).map(Value::Matrix)
+            }
+            _ => args
+                .iter()
+                .map(|r| expect_vector(name, r))
+                .collect::<EvaluationResult<_>>()
+                .and_then(|m| rectangular(name, m))
+                .map(Value::Matrix),
+        },
+        B::Id => {
+            let size = integer(0)?;
+            if size > MAX_IDENTITY_SIZE {
+                return invalid_arguments(name, "the size of the matrix is too large");
+            }
+            Ok(Value::Matrix(identity(size.max(0) as usize)))
+        }
+        B::NumRows => num(expect_matrix(name, &argument(0)?)?.len() as f64),
+        B::NumColumns => num(expect_matrix(name, &argument(0)?)?
+            .first()
+            .map(|r| r.len())
+            .unwrap_or(0) as f64),
+        B::RowVector => match &args[..] {
+            [Value::List(l)] => numbers_of(name, l).map(|r| Value::Matrix(vec![r])),
+            _ => numbers_of(name, &args).map(|r| Value::Matrix(vec![r])),
+        },
+        B::Dot => {
+            let a = expect_vector(name, &argument(0)?)?;
+            let b = expect_vector(name, &argument(1)?)?;
+            num(elementwise(name, a, b, |x, y| x * y)?.into_iter().sum())
+        }
+        B::Cross => {
+            let a = expect_vector(name, &argument(0)?)?;
+            let b = expect_vector(name, &argument(1)?)?;
+            if a.len() != 3 || b.len() != 3 {
+                return invalid_arguments(name, "expected two vectors of length 3");
+            }
+            Ok(Value::Vector(vec![
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+            ]))
+        }
+        B::Angle => {
+            let a = expect_vector(name, &argument(0)?)?;
+            let b = expect_vector(name, &argument(1)?)?;
+            let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+            let (na, nb) = (norm(&a), norm(&b));
+            let dot: f64 = elementwise(name, a, b, |x, y| x * y)?.into_iter().sum();
+            num((dot / (na * nb)).acos())
+        }
+        B::IsZeroVector => boolean(
+            expect_vector(name, &argument(0)?)?
+                .iter()
+                .all(|x| *x == 0.0),
+        ),
+        B::IsScalarMultiple => {
+            let a = expect_vector(name, &argument(0)?)?;
+            let b = expect_vector(name, &argument(1)?)?;
+            let rel_tol = args.get(2).map(|_| number(2)).transpose()?.unwrap_or(1e-15);
+            let abs_tol = args.get(3).map(|_| number(3)).transpose()?.unwrap_or(1e-15);
+            if a.len() != b.len() {
+                return boolean(false);
+            }
+            let factor = a
+                .iter()
+                .zip(b.iter())
+                .find(|(x, _)| x.abs() > abs_tol)
+                .map(|(x, y)| y / x);
+            boolean(match factor {
+                None => b.iter().all(|y| y.abs() <= abs_tol),
+                Some(factor) => a.iter().zip(b.iter()).all(|(x, y)| {
+                    let expected = x * factor;
+                    (expected - y).abs() <= abs_tol.max(rel_tol * y.abs().max(expected.abs()))
+                }),
+            })
+        }
+        B::Det => {
+            let m = expect_matrix(name, &argument(0)?)?;
+            if m.iter().any(|r| r.len() != m.len()) {
+                return invalid_arguments(name, "expected a square matrix");
+            }
+            num(determinant(m))
+        }
+        B::Transpose => match argument(0)? {
+            Value::Vector(v) => Ok(Value::Matrix(vec![v])),
+            v => Ok(Value::Matrix(transpose(&expect_matrix(name, &v)?))),
+        },
+        B::SumCells => num(expect_matrix(name, &argument(0)?)?
+            .iter()
+            .flat_map(|r| r.iter())
+            .sum()),
+        B::Augment => {
+            let matrices = spread_matrices(name, &args)?;
+            let rows = matrices.iter().map(|m| m.len()).max().unwrap_or(0);
+            Ok(Value::Matrix(
+                (0..rows)
+                    .map(|i| {
+                        matrices
+                            .iter()
+                            .flat_map(|m| m.get(i).cloned().unwrap_or_default())
+                            .collect()
+                    })
+                    .collect(),
+            ))
+        }
+        B::Stack => rectangular(
+            name,
+            spread_matrices(name, &args)?
+                .into_iter()
+                .flatten()
+                .collect(),
+        )
+        .map(Value::Matrix),
+        B::CombineDiagonally => {
+            let matrices = spread_matrices(name, &args)?;
+            let columns: usize = matrices
+                .iter()
+                .map(|m| m.first().map(|r| r.len()).unwrap_or(0))
+                .sum();
+            let mut result = Vec::new();
+            let mut offset = 0;
+            for m in matrices {
+                let width = m.first().map(|r| r.len()).unwrap_or(0);
+                for row in m {
+                    let mut new_row = vec![0.0; columns];
+                    new_row[offset..offset + row.len()].copy_from_slice(&row);
+                    result.push(new_row);
+                }
+                offset += width;
+            }
+            Ok(Value::Matrix(result))
+        }
+
+        // Strings
+        B::String => string_value(argument(0)?.to_plain_string()),
+        B::LaTeX | B::Safe | B::Render => string_value(string(0)?),
+        B::Capitalise => {
+            let s = string(0)?;
+            let mut chars = s.chars();
+            string_value(match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => s,
+            })
+        }
+        B::Pluralise => {
+            let n = number(0)?;
+            if n == 1.0 {
+                string_value(string(1)?)
+            } else {
+                string_value(string(2)?)
+            }
+        }
+        B::Upper => string_value(string(0)?.to_uppercase()),
+        B::Lower => string_value(string(0)?.to_lowercase()),
+        B::Join => {
+            let items = collection(0)?;
+            let delimiter = string(1)?;
+            string_value(
+                items
+                    .iter()
+                    .map(|i| i.to_plain_string())
+                    .collect::<Vec<_>>()
+                    .join(&delimiter[..]),
+            )
+        }
+        B::Split => {
+            let s = string(0)?;
+            let delimiter = string(1)?;
+            Ok(Value::List(
+                s.split(&delimiter[..])
+                    .map(|p| Value::String(p.to_string()))
+                    .collect(),
+            ))
+        }
+        B::Trim => string_value(string(0)?.trim().to_string()),
+        B::Currency => {
+            let n = number(0)?;
+            let (prefix, suffix) = (string(1)?, string(2)?);
+            let amount = if is_integer(n) {
+                format_number(n)
+            } else {
+                format!("{:.2}", n)
+            };
+            string_value(format!("{}{}{}", prefix, amount, suffix))
+        }
+        B::SeparateThousands => {
+            let n = format_number(number(0)?);
+            let separator = string(1)?;
+            let (sign, n) = match n.strip_prefix('-') {
+                Some(rest) => ("-", rest.to_string()),
+                None => ("", n),
+            };
+            let (integer_part, rest) = match n.split_once('.') {
+                Some((i, r)) => (i.to_string(), format!(".{}", r)),
+                None => (n.clone(), String::new()),
+            };
+            let digits = integer_part.chars().collect::<Vec<_>>();
+            let groups = digits
+                .rchunks(3)
+                .rev()
+                .map(|c| c.iter().collect::<String>())
+                .collect::<Vec<_>>();
+            string_value(format!("{}{}{}", sign, groups.join(&separator[..]), rest))
+        }
+        B::UnPercent => {
+            let s = string(0)?;
+            num(s
+                .trim()
+                .trim_end_matches('%')
+                .trim()
+                .parse::<f64>()
+                .unwrap_or(f64::NAN)
+                / 100.0)
+        }
+        B::LPad | B::RPad => {
+            let s = string(0)?;
+            let n = integer(1)?.max(0) as usize;
+            let padding = string(2)?;
+            let length = s.chars().count();
+            if length >= n || padding.is_empty() {
+                return string_value(s);
+            }
+            let fill: String = padding.chars().cycle().take(n - length).collect();
+            string_value(if matches!(function, B::LPad) {
+                fill + &s
+            } else {
+                s + &fill
+            })
+        }
+        B::FormatString => {
+            let mut s = string(0)?;
+            for (i, v) in collection(1)?.into_iter().enumerate() {
+                s = s.replace(&format!("{{{}}}", i)[..], &v.to_plain_string()[..]);
+            }
+            string_value(s)
+        }
+        B::LetterOrdinal => {
+            let n = integer(0)?;
+            if n < 0 {
+                return invalid_arguments(name, "expected a non-negative integer");
+            }
+            string_value(letter_ordinal(n))
+        }
+        B::IsBool => match argument(0)? {
+            Value::Boolean(_) => boolean(true),
+            Value::String(s) => boolean(matches!(
+                &s.trim().to_lowercase()[..],
+                "true" | "false" | "yes" | "no"
+            )),
+            _ => boolean(false),
+        },
+        B::IsClose => {
+            let (a, b) = (number(0)?, number(1)?);
+            let rel_tol = args.get(2).map(|_| number(2)).transpose()?.unwrap_or(1e-15);
+            let abs_tol = args.get(3).map(|_| number(3)).transpose()?.unwrap_or(1e-15);
+            boolean((a - b).abs() <= abs_tol.max(rel_tol * a.abs().max(b.abs())))
+        }
+
+        // Lists
+        B::All => {
+            let items = collection(0)?;
+            boolean(
+                items
+                    .iter()
+                    .map(|v| expect_bool(name, v))
+                    .collect::<EvaluationResult<Vec<_>>>()?
+                    .into_iter()
+                    .all(|b| b),
+            )
+        }
+        B::Some => {
+            let items = collection(0)?;
+            boolean(
+                items
+                    .iter()
+                    .map(|v| expect_bool(name, v))
+                    .collect::<EvaluationResult<Vec<_>>>()?
+                    .into_iter()
+                    .any(|b| b),
+            )
+        }
+        B::Flatten => Ok(Value::List(
+            collection(0)?
+                .iter()
+                .map(|l| collection_items(name, l))
+                .collect::<EvaluationResult<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect(),
+        )),
+        B::Sort => sort_values(name, collection(0)?).map(Value::List),
+        B::SortDestinations => {
+            let items = collection(0)?;
+            let mut indices = (0..items.len()).collect::<Vec<_>>();
+            let mut error = None;
+            indices.sort_by(|a, b| {
+                compare_values(name, &items[*a], &items[*b]).unwrap_or_else(|e| {
+                    error = Some(e);
+                    std::cmp::Ordering::Equal
+                })
+            });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            let mut destinations = vec![Value::Number(0.0); items.len()];
+            for (destination, index) in indices.into_iter().enumerate() {
+                destinations[index] = Value::Number(destination as f64);
+            }
+            Ok(Value::List(destinations))
+        }
+        B::SortBy => {
+            let key = argument(0)?;
+            let items = collection(1)?;
+            let keyed = items
+                .into_iter()
+                .map(|i| indexation(i.clone(), key.clone()).map(|k| (k, i)))
+                .collect::<EvaluationResult<Vec<_>>>()?;
+            let mut error = None;
+            let mut keyed = keyed;
+            keyed.sort_by(|(a, _), (b, _)| {
+                compare_values(name, a, b).unwrap_or_else(|e| {
+                    error = Some(e);
+                    std::cmp::Ordering::Equal
+                })
+            });
+            match error {
+                Some(e) => Err(e),
+                None => Ok(Value::List(keyed.into_iter().map(|(_, i)| i).collect())),
+            }
+        }
+        B::GroupBy => {
+            let key = argument(0)?;
+            let items = collection(1)?;
+            let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+            for item in items {
+                let k = indexation(item.clone(), key.clone())?;
+                match groups.iter_mut().find(|(g, _)| values_equal(g, &k)) {
+                    Some((_, group)) => group.push(item),
+                    None => groups.push((k, vec![item])),
+                }
+            }
+            Ok(Value::List(
+                groups
+                    .into_iter()
+                    .map(|(k, g)| Value::List(vec![k, Value::List(g)]))
+                    .collect(),
+            ))
+        }
+        B::Reverse => {
+            let mut items = collection(0)?;
+            items.reverse();
+            Ok(Value::List(items))
+        }
+        B::Indices => {
+            let items = collection(0)?;
+            let value = argument(1)?;
+            Ok(Value::List(
+                items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, i)| values_equal(i, &value))
+                    .map(|(i, _)| Value::Number(i as f64))
+                    .collect(),
+            ))
+        }
+        B::Distinct => Ok(Value::List(distinct(collection(0)?.into_iter()))),
+        B::List => Ok(Value::List(collection(0)?)),
+        B::Sum => num(numbers_of(name, &collection(0)?)?.into_iter().sum()),
+        B::Prod => num(numbers_of(name, &collection(0)?)?.into_iter().product()),
+        B::Product => {
+            let lists = match &args[..] {
+                [_, Value::Number(n)] if *n > MAX_COMBINATIONS => {
+                    return invalid_arguments(name, "the amount of lists is too large");
+                }
+                [l, Value::Number(n)] => vec![collection_items(name, l)?; *n as usize],
+                _ => args
+                    .iter()
+                    .map(|l| collection_items(name, l))
+                    .collect::<EvaluationResult<Vec<_>>>()?,
+            };
+            let amount = lists.iter().map(|l| l.len() as f64).product::<f64>();
+            if amount * lists.len().max(1) as f64 > MAX_COMBINATIONS {
+                return invalid_arguments(name, "the result is too large");
+            }
+            let mut result: Vec<Vec<Value>> = vec![vec![]];
+            for list in lists {
+                result = result
+                    .into_iter()
+                    .flat_map(|prefix| {
+                        list.iter().map(move |item| {
+                            let mut p = prefix.clone();
+                            p.push(item.clone());
+                            p
+                        })
+                    })
+                    .collect();
+            }
+            Ok(Value::List(result.into_iter().map(Value::List).collect()))
+        }
+        B::Zip => {
+            let lists = args
+                .iter()
+                .map(|l| collection_items(name, l))
+                .collect::<EvaluationResult<Vec<_>>>()?;
+            let length = lists.iter().map(|l| l.len()).min().unwrap_or(0);
+            Ok(Value::List(
+                (0..length)
+                    .map(|i| Value::List(lists.iter().map(|l| l[i].clone()).collect()))
+                    .collect(),
+            ))
+        }
+        B::Combinations | B::CombinationsWithReplacement | B::Permutations => {
+            let items = collection(0)?;
+            let r = integer(1)?.max(0) as usize;
+            let (n, k) = (items.len() as f64, r as f64);
+            let amount = match function {
+                B::Combinations => binomial(n, k),
+                B::CombinationsWithReplacement if n == 0.0 => 0.0,
+                B::CombinationsWithReplacement => binomial(n + k - 1.0, k),
+                _ => permutation_count(n, k),
+            };
+            if amount * k.max(1.0) > MAX_COMBINATIONS {
+                return invalid_arguments(name, "the result is too large");
+            }
+            let result = match function {
+                B::Combinations => combinations(&items, r, false),
+                B::CombinationsWithReplacement => combinations(&items, r, true),
+                _ => permutations(&items, r),
+            };
+            Ok(Value::List(result.into_iter().map(Value::List).collect()))
+        }
+        B::Frequencies => {
+            let mut frequencies: Vec<(Value, usize)> = Vec::new();
+            for item in collection(0)? {
+                match frequencies.iter_mut().find(|(v, _)| values_equal(v, &item)) {
+                    Some((_, count)) => *count += 1,
+                    None => frequencies.push((item, 1)),
+                }
+            }
+            Ok(Value::List(
+                frequencies
+                    .into_iter()
+                    .map(|(v, c)| Value::List(vec![v, Value::Number(c as f64)]))
+                    .collect(),
+            ))
+        }
+        B::Enumerate => Ok(Value::List(
+            collection(0)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| Value::List(vec![Value::Number(i as f64), v]))
+                .collect(),
+        )),
+
+        // Dictionaries
+        B::Get => match argument(0)? {
+            Value::Dictionary(d) => Ok(d.get(&string(1)?).cloned().unwrap_or(argument(2)?)),
+            v => invalid_arguments(name, &format!("expected a dictionary but got {}", v)),
+        },
+        B::Dict => {
+            let pairs = match &args[..] {
+                [] => vec![],
+                [Value::Dictionary(d)] => return Ok(Value::Dictionary(d.clone())),
+                [Value::List(l)] if l.iter().all(|p| matches!(p, Value::List(_))) => l.clone(),
+                _ => args.clone(),
+            };
+            let mut dict = BTreeMap::new();
+            for pair in pairs {
+                match collection_items(name, &pair)?.as_slice() {
+                    [Value::String(k), v] => {
+                        dict.insert(k.clone(), v.clone());
+                    }
+                    _ => return invalid_arguments(name, "expected pairs of a string and a value"),
+                }
+            }
+            Ok(Value::Dictionary(dict))
+        }
+        B::Keys => match argument(0)? {
+            Value::Dictionary(d) => Ok(Value::List(d.into_keys().map(Value::String).collect())),
+            v => invalid_arguments(name, &format!("expected a dictionary but got {}", v)),
+        },
+        B::Values => match argument(0)? {
+            Value::Dictionary(d) => match args.get(1) {
+                Some(keys) => Ok(Value::List(
+                    collection_items(name, keys)?
+                        .iter()
+                        .map(|k| indexation(Value::Dictionary(d.clone()), k.clone()))
+                        .collect::<EvaluationResult<_>>()?,
+                )),
+                None => Ok(Value::List(d.into_values().collect())),
+            },
+            v => invalid_arguments(name, &format!("expected a dictionary but got {}", v)),
+        },
+        B::Items => match argument(0)? {
+            Value::Dictionary(d) => Ok(Value::List(
+                d.into_iter()
+                    .map(|(k, v)| Value::List(vec![Value::String(k), v]))
+                    .collect(),
+            )),
+            v => invalid_arguments(name, &format!("expected a dictionary but got {}", v)),
+        },
+
+        // Sets
+        B::Set => Ok(Value::Set(distinct(
+            spread_arguments(args.clone()).into_iter(),
+        ))),
+        B::Union => {
+            let items = args
+                .iter()
+                .map(|s| collection_items(name, s))
+                .collect::<EvaluationResult<Vec<_>>>()?;
+            Ok(Value::Set(distinct(items.into_iter().flatten())))
+        }
+        B::Intersection => {
+            let a = collection(0)?;
+            let b = collection(1)?;
+            Ok(Value::Set(distinct(
+                a.into_iter()
+                    .filter(|x| b.iter().any(|y| values_equal(x, y))),
+            )))
+        }
+
+        // Randomisation
+        B::Random => {
+            let options = match &args[..] {
+                [Value::Range(start, end, step)] if *step == 0.0 => {
+                    return num(start + random.next_f64() * (end - start));
+                }
+                // Sample the range without creating all of its items, it can be very large
+                [Value::Range(start, end, step)] => {
+                    let amount = ((end - start) / step + 1e-10).floor() + 1.0;
+                    if amount.is_nan() || amount < 1.0 {
+                        return invalid_arguments(name, "there is nothing to choose from");
+                    }
+                    if amount.is_infinite() {
+                        return invalid_arguments(name, "the range is too large");
+                    }
+                    return num(start + step * (random.next_f64() * amount).floor());
+                }
+                [single] if single.to_list().is_some() => single.to_list().unwrap(),
+                _ => args.clone(),
+            };
+            if options.is_empty() {
+                return invalid_arguments(name, "there is nothing to choose from");
+            }
+            Ok(options[random.below(options.len())].clone())
+        }
+        B::WeightedRandom => {
+            let options = collection(0)?
+                .iter()
+                .map(|pair| match collection_items(name, pair)?.as_slice() {
+                    [v, Value::Number(w)] => Ok((v.clone(), w.max(0.0))),
+                    _ => invalid_arguments(name, "expected pairs of a value and a weight"),
+                })
+                .collect::<EvaluationResult<Vec<_>>>()?;
+            let total: f64 = options.iter().map(|(_, w)| w).sum();
+            let mut choice = random.next_f64() * total;
+            for (v, w) in options.iter() {
+                if choice < *w {
+                    return Ok(v.clone());
+                }
+                choice -= w;
+            }
+            options.last().map(|(v, _)| v.clone()).ok_or_else(|| {
+                EvaluationError::InvalidArguments(
+                    name.to_string(),
+                    "there is nothing to choose from".to_string(),
+                )
+            })
+        }
+        B::Deal => {
+            let n = integer(0)?;
+            if n as f64 > MAX_RANGE_LENGTH {
+                return invalid_arguments(name, "the amount of items is too large");
+            }
+            let mut items = (0..n.max(0))
+                .map(|i| Value::Number(i as f64))
+                .collect::<Vec<_>>();
+            random.shuffle(&mut items);
+            Ok(Value::List(items))
+        }
+        B::Reorder => {
+            let items = collection(0)?;
+            let order = numbers_of(name, &collection(1)?)?;
+            Ok(Value::List(
+                order
+                    .into_iter()
+                    .map(|i| {
+                        items.get(i as usize).cloned().ok_or_else(|| {
+                            EvaluationError::InvalidArguments(
+                                name.to_string(),
+                                format!("index {} is out of bounds", i),
+                            )
+                        })
+                    })
+                    .collect::<EvaluationResult<_>>()?,
+            ))
+        }
+        B::Shuffle => {
+            let mut items = collection(0)?;
+            random.shuffle(&mut items);
+            Ok(Value::List(items))
+        }
+        B::ShuffleTogether => {
+            let lists = collection(0)?
+                .iter()
+                .map(|l| collection_items(name, l))
+                .collect::<EvaluationResult<Vec<_>>>()?;
+            let length = lists.first().map(|l| l.len()).unwrap_or(0);
+            if lists.iter().any(|l| l.len() != length) {
+                return invalid_arguments(name, "the lists should have the same length");
+            }
+            let mut order = (0..length).collect::<Vec<_>>();
+            random.shuffle(&mut order);
+            Ok(Value::List(
+                lists
+                    .into_iter()
+                    .map(|l| Value::List(order.iter().map(|i| l[*i].clone()).collect()))
+                    .collect(),
+            ))
+        }
+
+        // Control flow
+        B::Award => {
+            let a = number(0)?;
+            match argument(1)? {
+                Value::Boolean(true) => num(a),
+                Value::Boolean(false) => num(0.0),
+                v => invalid_arguments(name, &format!("expected a boolean but got {}", v)),
+            }
+        }
+
+        // JSON
+        B::JsonDecode => serde_json::from_str::<serde_json::Value>(&string(0)?[..])
+            .map(|j| json_to_value(&j))
+            .map_err(|e| EvaluationError::InvalidArguments(name.to_string(), e.to_string())),
+        B::JsonEncode => string_value(value_to_json(&argument(0)?).to_string()),
+
+        _ => Err(EvaluationError::UnsupportedFunction(name.to_string())),
+    }
+}
+
+fn spread_matrices(function: &str, args: &[Value]) -> EvaluationResult<Vec<Vec<Vec<f64>>>> {
+    match args {
+        [Value::List(l)] => l.iter().map(|m| expect_matrix(function, m)).collect(),
+        _ => args.iter().map(|m| expect_matrix(function, m)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jme::JMEString;
+    use std::convert::TryInto;
+
+    fn parse(s: &str) -> Expr {
+        let jme: JMEString = s.to_string().try_into().unwrap();
+        jme.ast().unwrap().clone()
+    }
+
+    fn evaluate(s: &str) -> EvaluationResult<Value> {
+        Environment::new(0).evaluate(&parse(s))
+    }
+
+    fn evaluate_string(s: &str) -> String {
+        evaluate(s).unwrap().to_string()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(evaluate_string("1+2*3"), "7");
+        assert_eq!(evaluate_string("-2^2"), "-4");
+        assert_eq!(evaluate_string("(1+2)/4"), "0.75");
+        assert_eq!(evaluate_string("0.1+0.2"), "0.3");
+        assert_eq!(evaluate_string("5!"), "120");
+        assert_eq!(evaluate_string("[1,2,3]+4"), "[1,2,3,4]");
+        assert_eq!(evaluate_string("\"hi \"+\"there\""), "\"hi there\"");
+        assert_eq!(evaluate_string("[1,2,3,2] except 2"), "[1,3]");
+    }
+
+    #[test]
+    fn vectors_and_matrices() {
+        assert_eq!(evaluate_string("vector(1,2)+vector(3,4)"), "vector(4,6)");
+        assert_eq!(
+            evaluate_string("matrix([1,2],[3,4])*vector(1,2)"),
+            "vector(5,11)"
+        );
+        assert_eq!(evaluate_string("det(matrix([1,2],[3,4]))"), "-2");
+        assert_eq!(
+            evaluate_string("cross(vector(1,2,3),vector(4,5,6))"),
+            "vector(-3,6,-3)"
+        );
+        assert_eq!(evaluate_string("det(matrix([[nan,1],[1,1]]))"), "nan");
+    }
+
+    #[test]
+    fn invalid_matrices() {
+        for expr in [
+            "matrix([1,2],[3])",
+            "matrix([[1,2],[3]])",
+            "transpose(matrix([1],[2,3]))",
+            "transpose([[1],[2,3]])",
+            "stack(matrix([1,2]),matrix([3]))",
+        ] {
+            assert!(
+                matches!(evaluate(expr), Err(EvaluationError::InvalidArguments(_, _))),
+                "{} should be invalid",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn size_limits() {
+        assert!(matches!(
+            evaluate("repeat(1, 10^9)"),
+            Err(EvaluationError::InvalidArguments(_, _))
+        ));
+        assert!(matches!(
+            evaluate("id(10^6)"),
+            Err(EvaluationError::InvalidArguments(_, _))
+        ));
+        assert!(matches!(
+            evaluate("id(2)^(10^9)"),
+            Err(EvaluationError::InvalidOperation(_))
+        ));
+        assert_eq!(evaluate_string("id(2)^3"), "matrix([1,0],[0,1])");
+    }
+
+    fn is_invalid_arguments(s: &str) -> bool {
+        matches!(evaluate(s), Err(EvaluationError::InvalidArguments(_, _)))
+    }
+
+    #[test]
+    fn iteration_limits() {
+        assert!(is_invalid_arguments("iterate(x+1, x, 0, 10^9)"));
+        assert!(is_invalid_arguments(
+            "iterate_until(x+1, x, 0, false, 10^9)"
+        ));
+        assert_eq!(evaluate_string("iterate(x+1, x, 0, 3)"), "[0,1,2,3]");
+    }
+
+    #[test]
+    fn collection_limits() {
+        assert!(is_invalid_arguments("deal(10^12)"));
+        assert!(is_invalid_arguments("product([1,2], 10^9)"));
+        assert!(is_invalid_arguments(
+            "product(1..100, 1..100, 1..100, 1..100)"
+        ));
+        assert!(is_invalid_arguments("combinations(1..100, 50)"));
+        assert!(is_invalid_arguments(
+            "combinations_with_replacement([1], 10^9)"
+        ));
+        assert!(is_invalid_arguments("permutations(1..20, 20)"));
+        assert_eq!(evaluate_string("len(deal(5))"), "5");
+        assert_eq!(
+            evaluate_string("product([1,2], 2)"),
+            "[[1,1],[1,2],[2,1],[2,2]]"
+        );
+        assert_eq!(
+            evaluate_string("combinations([1,2,3], 2)"),
+            "[[1,2],[1,3],[2,3]]"
+        );
+        assert_eq!(
+            evaluate_string("combinations_with_replacement([1,2], 2)"),
+            "[[1,1],[1,2],[2,2]]"
+        );
+        assert_eq!(evaluate_string("permutations([1,2,3], 4)"), "[]");
+    }
+
+    #[test]
+    fn number_theory_limits() {
+        assert_eq!(evaluate_string("171!"), "infinity");
+        assert_eq!(evaluate_string("perm(1000, 2)"), "999000");
+        assert_eq!(evaluate_string("comb(1000, 2)"), "499500");
+        assert!(is_invalid_arguments("divisors(10^15)"));
+        assert_eq!(evaluate_string("divisors(12)"), "[1,2,3,4,6,12]");
+        assert_eq!(evaluate_string("proper_divisors(16)"), "[1,2,4,8]");
+        assert!(is_invalid_arguments("factorise(10^9+7)"));
+        assert_eq!(evaluate_string("factorise(12)"), "[2,1]");
+    }
+
+    #[test]
+    fn precision_limits() {
+        assert!(is_invalid_arguments("precround(1/3, 10^12)"));
+        assert!(is_invalid_arguments("precround(1/3, -10^12)"));
+        assert!(is_invalid_arguments("siground(1/3, 10^12)"));
+        assert!(is_invalid_arguments("dpformat(1/3, 10^12)"));
+        assert!(is_invalid_arguments("sigformat(1/3, 10^12)"));
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let mut env = Environment::new(0);
+        env.add_function(
+            "forever",
+            Function {
+                parameters: vec!["x".to_string()],
+                definition: parse("forever(x+1)"),
+            },
+        );
+        assert!(matches!(
+            env.evaluate(&parse("forever(0)")),
+            Err(EvaluationError::InvalidArguments(_, _))
+        ));
+        // The depth is reset after the failing call
+        env.add_function(
+            "double",
+            Function {
+                parameters: vec!["x".to_string()],
+                definition: parse("2x"),
+            },
+        );
+        assert_eq!(env.evaluate(&parse("double(2)")).unwrap().to_string(), "4");
+    }
+
+    #[test]
+    fn out_of_range_integers() {
+        assert!(is_invalid_arguments("letterordinal(-1)"));
+        assert_eq!(evaluate_string("letterordinal(27)"), "\"ab\"");
+        assert!(is_invalid_arguments("lcm(2^52+1, 2^52-1, 2^51-1)"));
+        assert!(is_invalid_arguments("gcd(-2^63, 2)"));
+        assert!(is_invalid_arguments("tobase(-2^63, 2)"));
+        assert_eq!(evaluate_string("lcm(4, 6)"), "12");
+    }
+
+    #[test]
+    fn random_from_large_range() {
+        let value = evaluate("random(0..10^12)").unwrap();
+        let n = value.as_number().unwrap();
+        assert!(is_integer(n) && (0.0..=1e12).contains(&n));
+        let value = evaluate("random(0..10^12#2)").unwrap();
+        assert_eq!(value.as_number().unwrap() % 2.0, 0.0);
+        assert!(is_invalid_arguments("random(5..1)"));
+    }
+
+    #[test]
+    fn logic_and_relations() {
+        assert_eq!(evaluate_string("1<2 and not false"), "true");
+        assert_eq!(evaluate_string("3 in [1,2,3]"), "true");
+        assert_eq!(evaluate_string("3 | 9"), "true");
+        assert_eq!(evaluate_string("2.5 isa \"integer\""), "false");
+        assert_eq!(evaluate_string("if(1>2, \"a\", \"b\")"), "\"b\"");
+    }
+
+    #[test]
+    fn collections() {
+        assert_eq!(evaluate_string("list(1..5#2)"), "[1,3,5]");
+        assert_eq!(evaluate_string("[0,1,2,3,4,5][1..6#2]"), "[1,3,5]");
+        assert_eq!(evaluate_string("\"hello\"[1..4]"), "\"ell\"");
+        assert_eq!(evaluate_string("[\"a\": 1, b: 2][\"b\"]"), "2");
+        assert_eq!(evaluate_string("map(x^2, x, 1..3)"), "[1,4,9]");
+        assert_eq!(evaluate_string("map(x+y, [x,y], [[1,2],[3,4]])"), "[3,7]");
+        assert_eq!(evaluate_string("filter(x>1, x, [1,2,3])"), "[2,3]");
+        assert_eq!(evaluate_string("let(a, 2, b, a+1, a*b)"), "6");
+        assert_eq!(evaluate_string("sort([3,1,2])"), "[1,2,3]");
+        assert_eq!(evaluate_string("sum(vector(4,5,6))"), "15");
+        assert_eq!(evaluate_string("len(set([1,2,2]))"), "2");
+    }
+
+    #[test]
+    fn rounding_and_formatting() {
+        assert_eq!(evaluate_string("precround(1/3, 2)"), "0.33");
+        assert_eq!(evaluate_string("siground(1234, 2)"), "1200");
+        assert_eq!(evaluate_string("dpformat(1.2, 3)"), "\"1.200\"");
+        assert_eq!(evaluate_string("countsigfigs(\"0.0120\")"), "3");
+        assert_eq!(evaluate_string("tobinary(5)"), "\"101\"");
+    }
+
+    #[test]
+    fn random_values_are_reproducible() {
+        let expr = parse("repeat(random(1..100), 10)");
+        let a = Environment::new(42).evaluate(&expr).unwrap();
+        let b = Environment::new(42).evaluate(&expr).unwrap();
+        assert_eq!(a, b);
+        for v in a.to_list().unwrap() {
+            let n = v.as_number().unwrap();
+            assert!((1.0..=100.0).contains(&n) && is_integer(n));
+        }
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let mut env = Environment::new(0);
+        env.add_definition("b", parse("a + 1"));
+        env.add_definition("a", parse("2"));
+        env.add_function(
+            "double",
+            Function {
+                parameters: vec!["x".to_string()],
+                definition: parse("2x"),
+            },
+        );
+        env.evaluate_definitions().unwrap();
+        assert_eq!(env.variable("b"), Some(&Value::Number(3.0)));
+        assert_eq!(
+            env.evaluate(&parse("double(b)")).unwrap(),
+            Value::Number(6.0)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            evaluate("x + 1"),
+            Err(EvaluationError::UnknownVariable("x".to_string()))
+        );
+        assert_eq!(
+            evaluate("unknown_function(1)"),
+            Err(EvaluationError::UnknownFunction(
+                "unknown_function".to_string()
+            ))
+        );
+        let mut env = Environment::new(0);
+        env.add_definition("a", parse("b"));
+        env.add_definition("b", parse("a"));
+        assert_eq!(
+            env.evaluate_definitions(),
+            Err(EvaluationError::CircularDefinition(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "a".to_string()
+            ]))
+        );
+    }
+}
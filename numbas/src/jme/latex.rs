@@ -0,0 +1,551 @@
+//! Renders expressions as LaTeX, like the Numbas maths renderer does
+//!
+//! The rendering can be tweaked with the display options of answer simplification, see
+//! [LatexOptions].
+
+use crate::jme::ast::{
+    ArithmeticOperator, Expr, Ident, LogicalOperator, PrefixOperator, RangeOperator,
+    RelationalOperator,
+};
+use crate::jme::printer::{
+    ADDITION, ATOM, CAST, LOGIC, MULTIPLICATION, POWER, PREFIX, RANGE, RANGE_STEP, RELATION,
+    SEQUENCE,
+};
+use crate::question::answer_simplification::{
+    AnswerSimplificationDisplayOption, AnswerSimplificationType,
+};
+
+const GREEK_LETTERS: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+    "lambda", "mu", "nu", "xi", "omicron", "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi",
+    "psi", "omega", "Gamma", "Delta", "Theta", "Lambda", "Xi", "Pi", "Sigma", "Upsilon", "Phi",
+    "Psi", "Omega",
+];
+
+/// The functions that have a LaTeX command with the same name
+const LATEX_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "sec", "csc", "cot", "sinh", "cosh", "tanh", "arcsin", "arccos", "arctan",
+    "ln", "log", "det", "gcd", "max", "min",
+];
+
+/// The display options that change how an expression is rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatexOptions {
+    /// Show non-integer numbers as fractions instead of decimals
+    pub broken_as_fractions: bool,
+    /// Show improper fractions as an integer next to a proper fraction
+    pub mixed_fractions: bool,
+    /// Show fractions on a single line, with a slash
+    pub flat_fractions: bool,
+    /// Show vectors as rows instead of columns
+    pub vector_as_row: bool,
+    /// Always show the multiplication sign between multiplicands
+    pub always_show_multiplication_sign: bool,
+    /// Use `\cdot` instead of `\times` as multiplication sign
+    pub use_dot_as_multiplication_sign: bool,
+    /// Show matrices without parentheses
+    pub matrices_without_parentheses: bool,
+}
+
+impl std::convert::From<&[AnswerSimplificationType]> for LatexOptions {
+    fn from(simplification: &[AnswerSimplificationType]) -> Self {
+        let mut options = LatexOptions::default();
+        for item in simplification.iter() {
+            if let AnswerSimplificationType::DisplayOption(o) = item {
+                match *o {
+                    AnswerSimplificationDisplayOption::Fractions(b) => {
+                        options.broken_as_fractions = b
+                    }
+                    AnswerSimplificationDisplayOption::MixedFractions(b) => {
+                        options.mixed_fractions = b
+                    }
+                    AnswerSimplificationDisplayOption::FlatFractions(b) => {
+                        options.flat_fractions = b
+                    }
+                    AnswerSimplificationDisplayOption::RowVector(b) => options.vector_as_row = b,
+                    AnswerSimplificationDisplayOption::AlwaysShowMultiplicationSign(b) => {
+                        options.always_show_multiplication_sign = b
+                    }
+                    AnswerSimplificationDisplayOption::DotAsMultiplicationSign(b) => {
+                        options.use_dot_as_multiplication_sign = b
+                    }
+                    AnswerSimplificationDisplayOption::MatricesWithoutParentheses(b) => {
+                        options.matrices_without_parentheses = b
+                    }
+                }
+            }
+        }
+        options
+    }
+}
+
+impl Expr {
+    /// Render this expression as LaTeX
+    pub fn to_latex(&self, options: &LatexOptions) -> String {
+        Renderer { options }.render(self)
+    }
+}
+
+struct Renderer<'a> {
+    options: &'a LatexOptions,
+}
+
+impl<'a> Renderer<'a> {
+    fn render(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Str(s) => format!("\\text{{{}}}", escape_text(s)),
+            Expr::Int(i) => i.to_string(),
+            Expr::Float(i, broken) => self.number(*i, broken),
+            Expr::Bool(b) => format!("\\text{{{}}}", b),
+            Expr::Ident(i) => ident(i),
+            Expr::Constant(c) => match &c.name().to_lowercase()[..] {
+                "pi" | "π" => "\\pi".to_string(),
+                "infinity" | "infty" | "∞" => "\\infty".to_string(),
+                "nan" => "\\text{NaN}".to_string(),
+                _ => ident(c),
+            },
+            Expr::Arithmetic(op, lhs, rhs) => match op {
+                ArithmeticOperator::Add => self.binary(ADDITION, lhs, " + ", rhs),
+                ArithmeticOperator::Subtract => self.binary(ADDITION, lhs, " - ", rhs),
+                ArithmeticOperator::Except => self.binary(ADDITION, lhs, " \\text{ except } ", rhs),
+                ArithmeticOperator::Multiply => self.multiplication(lhs, rhs),
+                ArithmeticOperator::Divide => {
+                    if self.options.flat_fractions {
+                        self.binary(MULTIPLICATION, lhs, "/", rhs)
+                    } else {
+                        self.fraction(&self.render(lhs), &self.render(rhs))
+                    }
+                }
+                ArithmeticOperator::Power => {
+                    format!("{}^{{{}}}", self.operand(lhs, POWER + 1), self.render(rhs))
+                }
+            },
+            Expr::Relation(op, lhs, rhs) => {
+                let symbol = match op {
+                    RelationalOperator::LessThan => " < ",
+                    RelationalOperator::LessThanOrEqual => " \\leq ",
+                    RelationalOperator::GreaterThan => " > ",
+                    RelationalOperator::GreaterThanOrEqual => " \\geq ",
+                    RelationalOperator::Equals => " = ",
+                    RelationalOperator::NotEquals => " \\neq ",
+                    RelationalOperator::In => " \\in ",
+                    RelationalOperator::IsA => " \\text{ isa } ",
+                    RelationalOperator::Divides => " \\mid ",
+                };
+                self.binary(RELATION, lhs, symbol, rhs)
+            }
+            Expr::Logic(op, lhs, rhs) => {
+                let symbol = match op {
+                    LogicalOperator::And => " \\land ",
+                    LogicalOperator::Or => " \\lor ",
+                    LogicalOperator::Xor => " \\oplus ",
+                    LogicalOperator::Implies => " \\implies ",
+                };
+                self.binary(LOGIC, lhs, symbol, rhs)
+            }
+            Expr::Range(RangeOperator::Create, lhs, rhs) => {
+                self.binary(RANGE, lhs, " \\ldots ", rhs)
+            }
+            Expr::Range(RangeOperator::Step, lhs, rhs) => {
+                self.binary(RANGE_STEP, lhs, " \\text{ step } ", rhs)
+            }
+            Expr::Cast(lhs, rhs) => self.binary(CAST, lhs, " \\text{ as } ", rhs),
+            Expr::Sequence(lhs, rhs) => self.binary(SEQUENCE, lhs, " ; ", rhs),
+            Expr::List(items) => format!("\\left[ {} \\right]", self.list(items, ", ")),
+            Expr::Dictionary(items) => format!(
+                "\\left[ {} \\right]",
+                items
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            Expr::Str(k) => format!("\\text{{{}}}", escape_text(k)),
+                            k => self.render(k),
+                        };
+                        format!("{}: {}", key, self.render(value))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expr::FunctionApplication(name, arguments) => self.function(name, arguments),
+            Expr::Prefix(op, operand) => match op {
+                PrefixOperator::Minus => format!("-{}", self.operand(operand, PREFIX)),
+                PrefixOperator::Not => format!("\\neg {}", self.operand(operand, PREFIX)),
+            },
+            Expr::Faculty(operand) => format!("{}!", self.operand(operand, ATOM)),
+            Expr::Indexation(operand, index) => format!(
+                "{}\\left[ {} \\right]",
+                self.operand(operand, ATOM),
+                self.render(index)
+            ),
+        }
+    }
+
+    /// The precedence of the rendered expression
+    ///
+    /// Fractions that are not flat are grouped by `\frac`, so they never need parentheses.
+    fn precedence(&self, expr: &Expr) -> u8 {
+        match expr {
+            Expr::Arithmetic(ArithmeticOperator::Divide, ..) if !self.options.flat_fractions => {
+                ATOM
+            }
+            Expr::Float(i, broken) if self.options.broken_as_fractions => {
+                match decimal_to_fraction(*i, broken) {
+                    Some((n, d))
+                        if d != 1 && (self.options.flat_fractions || self.is_mixed(n, d)) =>
+                    {
+                        MULTIPLICATION
+                    }
+                    _ => ATOM,
+                }
+            }
+            _ => expr.precedence(),
+        }
+    }
+
+    /// Render an expression, with parentheses if it binds weaker than `minimum`
+    fn operand(&self, expr: &Expr, minimum: u8) -> String {
+        if self.precedence(expr) < minimum {
+            format!("\\left( {} \\right)", self.render(expr))
+        } else {
+            self.render(expr)
+        }
+    }
+
+    /// Render a left associative binary operation
+    fn binary(&self, precedence: u8, lhs: &Expr, symbol: &str, rhs: &Expr) -> String {
+        format!(
+            "{}{}{}",
+            self.operand(lhs, precedence),
+            symbol,
+            self.operand(rhs, precedence + 1)
+        )
+    }
+
+    fn list(&self, items: &[Expr], separator: &str) -> String {
+        items
+            .iter()
+            .map(|i| self.render(i))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// Render a multiplication, the sign is left out when it is clear without it
+    fn multiplication(&self, lhs: &Expr, rhs: &Expr) -> String {
+        let lhs = self.operand(lhs, MULTIPLICATION);
+        let rhs = self.operand(rhs, MULTIPLICATION + 1);
+        let rhs_starts_with_number =
+            rhs.starts_with(|c: char| c.is_ascii_digit() || c == '-') || rhs.starts_with("\\frac");
+        if self.options.always_show_multiplication_sign || rhs_starts_with_number {
+            let sign = if self.options.use_dot_as_multiplication_sign {
+                "\\cdot"
+            } else {
+                "\\times"
+            };
+            format!("{} {} {}", lhs, sign, rhs)
+        } else {
+            format!("{} {}", lhs, rhs)
+        }
+    }
+
+    fn fraction(&self, numerator: &str, denominator: &str) -> String {
+        if self.options.flat_fractions {
+            format!("{}/{}", numerator, denominator)
+        } else {
+            format!("\\frac{{{}}}{{{}}}", numerator, denominator)
+        }
+    }
+
+    fn is_mixed(&self, numerator: u128, denominator: u128) -> bool {
+        self.options.mixed_fractions && numerator > denominator
+    }
+
+    fn number(&self, integer: isize, broken: &str) -> String {
+        let decimal = || {
+            if broken.is_empty() {
+                integer.to_string()
+            } else {
+                format!("{}.{}", integer, broken)
+            }
+        };
+        if !self.options.broken_as_fractions {
+            return decimal();
+        }
+        match decimal_to_fraction(integer, broken) {
+            Some((n, 1)) => n.to_string(),
+            Some((n, d)) if self.is_mixed(n, d) => {
+                format!(
+                    "{} {}",
+                    n / d,
+                    self.fraction(&(n % d).to_string(), &d.to_string())
+                )
+            }
+            Some((n, d)) => self.fraction(&n.to_string(), &d.to_string()),
+            None => decimal(),
+        }
+    }
+
+    fn function(&self, name: &Ident, arguments: &[Expr]) -> String {
+        let lowercase_name = name.name().to_lowercase();
+        match (&lowercase_name[..], arguments) {
+            ("sqrt", [x]) => format!("\\sqrt{{{}}}", self.render(x)),
+            ("root", [x, n]) => format!("\\sqrt[{}]{{{}}}", self.render(n), self.render(x)),
+            ("abs", [x]) => format!("\\left| {} \\right|", self.render(x)),
+            ("floor", [x]) => format!("\\left\\lfloor {} \\right\\rfloor", self.render(x)),
+            ("ceil", [x]) => format!("\\left\\lceil {} \\right\\rceil", self.render(x)),
+            ("exp", [x]) => format!("e^{{{}}}", self.render(x)),
+            ("fact", [x]) => format!("{}!", self.operand(x, ATOM)),
+            ("log", [x, base]) => format!(
+                "\\log_{{{}}}\\left( {} \\right)",
+                self.render(base),
+                self.render(x)
+            ),
+            ("vector", _) => {
+                let separator = if self.options.vector_as_row {
+                    " & "
+                } else {
+                    " \\\\ "
+                };
+                format!(
+                    "\\begin{{pmatrix}} {} \\end{{pmatrix}}",
+                    self.list(arguments, separator)
+                )
+            }
+            ("matrix", rows) if rows.iter().all(|r| matches!(r, Expr::List(_))) => {
+                let environment = if self.options.matrices_without_parentheses {
+                    "matrix"
+                } else {
+                    "pmatrix"
+                };
+                let rows = rows
+                    .iter()
+                    .map(|r| match r {
+                        Expr::List(cells) => self.list(cells, " & "),
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" \\\\ ");
+                format!(
+                    "\\begin{{{}}} {} \\end{{{}}}",
+                    environment, rows, environment
+                )
+            }
+            (n, _) => {
+                let name = if LATEX_FUNCTIONS.contains(&n) {
+                    format!("\\{}", n)
+                } else if name.name().chars().count() == 1 {
+                    name.name().to_string()
+                } else {
+                    format!("\\operatorname{{{}}}", escape_name(name.name()))
+                };
+                format!("{}\\left( {} \\right)", name, self.list(arguments, ", "))
+            }
+        }
+    }
+}
+
+/// Convert a decimal number to a fraction in lowest terms
+fn decimal_to_fraction(integer: isize, broken: &str) -> Option<(u128, u128)> {
+    if broken.len() > 30 {
+        return None;
+    }
+    let denominator = 10u128.pow(broken.len() as u32);
+    let fractional: u128 = if broken.is_empty() {
+        0
+    } else {
+        broken.parse().ok()?
+    };
+    let numerator = (integer.unsigned_abs() as u128)
+        .checked_mul(denominator)?
+        .checked_add(fractional)?;
+    let divisor = gcd(numerator, denominator);
+    Some((numerator / divisor, denominator / divisor))
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn escape_text(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\textbackslash{}"),
+            '~' => result.push_str("\\textasciitilde{}"),
+            '^' => result.push_str("\\textasciicircum{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                result.push('\\');
+                result.push(c)
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn escape_name(s: &str) -> String {
+    s.replace('_', "\\_")
+}
+
+/// Render the name of a variable
+///
+/// Greek letters are written with their commands, a part after an underscore or trailing digits
+/// are written as subscript and names with multiple letters are written upright.
+fn name(s: &str) -> String {
+    if let Some((base, subscript)) = s.split_once('_') {
+        if !base.is_empty() && !subscript.is_empty() {
+            return format!("{}_{{{}}}", name(base), name(subscript));
+        }
+    }
+    let digits_start = s.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits_start > 0 && digits_start < s.len() {
+        return format!("{}_{{{}}}", name(&s[..digits_start]), &s[digits_start..]);
+    }
+    if GREEK_LETTERS.contains(&s) {
+        format!("\\{}", s)
+    } else if s.chars().count() == 1 {
+        s.to_string()
+    } else {
+        format!("\\mathrm{{{}}}", escape_name(s))
+    }
+}
+
+fn ident(i: &Ident) -> String {
+    let without_primes = i.name().trim_end_matches('\'');
+    let primes = &i.name()[without_primes.len()..];
+    let mut result = format!("{}{}", name(without_primes), primes);
+    for annotation in i.annotations().iter().rev() {
+        result = match &annotation[..] {
+            "vector" | "vec" | "v" => format!("\\boldsymbol{{{}}}", result),
+            "unit" => format!("\\hat{{{}}}", result),
+            "dot" => format!("\\dot{{{}}}", result),
+            "matrix" | "m" => format!("\\mathbf{{{}}}", result),
+            "op" => format!("\\operatorname{{{}}}", result),
+            "diff" => format!("\\mathrm{{d}}{}", result),
+            "degrees" => format!("{}^{{\\circ}}", result),
+            _ => result,
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jme::parser::{consume_one_expression, parse_as_jme};
+    use std::convert::TryInto;
+
+    fn latex(input: &str, options: &LatexOptions) -> String {
+        consume_one_expression(parse_as_jme(input).unwrap())
+            .unwrap()
+            .to_latex(options)
+    }
+
+    #[test]
+    fn default_options() {
+        let options = LatexOptions::default();
+        for (input, expected) in vec![
+            ("2x^2 + 3", "2 x^{2} + 3"),
+            ("2*3", "2 \\times 3"),
+            ("(a+b)/(c-1)", "\\frac{a + b}{c - 1}"),
+            ("(a+b)^2", "\\left( a + b \\right)^{2}"),
+            ("-x^2", "-x^{2}"),
+            ("sqrt(x1) + root(x, 3)", "\\sqrt{x_{1}} + \\sqrt[3]{x}"),
+            (
+                "sin(theta) <> abs(y_max)",
+                "\\sin\\left( \\theta \\right) \\neq \\left| y_{\\mathrm{max}} \\right|",
+            ),
+            ("vec:v + speed", "\\boldsymbol{v} + \\mathrm{speed}"),
+            ("0.5 * pi", "0.5 \\pi"),
+            ("vector(1, 2)", "\\begin{pmatrix} 1 \\\\ 2 \\end{pmatrix}"),
+            (
+                "matrix([1, 2], [3, 4])",
+                "\\begin{pmatrix} 1 & 2 \\\\ 3 & 4 \\end{pmatrix}",
+            ),
+            ("a - (b - c)", "a - \\left( b - c \\right)"),
+            ("x > 1 and not y", "x > 1 \\land \\neg y"),
+            ("f(x)", "f\\left( x \\right)"),
+            ("\"50%\"", "\\text{50\\%}"),
+        ] {
+            assert_eq!(latex(input, &options), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn display_options() {
+        let fractions = LatexOptions {
+            broken_as_fractions: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            latex("0.5 + 1.25", &fractions),
+            "\\frac{1}{2} + \\frac{5}{4}"
+        );
+        assert_eq!(latex("2.0", &fractions), "2");
+        let mixed = LatexOptions {
+            mixed_fractions: true,
+            ..fractions
+        };
+        assert_eq!(latex("1.25", &mixed), "1 \\frac{1}{4}");
+        assert_eq!(
+            latex("1.25^2", &mixed),
+            "\\left( 1 \\frac{1}{4} \\right)^{2}"
+        );
+        let flat = LatexOptions {
+            flat_fractions: true,
+            ..fractions
+        };
+        assert_eq!(
+            latex("0.5 + a/(b+1)", &flat),
+            "1/2 + a/\\left( b + 1 \\right)"
+        );
+        let row = LatexOptions {
+            vector_as_row: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            latex("vector(1, 2)", &row),
+            "\\begin{pmatrix} 1 & 2 \\end{pmatrix}"
+        );
+        let times = LatexOptions {
+            always_show_multiplication_sign: true,
+            ..Default::default()
+        };
+        assert_eq!(latex("2x", &times), "2 \\times x");
+        let dot = LatexOptions {
+            use_dot_as_multiplication_sign: true,
+            ..times
+        };
+        assert_eq!(latex("2x", &dot), "2 \\cdot x");
+        let bare = LatexOptions {
+            matrices_without_parentheses: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            latex("matrix([1, 2])", &bare),
+            "\\begin{matrix} 1 & 2 \\end{matrix}"
+        );
+    }
+
+    #[test]
+    fn options_from_display_options() {
+        let simplification: Vec<AnswerSimplificationType> = vec![
+            "fractionNumbers".try_into().unwrap(),
+            "timesDot".try_into().unwrap(),
+            "!rowVector".try_into().unwrap(),
+            "basic".try_into().unwrap(),
+        ];
+        let options: LatexOptions = simplification[..].into();
+        assert_eq!(
+            options,
+            LatexOptions {
+                broken_as_fractions: true,
+                use_dot_as_multiplication_sign: true,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Contains the static analysis of the notes of marking algorithms
+//!
+//! Only the parts of a marking algorithm that can be checked without knowing the base algorithm
+//! that it extends are analysed here; the detection of circular notes is left to the caller,
+//! which knows the dependencies of the notes.
+
+use crate::jme::ast::{check_signatures, Expr, FunctionCallError, Ident};
+use crate::jme::builtin_functions::{ParameterType, Signature};
+use crate::jme::types::TypeEnvironment;
+use std::collections::BTreeSet;
+
+/// The notes that a marking algorithm has to define
+pub const REQUIRED_NOTES: [&str; 2] = ["mark", "interpreted_answer"];
+
+/// The variables that are available in every marking algorithm
+pub const MARKING_VARIABLES: [&str; 11] = [
+    "path",
+    "studentanswer",
+    "settings",
+    "marking_parameters",
+    "partweight",
+    "gaps",
+    "steps",
+    "answered",
+    "question_definitions",
+    "input_options",
+    "raw_studentanswer",
+];
+
+/// The functions that are available in every marking algorithm
+pub const MARKING_FUNCTIONS: [&str; 21] = [
+    "correct",
+    "incorrect",
+    "correctif",
+    "set_credit",
+    "multiply_credit",
+    "add_credit",
+    "sub_credit",
+    "end",
+    "fail",
+    "warn",
+    "feedback",
+    "positive_feedback",
+    "negative_feedback",
+    "concat_feedback",
+    "apply",
+    "apply_marking_script",
+    "submit_part",
+    "mark_part",
+    "mark_action",
+    "get_scope_from_part",
+    "marking_parameters",
+];
+
+/// A problem in the notes of a marking algorithm
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MarkingError {
+    /// A note that the marking algorithm has to define is missing
+    MissingNote(String),
+    /// Notes that depend on each other, e.g. `["a", "b", "a"]`
+    CircularNotes(Vec<String>),
+    /// A marking function is called with arguments that it doesn't accept
+    FunctionCall(FunctionCallError),
+    /// The argument of `apply` is not the name of a note
+    UnknownAppliedNote(String),
+}
+
+impl std::fmt::Display for MarkingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MarkingError::MissingNote(name) => {
+                write!(f, "The marking algorithm does not define the note {}", name)
+            }
+            MarkingError::CircularNotes(cycle) => {
+                write!(f, "The notes depend on each other: {}", cycle.join(" -> "))
+            }
+            MarkingError::FunctionCall(e) => write!(f, "{}", e),
+            MarkingError::UnknownAppliedNote(argument) => write!(
+                f,
+                "apply is called with {}, but expects the name of a note",
+                argument
+            ),
+        }
+    }
+}
+
+/// The ways to call a function that is only available in marking algorithms, None if it is
+/// not such a function or its arguments are not checked
+fn marking_function_signatures(name: &str) -> Option<Vec<Signature>> {
+    use ParameterType::*;
+    Some(match name {
+        "correct" | "incorrect" => vec![Signature::optional(&[], &[Html])],
+        "correctif" => vec![Signature::new(&[Boolean])],
+        "set_credit" | "multiply_credit" | "add_credit" | "sub_credit" => {
+            vec![Signature::new(&[Number, Html])]
+        }
+        "end" => vec![Signature::new(&[])],
+        "fail" | "warn" | "feedback" | "positive_feedback" | "negative_feedback" => {
+            vec![Signature::new(&[Html])]
+        }
+        "concat_feedback" => vec![Signature::optional(&[List, Number], &[Boolean])],
+        "apply" => vec![Signature::new(&[Anything])],
+        "apply_marking_script" => vec![Signature::new(&[String, Anything, Dict, Number])],
+        "submit_part" => vec![Signature::new(&[String])],
+        "mark_part" => vec![Signature::new(&[String, Anything])],
+        _ => return None,
+    })
+}
+
+/// The required notes that are not in the given note names
+pub fn missing_required_notes(names: &[String]) -> Vec<MarkingError> {
+    REQUIRED_NOTES
+        .iter()
+        .filter(|required| !names.iter().any(|n| n.eq_ignore_ascii_case(required)))
+        .map(|required| MarkingError::MissingNote(required.to_string()))
+        .collect()
+}
+
+impl Expr {
+    /// The notes of the given (lowercase) note names that are used in this expression
+    pub fn note_dependencies(&self, notes: &[String]) -> BTreeSet<String> {
+        self.unknown_identifiers()
+            .into_iter()
+            .map(|i| i.name().to_lowercase())
+            .filter(|name| notes.contains(name))
+            .collect()
+    }
+
+    /// The calls of marking functions with invalid arguments in this expression, when it is
+    /// used in a marking algorithm that can use the notes with the given (lowercase) names
+    pub fn marking_errors(&self, notes: &[String]) -> Vec<MarkingError> {
+        let mut result = Vec::new();
+        self.collect_marking_errors(notes, &TypeEnvironment::new(), &mut result);
+        result
+    }
+
+    fn collect_marking_errors(
+        &self,
+        notes: &[String],
+        env: &TypeEnvironment,
+        result: &mut Vec<MarkingError>,
+    ) {
+        match self {
+            Expr::Str(_)
+            | Expr::Int(_)
+            | Expr::Float(_, _)
+            | Expr::Bool(_)
+            | Expr::Ident(_)
+            | Expr::Constant(_) => (),
+            Expr::FunctionApplication(ident, args) => {
+                check_marking_function_call(ident, args, notes, env, result);
+                for arg in args.iter() {
+                    arg.collect_marking_errors(notes, env, result);
+                }
+            }
+            Expr::List(es) => {
+                for e in es.iter() {
+                    e.collect_marking_errors(notes, env, result)
+                }
+            }
+            Expr::Dictionary(es) => {
+                for (k, v) in es.iter() {
+                    k.collect_marking_errors(notes, env, result);
+                    v.collect_marking_errors(notes, env, result);
+                }
+            }
+            Expr::Prefix(_, e1) | Expr::Faculty(e1) => {
+                e1.collect_marking_errors(notes, env, result)
+            }
+            Expr::Range(_, e1, e2)
+            | Expr::Arithmetic(_, e1, e2)
+            | Expr::Relation(_, e1, e2)
+            | Expr::Logic(_, e1, e2)
+            | Expr::Indexation(e1, e2)
+            | Expr::Cast(e1, e2)
+            | Expr::Sequence(e1, e2) => {
+                e1.collect_marking_errors(notes, env, result);
+                e2.collect_marking_errors(notes, env, result);
+            }
+        }
+    }
+}
+
+fn check_marking_function_call(
+    ident: &Ident,
+    args: &[Expr],
+    notes: &[String],
+    env: &TypeEnvironment,
+    result: &mut Vec<MarkingError>,
+) {
+    let name = ident.name().to_lowercase();
+    let signatures = match marking_function_signatures(&name[..]) {
+        Some(s) => s,
+        None => return,
+    };
+    let argument_types: Vec<_> = args
+        .iter()
+        .map(|a| a.infer_type(env).map(|t| t.name()))
+        .collect();
+    if let Some(error) = check_signatures(ident, signatures, &argument_types) {
+        result.push(MarkingError::FunctionCall(error));
+    } else if name == "apply" {
+        match &args[0] {
+            Expr::Ident(note) if notes.contains(&note.name().to_lowercase()) => (),
+            Expr::Ident(note) => {
+                result.push(MarkingError::UnknownAppliedNote(note.name().to_string()))
+            }
+            // e.g. `apply(marking_script)`: the applied value is not known statically
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jme::JMEString;
+    use std::convert::TryFrom;
+
+    fn errors(expression: &str, notes: &[&str]) -> Vec<String> {
+        let notes: Vec<_> = notes.iter().map(|n| n.to_string()).collect();
+        JMEString::try_from(expression.to_string())
+            .unwrap()
+            .ast()
+            .unwrap()
+            .marking_errors(&notes[..])
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn marking_function_calls() {
+        assert!(errors(r#"correctif(a=1); set_credit(0.5, "half")"#, &[]).is_empty());
+        assert!(errors("apply(validnumber)", &["validnumber"]).is_empty());
+        assert!(errors("feedback(message)", &[]).is_empty());
+        assert_eq!(
+            errors(r#"set_credit("half")"#, &[]),
+            vec!["set_credit is called with 1 argument, but expects set_credit(number, html)"]
+        );
+        assert_eq!(errors("correctif(1)", &[]).len(), 1);
+        assert_eq!(
+            errors("apply(validnumbr)", &["validnumber"]),
+            vec!["apply is called with validnumbr, but expects the name of a note"]
+        );
+    }
+
+    #[test]
+    fn checked_marking_functions_are_available() {
+        let checked = [
+            "correct",
+            "correctif",
+            "set_credit",
+            "end",
+            "fail",
+            "concat_feedback",
+            "apply",
+            "apply_marking_script",
+            "submit_part",
+            "mark_part",
+        ];
+        for name in checked.iter() {
+            assert!(marking_function_signatures(name).is_some(), "{}", name);
+            assert!(MARKING_FUNCTIONS.contains(name), "{}", name);
+        }
+        for name in MARKING_VARIABLES.iter() {
+            assert!(marking_function_signatures(name).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn note_dependencies() {
+        let notes = ["a".to_string(), "b".to_string()];
+        let expression = JMEString::try_from("A + map(b, b, c)".to_string()).unwrap();
+        assert_eq!(
+            expression.ast().unwrap().note_dependencies(&notes[..]),
+            vec!["a".to_string()].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn required_notes() {
+        assert_eq!(
+            missing_required_notes(&["Mark".to_string()]),
+            vec![MarkingError::MissingNote("interpreted_answer".to_string())]
+        );
+    }
+}
@@ -6,7 +6,15 @@ use std::convert::TryInto;
 
 pub mod ast;
 pub mod builtin_functions;
+#[cfg(test)]
+mod doc_tests;
+pub mod evaluate;
+pub mod latex;
+pub mod marking;
 pub mod parser;
+pub mod printer;
+pub mod simplify;
+pub mod types;
 
 macro_rules! impl_string_json_schema {
     ($t: ty, $e: expr) => {
@@ -94,6 +102,10 @@ impl JMEString {
     pub fn is_empty(&self) -> bool {
         self.s.is_empty()
     }
+    /// The parsed expression, None if the string is empty
+    pub fn ast(&self) -> Option<&ast::Expr> {
+        self.ast.as_ref()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Comparable, Eq, Default)]
@@ -155,6 +167,10 @@ impl EmbracedJMEString {
             asts: None,
         }
     }
+    /// The parsed embraced expressions
+    pub fn asts(&self) -> &[ast::Expr] {
+        self.asts.as_deref().unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Comparable, Eq, Default)]
@@ -165,6 +181,8 @@ pub struct ContentAreaString {
     s: String,
     #[comparable_ignore]
     asts: Option<Vec<ast::Expr>>,
+    #[comparable_ignore]
+    simplification_rules: Vec<String>,
 }
 impl_string_json_schema!(ContentAreaString, "ContentAreaString");
 
@@ -172,17 +190,20 @@ impl std::convert::TryFrom<String> for ContentAreaString {
     type Error = parser::ConsumeError;
     fn try_from(s: String) -> Result<Self, Self::Error> {
         let trimmed = s.trim();
-        let asts = if trimmed.is_empty() {
-            None
+        let (asts, simplification_rules) = if trimmed.is_empty() {
+            (None, Vec::new())
         } else {
             let pairs = parser::parse_as_content_area(trimmed)
                 .map_err(|e| parser::ConsumeError::HTMLParseError(vec![e]))?;
+            let simplification_rules =
+                parser::consume_content_area_simplification_rules(pairs.clone());
             let asts = parser::consume_content_area_expressions(pairs)?;
-            Some(asts)
+            (Some(asts), simplification_rules)
         };
         Ok(Self {
             s: trimmed.to_owned(),
             asts,
+            simplification_rules,
         })
     }
 }
@@ -203,6 +224,28 @@ impl ContentAreaString {
     pub fn is_empty(&self) -> bool {
         self.s.is_empty()
     }
+    /// The parsed expressions that are substituted into the content area
+    pub fn asts(&self) -> &[ast::Expr] {
+        self.asts.as_deref().unwrap_or_default()
+    }
+    /// The names of the rules that are used in `\var[rules]{}` and `\simplify[rules]{}`
+    pub fn simplification_rules(&self) -> &[String] {
+        &self.simplification_rules[..]
+    }
+    /// The rule names that are not builtin rules or display options, so they should be rulesets
+    /// defined in the question
+    pub fn unknown_simplification_rules(&self) -> Vec<String> {
+        self.simplification_rules
+            .iter()
+            .filter(|rule| {
+                crate::question::answer_simplification::AnswerSimplificationType::try_from(
+                    &rule[..],
+                )
+                .is_err()
+            })
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Comparable, Eq, Default)]
@@ -272,6 +315,38 @@ mod test {
         assert_eq!(res.unwrap().asts.unwrap().len(), 3);
     }
 
+    #[test]
+    fn content_area_with_simplify() {
+        let res = ContentAreaString::try_from(
+            r#"<p>$\simplify[!basic]{x^{a} + {b}y}$ and {c}</p>"#.to_string(),
+        )
+        .unwrap();
+        let names: Vec<_> = res
+            .asts()
+            .iter()
+            .flat_map(|a| a.unknown_identifiers())
+            .map(|e| e.name().to_owned())
+            .collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn content_area_simplification_rules() {
+        let res = ContentAreaString::try_from(
+            r#"<p>$\var[fractionNumbers, !basic]{x}$ and \(\simplify[myRules, unitfactr]{ {a}x }\)</p>"#
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.simplification_rules(),
+            vec!["fractionNumbers", "!basic", "myRules", "unitfactr"]
+        );
+        assert_eq!(
+            res.unknown_simplification_rules(),
+            vec!["myRules", "unitfactr"]
+        );
+    }
+
     #[test]
     fn diagnosys() {
         let s = include_str!("test_assets/diagnosys.jme");
@@ -1,5 +1,5 @@
 use crate::jme::ast;
-use pest::error::Error;
+use pest::error::{Error, InputLocation, LineColLocation};
 use pest::iterators::Pair;
 use pest::iterators::Pairs;
 use pest::prec_climber::{Assoc, Operator, PrecClimber};
@@ -54,7 +54,7 @@ pub enum ParserExpr<'i> {
     FunctionApplication(String, Vec<ParserNode<'i>>),
     Prefix(String, Box<ParserNode<'i>>),
     Faculty(Box<ParserNode<'i>>),
-    Indexation(Box<ParserNode<'i>>),
+    Indexation(Box<ParserNode<'i>>, Box<ParserNode<'i>>),
     Superscript(Box<ParserNode<'i>>, String),
     Cast(Box<ParserNode<'i>>, Box<ParserNode<'i>>),
     Sequence(Box<ParserNode<'i>>, Box<ParserNode<'i>>),
@@ -128,7 +128,9 @@ impl<'i> std::convert::TryFrom<ParserExpr<'i>> for ast::Expr {
             ),
             ParserExpr::Prefix(s, n) => ast::Expr::Prefix(s.into(), Box::new((*n).try_into()?)),
             ParserExpr::Faculty(n) => ast::Expr::Faculty(Box::new((*n).try_into()?)),
-            ParserExpr::Indexation(n) => ast::Expr::Indexation(Box::new((*n).try_into()?)),
+            ParserExpr::Indexation(n1, n2) => {
+                ast::Expr::Indexation(Box::new((*n1).try_into()?), Box::new((*n2).try_into()?))
+            }
             ParserExpr::Cast(n1, n2) => {
                 ast::Expr::Cast(Box::new((*n1).try_into()?), Box::new((*n2).try_into()?))
             }
@@ -216,20 +218,81 @@ impl std::fmt::Display for ConsumeError {
     }
 }
 
+impl ConsumeError {
+    /// The line and column (both starting at one) of the first error within the parsed string
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        fn start<R: pest::RuleType>(e: &Error<R>) -> (usize, usize) {
+            match e.line_col {
+                LineColLocation::Pos(line_col) => line_col,
+                LineColLocation::Span(start, _) => start,
+            }
+        }
+        match self {
+            ConsumeError::JMEParseError(errors) => errors.first().map(start),
+            ConsumeError::HTMLParseError(errors) => errors.first().map(start),
+            ConsumeError::UnknownParseError => None,
+        }
+    }
+}
+
+/// Move an error in a substring of the input, that starts at offset, to its position in the input
+fn relocate_error<R: pest::RuleType>(error: Error<R>, input: &str, offset: usize) -> Error<R> {
+    let position = match error.location {
+        InputLocation::Pos(p) => p,
+        InputLocation::Span((start, _)) => start,
+    };
+    match pest::Position::new(input, offset + position) {
+        Some(position) => Error::new_from_pos(error.variant.clone(), position),
+        None => error,
+    }
+}
+
 pub fn consume_content_area_expressions(
     pairs: Pairs<HTMLRule>,
 ) -> Result<Vec<ast::Expr>, ConsumeError> {
-    let pairs = pairs.clone().next().unwrap().into_inner();
+    let content_area = pairs.clone().next().unwrap();
+    let input = content_area.as_str();
+    let start = content_area.as_span().start();
+    let pairs = content_area.into_inner();
     let mut asts = vec![];
-    for expression in pairs.filter(|p| p.as_rule() == HTMLRule::expression) {
-        let parsed_jme =
-            parse_as_jme(expression.as_str()).map_err(|e| ConsumeError::JMEParseError(vec![e]))?;
-        let ast = consume_one_expression(parsed_jme)?;
-        asts.push(ast);
+    for expression in pairs {
+        // The errors are reported at their position in the content area
+        let offset = expression.as_span().start() - start;
+        let relocate = |e| ConsumeError::JMEParseError(vec![relocate_error(e, input, offset)]);
+        match expression.as_rule() {
+            HTMLRule::expression => {
+                let parsed_jme = parse_as_jme(expression.as_str()).map_err(relocate)?;
+                let ast = consume_one_expression(parsed_jme)?;
+                asts.push(ast);
+            }
+            HTMLRule::simplify_expression => {
+                let parsed_jme = parse_as_embraced_jme(expression.as_str()).map_err(relocate)?;
+                asts.extend(consume_expressions(parsed_jme)?);
+            }
+            _ => (),
+        }
     }
     Ok(asts)
 }
 
+/// The names of the simplification rules of the `\var[rules]{}` and `\simplify[rules]{}`
+/// commands in a content area
+pub fn consume_content_area_simplification_rules(pairs: Pairs<HTMLRule>) -> Vec<String> {
+    let pairs = pairs.clone().next().unwrap().into_inner();
+    pairs
+        .filter(|pair| pair.as_rule() == HTMLRule::latex_jme_simplification)
+        .flat_map(|pair| {
+            pair.as_str()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(|rule| rule.trim().to_string())
+                .filter(|rule| !rule.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
 pub fn consume_notes(pairs: Pairs<Rule>) -> Result<Vec<ast::Note>, ConsumeError> {
     let pairs = pairs.clone().next().unwrap().into_inner();
     let res_res = consume_note_with_spans(pairs);
@@ -444,15 +507,12 @@ fn consume_expression_internal<'i>(
                         let span = pair.as_span();
                         let pairs = pair.into_inner();
                         let mut elements = Vec::new();
-                        for p in pairs.filter(|p| p.as_rule() == Rule::expression) {
+                        for p in pairs.filter(|p| p.as_rule() == Rule::dictionary_item) {
                             let mut item = p.into_inner();
                             let key_pair = item.next().unwrap();
                             let value_pair = item.next().unwrap();
                             elements.push((
-                                consume_expression_internal(
-                                    key_pair.into_inner().peekable(),
-                                    climber,
-                                )?,
+                                consume_key(key_pair)?,
                                 consume_expression_internal(
                                     value_pair.into_inner().peekable(),
                                     climber,
@@ -503,9 +563,14 @@ fn consume_expression_internal<'i>(
                         }
                         Rule::index_operator => {
                             let start = node.span.start_pos();
+                            let end = pair.as_span().end_pos();
+                            let index = consume_expression_internal(
+                                pair.into_inner().next().unwrap().into_inner().peekable(),
+                                climber,
+                            )?;
                             Ok(ParserNode {
-                                expr: ParserExpr::Indexation(Box::new(node)),
-                                span: start.span(&pair.as_span().end_pos()),
+                                expr: ParserExpr::Indexation(Box::new(node), Box::new(index)),
+                                span: start.span(&end),
                             })
                         }
                         Rule::superscript_operator => {
@@ -617,22 +682,7 @@ fn consume_expression_internal<'i>(
                 span: start.span(&end),
             })
         }
-        Rule::power => {
-            let lhs = lhs?;
-            let rhs = rhs?;
-
-            let start = lhs.span.start_pos();
-            let end = rhs.span.end_pos();
-
-            Ok(ParserNode {
-                expr: ParserExpr::Arithmetic(
-                    ast::ArithmeticOperator::Power,
-                    Box::new(lhs),
-                    Box::new(rhs),
-                ),
-                span: start.span(&end),
-            })
-        }
+        Rule::power => Ok(consume_power(lhs?, op, rhs?)),
         Rule::range_separator => {
             let lhs = lhs?;
             let rhs = rhs?;
@@ -714,6 +764,60 @@ fn consume_expression_internal<'i>(
     climber.climb(pairs, term, infix)
 }
 
+/// Create the node for `lhs ^ rhs`
+///
+/// Prefix operators bind less tightly than the power operator, so `-x^2` is `-(x^2)`.
+/// The climber sees the prefix as part of the lhs term, so we move it outwards if the lhs
+/// wasn't grouped by brackets (i.e. there is only whitespace between the lhs and the operator).
+fn consume_power<'i>(
+    lhs: ParserNode<'i>,
+    op: Pair<'i, Rule>,
+    rhs: ParserNode<'i>,
+) -> ParserNode<'i> {
+    let start = lhs.span.start_pos();
+    let end = rhs.span.end_pos();
+    let between_is_blank = lhs
+        .span
+        .end_pos()
+        .span(&op.as_span().start_pos())
+        .as_str()
+        .trim()
+        .is_empty();
+    match lhs.expr {
+        ParserExpr::Prefix(prefix, inner) if between_is_blank => ParserNode {
+            expr: ParserExpr::Prefix(prefix, Box::new(consume_power(*inner, op, rhs))),
+            span: start.span(&end),
+        },
+        _ => ParserNode {
+            expr: ParserExpr::Arithmetic(
+                ast::ArithmeticOperator::Power,
+                Box::new(lhs),
+                Box::new(rhs),
+            ),
+            span: start.span(&end),
+        },
+    }
+}
+
+/// Consume a dictionary key, these are either names or strings
+fn consume_key(pair: Pair<Rule>) -> ParserResult<ParserNode, Rule> {
+    let span = pair.as_span();
+    let expr = match pair.as_rule() {
+        Rule::ident_name => ParserExpr::Str(pair.as_str().trim().to_owned()),
+        Rule::string => {
+            let string = unescape(pair.as_str().trim()).expect("incorrect string literal");
+            ParserExpr::Str(string[1..string.len() - 1].to_owned())
+        }
+        r => {
+            return Err(ParserResultError::EnteredUnreachableCode(format!(
+                "Unexpected rule {:?} as key",
+                r
+            )))
+        }
+    };
+    Ok(ParserNode { expr, span })
+}
+
 fn unescape(string: &str) -> Option<String> {
     let mut result = String::new();
     let mut chars = string.chars();
@@ -869,6 +973,17 @@ mod test {
         assert!(parse_as_embraced_jme("hallo {x+5} test {x*y+7} \\{xxxtest\\}").is_ok());
     }
 
+    #[test]
+    fn error_positions() {
+        let jme = parse_as_jme("1 +* 2").map_err(|e| ConsumeError::JMEParseError(vec![e]));
+        assert_eq!(jme.err().and_then(|e| e.line_col()), Some((1, 4)));
+        // Errors in embedded expressions are reported at their position in the content area
+        let content_area = parse_as_content_area("<p>Test</p>\n<p>Value {1 +* 2}</p>")
+            .map_err(|e| ConsumeError::HTMLParseError(vec![e]))
+            .and_then(consume_content_area_expressions);
+        assert_eq!(content_area.err().and_then(|e| e.line_col()), Some((2, 14)));
+    }
+
     #[test]
     fn superscripts() {
         assert!(parse_as_jme("x¹").is_ok());
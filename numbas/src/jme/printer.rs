@@ -0,0 +1,289 @@
+//! Writes an expression as canonical JME
+//!
+//! Operators are written in their canonical form (e.g. `and` instead of `&&`), implicit
+//! multiplication is made explicit and parentheses are only added where they are needed.
+//! Parsing the result gives the same expression again.
+
+use crate::jme::ast::{
+    ArithmeticOperator, Expr, LogicalOperator, PrefixOperator, RangeOperator, RelationalOperator,
+};
+use std::fmt;
+
+/// The words that can't be used as the key of a dictionary without quotes
+const RESERVED_WORDS: &[&str] = &[
+    "true", "false", "infty", "infinity", "nan", "e", "i", "pi", "and", "or", "xor", "implies",
+    "not", "in", "except", "isa", "as",
+];
+
+// The precedences of the operators, higher binds stronger. This follows the parser, where
+// prefix operators bind less tightly than the power operator.
+pub(crate) const SEQUENCE: u8 = 0;
+pub(crate) const LOGIC: u8 = 1;
+pub(crate) const RELATION: u8 = 2;
+pub(crate) const CAST: u8 = 3;
+pub(crate) const ADDITION: u8 = 4;
+pub(crate) const MULTIPLICATION: u8 = 5;
+pub(crate) const RANGE_STEP: u8 = 6;
+pub(crate) const RANGE: u8 = 7;
+pub(crate) const PREFIX: u8 = 8;
+pub(crate) const POWER: u8 = 9;
+pub(crate) const ATOM: u8 = 10;
+
+impl ArithmeticOperator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            ArithmeticOperator::Add => " + ",
+            ArithmeticOperator::Subtract => " - ",
+            ArithmeticOperator::Multiply => " * ",
+            ArithmeticOperator::Divide => " / ",
+            ArithmeticOperator::Power => "^",
+            ArithmeticOperator::Except => " except ",
+        }
+    }
+}
+
+impl RelationalOperator {
+    pub(crate) fn symbol(&self) -> &'static str {
+        match self {
+            RelationalOperator::LessThan => " < ",
+            RelationalOperator::LessThanOrEqual => " <= ",
+            RelationalOperator::GreaterThan => " > ",
+            RelationalOperator::GreaterThanOrEqual => " >= ",
+            RelationalOperator::Equals => " = ",
+            RelationalOperator::NotEquals => " <> ",
+            RelationalOperator::In => " in ",
+            RelationalOperator::IsA => " isa ",
+            RelationalOperator::Divides => " | ",
+        }
+    }
+}
+
+impl LogicalOperator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            LogicalOperator::And => " and ",
+            LogicalOperator::Or => " or ",
+            LogicalOperator::Xor => " xor ",
+            LogicalOperator::Implies => " implies ",
+        }
+    }
+}
+
+impl RangeOperator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            RangeOperator::Create => "..",
+            RangeOperator::Step => "#",
+        }
+    }
+}
+
+impl Expr {
+    pub(crate) fn precedence(&self) -> u8 {
+        match self {
+            Expr::Sequence(..) => SEQUENCE,
+            Expr::Logic(..) => LOGIC,
+            Expr::Relation(..) => RELATION,
+            Expr::Cast(..) => CAST,
+            Expr::Arithmetic(op, ..) => match op {
+                ArithmeticOperator::Add
+                | ArithmeticOperator::Subtract
+                | ArithmeticOperator::Except => ADDITION,
+                ArithmeticOperator::Multiply | ArithmeticOperator::Divide => MULTIPLICATION,
+                ArithmeticOperator::Power => POWER,
+            },
+            Expr::Range(RangeOperator::Step, ..) => RANGE_STEP,
+            Expr::Range(RangeOperator::Create, ..) => RANGE,
+            Expr::Prefix(..) => PREFIX,
+            Expr::Int(i) if *i < 0 => PREFIX,
+            _ => ATOM,
+        }
+    }
+}
+
+/// Write an expression, with parentheses if it binds weaker than `minimum`
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr, minimum: u8) -> fmt::Result {
+    if expr.precedence() < minimum {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+/// Write a binary operation
+///
+/// All operators are left associative, except for the power operator. The exponent can start
+/// with a prefix operator, e.g. `2^-x`.
+fn write_binary(
+    f: &mut fmt::Formatter,
+    precedence: u8,
+    lhs: &Expr,
+    symbol: &str,
+    rhs: &Expr,
+) -> fmt::Result {
+    let (lhs_minimum, rhs_minimum) = if precedence == POWER {
+        (precedence + 1, PREFIX)
+    } else {
+        (precedence, precedence + 1)
+    };
+    write_operand(f, lhs, lhs_minimum)?;
+    write!(f, "{}", symbol)?;
+    write_operand(f, rhs, rhs_minimum)
+}
+
+fn write_list(f: &mut fmt::Formatter, items: &[Expr]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '\\' => write!(f, "\\\\")?,
+            '"' => write!(f, "\\\"")?,
+            '\n' => write!(f, "\\n")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn is_plain_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_') && !RESERVED_WORDS.contains(&key)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Str(s) => write_string(f, s),
+            Expr::Int(i) => write!(f, "{}", i),
+            Expr::Float(i, broken) => write!(f, "{}.{}", i, broken),
+            Expr::Bool(b) => write!(f, "{}", b),
+            Expr::Ident(i) | Expr::Constant(i) => write!(f, "{}", i),
+            Expr::Range(op, lhs, rhs) => write_binary(f, self.precedence(), lhs, op.symbol(), rhs),
+            Expr::Arithmetic(op, lhs, rhs) => {
+                write_binary(f, self.precedence(), lhs, op.symbol(), rhs)
+            }
+            Expr::Relation(op, lhs, rhs) => {
+                write_binary(f, self.precedence(), lhs, op.symbol(), rhs)
+            }
+            Expr::Logic(op, lhs, rhs) => write_binary(f, self.precedence(), lhs, op.symbol(), rhs),
+            Expr::Cast(lhs, rhs) => write_binary(f, CAST, lhs, " as ", rhs),
+            Expr::Sequence(lhs, rhs) => write_binary(f, SEQUENCE, lhs, "; ", rhs),
+            Expr::List(items) => {
+                write!(f, "[")?;
+                write_list(f, items)?;
+                write!(f, "]")
+            }
+            Expr::Dictionary(items) => {
+                write!(f, "[")?;
+                for (i, (key, value)) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match key {
+                        Expr::Str(k) if is_plain_key(k) => write!(f, "{}", k)?,
+                        Expr::Str(k) => write_string(f, k)?,
+                        k => write!(f, "{}", k)?,
+                    }
+                    write!(f, ": {}", value)?;
+                }
+                write!(f, "]")
+            }
+            Expr::FunctionApplication(name, arguments) => {
+                write!(f, "{}(", name)?;
+                write_list(f, arguments)?;
+                write!(f, ")")
+            }
+            Expr::Prefix(op, operand) => {
+                match op {
+                    PrefixOperator::Not => write!(f, "not ")?,
+                    PrefixOperator::Minus => write!(f, "-")?,
+                }
+                write_operand(f, operand, PREFIX)
+            }
+            Expr::Faculty(operand) => {
+                write_operand(f, operand, ATOM)?;
+                write!(f, "!")
+            }
+            Expr::Indexation(operand, index) => {
+                write_operand(f, operand, ATOM)?;
+                write!(f, "[{}]", index)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jme::parser::{consume_one_expression, parse_as_jme};
+
+    fn parse(input: &str) -> crate::jme::ast::Expr {
+        consume_one_expression(parse_as_jme(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn canonical() {
+        for (input, expected) in vec![
+            (
+                "a*7>5&&true||9^10+8*5<6/10",
+                "a * 7 > 5 and true or 9^10 + 8 * 5 < 6 / 10",
+            ),
+            ("(a+1)(b+2)", "(a + 1) * (b + 2)"),
+            ("2x^2", "2 * x^2"),
+            ("a-(b-c)", "a - (b - c)"),
+            ("(a-b)-c", "a - b - c"),
+            ("(a^b)^c", "(a^b)^c"),
+            ("a^(b^c)", "a^b^c"),
+            ("-x^2", "-x^2"),
+            ("-(x^2)", "-x^2"),
+            ("(-x)^2", "(-x)^2"),
+            ("2^(-x)", "2^-x"),
+            ("-(a+b)", "-(a + b)"),
+            ("(-x)!", "(-x)!"),
+            ("(a+b)!", "(a + b)!"),
+            ("!(a and b)", "not (a and b)"),
+            ("random(1 .. 10 # 2)", "random(1..10#2)"),
+            ("{a} * [1,2,3][0]", "a * [1, 2, 3][0]"),
+            ("['a': 1, \"not a key\": x]", "[a: 1, \"not a key\": x]"),
+            ("x as 'number'", "x as \"number\""),
+            ("'say \"hi\"'", "\"say \\\"hi\\\"\""),
+            ("vec:x + pi", "vec:x + pi"),
+            ("a and (b or c)", "a and (b or c)"),
+            ("f(x)[0]", "f(x)[0]"),
+            ("1.50 + x⁵", "1.50 + x^5"),
+        ] {
+            let printed = parse(input).to_string();
+            assert_eq!(printed, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn round_trip_doc_tests() {
+        let doc_tests: serde_json::Value =
+            serde_json::from_str(include_str!("numbas-jme-doc-tests.json")).unwrap();
+        let inputs = doc_tests
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|test| test["fns"].as_array().unwrap())
+            .flat_map(|r#fn| r#fn["examples"].as_array().unwrap())
+            .map(|example| example["in"].as_str().unwrap());
+        for input in inputs {
+            let ast = parse(input);
+            let printed = ast.to_string();
+            assert_eq!(parse(&printed), ast, "{} -> {}", input, printed);
+        }
+    }
+}
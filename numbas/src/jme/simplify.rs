@@ -0,0 +1,807 @@
+//! Contains a rewriting engine for the answer simplification rules
+//!
+//! See https://docs.numbas.org.uk/en/latest/simplification.html for the meaning of the rules.
+
+use crate::jme::ast::{ArithmeticOperator, Expr, Ident, PrefixOperator};
+use crate::question::answer_simplification::AnswerSimplificationRule;
+use std::mem::discriminant;
+
+/// The maximal amount of passes over an expression before giving up
+const MAX_PASSES: usize = 100;
+
+/// Pairs of rules that can rewrite each others results forever
+const KNOWN_LOOPS: &[(AnswerSimplificationRule, AnswerSimplificationRule)] = &[(
+    AnswerSimplificationRule::CanonicalOrder(true),
+    AnswerSimplificationRule::NoLeadingMinus(true),
+)];
+
+/// The rules that are turned on by `all`
+const ALL_RULES: &[AnswerSimplificationRule] = &[
+    AnswerSimplificationRule::Basic(true),
+    AnswerSimplificationRule::CancelUnitFactors(true),
+    AnswerSimplificationRule::CancelUnitPowers(true),
+    AnswerSimplificationRule::CancelUnitDenominators(true),
+    AnswerSimplificationRule::CancelZeroFactors(true),
+    AnswerSimplificationRule::OmitZeroTerms(true),
+    AnswerSimplificationRule::CancelZeroPowers(true),
+    AnswerSimplificationRule::NoLeadingMinus(true),
+    AnswerSimplificationRule::CollectNumbers(true),
+    AnswerSimplificationRule::Fractions(true),
+    AnswerSimplificationRule::CancelPowersWithBaseZero(true),
+    AnswerSimplificationRule::ConstantsFirst(true),
+    AnswerSimplificationRule::CollectSqrtProducts(true),
+    AnswerSimplificationRule::CollectSqrtDivisions(true),
+    AnswerSimplificationRule::CancelSqrtSquares(true),
+    AnswerSimplificationRule::Trigonometric(true),
+    AnswerSimplificationRule::EvaluatePowersOfNumbers(true),
+    AnswerSimplificationRule::CollectTerms(true),
+    AnswerSimplificationRule::CollectPowersOfCommonFactors(true),
+    AnswerSimplificationRule::CollectLikeFractions(true),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimplificationError {
+    /// The rules keep rewriting the expression into an expression that was seen before
+    Loop(Vec<Expr>),
+    /// The expression still changes after the maximal amount of passes
+    TooManyPasses,
+}
+
+impl std::fmt::Display for SimplificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Loop(expressions) => write!(
+                f,
+                "The simplification loops: {}",
+                expressions
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            Self::TooManyPasses => write!(
+                f,
+                "The simplification did not finish after {} passes",
+                MAX_PASSES
+            ),
+        }
+    }
+}
+
+/// The set of simplification rules that are turned on
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RuleSet {
+    rules: Vec<AnswerSimplificationRule>,
+}
+
+impl std::convert::From<&[AnswerSimplificationRule]> for RuleSet {
+    /// The rules are handled in order, so `[all, !collectNumbers]` turns on all rules except
+    /// `collectNumbers`.
+    fn from(items: &[AnswerSimplificationRule]) -> Self {
+        let mut set = RuleSet::default();
+        for item in items.iter() {
+            match item {
+                AnswerSimplificationRule::All(on) => {
+                    for rule in ALL_RULES.iter() {
+                        set.set(rule, *on);
+                    }
+                }
+                AnswerSimplificationRule::Basic(on)
+                | AnswerSimplificationRule::CancelUnitFactors(on)
+                | AnswerSimplificationRule::CancelUnitPowers(on)
+                | AnswerSimplificationRule::CancelUnitDenominators(on)
+                | AnswerSimplificationRule::CancelZeroFactors(on)
+                | AnswerSimplificationRule::OmitZeroTerms(on)
+                | AnswerSimplificationRule::CancelZeroPowers(on)
+                | AnswerSimplificationRule::NoLeadingMinus(on)
+                | AnswerSimplificationRule::CollectNumbers(on)
+                | AnswerSimplificationRule::Fractions(on)
+                | AnswerSimplificationRule::CancelPowersWithBaseZero(on)
+                | AnswerSimplificationRule::ConstantsFirst(on)
+                | AnswerSimplificationRule::CollectSqrtProducts(on)
+                | AnswerSimplificationRule::CollectSqrtDivisions(on)
+                | AnswerSimplificationRule::CancelSqrtSquares(on)
+                | AnswerSimplificationRule::Trigonometric(on)
+                | AnswerSimplificationRule::EvaluatePowersOfNumbers(on)
+                | AnswerSimplificationRule::CollectTerms(on)
+                | AnswerSimplificationRule::CollectPowersOfCommonFactors(on)
+                | AnswerSimplificationRule::CollectLikeFractions(on)
+                | AnswerSimplificationRule::CanonicalOrder(on)
+                | AnswerSimplificationRule::ExpandBrackets(on) => set.set(item, *on),
+            }
+        }
+        set
+    }
+}
+
+impl RuleSet {
+    fn set(&mut self, rule: &AnswerSimplificationRule, on: bool) {
+        self.rules.retain(|r| discriminant(r) != discriminant(rule));
+        if on {
+            self.rules.push(rule.clone());
+        }
+    }
+
+    /// Whether the given rule is turned on, the value of the rule is ignored
+    pub fn contains(&self, rule: &AnswerSimplificationRule) -> bool {
+        self.rules
+            .iter()
+            .any(|r| discriminant(r) == discriminant(rule))
+    }
+
+    /// The pairs of turned on rules that are known to loop
+    pub fn looping_rules(&self) -> Vec<(AnswerSimplificationRule, AnswerSimplificationRule)> {
+        KNOWN_LOOPS
+            .iter()
+            .filter(|(a, b)| self.contains(a) && self.contains(b))
+            .cloned()
+            .collect()
+    }
+
+    /// Apply the rules until the expression doesn't change anymore
+    pub fn simplify(&self, expr: &Expr) -> Result<Expr, SimplificationError> {
+        let mut history = vec![expr.clone()];
+        for _ in 0..MAX_PASSES {
+            let current = history.last().unwrap();
+            let next = self.rewrite(current.clone());
+            if &next == current {
+                return Ok(next);
+            }
+            if let Some(position) = history.iter().position(|e| e == &next) {
+                let mut cycle = history.split_off(position);
+                cycle.push(next);
+                return Err(SimplificationError::Loop(cycle));
+            }
+            history.push(next);
+        }
+        Err(SimplificationError::TooManyPasses)
+    }
+
+    /// Rewrite the children and then apply the first matching rule to the expression
+    fn rewrite(&self, expr: Expr) -> Expr {
+        let expr = map_children(expr, |e| self.rewrite(e));
+        for rule in self.rules.iter() {
+            if let Some(rewritten) = apply_rule(rule, &expr) {
+                return rewritten;
+            }
+        }
+        expr
+    }
+}
+
+fn map_children(expr: Expr, f: impl Fn(Expr) -> Expr) -> Expr {
+    let b = |e: Box<Expr>| Box::new(f(*e));
+    match expr {
+        Expr::Range(op, lhs, rhs) => Expr::Range(op, b(lhs), b(rhs)),
+        Expr::Arithmetic(op, lhs, rhs) => Expr::Arithmetic(op, b(lhs), b(rhs)),
+        Expr::Relation(op, lhs, rhs) => Expr::Relation(op, b(lhs), b(rhs)),
+        Expr::Logic(op, lhs, rhs) => Expr::Logic(op, b(lhs), b(rhs)),
+        Expr::List(items) => Expr::List(items.into_iter().map(&f).collect()),
+        Expr::Dictionary(items) => {
+            Expr::Dictionary(items.into_iter().map(|(k, v)| (k, f(v))).collect())
+        }
+        Expr::FunctionApplication(name, arguments) => {
+            Expr::FunctionApplication(name, arguments.into_iter().map(&f).collect())
+        }
+        Expr::Prefix(op, operand) => Expr::Prefix(op, b(operand)),
+        Expr::Faculty(operand) => Expr::Faculty(b(operand)),
+        Expr::Indexation(operand, index) => Expr::Indexation(b(operand), b(index)),
+        Expr::Cast(lhs, rhs) => Expr::Cast(b(lhs), rhs),
+        Expr::Sequence(lhs, rhs) => Expr::Sequence(b(lhs), b(rhs)),
+        e => e,
+    }
+}
+
+fn apply_rule(rule: &AnswerSimplificationRule, expr: &Expr) -> Option<Expr> {
+    match rule {
+        AnswerSimplificationRule::All(_) => None,
+        AnswerSimplificationRule::Basic(_) => basic(expr),
+        AnswerSimplificationRule::CancelUnitFactors(_) => match op(expr)? {
+            (ArithmeticOperator::Multiply, x, y) if as_int(x) == Some(1) => Some(y.clone()),
+            (ArithmeticOperator::Multiply, x, y) if as_int(y) == Some(1) => Some(x.clone()),
+            _ => None,
+        },
+        AnswerSimplificationRule::CancelUnitPowers(_) => match op(expr)? {
+            (ArithmeticOperator::Power, x, y) if as_int(y) == Some(1) => Some(x.clone()),
+            _ => None,
+        },
+        AnswerSimplificationRule::CancelUnitDenominators(_) => match op(expr)? {
+            (ArithmeticOperator::Divide, x, y) if as_int(y) == Some(1) => Some(x.clone()),
+            _ => None,
+        },
+        AnswerSimplificationRule::CancelZeroFactors(_) => match op(expr)? {
+            (ArithmeticOperator::Multiply, x, y)
+                if as_int(x) == Some(0) || as_int(y) == Some(0) =>
+            {
+                Some(Expr::Int(0))
+            }
+            (ArithmeticOperator::Divide, x, _) if as_int(x) == Some(0) => Some(Expr::Int(0)),
+            _ => None,
+        },
+        AnswerSimplificationRule::OmitZeroTerms(_) => match op(expr)? {
+            (ArithmeticOperator::Add, x, y) if as_int(x) == Some(0) => Some(y.clone()),
+            (ArithmeticOperator::Add, x, y) | (ArithmeticOperator::Subtract, x, y)
+                if as_int(y) == Some(0) =>
+            {
+                Some(x.clone())
+            }
+            (ArithmeticOperator::Subtract, x, y) if as_int(x) == Some(0) => Some(negate(y.clone())),
+            _ => None,
+        },
+        AnswerSimplificationRule::CancelZeroPowers(_) => match op(expr)? {
+            (ArithmeticOperator::Power, _, y) if as_int(y) == Some(0) => Some(Expr::Int(1)),
+            _ => None,
+        },
+        AnswerSimplificationRule::CancelPowersWithBaseZero(_) => match op(expr)? {
+            (ArithmeticOperator::Power, x, _) if as_int(x) == Some(0) => Some(Expr::Int(0)),
+            _ => None,
+        },
+        AnswerSimplificationRule::NoLeadingMinus(_) => match expr {
+            Expr::Arithmetic(ArithmeticOperator::Add, x, y) => match &**x {
+                Expr::Prefix(PrefixOperator::Minus, x) => Some(arithmetic(
+                    ArithmeticOperator::Subtract,
+                    (**y).clone(),
+                    (**x).clone(),
+                )),
+                _ => None,
+            },
+            Expr::Prefix(PrefixOperator::Minus, x) if as_int(x) == Some(0) => Some(Expr::Int(0)),
+            _ => None,
+        },
+        AnswerSimplificationRule::CollectNumbers(_) => collect_numbers(expr),
+        AnswerSimplificationRule::Fractions(_) => match op(expr)? {
+            (ArithmeticOperator::Divide, x, y) => {
+                let (n, d) = (as_int(x)?, as_int(y)?);
+                if d == 0 {
+                    return None;
+                }
+                let divisor = gcd(n, d) * d.signum();
+                if divisor == 1 {
+                    None
+                } else if d / divisor == 1 {
+                    Some(int(n / divisor))
+                } else {
+                    Some(arithmetic(
+                        ArithmeticOperator::Divide,
+                        int(n / divisor),
+                        int(d / divisor),
+                    ))
+                }
+            }
+            _ => None,
+        },
+        AnswerSimplificationRule::ConstantsFirst(_) => match op(expr)? {
+            (ArithmeticOperator::Multiply, x, y) if !is_number(x) && is_number(y) => Some(
+                arithmetic(ArithmeticOperator::Multiply, y.clone(), x.clone()),
+            ),
+            _ => None,
+        },
+        AnswerSimplificationRule::CollectSqrtProducts(_) => match op(expr)? {
+            (ArithmeticOperator::Multiply, x, y) => Some(function(
+                "sqrt",
+                vec![arithmetic(
+                    ArithmeticOperator::Multiply,
+                    sqrt_argument(x)?.clone(),
+                    sqrt_argument(y)?.clone(),
+                )],
+            )),
+            _ => None,
+        },
+        AnswerSimplificationRule::CollectSqrtDivisions(_) => match op(expr)? {
+            (ArithmeticOperator::Divide, x, y) => Some(function(
+                "sqrt",
+                vec![arithmetic(
+                    ArithmeticOperator::Divide,
+                    sqrt_argument(x)?.clone(),
+                    sqrt_argument(y)?.clone(),
+                )],
+            )),
+            _ => None,
+        },
+        AnswerSimplificationRule::CancelSqrtSquares(_) => cancel_sqrt_squares(expr),
+        AnswerSimplificationRule::Trigonometric(_) => trigonometric(expr),
+        AnswerSimplificationRule::EvaluatePowersOfNumbers(_) => match op(expr)? {
+            (ArithmeticOperator::Power, x, y) => {
+                let exponent: u32 = as_int(y)?.try_into().ok()?;
+                Some(int(as_int(x)?.checked_pow(exponent)?))
+            }
+            _ => None,
+        },
+        AnswerSimplificationRule::CollectTerms(_) => collect_terms(expr),
+        AnswerSimplificationRule::CollectPowersOfCommonFactors(_) => collect_factors(expr),
+        AnswerSimplificationRule::CollectLikeFractions(_) => match op(expr)? {
+            (o @ ArithmeticOperator::Add, x, y) | (o @ ArithmeticOperator::Subtract, x, y) => {
+                match (op(x)?, op(y)?) {
+                    ((ArithmeticOperator::Divide, a, c1), (ArithmeticOperator::Divide, b, c2))
+                        if c1 == c2 =>
+                    {
+                        Some(arithmetic(
+                            ArithmeticOperator::Divide,
+                            arithmetic(o, a.clone(), b.clone()),
+                            c1.clone(),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        AnswerSimplificationRule::CanonicalOrder(_) => canonical_order(expr),
+        AnswerSimplificationRule::ExpandBrackets(_) => match op(expr)? {
+            (ArithmeticOperator::Multiply, x, y) => {
+                if let Some((o @ (ArithmeticOperator::Add | ArithmeticOperator::Subtract), a, b)) =
+                    op(x)
+                {
+                    Some(arithmetic(
+                        o,
+                        arithmetic(ArithmeticOperator::Multiply, a.clone(), y.clone()),
+                        arithmetic(ArithmeticOperator::Multiply, b.clone(), y.clone()),
+                    ))
+                } else if let Some((
+                    o @ (ArithmeticOperator::Add | ArithmeticOperator::Subtract),
+                    a,
+                    b,
+                )) = op(y)
+                {
+                    Some(arithmetic(
+                        o,
+                        arithmetic(ArithmeticOperator::Multiply, x.clone(), a.clone()),
+                        arithmetic(ArithmeticOperator::Multiply, x.clone(), b.clone()),
+                    ))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        },
+    }
+}
+
+fn basic(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Arithmetic(o, x, y) => match (o, &**x, &**y) {
+            (ArithmeticOperator::Add, x, Expr::Prefix(PrefixOperator::Minus, y)) => Some(
+                arithmetic(ArithmeticOperator::Subtract, x.clone(), (**y).clone()),
+            ),
+            (ArithmeticOperator::Subtract, x, Expr::Prefix(PrefixOperator::Minus, y)) => Some(
+                arithmetic(ArithmeticOperator::Add, x.clone(), (**y).clone()),
+            ),
+            (
+                o @ (ArithmeticOperator::Multiply | ArithmeticOperator::Divide),
+                Expr::Prefix(PrefixOperator::Minus, x),
+                y,
+            ) => Some(negate(arithmetic(*o, (**x).clone(), y.clone()))),
+            (
+                o @ (ArithmeticOperator::Multiply | ArithmeticOperator::Divide),
+                x,
+                Expr::Prefix(PrefixOperator::Minus, y),
+            ) => Some(negate(arithmetic(*o, x.clone(), (**y).clone()))),
+            _ => None,
+        },
+        Expr::Prefix(PrefixOperator::Minus, x) => match &**x {
+            Expr::Prefix(PrefixOperator::Minus, x) => Some((**x).clone()),
+            _ => None,
+        },
+        Expr::Prefix(PrefixOperator::Not, x) => match &**x {
+            Expr::Bool(b) => Some(Expr::Bool(!b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn collect_numbers(expr: &Expr) -> Option<Expr> {
+    let (o, x, y) = op(expr)?;
+    let evaluate = |o: ArithmeticOperator, a: isize, b: isize| match o {
+        ArithmeticOperator::Add => a.checked_add(b),
+        ArithmeticOperator::Subtract => a.checked_sub(b),
+        ArithmeticOperator::Multiply => a.checked_mul(b),
+        _ => None,
+    };
+    if let (Some(a), Some(b)) = (as_int(x), as_int(y)) {
+        return evaluate(o, a, b).map(int);
+    }
+    // (x + n) + m is x + (n + m) and n * (m * x) is (n * m) * x
+    match (o, op(x), op(y)) {
+        (
+            ArithmeticOperator::Add | ArithmeticOperator::Subtract,
+            Some((inner @ (ArithmeticOperator::Add | ArithmeticOperator::Subtract), z, n)),
+            _,
+        ) => {
+            let n = if inner == ArithmeticOperator::Add {
+                as_int(n)?
+            } else {
+                -as_int(n)?
+            };
+            let total = evaluate(o, n, as_int(y)?)?;
+            Some(arithmetic(ArithmeticOperator::Add, z.clone(), int(total)))
+        }
+        (ArithmeticOperator::Multiply, _, Some((ArithmeticOperator::Multiply, m, z))) => {
+            let product = as_int(x)?.checked_mul(as_int(m)?)?;
+            Some(arithmetic(
+                ArithmeticOperator::Multiply,
+                int(product),
+                z.clone(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn cancel_sqrt_squares(expr: &Expr) -> Option<Expr> {
+    if let Some(argument) = sqrt_argument(expr) {
+        if let Some((ArithmeticOperator::Power, x, y)) = op(argument) {
+            if as_int(y) == Some(2) {
+                return Some(x.clone());
+            }
+        }
+        let n = as_int(argument)?;
+        let root = (n.max(0) as f64).sqrt().round() as isize;
+        return if root * root == n {
+            Some(int(root))
+        } else {
+            None
+        };
+    }
+    match op(expr)? {
+        (ArithmeticOperator::Power, x, y) if as_int(y) == Some(2) => sqrt_argument(x).cloned(),
+        _ => None,
+    }
+}
+
+fn trigonometric(expr: &Expr) -> Option<Expr> {
+    if let Expr::FunctionApplication(name, arguments) = expr {
+        let argument_is = |value: &str| match &arguments[..] {
+            [Expr::Int(0)] => value == "0",
+            [Expr::Constant(c)] => value == "pi" && matches!(c.name(), "pi" | "π"),
+            _ => false,
+        };
+        return match &name.name().to_lowercase()[..] {
+            "sin" | "tan" if argument_is("0") || argument_is("pi") => Some(Expr::Int(0)),
+            "cos" if argument_is("0") => Some(Expr::Int(1)),
+            "cos" if argument_is("pi") => Some(int(-1)),
+            _ => None,
+        };
+    }
+    // sin(x)^2 + cos(x)^2 is 1
+    let squared_argument = |e: &Expr, function: &str| match op(e)? {
+        (ArithmeticOperator::Power, Expr::FunctionApplication(name, arguments), y)
+            if as_int(y) == Some(2) && name.name().eq_ignore_ascii_case(function) =>
+        {
+            match &arguments[..] {
+                [argument] => Some(argument.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    match op(expr)? {
+        (ArithmeticOperator::Add, x, y) => {
+            let (a, b) = match (squared_argument(x, "sin"), squared_argument(y, "cos")) {
+                (Some(a), Some(b)) => (a, b),
+                _ => (squared_argument(x, "cos")?, squared_argument(y, "sin")?),
+            };
+            if a == b {
+                Some(Expr::Int(1))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Split a sum into its terms, each term is negated or not
+fn terms(expr: &Expr, negated: bool, result: &mut Vec<(bool, Expr)>) {
+    match expr {
+        Expr::Arithmetic(ArithmeticOperator::Add, x, y) => {
+            terms(x, negated, result);
+            terms(y, negated, result);
+        }
+        Expr::Arithmetic(ArithmeticOperator::Subtract, x, y) => {
+            terms(x, negated, result);
+            terms(y, !negated, result);
+        }
+        Expr::Prefix(PrefixOperator::Minus, x) if !is_number(expr) => terms(x, !negated, result),
+        e => result.push((negated, e.clone())),
+    }
+}
+
+fn sum(terms: Vec<(bool, Expr)>) -> Expr {
+    let mut terms = terms.into_iter();
+    let first = match terms.next() {
+        Some((true, e)) => negate(e),
+        Some((false, e)) => e,
+        None => return Expr::Int(0),
+    };
+    terms.fold(first, |total, (negated, e)| {
+        let o = if negated {
+            ArithmeticOperator::Subtract
+        } else {
+            ArithmeticOperator::Add
+        };
+        arithmetic(o, total, e)
+    })
+}
+
+/// Split a product into its factors
+fn factors(expr: &Expr, result: &mut Vec<Expr>) {
+    match expr {
+        Expr::Arithmetic(ArithmeticOperator::Multiply, x, y) => {
+            factors(x, result);
+            factors(y, result);
+        }
+        e => result.push(e.clone()),
+    }
+}
+
+fn product(factors: Vec<Expr>) -> Expr {
+    let mut factors = factors.into_iter();
+    let first = factors.next().unwrap_or(Expr::Int(1));
+    factors.fold(first, |total, e| {
+        arithmetic(ArithmeticOperator::Multiply, total, e)
+    })
+}
+
+fn collect_terms(expr: &Expr) -> Option<Expr> {
+    if !matches!(
+        op(expr)?.0,
+        ArithmeticOperator::Add | ArithmeticOperator::Subtract
+    ) {
+        return None;
+    }
+    let mut all_terms = Vec::new();
+    terms(expr, false, &mut all_terms);
+    // Each term is a coefficient times the rest of the term
+    let mut collected: Vec<(isize, Expr)> = Vec::new();
+    for (negated, term) in all_terms.iter() {
+        let (coefficient, rest) = match op(term) {
+            Some((ArithmeticOperator::Multiply, n, rest))
+                if as_int(n).is_some() && !is_number(rest) =>
+            {
+                (as_int(n).unwrap(), rest.clone())
+            }
+            _ => (1, term.clone()),
+        };
+        let coefficient = if *negated { -coefficient } else { coefficient };
+        match collected.iter_mut().find(|(_, r)| r == &rest) {
+            Some((c, _)) => *c = c.checked_add(coefficient)?,
+            None => collected.push((coefficient, rest)),
+        }
+    }
+    if collected.len() == all_terms.len() {
+        return None;
+    }
+    Some(sum(collected
+        .into_iter()
+        .filter(|(c, _)| *c != 0)
+        .map(|(c, rest)| {
+            let term = if c.abs() == 1 {
+                rest
+            } else {
+                arithmetic(ArithmeticOperator::Multiply, Expr::Int(c.abs()), rest)
+            };
+            (c < 0, term)
+        })
+        .collect()))
+}
+
+fn collect_factors(expr: &Expr) -> Option<Expr> {
+    if op(expr)?.0 != ArithmeticOperator::Multiply {
+        return None;
+    }
+    let mut all_factors = Vec::new();
+    factors(expr, &mut all_factors);
+    let mut collected: Vec<(Expr, Vec<Expr>)> = Vec::new();
+    for factor in all_factors.iter().filter(|f| !is_number(f)) {
+        let (base, exponent) = match op(factor) {
+            Some((ArithmeticOperator::Power, base, exponent)) => (base.clone(), exponent.clone()),
+            _ => (factor.clone(), Expr::Int(1)),
+        };
+        match collected.iter_mut().find(|(b, _)| b == &base) {
+            Some((_, exponents)) => exponents.push(exponent),
+            None => collected.push((base, vec![exponent])),
+        }
+    }
+    if collected.iter().all(|(_, exponents)| exponents.len() == 1) {
+        return None;
+    }
+    let numbers = all_factors.iter().filter(|f| is_number(f)).cloned();
+    Some(product(
+        numbers
+            .chain(collected.into_iter().map(|(base, exponents)| {
+                let exponent = match exponents.iter().map(as_int).sum::<Option<isize>>() {
+                    Some(n) => int(n),
+                    None => sum(exponents.into_iter().map(|e| (false, e)).collect()),
+                };
+                arithmetic(ArithmeticOperator::Power, base, exponent)
+            }))
+            .collect(),
+    ))
+}
+
+/// Sort the terms of sums and the factors of products
+///
+/// Numbers are put at the end of sums and at the start of products.
+fn canonical_order(expr: &Expr) -> Option<Expr> {
+    let key = |e: &Expr| (is_number(e), e.to_string());
+    match op(expr)?.0 {
+        ArithmeticOperator::Add | ArithmeticOperator::Subtract => {
+            let mut all_terms = Vec::new();
+            terms(expr, false, &mut all_terms);
+            let mut sorted = all_terms.clone();
+            sorted.sort_by_key(|(_, e)| key(e));
+            if sorted == all_terms {
+                None
+            } else {
+                Some(sum(sorted))
+            }
+        }
+        ArithmeticOperator::Multiply => {
+            let mut all_factors = Vec::new();
+            factors(expr, &mut all_factors);
+            let mut sorted = all_factors.clone();
+            sorted.sort_by_key(|e| {
+                let (number, s) = key(e);
+                (!number, s)
+            });
+            if sorted == all_factors {
+                None
+            } else {
+                Some(product(sorted))
+            }
+        }
+        _ => None,
+    }
+}
+
+fn op(expr: &Expr) -> Option<(ArithmeticOperator, &Expr, &Expr)> {
+    match expr {
+        Expr::Arithmetic(o, x, y) => Some((*o, x, y)),
+        _ => None,
+    }
+}
+
+fn sqrt_argument(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::FunctionApplication(name, arguments) if name.name().eq_ignore_ascii_case("sqrt") => {
+            match &arguments[..] {
+                [argument] => Some(argument),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The value of an integer literal, possibly negated
+fn as_int(expr: &Expr) -> Option<isize> {
+    match expr {
+        Expr::Int(i) => Some(*i),
+        Expr::Prefix(PrefixOperator::Minus, x) => match **x {
+            Expr::Int(i) => Some(-i),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_number(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(_) | Expr::Float(..) => true,
+        Expr::Prefix(PrefixOperator::Minus, x) => matches!(**x, Expr::Int(_) | Expr::Float(..)),
+        _ => false,
+    }
+}
+
+/// An integer literal, negative numbers are written with a prefix minus like the parser does
+fn int(i: isize) -> Expr {
+    if i < 0 {
+        negate(Expr::Int(-i))
+    } else {
+        Expr::Int(i)
+    }
+}
+
+fn negate(expr: Expr) -> Expr {
+    Expr::Prefix(PrefixOperator::Minus, Box::new(expr))
+}
+
+fn arithmetic(o: ArithmeticOperator, x: Expr, y: Expr) -> Expr {
+    Expr::Arithmetic(o, Box::new(x), Box::new(y))
+}
+
+fn function(name: &str, arguments: Vec<Expr>) -> Expr {
+    Expr::FunctionApplication(Ident::from(name.to_string()), arguments)
+}
+
+fn gcd(a: isize, b: isize) -> isize {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jme::parser::{consume_one_expression, parse_as_jme};
+    use std::convert::TryInto;
+
+    fn parse(input: &str) -> Expr {
+        consume_one_expression(parse_as_jme(input).unwrap()).unwrap()
+    }
+
+    fn rules(items: &[&str]) -> RuleSet {
+        let rules: Vec<AnswerSimplificationRule> =
+            items.iter().map(|i| (*i).try_into().unwrap()).collect();
+        RuleSet::from(&rules[..])
+    }
+
+    #[test]
+    fn simplify() {
+        let all = rules(&["all"]);
+        for (input, expected) in vec![
+            ("1*x + 0", "x"),
+            ("x^1 + y^0", "x + 1"),
+            ("2 + 3*4", "14"),
+            ("x + -y", "x - y"),
+            ("-(-x)", "x"),
+            ("6/4", "3 / 2"),
+            ("x*2", "2 * x"),
+            ("x + x + 2x", "4 * x"),
+            ("x*x^2", "x^3"),
+            ("sqrt(x)*sqrt(y)", "sqrt(x * y)"),
+            ("sqrt(16) + sqrt(x^2)", "4 + x"),
+            ("sin(x)^2 + cos(x)^2", "1"),
+            ("2^10", "1024"),
+            ("a/c + b/c", "(a + b) / c"),
+            ("-x + y", "y - x"),
+            ("not true", "false"),
+        ] {
+            assert_eq!(
+                all.simplify(&parse(input)).unwrap().to_string(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rules_are_handled_in_order() {
+        let set = rules(&["all", "!collectNumbers", "expandBrackets"]);
+        assert!(!set.contains(&AnswerSimplificationRule::CollectNumbers(true)));
+        assert!(set.contains(&AnswerSimplificationRule::ExpandBrackets(true)));
+        assert!(!set.contains(&AnswerSimplificationRule::CanonicalOrder(true)));
+        assert_eq!(
+            set.simplify(&parse("(x+1)*y")).unwrap().to_string(),
+            "x * y + y"
+        );
+        assert_eq!(
+            rules(&["canonicalOrder"])
+                .simplify(&parse("y*x + 1 + b"))
+                .unwrap()
+                .to_string(),
+            "b + x * y + 1"
+        );
+    }
+
+    #[test]
+    fn looping_rules() {
+        let set = rules(&["all", "canonicalOrder"]);
+        assert_eq!(
+            set.looping_rules(),
+            vec![(
+                AnswerSimplificationRule::CanonicalOrder(true),
+                AnswerSimplificationRule::NoLeadingMinus(true)
+            )]
+        );
+        assert!(matches!(
+            set.simplify(&parse("-x + y")),
+            Err(SimplificationError::Loop(_))
+        ));
+        assert!(rules(&["all"]).looping_rules().is_empty());
+    }
+}
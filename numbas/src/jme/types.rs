@@ -0,0 +1,868 @@
+//! Contains the inference of the data types of JME expressions
+//!
+//! The inference is lenient: if the type of an expression can't be determined (e.g. because it
+//! uses an unknown variable), it is None and no errors are reported for it.
+
+use crate::jme::ast::{
+    check_function_call, ArithmeticOperator, Expr, FunctionCallError, Ident, PrefixOperator,
+    RangeOperator, RelationalOperator,
+};
+use crate::jme::builtin_functions::{BuiltinFunctions, ParameterType};
+use crate::question::function::FunctionType;
+use std::collections::HashMap;
+
+/// The functions whose arguments can use names that are bound by other arguments
+const BINDING_FUNCTIONS: &[&str] = &[
+    "map",
+    "filter",
+    "foldl",
+    "iterate",
+    "iterate_until",
+    "take",
+    "let",
+    "satisfy",
+    "try",
+];
+
+/// The data type of a JME value
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum JMEType {
+    Number,
+    Integer,
+    Rational,
+    Decimal,
+    String,
+    Boolean,
+    List,
+    Dict,
+    Range,
+    Matrix,
+    Vector,
+    Set,
+    Expression,
+    Html,
+    Nothing,
+}
+
+impl JMEType {
+    /// The name of the type, as used by the `isa` and `as` operators
+    pub fn name(&self) -> &'static str {
+        match self {
+            JMEType::Number => "number",
+            JMEType::Integer => "integer",
+            JMEType::Rational => "rational",
+            JMEType::Decimal => "decimal",
+            JMEType::String => "string",
+            JMEType::Boolean => "boolean",
+            JMEType::List => "list",
+            JMEType::Dict => "dict",
+            JMEType::Range => "range",
+            JMEType::Matrix => "matrix",
+            JMEType::Vector => "vector",
+            JMEType::Set => "set",
+            JMEType::Expression => "expression",
+            JMEType::Html => "html",
+            JMEType::Nothing => "nothing",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match &name.to_lowercase()[..] {
+            "number" => JMEType::Number,
+            "integer" => JMEType::Integer,
+            "rational" => JMEType::Rational,
+            "decimal" => JMEType::Decimal,
+            "string" => JMEType::String,
+            "boolean" => JMEType::Boolean,
+            "list" => JMEType::List,
+            "dict" => JMEType::Dict,
+            "range" => JMEType::Range,
+            "matrix" => JMEType::Matrix,
+            "vector" => JMEType::Vector,
+            "set" => JMEType::Set,
+            "expression" => JMEType::Expression,
+            "html" => JMEType::Html,
+            "nothing" => JMEType::Nothing,
+            _ => return None,
+        })
+    }
+
+    /// The type of the values of a custom function parameter or output
+    pub fn from_function_type(t: &FunctionType) -> Option<Self> {
+        match t {
+            FunctionType::Boolean => Some(JMEType::Boolean),
+            FunctionType::Decimal => Some(JMEType::Decimal),
+            FunctionType::Dictionary => Some(JMEType::Dict),
+            FunctionType::Expression => Some(JMEType::Expression),
+            FunctionType::HTML => Some(JMEType::Html),
+            FunctionType::Integer => Some(JMEType::Integer),
+            FunctionType::List => Some(JMEType::List),
+            FunctionType::Matrix => Some(JMEType::Matrix),
+            FunctionType::Nothing => Some(JMEType::Nothing),
+            FunctionType::Number => Some(JMEType::Number),
+            FunctionType::Range => Some(JMEType::Range),
+            FunctionType::Rational => Some(JMEType::Rational),
+            FunctionType::Set => Some(JMEType::Set),
+            FunctionType::r#String => Some(JMEType::String),
+            FunctionType::Vector => Some(JMEType::Vector),
+            FunctionType::KeyPair | FunctionType::ExtensionGeogebraApplet => None,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            JMEType::Number | JMEType::Integer | JMEType::Rational | JMEType::Decimal
+        )
+    }
+
+    /// Whether values of these types can be compared with each other
+    fn is_comparable_with(&self, other: &JMEType) -> bool {
+        let group = |t: &JMEType| match t {
+            t if t.is_numeric() => 0,
+            JMEType::String | JMEType::Html => 1,
+            JMEType::List | JMEType::Range | JMEType::Set => 2,
+            _ => 3,
+        };
+        // Any value can be compared with nothing, e.g. to check whether it is set
+        self == other
+            || self == &JMEType::Nothing
+            || other == &JMEType::Nothing
+            || (group(self) == group(other) && group(self) != 3)
+    }
+}
+
+impl std::fmt::Display for JMEType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The type of the values of a numeric operation on two numeric types
+fn numeric_result(op: ArithmeticOperator, a: JMEType, b: JMEType) -> JMEType {
+    match (op, a, b) {
+        (_, JMEType::Decimal, _) | (_, _, JMEType::Decimal) => JMEType::Decimal,
+        (_, JMEType::Number, _) | (_, _, JMEType::Number) => JMEType::Number,
+        (ArithmeticOperator::Power, _, _) => JMEType::Number,
+        (ArithmeticOperator::Divide, _, _) => JMEType::Rational,
+        (_, JMEType::Integer, JMEType::Integer) => JMEType::Integer,
+        _ => JMEType::Rational,
+    }
+}
+
+/// The common type of two values, e.g. of the branches of an `if`
+fn common_type(a: Option<JMEType>, b: Option<JMEType>) -> Option<JMEType> {
+    match (a?, b?) {
+        (a, b) if a == b => Some(a),
+        (a, b) if a.is_numeric() && b.is_numeric() => {
+            Some(numeric_result(ArithmeticOperator::Add, a, b))
+        }
+        _ => None,
+    }
+}
+
+/// The type of a custom function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionSignature {
+    pub parameters: Vec<Option<JMEType>>,
+    pub output: Option<JMEType>,
+}
+
+/// The known types of the variables and the custom functions
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TypeEnvironment {
+    variables: HashMap<String, JMEType>,
+    functions: HashMap<String, FunctionSignature>,
+}
+
+impl TypeEnvironment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set_variable(&mut self, name: &str, t: JMEType) {
+        self.variables.insert(name.to_lowercase(), t);
+    }
+    /// Forget the type of a variable, e.g. because it is shadowed by a parameter of unknown type
+    pub fn remove_variable(&mut self, name: &str) {
+        self.variables.remove(&name.to_lowercase());
+    }
+    pub fn variable(&self, name: &str) -> Option<JMEType> {
+        self.variables.get(&name.to_lowercase()).copied()
+    }
+    pub fn add_function(&mut self, name: &str, signature: FunctionSignature) {
+        self.functions.insert(name.to_lowercase(), signature);
+    }
+    pub fn function(&self, name: &str) -> Option<&FunctionSignature> {
+        self.functions.get(&name.to_lowercase())
+    }
+
+    /// The environment in which the given names are not known anymore
+    fn without(&self, names: &[String]) -> Self {
+        let mut result = self.clone();
+        for name in names.iter() {
+            result.variables.remove(name);
+        }
+        result
+    }
+}
+
+/// A part of an expression that will fail or that doesn't do what was intended
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TypeError {
+    /// Two values are compared that can't be compared (or are never equal)
+    Comparison {
+        operator: RelationalOperator,
+        lhs: JMEType,
+        rhs: JMEType,
+    },
+    /// A builtin function is called with an argument of the wrong type
+    FunctionCall(FunctionCallError),
+    /// A custom function is called with an argument of the wrong type
+    CustomFunctionArgument {
+        function: Ident,
+        /// The position of the argument, starting at zero
+        position: usize,
+        expected: JMEType,
+        found: JMEType,
+    },
+    /// A custom function is called with the wrong amount of arguments
+    CustomFunctionArity {
+        function: Ident,
+        count: usize,
+        expected: usize,
+    },
+    /// The expression should have an other type, e.g. the answer of a number entry part
+    UnexpectedType {
+        expected: ParameterType,
+        found: JMEType,
+    },
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypeError::Comparison { operator, lhs, rhs } => write!(
+                f,
+                "A {} is compared with a {} using {}",
+                lhs,
+                rhs,
+                operator.symbol().trim()
+            ),
+            TypeError::FunctionCall(e) => write!(f, "{}", e),
+            TypeError::CustomFunctionArgument {
+                function,
+                position,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Argument {} of {} should be a {}, but is a {}",
+                position + 1,
+                function,
+                expected,
+                found
+            ),
+            TypeError::CustomFunctionArity {
+                function,
+                count,
+                expected,
+            } => write!(
+                f,
+                "{} is called with {} arguments, but expects {}",
+                function, count, expected
+            ),
+            TypeError::UnexpectedType { expected, found } => {
+                write!(
+                    f,
+                    "Expected a {}, but the expression is a {}",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// The type of the value of this expression, None if it can't be determined
+    pub fn infer_type(&self, env: &TypeEnvironment) -> Option<JMEType> {
+        match self {
+            Expr::Str(_) => Some(JMEType::String),
+            Expr::Int(_) => Some(JMEType::Integer),
+            Expr::Float(_, _) => Some(JMEType::Number),
+            Expr::Bool(_) => Some(JMEType::Boolean),
+            Expr::List(_) => Some(JMEType::List),
+            Expr::Dictionary(_) => Some(JMEType::Dict),
+            Expr::Range(_, _, _) => Some(JMEType::Range),
+            Expr::Ident(ident) => match env.variable(ident.name()) {
+                Some(t) => Some(t),
+                None if ident.name().eq_ignore_ascii_case("nothing") => Some(JMEType::Nothing),
+                None => None,
+            },
+            Expr::Constant(_) => Some(JMEType::Number),
+            Expr::Prefix(PrefixOperator::Not, _) => Some(JMEType::Boolean),
+            Expr::Prefix(PrefixOperator::Minus, e) => e
+                .infer_type(env)
+                .filter(|t| t.is_numeric() || matches!(t, JMEType::Vector | JMEType::Matrix)),
+            Expr::Faculty(e) => match e.infer_type(env)? {
+                JMEType::Integer => Some(JMEType::Integer),
+                t if t.is_numeric() => Some(JMEType::Number),
+                _ => None,
+            },
+            Expr::Arithmetic(op, a, b) => {
+                infer_arithmetic(*op, a.infer_type(env), b.infer_type(env))
+            }
+            Expr::Relation(_, _, _) | Expr::Logic(_, _, _) => Some(JMEType::Boolean),
+            Expr::FunctionApplication(ident, args) => infer_function(ident, args, env),
+            Expr::Indexation(e, index) => {
+                let is_slice = matches!(**index, Expr::Range(_, _, _) | Expr::List(_))
+                    || index.infer_type(env) == Some(JMEType::Range);
+                match (e.infer_type(env)?, is_slice) {
+                    (JMEType::String, _) => Some(JMEType::String),
+                    (JMEType::List, true) => Some(JMEType::List),
+                    (JMEType::Vector, true) => Some(JMEType::Vector),
+                    (JMEType::Vector, false) => Some(JMEType::Number),
+                    (JMEType::Matrix, true) => Some(JMEType::Matrix),
+                    (JMEType::Matrix, false) => Some(JMEType::Vector),
+                    (JMEType::Range, false) => Some(JMEType::Number),
+                    _ => None,
+                }
+            }
+            Expr::Cast(_, t) => match &**t {
+                Expr::Str(name) => JMEType::from_name(name),
+                _ => None,
+            },
+            Expr::Sequence(_, e) => e.infer_type(env),
+        }
+    }
+
+    /// The type errors in this expression, and all of its subexpressions
+    pub fn type_errors(&self, env: &TypeEnvironment) -> Vec<TypeError> {
+        let mut result = Vec::new();
+        self.collect_type_errors(env, &mut result);
+        result
+    }
+
+    /// The type errors in this expression, including an error if its type is not the expected
+    /// one
+    pub fn type_errors_expecting(
+        &self,
+        env: &TypeEnvironment,
+        expected: ParameterType,
+    ) -> Vec<TypeError> {
+        let mut result = self.type_errors(env);
+        if let Some(found) = self.infer_type(env) {
+            if !expected.accepts(found.name()) {
+                result.push(TypeError::UnexpectedType { expected, found });
+            }
+        }
+        result
+    }
+
+    fn collect_type_errors(&self, env: &TypeEnvironment, result: &mut Vec<TypeError>) {
+        match self {
+            Expr::Str(_)
+            | Expr::Int(_)
+            | Expr::Float(_, _)
+            | Expr::Bool(_)
+            | Expr::Ident(_)
+            | Expr::Constant(_) => (),
+            Expr::Relation(op, a, b) => {
+                if let (Some(lhs), Some(rhs)) = (a.infer_type(env), b.infer_type(env)) {
+                    let is_comparison = !matches!(
+                        op,
+                        RelationalOperator::In
+                            | RelationalOperator::IsA
+                            | RelationalOperator::Divides
+                    );
+                    if is_comparison && !lhs.is_comparable_with(&rhs) {
+                        result.push(TypeError::Comparison {
+                            operator: *op,
+                            lhs,
+                            rhs,
+                        });
+                    }
+                }
+                a.collect_type_errors(env, result);
+                b.collect_type_errors(env, result);
+            }
+            Expr::FunctionApplication(ident, args) => {
+                let name = ident.name().to_lowercase();
+                let env = if BINDING_FUNCTIONS.contains(&&name[..]) {
+                    env.without(&bound_names(args))
+                } else {
+                    env.clone()
+                };
+                if let Some(error) = check_call(ident, args, &env) {
+                    result.push(error);
+                }
+                for arg in args.iter() {
+                    arg.collect_type_errors(&env, result);
+                }
+            }
+            Expr::List(es) => {
+                for e in es.iter() {
+                    e.collect_type_errors(env, result)
+                }
+            }
+            Expr::Dictionary(es) => {
+                for (k, v) in es.iter() {
+                    k.collect_type_errors(env, result);
+                    v.collect_type_errors(env, result);
+                }
+            }
+            Expr::Prefix(_, e1) | Expr::Faculty(e1) => e1.collect_type_errors(env, result),
+            Expr::Range(_, e1, e2)
+            | Expr::Arithmetic(_, e1, e2)
+            | Expr::Logic(_, e1, e2)
+            | Expr::Indexation(e1, e2)
+            | Expr::Cast(e1, e2)
+            | Expr::Sequence(e1, e2) => {
+                e1.collect_type_errors(env, result);
+                e2.collect_type_errors(env, result);
+            }
+        }
+    }
+}
+
+/// The names that can be bound by the arguments of a function like `map` or `let`
+fn bound_names(args: &[Expr]) -> Vec<String> {
+    args.iter()
+        .flat_map(|arg| match arg {
+            Expr::Dictionary(items) => items
+                .iter()
+                .filter_map(|(k, _)| match k {
+                    Expr::Str(name) => Some(name.to_lowercase()),
+                    _ => None,
+                })
+                .collect(),
+            arg => arg.bound_names().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Check the arguments of a function call against the signatures of the function
+///
+/// Mismatches of literal arguments are not reported, see `Expr::invalid_function_calls`.
+fn check_call(ident: &Ident, args: &[Expr], env: &TypeEnvironment) -> Option<TypeError> {
+    let types: Vec<_> = args.iter().map(|a| a.infer_type(env)).collect();
+    if let Some(function) = env.function(ident.name()) {
+        if function.parameters.len() != args.len() {
+            return Some(TypeError::CustomFunctionArity {
+                function: ident.clone(),
+                count: args.len(),
+                expected: function.parameters.len(),
+            });
+        }
+        return function
+            .parameters
+            .iter()
+            .zip(types.iter())
+            .enumerate()
+            .find_map(|(position, types)| match types {
+                (Some(expected), Some(found))
+                    if !ParameterType::from(*expected).accepts(found.name()) =>
+                {
+                    Some(TypeError::CustomFunctionArgument {
+                        function: ident.clone(),
+                        position,
+                        expected: *expected,
+                        found: *found,
+                    })
+                }
+                _ => None,
+            });
+    }
+    let type_names: Vec<_> = types.iter().map(|t| t.map(|t| t.name())).collect();
+    match check_function_call(ident, &type_names)? {
+        FunctionCallError::InvalidArgumentType { position, .. }
+            if args[position].literal_type().is_some() =>
+        {
+            None
+        }
+        FunctionCallError::WrongNumberOfArguments { .. } => None,
+        error => Some(TypeError::FunctionCall(error)),
+    }
+}
+
+impl std::convert::From<JMEType> for ParameterType {
+    /// The parameter type that accepts the values of the given type
+    fn from(t: JMEType) -> Self {
+        match t {
+            JMEType::Number | JMEType::Integer | JMEType::Rational | JMEType::Decimal => {
+                ParameterType::Number
+            }
+            JMEType::String => ParameterType::String,
+            JMEType::Boolean => ParameterType::Boolean,
+            JMEType::List => ParameterType::List,
+            JMEType::Dict => ParameterType::Dict,
+            JMEType::Range => ParameterType::Range,
+            JMEType::Matrix => ParameterType::Matrix,
+            JMEType::Vector => ParameterType::Vector,
+            JMEType::Html => ParameterType::Html,
+            JMEType::Set | JMEType::Expression | JMEType::Nothing => ParameterType::Anything,
+        }
+    }
+}
+
+fn infer_arithmetic(
+    op: ArithmeticOperator,
+    a: Option<JMEType>,
+    b: Option<JMEType>,
+) -> Option<JMEType> {
+    use ArithmeticOperator as O;
+    use JMEType as T;
+    if op == O::Except {
+        return Some(T::List);
+    }
+    match (op, a, b) {
+        (_, Some(a), Some(b)) if a.is_numeric() && b.is_numeric() => Some(numeric_result(op, a, b)),
+        (O::Add, Some(T::String), _) | (O::Add, _, Some(T::String)) => Some(T::String),
+        (O::Add, Some(T::List), _) | (O::Add, _, Some(T::List)) => Some(T::List),
+        (O::Add, Some(T::Dict), Some(T::Dict)) => Some(T::Dict),
+        (O::Add | O::Subtract, Some(T::Vector), Some(T::Vector)) => Some(T::Vector),
+        (O::Add | O::Subtract, Some(T::Matrix), Some(T::Matrix)) => Some(T::Matrix),
+        (O::Add | O::Subtract, Some(T::Set), Some(T::Set)) => Some(T::Set),
+        (O::Multiply, Some(n), Some(T::Vector)) if n.is_numeric() => Some(T::Vector),
+        (O::Multiply | O::Divide, Some(T::Vector), Some(n)) if n.is_numeric() => Some(T::Vector),
+        (O::Multiply, Some(n), Some(T::Matrix)) if n.is_numeric() => Some(T::Matrix),
+        (O::Multiply | O::Divide | O::Power, Some(T::Matrix), Some(n)) if n.is_numeric() => {
+            Some(T::Matrix)
+        }
+        (O::Multiply, Some(T::Matrix), Some(T::Matrix)) => Some(T::Matrix),
+        (O::Multiply, Some(T::Matrix), Some(T::Vector)) => Some(T::Vector),
+        _ => None,
+    }
+}
+
+fn infer_function(ident: &Ident, args: &[Expr], env: &TypeEnvironment) -> Option<JMEType> {
+    if let Some(function) = env.function(ident.name()) {
+        return function.output;
+    }
+    let function = BuiltinFunctions::get(ident.name())
+        .or_else(|| BuiltinFunctions::get(&ident.name().to_lowercase()[..]))?;
+    let name = ident.name().to_lowercase();
+    let env = if BINDING_FUNCTIONS.contains(&&name[..]) {
+        env.without(&bound_names(args))
+    } else {
+        env.clone()
+    };
+    let arg = |i: usize| args.get(i).and_then(|a| a.infer_type(&env));
+    use BuiltinFunctions as F;
+    use JMEType as T;
+    match function {
+        F::Exp
+        | F::Ln
+        | F::Log
+        | F::Sqrt
+        | F::Root
+        | F::Arg
+        | F::Re
+        | F::Im
+        | F::Conj
+        | F::Degrees
+        | F::Radians
+        | F::Sign
+        | F::Sin
+        | F::Cos
+        | F::Tan
+        | F::Cosec
+        | F::Sec
+        | F::Cot
+        | F::ArcSin
+        | F::ArcCos
+        | F::ArcTan
+        | F::ATan2
+        | F::Sinh
+        | F::Cosh
+        | F::Tanh
+        | F::Cosech
+        | F::Sech
+        | F::Coth
+        | F::ArcSinh
+        | F::ArcCosh
+        | F::ArcTanh
+        | F::Gamma
+        | F::Fract
+        | F::Clamp
+        | F::ToNearest
+        | F::ParseNumber
+        | F::ParseNumberOrFraction
+        | F::Angle
+        | F::Det
+        | F::SumCells
+        | F::Dot => Some(T::Number),
+        F::Int
+        | F::Fact
+        | F::Comb
+        | F::Perm
+        | F::GCD
+        | F::GCDWithoutPIorI
+        | F::LCM
+        | F::CountDP
+        | F::CountSigFigs
+        | F::NumRows
+        | F::NumColumns
+        | F::FromBinary
+        | F::FromOctal
+        | F::FromHexadecimal
+        | F::FromBase
+        | F::CanonicalCompare => Some(T::Integer),
+        F::Rational | F::RationalApproximation => Some(T::Rational),
+        F::Decimal | F::ParseDecimal | F::ParseDecimalOrFraction => Some(T::Decimal),
+        F::IsInt
+        | F::IsZero
+        | F::IsNan
+        | F::WithIntolerance
+        | F::ToGivenPrecision
+        | F::CoPrime
+        | F::IsZeroVector
+        | F::IsScalarMultiple
+        | F::IsBool
+        | F::IsClose
+        | F::ResultsEqual
+        | F::All
+        | F::Some
+        | F::IsNonEmptyHTML
+        | F::IsSet
+        | F::Matches
+        | F::NumericalCompare => Some(T::Boolean),
+        F::DPFormat
+        | F::SigFormat
+        | F::FormatNumber
+        | F::ScientificNumberLaTeX
+        | F::CleanNumber
+        | F::ToBinary
+        | F::ToOctal
+        | F::ToHexadecimal
+        | F::ToBase
+        | F::String
+        | F::LaTeX
+        | F::Safe
+        | F::Render
+        | F::Capitalise
+        | F::Pluralise
+        | F::Upper
+        | F::Lower
+        | F::Join
+        | F::ReplaceRegex
+        | F::Trim
+        | F::Currency
+        | F::SeparateThousands
+        | F::LPad
+        | F::RPad
+        | F::FormatString
+        | F::LetterOrdinal
+        | F::Translate
+        | F::JsonEncode
+        | F::Type
+        | F::InferType => Some(T::String),
+        F::Parse | F::Simplify | F::Substitute | F::ExpandJuxtapositions | F::Diff => {
+            Some(T::Expression)
+        }
+        F::ScientificNumberHTML | F::HTML | F::Table | F::Image | F::MaxWidth | F::MaxHeight => {
+            Some(T::Html)
+        }
+        F::Divisors
+        | F::ProperDivisors
+        | F::Factorise
+        | F::Split
+        | F::MatchRegex
+        | F::SplitRegex
+        | F::MatchNumber
+        | F::Repeat
+        | F::Map
+        | F::Filter
+        | F::Iterate
+        | F::IterateUntil
+        | F::Take
+        | F::Flatten
+        | F::Sort
+        | F::SortDestinations
+        | F::SortBy
+        | F::GroupBy
+        | F::Reverse
+        | F::Indices
+        | F::Distinct
+        | F::List
+        | F::Zip
+        | F::Combinations
+        | F::CombinationsWithReplacement
+        | F::Permutations
+        | F::Product
+        | F::Enumerate
+        | F::Keys
+        | F::Values
+        | F::Items
+        | F::Frequencies
+        | F::Deal
+        | F::Reorder
+        | F::Shuffle
+        | F::ShuffleTogether
+        | F::DefinedVariables
+        | F::Args
+        | F::FindVars => Some(T::List),
+        F::Vector | F::Cross => Some(T::Vector),
+        F::Matrix
+        | F::Id
+        | F::RowVector
+        | F::Transpose
+        | F::Augment
+        | F::Stack
+        | F::CombineDiagonally => Some(T::Matrix),
+        F::Dict | F::MakeVariables | F::InferVariableTypes => Some(T::Dict),
+        F::Set => Some(T::Set),
+        F::Union | F::Intersection => common_type(arg(0), arg(1)),
+        F::Abs => match arg(0)? {
+            T::String | T::List | T::Range | T::Set | T::Dict => Some(T::Integer),
+            T::Vector => Some(T::Number),
+            t if t.is_numeric() => Some(t),
+            _ => None,
+        },
+        F::Ceil | F::Floor | F::Round | F::Trunc => match arg(0)? {
+            T::Vector => Some(T::Vector),
+            T::Matrix => Some(T::Matrix),
+            T::Decimal => Some(T::Decimal),
+            t if t.is_numeric() => Some(T::Integer),
+            _ => None,
+        },
+        F::PrecRound | F::SigRound => match arg(0)? {
+            T::Vector => Some(T::Vector),
+            T::Matrix => Some(T::Matrix),
+            T::Decimal => Some(T::Decimal),
+            t if t.is_numeric() => Some(T::Number),
+            _ => None,
+        },
+        F::Mod => common_type(arg(0), arg(1)),
+        F::Max | F::Min if args.len() > 1 => args
+            .iter()
+            .map(|a| a.infer_type(&env))
+            .reduce(common_type)
+            .flatten(),
+        F::Sum | F::Prod => match arg(0)? {
+            T::Vector => Some(T::Number),
+            _ => None,
+        },
+        F::If => common_type(arg(1), arg(2)),
+        F::Assert => arg(1),
+        F::Random if args.len() > 1 => args
+            .iter()
+            .map(|a| a.infer_type(&env))
+            .reduce(common_type)
+            .flatten(),
+        F::Random => match args {
+            [Expr::Range(RangeOperator::Create, _, _)] => Some(T::Integer),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jme::parser::{consume_one_expression, parse_as_jme};
+
+    fn parse(input: &str) -> Expr {
+        consume_one_expression(parse_as_jme(input).unwrap()).unwrap()
+    }
+
+    fn environment() -> TypeEnvironment {
+        let mut env = TypeEnvironment::new();
+        env.set_variable("n", JMEType::Integer);
+        env.set_variable("l", JMEType::List);
+        env.set_variable("s", JMEType::String);
+        env.set_variable("v", JMEType::Vector);
+        env.add_function(
+            "double",
+            FunctionSignature {
+                parameters: vec![Some(JMEType::Number)],
+                output: Some(JMEType::Number),
+            },
+        );
+        env
+    }
+
+    #[test]
+    fn infer_type() {
+        let env = environment();
+        for (input, expected) in vec![
+            ("1 + 2", Some(JMEType::Integer)),
+            ("1 / 2", Some(JMEType::Rational)),
+            ("1.5 * n", Some(JMEType::Number)),
+            ("n^2", Some(JMEType::Number)),
+            ("\"a\" + n", Some(JMEType::String)),
+            ("l + [1]", Some(JMEType::List)),
+            ("2 * v", Some(JMEType::Vector)),
+            ("v[0]", Some(JMEType::Number)),
+            ("s[1..2]", Some(JMEType::String)),
+            ("n < 5", Some(JMEType::Boolean)),
+            ("precround(n / 3, 2)", Some(JMEType::Number)),
+            ("random(1..6)", Some(JMEType::Integer)),
+            ("random(l)", None),
+            ("if(n > 0, 1, 2.5)", Some(JMEType::Number)),
+            ("double(n)", Some(JMEType::Number)),
+            ("map(x^2, x, l)", Some(JMEType::List)),
+            ("matrix([1, 2]) * v", Some(JMEType::Vector)),
+            ("x as \"decimal\"", Some(JMEType::Decimal)),
+            ("unknown + 1", None),
+            ("len(l)", Some(JMEType::Integer)),
+        ] {
+            assert_eq!(parse(input).infer_type(&env), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn type_errors() {
+        let env = environment();
+        let errors = |input: &str| {
+            parse(input)
+                .type_errors(&env)
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("s = 1.5"),
+            vec!["A string is compared with a number using ="]
+        );
+        assert_eq!(
+            errors("if(l < 2.5, 1, 2)"),
+            vec!["A list is compared with a number using <"]
+        );
+        assert_eq!(
+            errors("sqrt(s)"),
+            vec!["Argument 1 of sqrt should be a number, but is a string"]
+        );
+        assert_eq!(
+            errors("double(l)"),
+            vec!["Argument 1 of double should be a number, but is a list"]
+        );
+        assert_eq!(
+            errors("double(1, 2)"),
+            vec!["double is called with 2 arguments, but expects 1"]
+        );
+        // Literal arguments are reported by invalid_function_calls
+        assert_eq!(errors("sqrt(\"4\")"), Vec::<String>::new());
+        // The bound names shadow the variables
+        assert_eq!(errors("map(s < 2, s, [1, 2])"), Vec::<String>::new());
+        assert_eq!(errors("n = 1.5 and s = \"a\""), Vec::<String>::new());
+        assert_eq!(errors("s = nothing or nothing <> l"), Vec::<String>::new());
+        // Numbas converts between lists, vectors and matrices
+        assert_eq!(
+            errors("max(v) + cross(matrix([1], [2], [3]), v)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors("ceil(v) + floor(matrix([1.5]))"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            parse("l")
+                .type_errors_expecting(&env, ParameterType::Number)
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>(),
+            vec!["Expected a number, but the expression is a list"]
+        );
+    }
+}
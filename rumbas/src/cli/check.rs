@@ -1,10 +1,13 @@
+use crate::cli::report::{deduplicate, FileReport, OutputFormat};
 use rayon::prelude::*;
 use rumbas::support::dependency_manager::DEPENDENCIES;
 use rumbas::support::file_manager::CACHE;
 use rumbas::support::rc::within_repo;
 use rumbas::support::to_numbas::ToNumbas;
+use rumbas::support::yaml::find_source_position;
 use rumbas_support::path::RumbasPath;
 use rumbas_support::preamble::Input;
+use rumbas_support::rumbas_check::{RumbasCheckDiagnostic, RumbasCheckLocation, RumbasCheckResult};
 use std::collections::HashSet;
 use std::path::Path;
 
@@ -26,14 +29,38 @@ pub fn find_all_files(path: RumbasPath) -> Vec<RumbasPath> {
     }
 }
 
-pub fn check(exam_question_paths: Vec<String>) {
-    match check_internal(exam_question_paths) {
-        Ok(_) => (),
-        Err(_) => std::process::exit(1),
+/// Settings to estimate how often the variables tests of questions fail
+#[derive(Debug, Clone, Copy)]
+pub struct VariablesTestCheck {
+    /// The amount of times the variables are generated for each question
+    pub runs: usize,
+    /// Warn when the chance that no valid variables are found within max_runs is higher than this
+    pub threshold: f64,
+}
+
+pub fn check(
+    exam_question_paths: Vec<String>,
+    variables_test: Option<VariablesTestCheck>,
+    format: OutputFormat,
+    locales: Vec<String>,
+) {
+    let result = check_internal(exam_question_paths, variables_test, &locales);
+    format.write("rumbas check", &result.reports);
+    if result.has_failures {
+        std::process::exit(1)
     }
 }
 
-pub fn check_internal(exam_question_paths: Vec<String>) -> Result<(), ()> {
+pub struct InternalCheckResult {
+    pub has_failures: bool,
+    pub reports: Vec<FileReport>,
+}
+
+pub fn check_internal(
+    exam_question_paths: Vec<String>,
+    variables_test: Option<VariablesTestCheck>,
+    locales: &[String],
+) -> InternalCheckResult {
     let mut files: HashSet<_> = HashSet::new();
     for exam_question_path in exam_question_paths.iter() {
         let path = Path::new(exam_question_path);
@@ -44,20 +71,51 @@ pub fn check_internal(exam_question_paths: Vec<String>) -> Result<(), ()> {
             if crate::cli::rc::check_rc(&path, false) {
                 files.extend(find_all_files(path).into_iter());
             } else {
-                return Err(());
+                return InternalCheckResult {
+                    has_failures: true,
+                    reports: vec![],
+                };
             }
         } else {
             log::error!(
                 "{:?} doesn't seem to belong to a rumbas project.",
                 exam_question_path
             );
-            return Err(());
+            return InternalCheckResult {
+                has_failures: true,
+                reports: vec![],
+            };
         }
     }
     let check_results: Vec<(CheckResult, _)> = files
         .into_par_iter()
-        .map(|file| (check_file(&file), file))
+        .map(|file| (check_file(&file, locales), file))
+        .collect();
+
+    for (check_result, path) in check_results.iter() {
+        if let CheckResult::Partial(p) = check_result {
+            if p.warnings.has_warnings() {
+                log::warn!("Check for {} has warnings:", path.display());
+                p.warnings.log_warnings();
+            }
+        }
+    }
+
+    if let Some(variables_test) = variables_test {
+        check_results
+            .par_iter()
+            .filter(|(result, _)| match result {
+                CheckResult::Partial(p) => p.failed.is_empty(),
+                _ => false,
+            })
+            .for_each(|(_, path)| check_variables_tests(path, variables_test));
+    }
+
+    let mut reports: Vec<_> = check_results
+        .iter()
+        .map(|(result, path)| FileReport::new(path, result.diagnostics()))
         .collect();
+    reports.sort_by(|a, b| a.file.cmp(&b.file));
 
     let failures: Vec<_> = check_results
         .par_iter()
@@ -72,16 +130,20 @@ pub fn check_internal(exam_question_paths: Vec<String>) -> Result<(), ()> {
             check_result.log(path);
         }
         log::error!("{} files failed.", failures.len());
-        Err(())
     } else {
         log::info!("All checks passed.");
-        Ok(())
+    }
+    InternalCheckResult {
+        has_failures: !failures.is_empty(),
+        reports,
     }
 }
 
 pub enum CheckResult {
     FailedParsing(rumbas::exam::ParseError),
     LocalesNotSet,
+    /// The requested locales that the file doesn't have
+    UnknownLocales(Vec<String>),
     FailedInputCheck(rumbas_support::input::InputCheckResult),
     Partial(RumbasCheckData),
 }
@@ -94,6 +156,7 @@ pub struct RumbasCheckData {
         rumbas::exam::locale::SupportedLocale,
         String,
     )>,
+    warnings: RumbasCheckResult,
 }
 
 impl RumbasCheckData {
@@ -123,42 +186,223 @@ impl RumbasCheckData {
     }
 }
 
+/// The diagnostic of a file that can't be parsed
+pub fn parse_error_diagnostic(e: &rumbas::exam::ParseError) -> RumbasCheckDiagnostic {
+    let location = match e {
+        rumbas::exam::ParseError::YamlError(y) => y.location(),
+        _ => None,
+    };
+    RumbasCheckDiagnostic {
+        kind: "invalid_yaml",
+        warning: false,
+        path: String::new(),
+        message: e.to_string(),
+        location,
+    }
+}
+
+/// The diagnostic of a file without locales
+pub fn locales_not_set_diagnostic() -> RumbasCheckDiagnostic {
+    RumbasCheckDiagnostic {
+        kind: "locales_not_set",
+        warning: false,
+        path: String::new(),
+        message: "Locales not set".to_string(),
+        location: None,
+    }
+}
+
+/// The diagnostic of a file that doesn't have all requested locales
+pub fn unknown_locales_diagnostic(locales: &[String]) -> RumbasCheckDiagnostic {
+    RumbasCheckDiagnostic {
+        kind: "unknown_locales",
+        warning: false,
+        path: String::new(),
+        message: format!("Unknown locales {}", locales.join(", ")),
+        location: None,
+    }
+}
+
 impl CheckResult {
     pub fn log(&self, path: &RumbasPath) {
         match self {
             Self::FailedParsing(e) => log::error!("{}", e),
             Self::LocalesNotSet => log::error!("Locales not set for {}!", path.display()),
+            Self::UnknownLocales(locales) => log::error!(
+                "{} doesn't have the locales {}.",
+                path.display(),
+                locales.join(", ")
+            ),
             Self::FailedInputCheck(e) => e.log(path),
             Self::Partial(r) => r.log(path),
         }
     }
+    /// All errors and warnings of the check, the same problem in multiple locales is only
+    /// reported once
+    pub fn diagnostics(&self) -> Vec<RumbasCheckDiagnostic> {
+        match self {
+            Self::FailedParsing(e) => vec![parse_error_diagnostic(e)],
+            Self::LocalesNotSet => vec![locales_not_set_diagnostic()],
+            Self::UnknownLocales(locales) => vec![unknown_locales_diagnostic(locales)],
+            Self::FailedInputCheck(e) => e.diagnostics(),
+            Self::Partial(r) => deduplicate(
+                r.failed
+                    .iter()
+                    .flat_map(|(_, result)| result.diagnostics())
+                    .chain(r.warnings.diagnostics()),
+            ),
+        }
+    }
+}
+
+/// Estimate how often the variables tests of the questions in the given file fail and warn when
+/// students are likely to get no valid variables
+pub fn check_variables_tests(path: &RumbasPath, settings: VariablesTestCheck) {
+    let loaded = match crate::cli::questions::load_questions_from_path(path) {
+        Ok(loaded) => loaded,
+        Err(_) => return,
+    };
+    for (name, question) in loaded.questions.iter() {
+        if question.variables_test.condition.is_empty() {
+            continue;
+        }
+        match question.estimate_variables_test(&loaded.locale, settings.runs) {
+            Ok(estimate) => {
+                let max_runs = question.variables_test.max_runs;
+                let failure_chance = estimate.failure_chance(max_runs);
+                if failure_chance > settings.threshold {
+                    log::warn!(
+                        "The variables test of {} is satisfied in {} of {} runs ({:.1}%), so there is a chance of {:.2e} that no valid variables are found within max_runs ({}).",
+                        name,
+                        estimate.satisfied,
+                        estimate.runs,
+                        100.0 * estimate.success_rate(),
+                        failure_chance,
+                        max_runs
+                    );
+                }
+            }
+            Err(e) => log::warn!("Could not estimate the variables test of {}: {}", name, e),
+        }
+    }
+}
+
+/// The location of a position within the source of a field in the first of the files that
+/// contains it
+///
+/// Yaml files are searched for the field with the given path, other files (e.g. the html of a
+/// file reference) for the source itself.
+fn locate_in_files(
+    files: &[RumbasPath],
+    field_path: &str,
+    source: &str,
+    line_col: (usize, usize),
+) -> Option<RumbasCheckLocation> {
+    files.iter().find_map(|file| {
+        let text = std::fs::read_to_string(file.absolute()).ok()?;
+        let is_yaml = matches!(
+            file.extension().and_then(|e| e.to_str()),
+            Some("yaml") | Some("yml")
+        );
+        let (line, column) = if is_yaml {
+            find_source_position(&text, field_path, source, line_col)?
+        } else {
+            find_text_position(&text, source, line_col)?
+        };
+        Some(RumbasCheckLocation {
+            file: file.display().to_string(),
+            line,
+            column,
+        })
+    })
+}
+
+/// The line and column (starting at one) in a text of a position within a string that is part
+/// of that text
+fn find_text_position(
+    text: &str,
+    source: &str,
+    (line, column): (usize, usize),
+) -> Option<(usize, usize)> {
+    let before = &text[..text.find(source)?];
+    let start_line = before.matches('\n').count();
+    let start_column = before.rsplit('\n').next().unwrap_or("").chars().count();
+    if line == 1 {
+        Some((start_line + 1, start_column + column))
+    } else {
+        Some((start_line + line, column))
+    }
+}
+
+/// Load the exam (or question) at the given path, combined with its templates and default files
+///
+/// The files it depends on are returned and registered in the dependency manager.
+pub fn load_exam_input(
+    path: &RumbasPath,
+) -> Result<
+    (
+        rumbas::exam::RecursiveTemplateExamInput,
+        HashSet<RumbasPath>,
+    ),
+    rumbas::exam::ParseError,
+> {
+    let mut exam_input = rumbas::exam::RecursiveTemplateExamInput::from_file(path)?;
+    // Load template files for exam
+    exam_input.load_files(path);
+
+    exam_input.combine_with_defaults(path);
+    exam_input.load_files(path);
+
+    let dependencies = exam_input.dependencies(path);
+    DEPENDENCIES.add_dependencies(path.clone(), dependencies.clone());
+    Ok((exam_input, dependencies))
+}
+
+/// Whether the locale is one of the given locales, all locales are included if none are given
+fn includes_locale(locales: &[String], locale: &str) -> bool {
+    locales.is_empty() || locales.iter().any(|l| l == locale)
 }
 
 /// Return true if parsing is ok
-pub fn check_file(path: &RumbasPath) -> CheckResult {
+///
+/// Only the given locales are checked and converted to numbas, all locales if none are given.
+pub fn check_file(path: &RumbasPath, locales: &[String]) -> CheckResult {
     log::info!("Checking {:?}", path.display());
-    let exam_input_result = rumbas::exam::RecursiveTemplateExamInput::from_file(path);
+    let exam_input_result = load_exam_input(path);
     match exam_input_result {
-        Ok(mut exam_input) => {
-            // Load template files for exam
-            exam_input.load_files(path);
-
-            exam_input.combine_with_defaults(path);
-            exam_input.load_files(path);
-
-            DEPENDENCIES.add_dependencies(path.clone(), exam_input.dependencies(path));
+        Ok((exam_input, dependencies)) => {
+            // The files in which the jme strings that can't be parsed are searched
+            let mut files: Vec<_> = dependencies.into_iter().collect();
+            files.sort_by_key(|f| f.absolute().to_path_buf());
+            files.insert(0, path.clone());
 
             let exam_result = exam_input.to_normal_safe();
             match exam_result {
                 Ok(exam) => {
+                    let unknown_locales: Vec<_> = locales
+                        .iter()
+                        .filter(|l| !exam.data.locales().iter().any(|el| &el.name == *l))
+                        .cloned()
+                        .collect();
                     if exam.data.locales().is_empty() {
                         CheckResult::LocalesNotSet
+                    } else if !unknown_locales.is_empty() {
+                        CheckResult::UnknownLocales(unknown_locales)
                     } else {
                         let mut failed_locales = Vec::new();
                         let mut passed_locales = Vec::new();
-                        for locale_item in exam.data.locales().iter() {
+                        let mut warnings = RumbasCheckResult::empty();
+                        let exam_locales: Vec<_> = exam
+                            .data
+                            .locales()
+                            .into_iter()
+                            .filter(|l| includes_locale(locales, &l.name))
+                            .collect();
+                        for locale_item in exam_locales.iter() {
                             let locale = locale_item.name.to_owned();
-                            let numbas = exam.to_numbas_safe(&locale);
+                            let (numbas, locale_warnings) =
+                                exam.to_numbas_safe_with_warnings(&locale);
+                            warnings.union(&locale_warnings);
                             match numbas {
                                 Ok(numbas_exam) => {
                                     passed_locales.push((
@@ -168,7 +412,10 @@ pub fn check_file(path: &RumbasPath) -> CheckResult {
                                         exam.data.numbas_settings().theme,
                                     ));
                                 }
-                                Err(check_result) => {
+                                Err(mut check_result) => {
+                                    check_result.locate_invalid_jme(|path, source, line_col| {
+                                        locate_in_files(&files, path, source, line_col)
+                                    });
                                     failed_locales.push((locale, check_result));
                                 }
                             }
@@ -176,6 +423,7 @@ pub fn check_file(path: &RumbasPath) -> CheckResult {
                         CheckResult::Partial(RumbasCheckData {
                             passed: passed_locales,
                             failed: failed_locales,
+                            warnings,
                         })
                     }
                 }
@@ -1,7 +1,17 @@
-use crate::cli::check::CheckResult;
+use crate::cli::check::{
+    load_exam_input, locales_not_set_diagnostic, parse_error_diagnostic,
+    unknown_locales_diagnostic, CheckResult,
+};
+use crate::cli::report::{deduplicate, FileReport, OutputFormat};
 use rayon::prelude::*;
+use rumbas::support::default::default_file_scope;
+use rumbas::support::dependency_manager::DEPENDENCIES;
+use rumbas::support::file_manager::RumbasRepoFileData;
+use rumbas::support::numbas_packager::{folder_files, NumbasPackager, RUNTIME_FOLDER};
 use rumbas::support::rc::within_repo;
 use rumbas_support::path::RumbasPath;
+use rumbas_support::rumbas_check::RumbasCheckDiagnostic;
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
 use std::env;
 use std::path::Path;
@@ -11,34 +21,59 @@ use std::path::PathBuf;
 /// It caches the .exam files that are given to Numbas.
 pub const CACHE_FOLDER: &str = ".rumbas";
 
+/// The extension of the files (next to the .exam files in the cache folder) that contain the hash
+/// of the last successful build, used to skip numbas when nothing changed.
+const BUILD_HASH_EXTENSION: &str = "hash";
+
 /// The name of the local folder used for the output.
 pub const OUTPUT_FOLDER: &str = "_output";
 
-pub fn compile(compile_paths: Vec<String>, use_scorm: bool, as_zip: bool, no_minification: bool) {
-    match compile_internal(
-        CompilationContext { compile_paths },
+pub fn compile(
+    compile_paths: Vec<String>,
+    use_scorm: bool,
+    as_zip: bool,
+    no_minification: bool,
+    format: OutputFormat,
+    changed_since: Option<String>,
+    locales: Vec<String>,
+    native: bool,
+) {
+    if native && !no_minification {
+        log::warn!(
+            "The native packager does not minify the js and css files, use --no-minification to hide this warning."
+        );
+    }
+    let result = compile_internal(
+        CompilationContext {
+            compile_paths,
+            changed_since,
+        },
         FileCompilationContext {
             use_scorm,
             as_zip,
             minify: !no_minification,
             output_folder: Path::new(OUTPUT_FOLDER).to_path_buf(),
+            locales,
+            native,
         },
-    )
-    .has_failures
-    {
-        false => (),
-        true => std::process::exit(1),
+    );
+    format.write("rumbas compile", &result.reports);
+    if result.has_failures {
+        std::process::exit(1)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct CompilationContext {
     pub compile_paths: Vec<String>,
+    /// Only compile the files that (or whose dependencies) changed since this git revision
+    pub changed_since: Option<String>,
 }
 
 pub struct InternalCompilationResult {
     pub has_failures: bool,
     pub created_outputs: Vec<PassedRumbasCompileData>,
+    pub reports: Vec<FileReport>,
 }
 
 pub fn compile_internal(
@@ -58,6 +93,7 @@ pub fn compile_internal(
                 return InternalCompilationResult {
                     has_failures: true,
                     created_outputs: vec![],
+                    reports: vec![],
                 };
             }
         } else {
@@ -68,14 +104,34 @@ pub fn compile_internal(
             return InternalCompilationResult {
                 has_failures: true,
                 created_outputs: vec![],
+                reports: vec![],
             };
         }
     }
+    if let Some(revision) = context.changed_since.as_ref() {
+        match select_changed_files(files, revision) {
+            Ok(changed_files) => files = changed_files,
+            Err(e) => {
+                log::error!("Could not find the changes since {}: {}", revision, e);
+                return InternalCompilationResult {
+                    has_failures: true,
+                    created_outputs: vec![],
+                    reports: vec![],
+                };
+            }
+        }
+    }
     let compile_results: Vec<(CompileResult, _)> = files
         .into_par_iter()
         .map(|file| (compile_file(&file_context, &file), file))
         .collect();
 
+    let mut reports: Vec<_> = compile_results
+        .iter()
+        .map(|(result, path)| FileReport::new(path, result.diagnostics()))
+        .collect();
+    reports.sort_by(|a, b| a.file.cmp(&b.file));
+
     let failures: Vec<_> = compile_results
         .par_iter()
         .filter(|(result, _)| match result {
@@ -110,12 +166,74 @@ pub fn compile_internal(
     InternalCompilationResult {
         has_failures,
         created_outputs,
+        reports,
+    }
+}
+
+/// Select the files that changed since the given revision or that depend on a changed file
+/// (e.g. via a default file, a template, an included file or a custom part type)
+fn select_changed_files(
+    files: HashSet<RumbasPath>,
+    revision: &str,
+) -> Result<HashSet<RumbasPath>, String> {
+    let root = match files.iter().next() {
+        Some(file) => file.root().to_path_buf(),
+        None => return Ok(files),
+    };
+    let changed = crate::cli::git::changed_files_since(&root, revision)?;
+
+    // Loading the files registers their dependencies
+    let dependencies: Vec<(RumbasPath, HashSet<RumbasPath>)> = files
+        .par_iter()
+        .map(|file| {
+            let dependencies = load_exam_input(file)
+                .map(|(_, dependencies)| dependencies)
+                .unwrap_or_default();
+            (file.clone(), dependencies)
+        })
+        .collect();
+
+    let mut selected = HashSet::new();
+    for changed_file in changed.iter() {
+        log::debug!("{} changed since {}", changed_file.display(), revision);
+        if files.contains(changed_file) {
+            selected.insert(changed_file.clone());
+        }
+        let dependency_path = RumbasRepoFileData::from(changed_file.clone()).dependency_path();
+        selected.extend(
+            DEPENDENCIES
+                .get_dependants(dependency_path)
+                .into_iter()
+                .filter(|dependant| files.contains(dependant)),
+        );
+        // Default files are used by all files in the folder that contains the defaults folder
+        if let Some(scope) = default_file_scope(changed_file) {
+            selected.extend(
+                dependencies
+                    .iter()
+                    .filter(|(file, dependencies)| {
+                        std::iter::once(file)
+                            .chain(dependencies.iter())
+                            .any(|f| f.project().starts_with(&scope))
+                    })
+                    .map(|(file, _)| file.clone()),
+            );
+        }
     }
+    log::info!(
+        "{} of the {} files changed since {}.",
+        selected.len(),
+        files.len(),
+        revision
+    );
+    Ok(selected)
 }
 
 pub enum CompileResult {
     FailedParsing(rumbas::exam::ParseError),
     LocalesNotSet,
+    /// The requested locales that the file doesn't have
+    UnknownLocales(Vec<String>),
     FailedInputCheck(rumbas_support::input::InputCheckResult),
     Partial(RumbasCompileData),
 }
@@ -141,6 +259,22 @@ impl RumbasCompileData {
     pub fn created_outputs(&self) -> Vec<PassedRumbasCompileData> {
         self.passed.clone()
     }
+    /// The problems of the check and the locales that numbas failed to compile
+    pub fn diagnostics(&self) -> Vec<RumbasCheckDiagnostic> {
+        let failed_compilations = self.failed.iter().map(|locale| RumbasCheckDiagnostic {
+            kind: "numbas_compilation_failed",
+            warning: false,
+            path: String::new(),
+            message: format!("Numbas failed to compile locale {}", locale),
+            location: None,
+        });
+        deduplicate(
+            self.failed_check
+                .iter()
+                .flat_map(|(_, result)| result.diagnostics())
+                .chain(failed_compilations),
+        )
+    }
     pub fn log(&self, path: &RumbasPath) {
         for (locale, check_result) in self.failed_check.iter() {
             log::error!(
@@ -175,10 +309,24 @@ impl CompileResult {
         match self {
             Self::FailedParsing(e) => log::error!("{}", e),
             Self::LocalesNotSet => log::error!("Locales not set for {}!", path.display()),
+            Self::UnknownLocales(locales) => log::error!(
+                "{} doesn't have the locales {}.",
+                path.display(),
+                locales.join(", ")
+            ),
             Self::FailedInputCheck(e) => e.log(path),
             Self::Partial(r) => r.log(path),
         }
     }
+    pub fn diagnostics(&self) -> Vec<RumbasCheckDiagnostic> {
+        match self {
+            Self::FailedParsing(e) => vec![parse_error_diagnostic(e)],
+            Self::LocalesNotSet => vec![locales_not_set_diagnostic()],
+            Self::UnknownLocales(locales) => vec![unknown_locales_diagnostic(locales)],
+            Self::FailedInputCheck(e) => e.diagnostics(),
+            Self::Partial(r) => r.diagnostics(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -187,14 +335,19 @@ pub struct FileCompilationContext {
     pub as_zip: bool,
     pub minify: bool,
     pub output_folder: PathBuf,
+    /// Only compile these locales, all locales if empty
+    pub locales: Vec<String>,
+    /// Package the exams with rumbas instead of the python compiler of Numbas
+    pub native: bool,
 }
 
 pub fn compile_file(context: &FileCompilationContext, path: &RumbasPath) -> CompileResult {
-    let check_result = crate::cli::check::check_file(path);
+    let check_result = crate::cli::check::check_file(path, &context.locales);
     match check_result {
         CheckResult::FailedParsing(f) => CompileResult::FailedParsing(f),
         CheckResult::FailedInputCheck(f) => CompileResult::FailedInputCheck(f),
         CheckResult::LocalesNotSet => CompileResult::LocalesNotSet,
+        CheckResult::UnknownLocales(l) => CompileResult::UnknownLocales(l),
         CheckResult::Partial(p) => {
             let mut passed_compilations = Vec::new();
             let mut failed_compilations = Vec::new();
@@ -209,6 +362,7 @@ pub fn compile_file(context: &FileCompilationContext, path: &RumbasPath) -> Comp
                     theme,
                     exam: numbas_exam,
                     minify: context.minify,
+                    native: context.native,
                     output_folder: path.keep_root(context.output_folder.as_path()),
                 };
                 if compiler.compile() {
@@ -239,6 +393,7 @@ pub struct NumbasCompiler {
     numbas_locale: String,
     theme: String,
     minify: bool,
+    native: bool,
     exam: numbas::exam::Exam,
     output_folder: RumbasPath,
 }
@@ -254,6 +409,56 @@ impl NumbasCompiler {
         let numbas_exam_name = self.exam_path.project().with_extension("exam");
         self.numbas_exam_folder().absolute().join(&numbas_exam_name)
     }
+    /// Returns the path where the hash of the last successful build is saved
+    fn build_hash_path(&self) -> PathBuf {
+        self.numbas_exam_path().with_extension(BUILD_HASH_EXTENSION)
+    }
+    /// The hash of everything that determines the output of numbas: the exam, the contents of
+    /// its resources and extensions, the theme (and the themes it inherits from), the Numbas
+    /// version, the locale and the flags.
+    /// None if the exam can't be serialized or a file can't be read.
+    fn build_hash(&self) -> Option<String> {
+        let exam_json = serde_json::to_string(&self.exam).ok()?;
+        let numbas_folder = PathBuf::from(env::var(rumbas::NUMBAS_FOLDER_ENV).ok()?);
+        let fields = [
+            clap::crate_version!().to_string(),
+            numbas_folder.display().to_string(),
+            numbas_version(&numbas_folder)?,
+            exam_json,
+            self.locale.clone(),
+            self.numbas_locale.clone(),
+            self.theme.clone(),
+            self.output_path().display().to_string(),
+        ];
+        let mut hasher = Sha256::new();
+        for field in fields.iter() {
+            hash_bytes(&mut hasher, field.as_bytes());
+        }
+        hasher.update([
+            self.use_scorm as u8,
+            self.as_zip as u8,
+            self.minify as u8,
+            self.native as u8,
+        ]);
+        let theme_paths = self.packager(numbas_folder).theme_paths().ok()?;
+        let extension_paths = self.exam.extensions.iter().map(PathBuf::from);
+        let resource_paths = self.exam.resources.iter().map(|r| PathBuf::from(&r.0[1]));
+        for path in theme_paths
+            .into_iter()
+            .chain(extension_paths)
+            .chain(resource_paths)
+        {
+            hash_contents(&mut hasher, &path)?;
+        }
+        Some(format!("{:x}", hasher.finalize()))
+    }
+    /// Whether the output of the last build with the same hash still exists
+    fn is_up_to_date(&self, build_hash: &str) -> bool {
+        self.output_path().exists()
+            && std::fs::read_to_string(self.build_hash_path())
+                .map(|saved_hash| saved_hash.trim() == build_hash)
+                .unwrap_or(false)
+    }
     /// Returns the locale folder within the output folder
     fn locale_output_folder(&self) -> PathBuf {
         self.output_folder.absolute().join(&self.locale)
@@ -330,9 +535,63 @@ impl NumbasCompiler {
             .output()
             .expect("failed to execute numbas process")
     }
+    /// Compile the saved .exam file with numbas, returns whether it succeeded
+    fn run_numbas(&self) -> bool {
+        let output = self.execute_numbas();
+        if !output.stdout.is_empty() {
+            log::debug!("{}", std::str::from_utf8(&output.stdout).unwrap());
+        }
+        if !output.stderr.is_empty() {
+            log::error!("Compilation failed. Use -v to see more");
+            log::debug!("{}", std::str::from_utf8(&output.stderr).unwrap());
+            return false;
+        }
+        true
+    }
+    /// The native packager for the exam with the given Numbas checkout
+    fn packager(&self, numbas_folder: PathBuf) -> NumbasPackager {
+        NumbasPackager {
+            numbas_folder,
+            theme: self.theme.clone(),
+            locale: self.numbas_locale.clone(),
+            scorm: self.use_scorm,
+        }
+    }
+    /// Package the exam with the native packager instead of numbas, returns whether it succeeded
+    fn package(&self) -> bool {
+        let numbas_path = env::var(rumbas::NUMBAS_FOLDER_ENV)
+            .expect(&format!("{} to be set", rumbas::NUMBAS_FOLDER_ENV)[..]);
+        let packager = self.packager(PathBuf::from(numbas_path));
+        let output_path = self.output_path();
+        let result = packager.package(&self.exam).and_then(|package| {
+            if self.as_zip {
+                package.write_to_zip(&output_path)
+            } else {
+                package.write_to_folder(&output_path)
+            }
+        });
+        if let Err(e) = result {
+            log::error!("Compilation failed: {}", e);
+            return false;
+        }
+        true
+    }
     /// Compile the numbas exam
     pub fn compile(&self) -> bool {
+        let build_hash = self.build_hash();
+        if let Some(build_hash) = build_hash.as_ref() {
+            if self.is_up_to_date(build_hash) {
+                log::info!(
+                    "Exam {} with locale {} is up to date.",
+                    self.exam_path.project().display(),
+                    self.locale
+                );
+                return true;
+            }
+        }
         self.create_folder_structure();
+        // The hash is only saved again after a successful build
+        std::fs::remove_file(self.build_hash_path()).unwrap_or(()); // If error, don't mind
         let exam_file_path = self.numbas_exam_path();
         let exam_write_res = self.exam.write(exam_file_path.to_str().unwrap());
         match exam_write_res {
@@ -359,17 +618,59 @@ impl NumbasCompiler {
                     self.locale
                 );
 
-                let output = self.execute_numbas();
-                if !output.stdout.is_empty() {
-                    log::debug!("{}", std::str::from_utf8(&output.stdout).unwrap());
-                }
-                if !output.stderr.is_empty() {
-                    log::error!("Compilation failed. Use -v to see more");
-                    log::debug!("{}", std::str::from_utf8(&output.stderr).unwrap());
+                let compiled = if self.native {
+                    self.package()
+                } else {
+                    self.run_numbas()
+                };
+                if !compiled {
                     return false;
                 }
+                if let Some(build_hash) = build_hash {
+                    if let Err(e) = std::fs::write(self.build_hash_path(), build_hash) {
+                        log::warn!("Failed saving the build hash: {}", e);
+                    }
+                }
             }
         }
         true
     }
 }
+
+/// Add the length and the bytes to the hasher, so consecutive fields can't be confused
+fn hash_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+/// Add the names and contents of the files at the path (a file or a folder) to the hasher
+///
+/// None if a file can't be read. A missing path only adds its name, numbas reports it.
+fn hash_contents(hasher: &mut Sha256, path: &Path) -> Option<()> {
+    hash_bytes(hasher, path.to_string_lossy().as_bytes());
+    if path.is_dir() {
+        for (name, file) in folder_files(path).ok()? {
+            hash_bytes(hasher, name.as_bytes());
+            hash_bytes(hasher, &std::fs::read(file).ok()?);
+        }
+    } else if path.is_file() {
+        hash_bytes(hasher, &std::fs::read(path).ok()?);
+    }
+    Some(())
+}
+
+/// The version of the Numbas checkout: the hash of its runtime, together with the commit of its
+/// git repository if it is one
+///
+/// The runtime is always hashed because the checkout can have uncommitted changes.
+fn numbas_version(numbas_folder: &Path) -> Option<String> {
+    let commit = git2::Repository::open(numbas_folder)
+        .ok()
+        .and_then(|repo| repo.head().ok()?.target());
+    let mut hasher = Sha256::new();
+    if let Some(commit) = commit {
+        hash_bytes(&mut hasher, commit.as_bytes());
+    }
+    hash_contents(&mut hasher, &numbas_folder.join(RUNTIME_FOLDER))?;
+    Some(format!("{:x}", hasher.finalize()))
+}
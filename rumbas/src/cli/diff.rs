@@ -0,0 +1,254 @@
+use crate::cli::check::find_all_files;
+use crate::cli::compile::CACHE_FOLDER;
+use crate::cli::git::{git_error, open_repository};
+use rumbas::exam::{RecursiveTemplateExam, RecursiveTemplateExamInput};
+use rumbas::support::changes::describe_changes;
+use rumbas::support::rc::{find_root, within_repo};
+use rumbas_support::path::RumbasPath;
+use rumbas_support::preamble::Input;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The name of the folder (within the cache folder) where the revisions are written to
+const DIFF_FOLDER: &str = "diff";
+
+pub fn diff(revision_a: String, revision_b: String, paths: Vec<String>) {
+    match diff_internal(revision_a, revision_b, paths) {
+        Ok((output, has_failures)) => {
+            std::io::stdout()
+                .write_all(output.as_bytes())
+                .expect("writing to stdout");
+            if has_failures {
+                std::process::exit(1)
+            }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1)
+        }
+    }
+}
+
+/// The commit that the given revision refers to
+fn resolve_revision<'a>(
+    repo: &'a git2::Repository,
+    revision: &str,
+) -> Result<git2::Commit<'a>, String> {
+    repo.revparse_single(revision)
+        .and_then(|o| o.peel_to_commit())
+        .map_err(|e| format!("Unknown revision {}: {}", revision, git_error(e)))
+}
+
+/// Write the files of the rumbas repository at the given commit to a folder in the cache folder
+///
+/// Returns the root of the rumbas repository in that folder.
+fn checkout_revision(
+    repo: &git2::Repository,
+    prefix: &Path,
+    revision: &str,
+    commit: &git2::Commit,
+    root: &Path,
+) -> Result<PathBuf, String> {
+    let mut tree = commit.tree().map_err(git_error)?;
+    if prefix != Path::new("") {
+        tree = tree
+            .get_path(prefix)
+            .and_then(|entry| entry.to_object(repo))
+            .and_then(|object| object.peel_to_tree())
+            .map_err(|_| {
+                format!(
+                    "The rumbas repository {} doesn't exist at revision {}",
+                    prefix.display(),
+                    revision
+                )
+            })?;
+    }
+    let folder = root
+        .join(CACHE_FOLDER)
+        .join(DIFF_FOLDER)
+        .join(commit.id().to_string());
+    std::fs::remove_dir_all(&folder).unwrap_or(()); // If error, don't mind
+
+    let mut error = None;
+    let walk_result = tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(git2::ObjectType::Blob) {
+            return git2::TreeWalkResult::Ok;
+        }
+        let path = folder.join(dir).join(entry.name().unwrap_or_default());
+        let result = entry
+            .to_object(repo)
+            .and_then(|object| object.peel_to_blob())
+            .map_err(git_error)
+            .and_then(|blob| {
+                std::fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| std::fs::write(&path, blob.content()))
+                    .map_err(|e| format!("Failed writing {}: {}", path.display(), e))
+            });
+        match result {
+            Ok(()) => git2::TreeWalkResult::Ok,
+            Err(e) => {
+                error = Some(e);
+                git2::TreeWalkResult::Abort
+            }
+        }
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    walk_result.map_err(git_error)?;
+    Ok(folder)
+}
+
+/// The exam and question files at the given path (within the repository) in a checked out revision
+fn find_files(version_root: &Path, project_path: &Path) -> Vec<RumbasPath> {
+    match RumbasPath::create(&version_root.join(project_path), version_root) {
+        Some(path) if path.is_file() || path.is_dir() => find_all_files(path),
+        _ => Vec::new(),
+    }
+}
+
+/// Load the exam (or question) at the given path in a checked out revision
+///
+/// The error describes why it could not be loaded.
+fn load_exam(path: &RumbasPath) -> Result<RecursiveTemplateExam, String> {
+    let mut exam_input = RecursiveTemplateExamInput::from_file(path).map_err(|e| e.to_string())?;
+    exam_input.load_files(path);
+    exam_input.combine_with_defaults(path);
+    exam_input.load_files(path);
+    exam_input.to_normal_safe().map_err(|check_result| {
+        let problems: Vec<_> = check_result
+            .diagnostics()
+            .into_iter()
+            .map(|d| format!("{}: {}", d.path, d.message))
+            .collect();
+        format!("{} is invalid: {}", path.display(), problems.join(", "))
+    })
+}
+
+/// Compare the exams and questions at the given paths in two revisions
+///
+/// Returns the description of the changes and whether some files could not be loaded.
+pub fn diff_internal(
+    revision_a: String,
+    revision_b: String,
+    paths: Vec<String>,
+) -> Result<(String, bool), String> {
+    let root = find_root(Path::new(".")).ok_or("Missing rc file")?;
+    let current = within_repo(Path::new(".")).ok_or("Missing rc file")?;
+    if !crate::cli::rc::check_rc(&current, false) {
+        return Err("The rumbas version of the repository is not supported".to_string());
+    }
+    let (repo, prefix) = open_repository(&root)?;
+
+    let commit_a = resolve_revision(&repo, &revision_a)?;
+    let commit_b = resolve_revision(&repo, &revision_b)?;
+    let root_a = checkout_revision(&repo, &prefix, &revision_a, &commit_a, &root)?;
+    // Both revisions are written to the same folder if they refer to the same commit
+    let root_b = if commit_b.id() == commit_a.id() {
+        Ok(root_a.clone())
+    } else {
+        checkout_revision(&repo, &prefix, &revision_b, &commit_b, &root)
+    };
+    let result = root_b
+        .clone()
+        .and_then(|root_b| compare_revisions(&root, &root_a, &root_b, paths));
+    // The checked out revisions are only needed for this comparison
+    for folder in std::iter::once(root_a).chain(root_b.ok()) {
+        std::fs::remove_dir_all(&folder).unwrap_or(()); // If error, don't mind
+    }
+    result
+}
+
+/// Compare the exams and questions at the given paths in two checked out revisions
+fn compare_revisions(
+    root: &Path,
+    root_a: &Path,
+    root_b: &Path,
+    paths: Vec<String>,
+) -> Result<(String, bool), String> {
+    let paths = if paths.is_empty() {
+        vec![
+            rumbas::EXAMS_FOLDER.to_string(),
+            rumbas::QUESTIONS_FOLDER.to_string(),
+        ]
+    } else {
+        paths
+    };
+    let current_dir = std::env::current_dir().map_err(|e| e.to_string())?;
+    let mut files: BTreeMap<PathBuf, (Option<RumbasPath>, Option<RumbasPath>)> = BTreeMap::new();
+    for path in paths.iter() {
+        let project_path = current_dir
+            .join(path)
+            .strip_prefix(root)
+            .map(|p| p.to_path_buf())
+            .map_err(|_| format!("{:?} doesn't seem to belong to a rumbas project.", path))?;
+        for file in find_files(root_a, &project_path) {
+            files.entry(file.project().to_path_buf()).or_default().0 = Some(file);
+        }
+        for file in find_files(root_b, &project_path) {
+            files.entry(file.project().to_path_buf()).or_default().1 = Some(file);
+        }
+    }
+
+    let mut output = String::new();
+    let mut has_failures = false;
+    for (project_path, versions) in files.iter() {
+        match versions {
+            (None, Some(_)) => output.push_str(&format!("{}: added\n", project_path.display())),
+            (Some(_), None) => output.push_str(&format!("{}: removed\n", project_path.display())),
+            (Some(a), Some(b)) => match load_exam(a).and_then(|exam_a| {
+                load_exam(b).map(|exam_b| describe_changes(&exam_a.data, &exam_b.data))
+            }) {
+                Ok(changes) => {
+                    if !changes.is_empty() {
+                        output.push_str(&format!("{}\n", project_path.display()));
+                        for change in changes.iter() {
+                            output.push_str(&format!("  {}\n", change));
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Could not compare {}: {}", project_path.display(), e);
+                    has_failures = true;
+                }
+            },
+            (None, None) => (),
+        }
+    }
+    Ok((output, has_failures))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rumbas::question::Question;
+    use rumbas::support::to_rumbas::ToRumbas;
+
+    fn question(max_runs: usize) -> Question {
+        let numbas_question: numbas::question::Question = serde_json::from_str(&format!(
+            r#"{{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {{"allowregen": true, "showfrontpage": false, "preventleave": false}},
+                "variables": {{
+                    "a": {{"name": "a", "group": "Ungrouped variables", "definition": "2", "description": "", "templateType": "anything"}}
+                }},
+                "variablesTest": {{"condition": "a > 1", "maxRuns": {}}}
+            }}"#,
+            max_runs
+        ))
+        .unwrap();
+        numbas_question.to_rumbas()
+    }
+
+    #[test]
+    fn question_changes() {
+        assert!(describe_changes(&question(100), &question(100)).is_empty());
+        assert_eq!(
+            describe_changes(&question(100), &question(20)),
+            vec!["variables_test.max_runs changed 100 → 20"]
+        );
+    }
+}
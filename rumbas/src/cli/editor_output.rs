@@ -88,23 +88,31 @@ pub fn create_editor_output_internal(context: EditorOutputContext) -> Result<(),
     let scorm_compilation_result = compile_internal(
         CompilationContext {
             compile_paths: compile_paths.clone(),
+            changed_since: None,
         },
         FileCompilationContext {
             use_scorm: true,
             as_zip: true,
             minify: true,
             output_folder: context.output_path.clone(),
+            locales: vec![],
+            native: false,
         },
     );
 
     println!("Compiling (preview) exam html-outputs.");
     let folder_compilation_result = compile_internal(
-        CompilationContext { compile_paths },
+        CompilationContext {
+            compile_paths,
+            changed_since: None,
+        },
         FileCompilationContext {
             use_scorm: false,
             as_zip: false,
             minify: false,
             output_folder: context.output_path.clone(),
+            locales: vec![],
+            native: false,
         },
     );
 
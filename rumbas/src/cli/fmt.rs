@@ -1,6 +1,7 @@
 use super::check;
 use rayon::prelude::*;
 use rumbas::support::file_manager::CACHE;
+use rumbas::support::jme_format::format_jme_in_yaml;
 use rumbas::support::rc::within_repo;
 use rumbas_support::path::RumbasPath;
 use rumbas_support::preamble::FileToLoad;
@@ -112,7 +113,7 @@ pub fn format_file(path: &RumbasPath) -> RumbasFormatResult {
                         }*/
                         let dump_res = yaml.format();
                         match dump_res {
-                            Ok(res) => match std::fs::write(path, res) {
+                            Ok(res) => match std::fs::write(path, format_jme_in_yaml(&res)) {
                                 Ok(_) => RumbasFormatResult::Ok,
                                 Err(_) => RumbasFormatResult::FailedWritingFile,
                             },
@@ -0,0 +1,111 @@
+use rumbas_support::path::RumbasPath;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+pub fn git_error(e: git2::Error) -> String {
+    e.message().to_string()
+}
+
+/// The canonical path of the working directory of the git repository
+fn workdir(repo: &git2::Repository) -> Result<PathBuf, String> {
+    repo.workdir()
+        .and_then(|w| w.canonicalize().ok())
+        .ok_or_else(|| "The git repository has no working directory".to_string())
+}
+
+/// Open the git repository that contains the rumbas repository at the given root
+///
+/// Returns the git repository and the path of the rumbas repository within it.
+pub fn open_repository(root: &Path) -> Result<(git2::Repository, PathBuf), String> {
+    let repo = git2::Repository::discover(root).map_err(git_error)?;
+    let prefix = root
+        .strip_prefix(workdir(&repo)?)
+        .map_err(|_| "The rumbas repository is not within the git repository")?
+        .to_path_buf();
+    Ok((repo, prefix))
+}
+
+/// The files of the rumbas repository at the given root that were added, modified, removed or
+/// renamed since the given revision (including the uncommitted changes)
+pub fn changed_files_since(root: &Path, revision: &str) -> Result<Vec<RumbasPath>, String> {
+    let (repo, _) = open_repository(root)?;
+    let workdir = workdir(&repo)?;
+    let tree = repo
+        .revparse_single(revision)
+        .and_then(|o| o.peel_to_tree())
+        .map_err(|e| format!("Unknown revision {}: {}", revision, git_error(e)))?;
+    let mut options = git2::DiffOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let diff = repo
+        .diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))
+        .map_err(git_error)?;
+
+    let paths: BTreeSet<PathBuf> = diff
+        .deltas()
+        .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+        .flatten()
+        .map(|p| workdir.join(p))
+        .collect();
+    Ok(paths
+        .into_iter()
+        .filter_map(|p| RumbasPath::create(&p, root))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(root: &Path, file: &str, content: &str) {
+        let path = root.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn commit_all(repo: &git2::Repository) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("rumbas", "rumbas@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn changed_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let root = folder.path().canonicalize().unwrap();
+        let repo = git2::Repository::init(&root).unwrap();
+        write(&root, "questions/modified.yaml", "statement: a");
+        write(&root, "questions/renamed.yaml", "statement: b");
+        write(&root, "exams/unchanged.yaml", "name: e");
+        commit_all(&repo);
+
+        write(&root, "questions/modified.yaml", "statement: c");
+        std::fs::rename(
+            root.join("questions/renamed.yaml"),
+            root.join("questions/new_name.yaml"),
+        )
+        .unwrap();
+        write(&root, "questions/untracked/new.yaml", "statement: d");
+
+        let changed: Vec<_> = changed_files_since(&root, "HEAD")
+            .unwrap()
+            .iter()
+            .map(|p| p.project().to_path_buf())
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                PathBuf::from("questions/modified.yaml"),
+                PathBuf::from("questions/new_name.yaml"),
+                PathBuf::from("questions/renamed.yaml"),
+                PathBuf::from("questions/untracked/new.yaml"),
+            ]
+        );
+        assert!(changed_files_since(&root, "unknown").is_err());
+    }
+}
@@ -0,0 +1,25 @@
+use crate::cli::questions::load_questions;
+use rumbas::question::variable_graph::VariableGraph;
+use std::io::Write;
+
+pub fn graph(question_path: String) {
+    match graph_internal(question_path) {
+        Ok(dot) => {
+            std::io::stdout()
+                .write_all(format!("{}\n", dot).as_bytes())
+                .expect("writing to stdout");
+        }
+        Err(_) => std::process::exit(1),
+    }
+}
+
+/// Create the DOT representation of the variable dependencies of the questions at the given path
+pub fn graph_internal(question_path: String) -> Result<String, ()> {
+    let loaded = load_questions(&question_path)?;
+    Ok(loaded
+        .questions
+        .iter()
+        .map(|(name, question)| VariableGraph::from_question(question, &loaded.locale).to_dot(name))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
@@ -1,5 +1,5 @@
 // See https://github.com/daboross/fern/blob/master/examples/pretty-colored.rs
-pub fn setup(level: log::LevelFilter) -> Result<(), fern::InitError> {
+pub fn setup(level: log::LevelFilter, to_stderr: bool) -> Result<(), fern::InitError> {
     // configure colors for the whole line
     let colors_line = fern::colors::ColoredLevelConfig::new()
         .error(fern::colors::Color::Red)
@@ -15,7 +15,7 @@ pub fn setup(level: log::LevelFilter) -> Result<(), fern::InitError> {
     // just clone `colors_line` and overwrite our changes
     let colors_level = colors_line.info(fern::colors::Color::Green);
 
-    fern::Dispatch::new()
+    let dispatch = fern::Dispatch::new()
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "{color_line}{date}[{target}][{level}{color_line}] {message}\x1B[0m",
@@ -29,8 +29,13 @@ pub fn setup(level: log::LevelFilter) -> Result<(), fern::InitError> {
                 message = message
             ))
         })
-        .level(level)
-        .chain(std::io::stdout())
+        .level(level);
+    let dispatch = if to_stderr {
+        dispatch.chain(std::io::stderr())
+    } else {
+        dispatch.chain(std::io::stdout())
+    };
+    dispatch
         //.chain(fern::log_file("output.log")?)
         .apply()?;
     Ok(())
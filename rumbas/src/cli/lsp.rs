@@ -0,0 +1,485 @@
+use crate::cli::check::check_file;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, Documentation, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use rumbas::exam::ExamFileTypeInput;
+use rumbas::question::custom_part_type::CustomPartTypeDefinitionInput;
+use rumbas::question::QuestionFileTypeInput;
+use rumbas::support::dependency_manager::DEPENDENCIES;
+use rumbas::support::file_manager::{
+    ExamFileToRead, QuestionFileToRead, RumbasRepoFileData, TextFileToRead, CACHE,
+};
+use rumbas::support::file_reference::FILE_PREFIX;
+use rumbas::support::json_schema::SchemaNavigator;
+use rumbas::support::rc::within_repo;
+use rumbas::support::template::TEMPLATE_PREFIX;
+use rumbas::support::yaml::{
+    find_field_position, yaml_key_at, yaml_key_paths, yaml_path_at, yaml_value_at, YamlPathPart,
+};
+use rumbas_support::input::FileToLoad;
+use rumbas_support::path::RumbasPath;
+use rumbas_support::rumbas_check::RumbasCheckDiagnostic;
+use schemars::schema_for;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+pub fn lsp() {
+    if let Err(e) = lsp_internal() {
+        log::error!("The language server stopped with error {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Run the language server on stdin and stdout until the client shuts it down
+fn lsp_internal() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        // The checks read the files from disk, so they are only done when a file is saved
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["{".to_string(), "(".to_string(), " ".to_string()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = LanguageServer::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                for (uri, diagnostics) in server.handle_notification(notification) {
+                    let params = PublishDiagnosticsParams {
+                        uri,
+                        diagnostics,
+                        version: None,
+                    };
+                    connection
+                        .sender
+                        .send(Message::Notification(Notification::new(
+                            PublishDiagnostics::METHOD.to_string(),
+                            params,
+                        )))?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    io_threads.join()?;
+    Ok(())
+}
+
+/// The json schemas of the different kinds of files
+struct Schemas {
+    exam: SchemaNavigator,
+    question: SchemaNavigator,
+    custom_part_type: SchemaNavigator,
+}
+
+impl Schemas {
+    fn new() -> Self {
+        let navigator = |schema: schemars::schema::RootSchema| {
+            SchemaNavigator::new(serde_json::to_value(schema).expect("serializable json schema"))
+        };
+        Self {
+            exam: navigator(schema_for!(ExamFileTypeInput)),
+            question: navigator(schema_for!(QuestionFileTypeInput)),
+            custom_part_type: navigator(schema_for!(CustomPartTypeDefinitionInput)),
+        }
+    }
+
+    fn for_path(&self, path: &RumbasPath) -> Option<&SchemaNavigator> {
+        if path.in_main_folder(rumbas::EXAMS_FOLDER) {
+            Some(&self.exam)
+        } else if path.in_main_folder(rumbas::QUESTIONS_FOLDER) {
+            Some(&self.question)
+        } else if path.in_main_folder(rumbas::CUSTOM_PART_TYPES_FOLDER) {
+            Some(&self.custom_part_type)
+        } else {
+            None
+        }
+    }
+}
+
+struct LanguageServer {
+    /// The content of the open documents, as edited in the client
+    documents: HashMap<Url, String>,
+    schemas: Schemas,
+}
+
+/// Parse the parameters of a request and create the response with the result of the handler
+fn respond<P: DeserializeOwned, R: Serialize>(
+    request: Request,
+    handler: impl FnOnce(P) -> R,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+impl LanguageServer {
+    fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            schemas: Schemas::new(),
+        }
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match &request.method.clone()[..] {
+            Completion::METHOD => respond(request, |p| self.completion(p)),
+            HoverRequest::METHOD => respond(request, |p| self.hover(p)),
+            GotoDefinition::METHOD => respond(request, |p| self.definition(p)),
+            _ => {
+                let message = format!("Unsupported request {}", request.method);
+                Response::new_err(request.id, ErrorCode::MethodNotFound as i32, message)
+            }
+        }
+    }
+
+    /// Handle a notification, returns the diagnostics that should be published
+    fn handle_notification(&mut self, notification: Notification) -> Vec<(Url, Vec<Diagnostic>)> {
+        fn parse<P: DeserializeOwned>(params: serde_json::Value) -> Option<P> {
+            serde_json::from_value(params).ok()
+        }
+        let Notification { method, params } = notification;
+        match &method[..] {
+            DidOpenTextDocument::METHOD => {
+                if let Some(p) = parse::<DidOpenTextDocumentParams>(params) {
+                    let uri = p.text_document.uri;
+                    self.documents.insert(uri.clone(), p.text_document.text);
+                    return self.diagnostics(&uri).into_iter().collect();
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                if let Some(mut p) = parse::<DidChangeTextDocumentParams>(params) {
+                    if let Some(change) = p.content_changes.pop() {
+                        self.documents.insert(p.text_document.uri, change.text);
+                    }
+                }
+            }
+            DidSaveTextDocument::METHOD => {
+                if let Some(p) = parse::<DidSaveTextDocumentParams>(params) {
+                    return self.saved(&p.text_document.uri);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Some(p) = parse::<DidCloseTextDocumentParams>(params) {
+                    self.documents.remove(&p.text_document.uri);
+                    return vec![(p.text_document.uri, Vec::new())];
+                }
+            }
+            _ => (),
+        }
+        Vec::new()
+    }
+
+    /// The path within the repository and the (possibly unsaved) content of a document
+    fn document(&self, uri: &Url) -> Option<(RumbasPath, String)> {
+        let path = within_repo(&uri.to_file_path().ok()?)?;
+        let text = match self.documents.get(uri) {
+            Some(text) => text.clone(),
+            None => std::fs::read_to_string(path.absolute()).ok()?,
+        };
+        Some((path, text))
+    }
+
+    /// Check the saved file and the open files that depend on it again
+    fn saved(&self, uri: &Url) -> Vec<(Url, Vec<Diagnostic>)> {
+        let path = match uri.to_file_path().ok().and_then(|p| within_repo(&p)) {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+        let file: FileToLoad = RumbasRepoFileData::from(path.clone()).into();
+        CACHE.delete_file(file);
+        let dependants = DEPENDENCIES.get_dependants(path.clone());
+        self.documents
+            .keys()
+            .filter(|open| {
+                *open == uri
+                    || open
+                        .to_file_path()
+                        .ok()
+                        .and_then(|p| within_repo(&p))
+                        .map(|p| dependants.contains(&p))
+                        .unwrap_or(false)
+            })
+            .filter_map(|open| self.diagnostics(open))
+            .collect()
+    }
+
+    /// Check an exam or question file
+    fn diagnostics(&self, uri: &Url) -> Option<(Url, Vec<Diagnostic>)> {
+        let (path, text) = self.document(uri)?;
+        if !path.in_main_folder(rumbas::EXAMS_FOLDER)
+            && !path.in_main_folder(rumbas::QUESTIONS_FOLDER)
+        {
+            return None;
+        }
+        let file_name = path.display().to_string();
+        let diagnostics = check_file(&path, &[])
+            .diagnostics()
+            .into_iter()
+            .map(|d| to_lsp_diagnostic(d, &text, &file_name))
+            .collect();
+        Some((uri.clone(), diagnostics))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let (path, text) = self.document(&position.text_document.uri)?;
+        let schema = self.schemas.for_path(&path)?;
+        let (line, column) = char_position(&text, position.position);
+        let yaml_path = yaml_path_at(&text, line, column)?;
+        let items = if schema.is_jme_at(&yaml_path) {
+            jme_identifiers(&text)
+                .into_iter()
+                .map(|(name, kind)| CompletionItem {
+                    label: name,
+                    kind: Some(kind),
+                    ..Default::default()
+                })
+                .collect()
+        } else {
+            schema
+                .fields_at(&yaml_path)
+                .into_iter()
+                .map(|field| CompletionItem {
+                    insert_text: Some(format!("{}: ", field.name)),
+                    label: field.name,
+                    kind: Some(CompletionItemKind::FIELD),
+                    documentation: field.description.map(Documentation::String),
+                    ..Default::default()
+                })
+                .collect()
+        };
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (path, text) = self.document(&position.text_document.uri)?;
+        let schema = self.schemas.for_path(&path)?;
+        let (line, column) = char_position(&text, position.position);
+        let key_path = yaml_key_at(&text, line, column)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: schema.description_at(&key_path)?,
+            }),
+            range: None,
+        })
+    }
+
+    /// Go to the file of a `file:` reference or to the parent of a template
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (path, text) = self.document(&position.text_document.uri)?;
+        let (line, column) = char_position(&text, position.position);
+        let (value_path, value) = yaml_value_at(&text, line, column)?;
+        let file_prefix = format!("{}:", FILE_PREFIX);
+        let target: RumbasPath = if let Some(file_name) = value.strip_prefix(&file_prefix[..]) {
+            TextFileToRead::with_file_name(file_name.to_string(), &path).into()
+        } else if value_path.last() == Some(&YamlPathPart::Key(TEMPLATE_PREFIX.to_string())) {
+            if path.in_main_folder(rumbas::EXAMS_FOLDER) {
+                ExamFileToRead::with_file_name(value, &path).into()
+            } else {
+                QuestionFileToRead::with_file_name(value, &path).into()
+            }
+        } else {
+            return None;
+        };
+        if !target.is_file() {
+            return None;
+        }
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri: Url::from_file_path(target.absolute()).ok()?,
+            range: Range::default(),
+        }))
+    }
+}
+
+/// The variables and functions that are defined in a question file
+fn jme_identifiers(text: &str) -> Vec<(String, CompletionItemKind)> {
+    yaml_key_paths(text)
+        .into_iter()
+        .filter_map(|(_, _, path)| match &path[..] {
+            [.., YamlPathPart::Key(parent), YamlPathPart::Key(name)] => match &parent[..] {
+                "variables" => Some((name.clone(), CompletionItemKind::VARIABLE)),
+                "functions" => Some((name.clone(), CompletionItemKind::FUNCTION)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// The line and column in characters of a position of the protocol, of which the column is
+/// counted in UTF-16 code units
+fn char_position(text: &str, position: Position) -> (usize, usize) {
+    let line = text.lines().nth(position.line as usize).unwrap_or("");
+    let mut units = 0;
+    let column = line
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= position.character as usize
+        })
+        .count();
+    (position.line as usize, column)
+}
+
+/// The column in UTF-16 code units (as used by the protocol) of a column in characters
+fn utf16_column(line: &str, column: usize) -> u32 {
+    let units: usize = line.chars().take(column).map(char::len_utf16).sum();
+    (units + column.saturating_sub(line.chars().count())) as u32
+}
+
+/// Position a diagnostic of a check in the given file
+///
+/// Problems in other files (e.g. templates) are shown at the start of the file.
+fn to_lsp_diagnostic(diagnostic: RumbasCheckDiagnostic, text: &str, file_name: &str) -> Diagnostic {
+    let mut message = diagnostic.message;
+    let position = match diagnostic.location {
+        Some(location) if location.file == file_name => Some((location.line, location.column)),
+        Some(location) => {
+            message = format!("{}: {}", location, message);
+            None
+        }
+        None => find_field_position(text, &diagnostic.path),
+    };
+    if !diagnostic.path.is_empty() {
+        message = format!("{}\n{}", diagnostic.path, message);
+    }
+    let (line, column) = position
+        .map(|(line, column)| (line.saturating_sub(1), column.saturating_sub(1)))
+        .unwrap_or((0, 0));
+    let line_text = text.lines().nth(line).unwrap_or("");
+    let end = line_text.chars().count().max(column + 1);
+    Diagnostic {
+        range: Range::new(
+            Position::new(line as u32, utf16_column(line_text, column)),
+            Position::new(line as u32, utf16_column(line_text, end)),
+        ),
+        severity: Some(if diagnostic.warning {
+            DiagnosticSeverity::WARNING
+        } else {
+            DiagnosticSeverity::ERROR
+        }),
+        code: Some(NumberOrString::String(diagnostic.kind.to_string())),
+        source: Some("rumbas".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rumbas_support::rumbas_check::RumbasCheckLocation;
+
+    const QUESTION: &str = "statement: |\n  <p>Test</p>\nparts:\n  - type: jme\n    answer: x +* 2\nvariables:\n  a: 1\n";
+
+    fn diagnostic(path: &str, location: Option<RumbasCheckLocation>) -> RumbasCheckDiagnostic {
+        RumbasCheckDiagnostic {
+            kind: "invalid_jme",
+            warning: false,
+            path: path.to_string(),
+            message: "problem".to_string(),
+            location,
+        }
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn diagnostic_at_location() {
+        let location = RumbasCheckLocation {
+            file: "questions/q.yaml".to_string(),
+            line: 5,
+            column: 15,
+        };
+        let result = to_lsp_diagnostic(
+            diagnostic("question.parts.0.answer", Some(location)),
+            QUESTION,
+            "questions/q.yaml",
+        );
+        assert_eq!(result.range, range((4, 14), (4, 18)));
+        assert_eq!(result.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            result.code,
+            Some(NumberOrString::String("invalid_jme".to_string()))
+        );
+        assert_eq!(result.message, "question.parts.0.answer\nproblem");
+    }
+
+    #[test]
+    fn diagnostic_in_other_file() {
+        let location = RumbasCheckLocation {
+            file: "questions/template.yaml".to_string(),
+            line: 2,
+            column: 3,
+        };
+        let result = to_lsp_diagnostic(
+            diagnostic("question.parts.0.answer", Some(location)),
+            QUESTION,
+            "questions/q.yaml",
+        );
+        assert_eq!(result.range, range((0, 0), (0, 12)));
+        assert_eq!(
+            result.message,
+            "question.parts.0.answer\nquestions/template.yaml:2:3: problem"
+        );
+    }
+
+    #[test]
+    fn diagnostic_at_field() {
+        let mut warning = diagnostic("question.variables.a", None);
+        warning.warning = true;
+        let result = to_lsp_diagnostic(warning, QUESTION, "questions/q.yaml");
+        assert_eq!(result.range, range((6, 2), (6, 6)));
+        assert_eq!(result.severity, Some(DiagnosticSeverity::WARNING));
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let text = "a: 1\nb: \"😀é\" # x\n";
+        assert_eq!(char_position(text, Position::new(1, 6)), (1, 5));
+        assert_eq!(char_position(text, Position::new(1, 7)), (1, 6));
+        assert_eq!(char_position(text, Position::new(1, 100)), (1, 11));
+        assert_eq!(utf16_column("b: \"😀é\" # x", 5), 6);
+        assert_eq!(utf16_column("b: \"😀é\" # x", 12), 13);
+    }
+
+    #[test]
+    fn diagnostic_without_position() {
+        let result = to_lsp_diagnostic(diagnostic("", None), "", "questions/q.yaml");
+        assert_eq!(result.range, range((0, 0), (0, 1)));
+        assert_eq!(result.message, "problem");
+    }
+}
@@ -1,21 +1,36 @@
 mod check;
 mod compile;
+mod diff;
 mod editor_output;
 mod fmt;
+mod git;
+mod graph;
 mod import;
 mod init;
 pub mod logger;
+mod lsp;
+mod questions;
 mod rc;
+mod report;
+mod sample;
 mod schema;
+mod serve;
 mod update_repo;
 mod watch;
 
 pub use check::check;
+pub use check::VariablesTestCheck;
 pub use compile::compile;
+pub use diff::diff;
 pub use editor_output::create_editor_output;
 pub use fmt::fmt;
+pub use graph::graph;
 pub use import::import;
 pub use init::init;
+pub use lsp::lsp;
+pub use report::OutputFormat;
+pub use sample::sample;
 pub use schema::schema;
+pub use serve::serve;
 pub use update_repo::update_repo;
 pub use watch::watch;
@@ -0,0 +1,89 @@
+use rumbas::exam::RecursiveTemplateExam;
+use rumbas::question::Question;
+use rumbas::support::rc::within_repo;
+use rumbas_support::path::RumbasPath;
+use rumbas_support::preamble::Input;
+use std::path::Path;
+
+/// The questions of a question (or exam) file, with the locale that should be used for them
+pub struct LoadedQuestions {
+    pub locale: String,
+    /// The questions with their name
+    pub questions: Vec<(String, Question)>,
+}
+
+/// Load the questions of the question (or exam) at the given path
+///
+/// Errors are logged.
+pub fn load_questions(question_path: &str) -> Result<LoadedQuestions, ()> {
+    let path = Path::new(question_path);
+    let path = within_repo(&path);
+    log::debug!("Found path within rumbas project {:?}", path);
+    let path = if let Some(path) = path {
+        path
+    } else {
+        log::error!(
+            "{:?} doesn't seem to belong to a rumbas project.",
+            question_path
+        );
+        return Err(());
+    };
+    if !crate::cli::rc::check_rc(&path, false) {
+        return Err(());
+    }
+    load_questions_from_path(&path)
+}
+
+/// Load the exam (or question) at the given path within a rumbas project, with its templates
+/// and defaults resolved
+///
+/// Errors are logged.
+pub fn load_exam_from_path(path: &RumbasPath) -> Result<RecursiveTemplateExam, ()> {
+    let mut exam_input = match rumbas::exam::RecursiveTemplateExamInput::from_file(path) {
+        Ok(exam_input) => exam_input,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(());
+        }
+    };
+    exam_input.load_files(path);
+    exam_input.combine_with_defaults(path);
+    exam_input.load_files(path);
+    exam_input.to_normal_safe().map_err(|check_result| {
+        check_result.log(path);
+    })
+}
+
+/// Load the questions of the question (or exam) at the given path within a rumbas project
+///
+/// Errors are logged.
+pub fn load_questions_from_path(path: &RumbasPath) -> Result<LoadedQuestions, ()> {
+    let exam = load_exam_from_path(path)?;
+    let locale = if let Some(locale) = exam.data.locales().first() {
+        locale.name.to_owned()
+    } else {
+        log::error!("Locales not set for {}!", path.display());
+        return Err(());
+    };
+    let questions = exam
+        .data
+        .question_groups()
+        .into_iter()
+        .flat_map(|qg| qg.questions.into_iter())
+        .map(|q| {
+            let name = match (q.question_path.clone(), q.template_data.first()) {
+                (Some(name), _) => name,
+                (None, Some(template)) => template.relative_template_path.clone(),
+                (None, None) => {
+                    log::error!(
+                        "A question of {} has no path and no template.",
+                        path.display()
+                    );
+                    return Err(());
+                }
+            };
+            Ok((name, q.data))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(LoadedQuestions { locale, questions })
+}
@@ -0,0 +1,556 @@
+use rumbas::support::yaml::find_field_position;
+use rumbas_support::path::RumbasPath;
+use rumbas_support::rumbas_check::{RumbasCheckDiagnostic, RumbasCheckLocation};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// The format in which the results of a check or compilation are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Log messages
+    Text,
+    /// A json object with the problems of each file
+    Json,
+    /// Static Analysis Results Interchange Format (SARIF) 2.1.0
+    Sarif,
+    /// JUnit xml with a testcase for each file
+    Junit,
+}
+
+impl OutputFormat {
+    /// Write the report to stdout, nothing is written for the text format because the problems
+    /// are already logged
+    pub fn write(&self, name: &str, reports: &[FileReport]) {
+        let output = match self {
+            Self::Text => return,
+            Self::Json => json_report(reports),
+            Self::Sarif => sarif_report(reports),
+            Self::Junit => junit_report(name, reports),
+        };
+        let mut stdout = std::io::stdout();
+        let written = stdout
+            .write_all(output.as_bytes())
+            .and_then(|_| stdout.write_all(b"\n"))
+            .and_then(|_| stdout.flush());
+        match written {
+            Ok(()) => (),
+            // The reader of the report (e.g. `head`) stopped reading
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => (),
+            Err(e) => log::error!("Could not write the report to stdout: {}", e),
+        }
+    }
+}
+
+/// The problems found in a checked (or compiled) file
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    /// The path of the file within the repository
+    pub file: String,
+    pub diagnostics: Vec<RumbasCheckDiagnostic>,
+}
+
+impl FileReport {
+    /// Create the report of a file, the diagnostics without location are positioned at the field
+    /// with the problem (if it is found in the file)
+    pub fn new(path: &RumbasPath, diagnostics: Vec<RumbasCheckDiagnostic>) -> Self {
+        let file = path.display().to_string();
+        let text = std::fs::read_to_string(path.absolute()).unwrap_or_default();
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                if diagnostic.location.is_none() {
+                    diagnostic.location =
+                        find_field_position(&text, &diagnostic.path).map(|(line, column)| {
+                            RumbasCheckLocation {
+                                file: file.clone(),
+                                line,
+                                column,
+                            }
+                        });
+                }
+                diagnostic
+            })
+            .collect();
+        Self { file, diagnostics }
+    }
+
+    /// Whether the file has no errors, warnings are allowed
+    pub fn passed(&self) -> bool {
+        self.diagnostics.iter().all(|d| d.warning)
+    }
+
+    /// The file that contains the problem
+    fn file_of<'a>(&'a self, diagnostic: &'a RumbasCheckDiagnostic) -> &'a str {
+        diagnostic
+            .location
+            .as_ref()
+            .map(|l| &l.file[..])
+            .unwrap_or(&self.file[..])
+    }
+}
+
+/// Remove the diagnostics that are reported more than once (e.g. for multiple locales)
+pub fn deduplicate(
+    diagnostics: impl IntoIterator<Item = RumbasCheckDiagnostic>,
+) -> Vec<RumbasCheckDiagnostic> {
+    let mut result = Vec::new();
+    for diagnostic in diagnostics {
+        if !result.contains(&diagnostic) {
+            result.push(diagnostic);
+        }
+    }
+    result
+}
+
+fn severity(diagnostic: &RumbasCheckDiagnostic) -> &'static str {
+    if diagnostic.warning {
+        "warning"
+    } else {
+        "error"
+    }
+}
+
+fn json_report(reports: &[FileReport]) -> String {
+    let files: Vec<_> = reports
+        .iter()
+        .map(|report| {
+            let diagnostics: Vec<_> = report
+                .diagnostics
+                .iter()
+                .map(|d| {
+                    json!({
+                        "file": report.file_of(d),
+                        "line": d.location.as_ref().map(|l| l.line),
+                        "column": d.location.as_ref().map(|l| l.column),
+                        "path": d.path,
+                        "severity": severity(d),
+                        "kind": d.kind,
+                        "message": d.message,
+                    })
+                })
+                .collect();
+            json!({
+                "file": report.file,
+                "passed": report.passed(),
+                "diagnostics": diagnostics,
+            })
+        })
+        .collect();
+    let failed = reports.iter().filter(|r| !r.passed()).count();
+    serde_json::to_string_pretty(&json!({ "files": files, "failed": failed }))
+        .expect("serializable json report")
+}
+
+fn sarif_report(reports: &[FileReport]) -> String {
+    let rules: BTreeSet<_> = reports
+        .iter()
+        .flat_map(|r| r.diagnostics.iter().map(|d| d.kind))
+        .collect();
+    let rules: Vec<_> = rules.into_iter().map(|id| json!({ "id": id })).collect();
+    let results: Vec<_> = reports
+        .iter()
+        .flat_map(|report| {
+            report.diagnostics.iter().map(move |d| {
+                let mut physical_location = json!({
+                    "artifactLocation": { "uri": report.file_of(d) }
+                });
+                if let Some(location) = &d.location {
+                    physical_location["region"] = json!({
+                        "startLine": location.line,
+                        "startColumn": location.column,
+                    });
+                }
+                let mut location = json!({ "physicalLocation": physical_location });
+                if !d.path.is_empty() {
+                    location["logicalLocations"] = json!([{ "fullyQualifiedName": d.path }]);
+                }
+                json!({
+                    "ruleId": d.kind,
+                    "level": severity(d),
+                    "message": { "text": d.message },
+                    "locations": [location],
+                })
+            })
+        })
+        .collect();
+    let sarif = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rumbas",
+                    "version": clap::crate_version!(),
+                    "informationUri": "https://github.com/m8rex/rumbas",
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }]
+    });
+    serde_json::to_string_pretty(&sarif).expect("serializable sarif report")
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn junit_report(name: &str, reports: &[FileReport]) -> String {
+    let name = escape_xml(name);
+    let failed = reports.iter().filter(|r| !r.passed()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"{0}\" tests=\"{1}\" failures=\"{2}\">\n  <testsuite name=\"{0}\" tests=\"{1}\" failures=\"{2}\">\n",
+        name,
+        reports.len(),
+        failed
+    ));
+    for report in reports.iter() {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\">\n",
+            escape_xml(&report.file),
+            name
+        ));
+        let describe = |d: &RumbasCheckDiagnostic| {
+            let position = match &d.location {
+                Some(location) => location.to_string(),
+                None => report.file.clone(),
+            };
+            escape_xml(&format!(
+                "[{}] {} {}: {}",
+                d.kind, position, d.path, d.message
+            ))
+        };
+        let (warnings, errors): (Vec<_>, Vec<_>) =
+            report.diagnostics.iter().partition(|d| d.warning);
+        if let Some(first) = errors.first() {
+            xml.push_str(&format!(
+                "      <failure message=\"{}\" type=\"{}\">{}</failure>\n",
+                escape_xml(&first.message),
+                first.kind,
+                errors
+                    .iter()
+                    .map(|d| describe(d))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+        }
+        if !warnings.is_empty() {
+            xml.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                warnings
+                    .iter()
+                    .map(|d| describe(d))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>");
+    xml
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use numbas::jme::JMEString;
+    use rumbas_support::rumbas_check::RumbasCheckResult;
+    use std::convert::TryFrom;
+
+    fn location(file: &str, line: usize, column: usize) -> RumbasCheckLocation {
+        RumbasCheckLocation {
+            file: file.to_string(),
+            line,
+            column,
+        }
+    }
+
+    /// The diagnostics of a check result of the field with the given path
+    fn diagnostics(mut result: RumbasCheckResult, path: &[&str]) -> Vec<RumbasCheckDiagnostic> {
+        for part in path.iter().rev() {
+            result.extend_path(part.to_string());
+        }
+        result.diagnostics()
+    }
+
+    fn reports() -> Vec<FileReport> {
+        let source = "x +* 2";
+        let error = JMEString::try_from(source.to_string()).unwrap_err();
+        let mut invalid_jme = RumbasCheckResult::from_invalid_jme(&error, source);
+        invalid_jme.locate_invalid_jme(|_, _, _| Some(location("questions/q.yaml", 5, 13)));
+        let function_calls = JMEString::try_from("len(1, 2)".to_string())
+            .unwrap()
+            .ast()
+            .unwrap()
+            .invalid_function_calls();
+
+        let mut q = diagnostics(invalid_jme, &["question", "parts", "0", "answer"]);
+        q.extend(diagnostics(
+            RumbasCheckResult::from_unused_variable("b".to_string()),
+            &["question", "variables"],
+        ));
+        let mut other = diagnostics(
+            RumbasCheckResult::from_invalid_function_calls(function_calls),
+            &["question", "advice"],
+        );
+        // The advice is defined in a template file
+        other[0].location = Some(location("questions/template.yaml", 2, 3));
+        let translation = diagnostics(
+            RumbasCheckResult::from_missing_translation(Some("nl".to_string())),
+            &["question", "statement"],
+        );
+        vec![
+            FileReport {
+                file: "questions/q.yaml".to_string(),
+                diagnostics: q,
+            },
+            FileReport {
+                file: "questions/other.yaml".to_string(),
+                diagnostics: other,
+            },
+            FileReport {
+                file: "questions/translated.yaml".to_string(),
+                diagnostics: translation,
+            },
+        ]
+    }
+
+    #[test]
+    fn passed() {
+        let reports = reports();
+        assert!(!reports[0].passed());
+        assert!(reports[1].passed());
+        assert!(!reports[2].passed());
+    }
+
+    /// The message of the jme that can't be parsed
+    fn parse_error(reports: &[FileReport]) -> String {
+        reports[0].diagnostics[0].message.clone()
+    }
+
+    #[test]
+    fn json() {
+        let reports = reports();
+        let report: serde_json::Value =
+            serde_json::from_str(&json_report(&reports)).expect("valid json");
+        assert_eq!(
+            report,
+            json!({
+                "failed": 2,
+                "files": [
+                    {
+                        "file": "questions/q.yaml",
+                        "passed": false,
+                        "diagnostics": [
+                            {
+                                "file": "questions/q.yaml",
+                                "line": 5,
+                                "column": 13,
+                                "path": "question.parts.0.answer",
+                                "severity": "error",
+                                "kind": "invalid_jme_string",
+                                "message": parse_error(&reports)
+                            },
+                            {
+                                "file": "questions/q.yaml",
+                                "line": null,
+                                "column": null,
+                                "path": "question.variables.b",
+                                "severity": "warning",
+                                "kind": "unused_jme_variable",
+                                "message": "Unused variable"
+                            }
+                        ]
+                    },
+                    {
+                        "file": "questions/other.yaml",
+                        "passed": true,
+                        "diagnostics": [
+                            {
+                                "file": "questions/template.yaml",
+                                "line": 2,
+                                "column": 3,
+                                "path": "question.advice",
+                                "severity": "warning",
+                                "kind": "invalid_jme_function_call",
+                                "message": "len is called with 2 arguments, but expects len(anything)"
+                            }
+                        ]
+                    },
+                    {
+                        "file": "questions/translated.yaml",
+                        "passed": false,
+                        "diagnostics": [
+                            {
+                                "file": "questions/translated.yaml",
+                                "line": null,
+                                "column": null,
+                                "path": "question.statement.nl",
+                                "severity": "error",
+                                "kind": "missing_translation",
+                                "message": "Missing translation"
+                            }
+                        ]
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn sarif() {
+        let reports = reports();
+        let report: serde_json::Value =
+            serde_json::from_str(&sarif_report(&reports)).expect("valid json");
+        assert_eq!(
+            report,
+            json!({
+                "$schema": SARIF_SCHEMA,
+                "version": "2.1.0",
+                "runs": [{
+                    "tool": {
+                        "driver": {
+                            "name": "rumbas",
+                            "version": clap::crate_version!(),
+                            "informationUri": "https://github.com/m8rex/rumbas",
+                            "rules": [
+                                { "id": "invalid_jme_function_call" },
+                                { "id": "invalid_jme_string" },
+                                { "id": "missing_translation" },
+                                { "id": "unused_jme_variable" }
+                            ]
+                        }
+                    },
+                    "results": [
+                        {
+                            "ruleId": "invalid_jme_string",
+                            "level": "error",
+                            "message": { "text": parse_error(&reports) },
+                            "locations": [{
+                                "physicalLocation": {
+                                    "artifactLocation": { "uri": "questions/q.yaml" },
+                                    "region": { "startLine": 5, "startColumn": 13 }
+                                },
+                                "logicalLocations": [
+                                    { "fullyQualifiedName": "question.parts.0.answer" }
+                                ]
+                            }]
+                        },
+                        {
+                            "ruleId": "unused_jme_variable",
+                            "level": "warning",
+                            "message": { "text": "Unused variable" },
+                            "locations": [{
+                                "physicalLocation": {
+                                    "artifactLocation": { "uri": "questions/q.yaml" }
+                                },
+                                "logicalLocations": [
+                                    { "fullyQualifiedName": "question.variables.b" }
+                                ]
+                            }]
+                        },
+                        {
+                            "ruleId": "invalid_jme_function_call",
+                            "level": "warning",
+                            "message": {
+                                "text": "len is called with 2 arguments, but expects len(anything)"
+                            },
+                            "locations": [{
+                                "physicalLocation": {
+                                    "artifactLocation": { "uri": "questions/template.yaml" },
+                                    "region": { "startLine": 2, "startColumn": 3 }
+                                },
+                                "logicalLocations": [
+                                    { "fullyQualifiedName": "question.advice" }
+                                ]
+                            }]
+                        },
+                        {
+                            "ruleId": "missing_translation",
+                            "level": "error",
+                            "message": { "text": "Missing translation" },
+                            "locations": [{
+                                "physicalLocation": {
+                                    "artifactLocation": { "uri": "questions/translated.yaml" }
+                                },
+                                "logicalLocations": [
+                                    { "fullyQualifiedName": "question.statement.nl" }
+                                ]
+                            }]
+                        }
+                    ]
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn junit() {
+        let reports = reports();
+        let parse_error = escape_xml(&parse_error(&reports));
+        assert_eq!(
+            junit_report("check <questions>", &reports),
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="check &lt;questions&gt;" tests="3" failures="2">
+  <testsuite name="check &lt;questions&gt;" tests="3" failures="2">
+    <testcase name="questions/q.yaml" classname="check &lt;questions&gt;">
+      <failure message="{0}" type="invalid_jme_string">[invalid_jme_string] questions/q.yaml:5:13 question.parts.0.answer: {0}</failure>
+      <system-out>[unused_jme_variable] questions/q.yaml question.variables.b: Unused variable</system-out>
+    </testcase>
+    <testcase name="questions/other.yaml" classname="check &lt;questions&gt;">
+      <system-out>[invalid_jme_function_call] questions/template.yaml:2:3 question.advice: len is called with 2 arguments, but expects len(anything)</system-out>
+    </testcase>
+    <testcase name="questions/translated.yaml" classname="check &lt;questions&gt;">
+      <failure message="Missing translation" type="missing_translation">[missing_translation] questions/translated.yaml question.statement.nl: Missing translation</failure>
+    </testcase>
+  </testsuite>
+</testsuites>"#,
+                parse_error
+            )
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_xml("&lt;"), "&amp;lt;");
+    }
+
+    #[test]
+    fn duplicates() {
+        let error = diagnostics(
+            RumbasCheckResult::from_missing_translation(Some("nl".to_string())),
+            &["question", "advice"],
+        )
+        .remove(0);
+        let warning = diagnostics(
+            RumbasCheckResult::from_unused_variable("a".to_string()),
+            &["question", "variables"],
+        )
+        .remove(0);
+        let mut located = error.clone();
+        located.location = Some(location("questions/q.yaml", 1, 1));
+        assert_eq!(
+            deduplicate(vec![
+                error.clone(),
+                warning.clone(),
+                error.clone(),
+                located.clone(),
+                warning.clone()
+            ]),
+            vec![error, warning, located]
+        );
+    }
+}
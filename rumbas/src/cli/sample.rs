@@ -0,0 +1,202 @@
+use crate::cli::questions::load_questions;
+use rumbas::question::sample::VariablesSample;
+use rumbas::question::Question;
+use std::io::Write;
+
+pub fn sample(question_path: String, seeds: u64) {
+    match sample_internal(question_path, seeds) {
+        Ok(output) => {
+            std::io::stdout()
+                .write_all(output.as_bytes())
+                .expect("writing to stdout");
+        }
+        Err(_) => std::process::exit(1),
+    }
+}
+
+/// Generate the variables of the questions at the given path for the seeds `0..seeds`
+///
+/// Returns a table with the generated values and a summary of the variables test for each question.
+pub fn sample_internal(question_path: String, seeds: u64) -> Result<String, ()> {
+    let loaded = load_questions(&question_path)?;
+    Ok(loaded
+        .questions
+        .iter()
+        .map(|(name, question)| sample_question(name, question, &loaded.locale, seeds))
+        .collect())
+}
+
+/// The table and summary of the variables of one question for the seeds `0..seeds`
+fn sample_question(name: &str, question: &Question, locale: &str, seeds: u64) -> String {
+    let samples: Vec<_> = (0..seeds)
+        .map(|seed| question.sample_variables(locale, seed))
+        .collect();
+    let variable_names: Vec<_> = question.variables.keys().cloned().collect();
+    let mut output = format!("{}\n\n", name);
+    output.push_str(&table(&variable_names, &samples));
+    output.push('\n');
+    output.push_str(&summary(&samples, question.variables_test.max_runs.max(1)));
+    output.push('\n');
+    output
+}
+
+fn table(variable_names: &[String], samples: &[VariablesSample]) -> String {
+    let header: Vec<_> = vec![
+        "seed".to_string(),
+        "runs".to_string(),
+        "condition".to_string(),
+    ]
+    .into_iter()
+    .chain(variable_names.iter().cloned())
+    .collect();
+    let rows: Vec<Vec<String>> =
+        samples
+            .iter()
+            .map(|sample| {
+                let mut row = vec![sample.seed.to_string(), sample.runs.to_string()];
+                match &sample.values {
+                    Ok(values) => {
+                        row.push(if sample.is_accepted() { "ok" } else { "failed" }.to_string());
+                        row.extend(variable_names.iter().map(|name| {
+                            values.get(name).map(|v| v.to_string()).unwrap_or_default()
+                        }));
+                    }
+                    Err(e) => {
+                        row.push("error".to_string());
+                        row.push(e.to_string());
+                    }
+                }
+                row
+            })
+            .collect();
+
+    let mut widths: Vec<_> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows.iter() {
+        // An error message is not aligned with the variable columns
+        if row.len() == header.len() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+    }
+    let format_row = |row: &[String]| {
+        row.iter()
+            .enumerate()
+            .map(|(i, cell)| match widths.get(i) {
+                Some(width) if row.len() == header.len() || i < 3 => {
+                    format!("{:width$}", cell, width = width)
+                }
+                _ => cell.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    let mut lines = vec![format_row(&header)];
+    lines.push(
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-|-"),
+    );
+    lines.extend(rows.iter().map(|row| format_row(row)));
+    lines.join("\n") + "\n"
+}
+
+fn summary(samples: &[VariablesSample], max_runs: usize) -> String {
+    let runs: usize = samples.iter().map(|s| s.runs).sum();
+    let failed_conditions: usize = samples.iter().map(|s| s.failed_conditions).sum();
+    let rejected = samples
+        .iter()
+        .filter(|s| s.values.is_ok() && !s.is_accepted())
+        .count();
+    let errors = samples.iter().filter(|s| s.values.is_err()).count();
+    let mut lines = vec![format!(
+        "The condition failed in {} of {} runs ({:.1}%).",
+        failed_conditions,
+        runs,
+        if runs == 0 {
+            0.0
+        } else {
+            100.0 * failed_conditions as f64 / runs as f64
+        }
+    )];
+    if rejected > 0 {
+        lines.push(format!(
+            "For {} of {} seeds, the condition was not satisfied within {} runs.",
+            rejected,
+            samples.len(),
+            max_runs
+        ));
+    }
+    if errors > 0 {
+        lines.push(format!(
+            "For {} of {} seeds, the variables could not be generated.",
+            errors,
+            samples.len()
+        ));
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rumbas::support::to_rumbas::ToRumbas;
+
+    fn question(condition: &str) -> Question {
+        let numbas_question: numbas::question::Question = serde_json::from_str(&format!(
+            r#"{{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {{"allowregen": true, "showfrontpage": false, "preventleave": false}},
+                "variables": {{
+                    "a": {{"name": "a", "group": "Ungrouped variables", "definition": "2", "description": "", "templateType": "anything"}},
+                    "b": {{"name": "b", "group": "Ungrouped variables", "definition": "a^2", "description": "", "templateType": "anything"}}
+                }},
+                "variablesTest": {{"condition": "{}", "maxRuns": 3}}
+            }}"#,
+            condition
+        ))
+        .unwrap();
+        numbas_question.to_rumbas()
+    }
+
+    #[test]
+    fn accepted_samples() {
+        assert_eq!(
+            sample_question("q", &question("b > 3"), "en", 2),
+            "q
+
+seed | runs | condition | a | b
+-----|------|-----------|---|--
+0    | 1    | ok        | 2 | 4
+1    | 1    | ok        | 2 | 4
+
+The condition failed in 0 of 2 runs (0.0%).
+
+"
+        );
+    }
+
+    #[test]
+    fn rejected_samples() {
+        assert_eq!(
+            sample_question("q", &question("b > 10"), "en", 2),
+            "q
+
+seed | runs | condition | a | b
+-----|------|-----------|---|--
+0    | 3    | failed    | 2 | 4
+1    | 3    | failed    | 2 | 4
+
+The condition failed in 6 of 6 runs (100.0%).
+For 2 of 2 seeds, the condition was not satisfied within 3 runs.
+
+"
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use crate::cli::compile::{FileCompilationContext, OUTPUT_FOLDER};
+use crate::cli::report::escape_xml;
+use crate::cli::watch::{watch_with_handler, WatchCompiler, WatchHandler};
+use rumbas::support::rc::find_root;
+use rumbas_support::path::RumbasPath;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The url on which the served pages listen for reload events
+const RELOAD_URL: &str = "/__rumbas/reload";
+/// The maximal amount of connections that are handled at the same time
+///
+/// Each connection is handled by its own thread and each open page keeps a connection open for
+/// the reload events (until the first event or heartbeat after the page is closed), so the
+/// amount of threads is limited.
+const MAX_CONNECTIONS: usize = 64;
+/// The time after which a connection is closed when reading the request or writing the response
+/// doesn't progress
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+/// The time between the heartbeats of the reload events, a write to a closed page fails so its
+/// connection is closed
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn serve(serve_path: String, port: u16, locales: Vec<String>, native: bool) {
+    let root = match find_root(Path::new(".")) {
+        Some(root) => root,
+        None => {
+            log::error!("Missing rc file");
+            std::process::exit(1)
+        }
+    };
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Could not listen on port {}: {}", port, e);
+            std::process::exit(1)
+        }
+    };
+    let output_folder = root.join(OUTPUT_FOLDER);
+    let events = Arc::new(ReloadEvents::default());
+
+    let server_events = events.clone();
+    std::thread::spawn(move || {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for mut stream in listener.incoming().flatten() {
+            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                log::warn!(
+                    "Refused a request because {} connections are open",
+                    MAX_CONNECTIONS
+                );
+                respond(
+                    &mut stream,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    b"Too many connections",
+                )
+                .unwrap_or(()); // If error, don't mind
+                continue;
+            }
+            let output_folder = output_folder.clone();
+            let events = server_events.clone();
+            let connections = connections.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &output_folder, &events) {
+                    log::debug!("Failed handling a request: {}", e);
+                }
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    log::info!("Serving the compiled exams on http://localhost:{}", port);
+
+    let compiler = WatchCompiler::new(FileCompilationContext {
+        use_scorm: false,
+        as_zip: false,
+        minify: false,
+        output_folder: Path::new(OUTPUT_FOLDER).to_path_buf(),
+        locales,
+        native,
+    });
+    watch_with_handler(&serve_path, &ServeHandler { compiler, events });
+}
+
+/// The browser tabs that listen for reload events
+#[derive(Default)]
+struct ReloadEvents {
+    listeners: Mutex<Vec<Sender<()>>>,
+}
+
+impl ReloadEvents {
+    fn listen(&self) -> Receiver<()> {
+        let (sender, receiver) = channel();
+        self.listeners.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Send a reload event to all listeners, the listeners of closed tabs are removed
+    fn reload(&self) {
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.retain(|listener| listener.send(()).is_ok());
+        log::debug!("Sent reload event to {} pages", listeners.len());
+    }
+}
+
+/// Compiles like `rumbas watch` and reloads the served pages after each recompilation
+struct ServeHandler {
+    compiler: WatchCompiler,
+    events: Arc<ReloadEvents>,
+}
+
+impl WatchHandler for ServeHandler {
+    fn handle_setup(&self, path: &str) {
+        self.compiler.handle_setup(path)
+    }
+    fn handle_file(&self, path: &RumbasPath) {
+        self.compiler.handle_file(path)
+    }
+    fn after_change(&self) {
+        self.events.reload();
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    output_folder: &Path,
+    events: &ReloadEvents,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are not used
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut stream = stream;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or("/");
+    if method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method not allowed",
+        );
+    }
+    let raw_path = target.split(['?', '#']).next().unwrap_or("/");
+    let url_path = percent_decode(raw_path);
+    if url_path == RELOAD_URL {
+        return send_reload_events(stream, events);
+    }
+
+    match resolve(output_folder, &url_path) {
+        Some(path) if path.is_dir() && !url_path.ends_with('/') => {
+            redirect(&mut stream, &format!("{}/", raw_path))
+        }
+        Some(path) if path.is_dir() && path.join("index.html").is_file() => {
+            serve_file(&mut stream, &path.join("index.html"))
+        }
+        Some(path) if path.is_dir() => {
+            let listing = directory_listing(&path, &url_path)?;
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                &inject_reload_snippet(listing.into_bytes()),
+            )
+        }
+        Some(path) if path.is_file() => serve_file(&mut stream, &path),
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found"),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn redirect(stream: &mut TcpStream, location: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        location
+    )?;
+    stream.flush()
+}
+
+/// Keep the connection open and send an event each time the exams are recompiled
+fn send_reload_events(mut stream: TcpStream, events: &ReloadEvents) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n"
+    )?;
+    stream.flush()?;
+    stream_reload_events(&mut stream, events.listen(), HEARTBEAT_INTERVAL)
+}
+
+/// Write a reload event for each received message and a heartbeat (a comment, which is ignored
+/// by the page) when no message is received within the interval
+fn stream_reload_events(
+    stream: &mut impl Write,
+    receiver: Receiver<()>,
+    heartbeat_interval: Duration,
+) -> std::io::Result<()> {
+    loop {
+        match receiver.recv_timeout(heartbeat_interval) {
+            Ok(()) => stream.write_all(b"data: reload\n\n")?,
+            Err(RecvTimeoutError::Timeout) => stream.write_all(b": heartbeat\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        stream.flush()?;
+    }
+}
+
+fn serve_file(stream: &mut TcpStream, path: &Path) -> std::io::Result<()> {
+    let content = std::fs::read(path)?;
+    let content_type = content_type(path);
+    if content_type.starts_with("text/html") {
+        respond(
+            stream,
+            "200 OK",
+            content_type,
+            &inject_reload_snippet(content),
+        )
+    } else {
+        respond(stream, "200 OK", content_type, &content)
+    }
+}
+
+/// The path of the file in the output folder for the path of an url, None if it would be outside
+/// the output folder
+fn resolve(output_folder: &Path, url_path: &str) -> Option<PathBuf> {
+    let mut path = output_folder.to_path_buf();
+    for part in url_path.split('/').filter(|p| !p.is_empty()) {
+        if !Path::new(part)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        path.push(part);
+    }
+    Some(path)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 3;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+    {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" => "application/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Add a script that reloads the page when the exams are recompiled
+fn inject_reload_snippet(html: Vec<u8>) -> Vec<u8> {
+    let snippet = format!(
+        r#"<script>new EventSource("{}").onmessage = function() {{ window.location.reload(); }};</script>"#,
+        RELOAD_URL
+    );
+    let html = String::from_utf8_lossy(&html).to_string();
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], snippet, &html[index..]),
+        None => format!("{}{}", html, snippet),
+    }
+    .into_bytes()
+}
+
+/// A page with links to the files and folders in a folder of the output
+fn directory_listing(path: &Path, url_path: &str) -> std::io::Result<String> {
+    let mut entries: Vec<_> = std::fs::read_dir(path)?
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() {
+                format!("{}/", name)
+            } else {
+                name
+            }
+        })
+        .collect();
+    entries.sort();
+    let title = escape_xml(url_path);
+    let links: String = entries
+        .iter()
+        .map(|name| format!("<li><a href=\"{0}\">{0}</a></li>", escape_xml(name)))
+        .collect();
+    Ok(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head><body><h1>{0}</h1><ul>{1}</ul></body></html>",
+        title, links
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_paths() {
+        let output = Path::new("/project/_output");
+        assert_eq!(
+            resolve(output, "/nl/exams/exam/index.html"),
+            Some(PathBuf::from("/project/_output/nl/exams/exam/index.html"))
+        );
+        assert_eq!(
+            resolve(output, "/"),
+            Some(PathBuf::from("/project/_output"))
+        );
+        assert_eq!(
+            resolve(output, "//nl//exams/"),
+            Some(PathBuf::from("/project/_output/nl/exams"))
+        );
+        assert_eq!(resolve(output, "/nl/../../secret"), None);
+        assert_eq!(resolve(output, "/nl/./exams"), None);
+        assert_eq!(resolve(output, &percent_decode("/%2e%2e/secret")), None);
+        assert_eq!(
+            resolve(output, &percent_decode("/nl/%2E%2E/%2e%2e/secret")),
+            None
+        );
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode("/exams/my%20exam/"), "/exams/my exam/");
+        assert_eq!(percent_decode("/%C3%A9t%C3%A9"), "/été");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("100%2"), "100%2");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+        assert_eq!(percent_decode("%2"), "%2");
+        assert_eq!(percent_decode("%41%4"), "A%4");
+    }
+
+    #[test]
+    fn reload_events() {
+        let (sender, receiver) = channel();
+        sender.send(()).unwrap();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(sender);
+        });
+        let mut output = Vec::new();
+        stream_reload_events(&mut output, receiver, Duration::from_millis(10)).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("data: reload\n\n: heartbeat\n\n"));
+        assert_eq!(output.matches("data: reload").count(), 1);
+    }
+
+    #[test]
+    fn reload_snippet() {
+        let snippet = format!(
+            r#"<script>new EventSource("{}").onmessage = function() {{ window.location.reload(); }};</script>"#,
+            RELOAD_URL
+        );
+        assert_eq!(
+            String::from_utf8(inject_reload_snippet(
+                b"<html><body><p>Exam</p></BODY></html>".to_vec()
+            ))
+            .unwrap(),
+            format!("<html><body><p>Exam</p>{}</BODY></html>", snippet)
+        );
+        assert_eq!(
+            String::from_utf8(inject_reload_snippet(b"<p>Exam</p>".to_vec())).unwrap(),
+            format!("<p>Exam</p>{}", snippet)
+        );
+    }
+}
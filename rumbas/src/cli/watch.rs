@@ -1,19 +1,40 @@
 use crate::cli::compile::{CompilationContext, FileCompilationContext};
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 use rumbas::support::dependency_manager::DEPENDENCIES;
-use rumbas::support::file_manager::RumbasRepoFileData;
 use rumbas::support::file_manager::CACHE;
-use rumbas::support::rc::within_repo;
+use rumbas::support::file_manager::{RumbasRepoFileData, RumbasRepoFileType};
+use rumbas::support::rc::find_root;
 use rumbas_support::input::FileToLoad;
 use rumbas_support::path::RumbasPath;
 use std::path::Path;
 use std::sync::mpsc::channel;
 use std::time::Duration;
 
-pub fn watch(watch_path: String, only_check: bool) {
+pub fn watch(
+    watch_path: String,
+    only_check: bool,
+    use_scorm: bool,
+    as_zip: bool,
+    no_minification: bool,
+    locales: Vec<String>,
+    native: bool,
+) {
+    if native && !no_minification && !only_check {
+        log::warn!(
+            "The native packager does not minify the js and css files, use --no-minification to hide this warning."
+        );
+    }
     watch_internal(WatchContext {
         watch_path,
         only_check,
+        file_context: FileCompilationContext {
+            use_scorm,
+            as_zip,
+            minify: !no_minification,
+            output_folder: Path::new(crate::cli::compile::OUTPUT_FOLDER).to_path_buf(),
+            locales,
+            native,
+        },
     })
 }
 
@@ -21,103 +42,222 @@ pub fn watch(watch_path: String, only_check: bool) {
 pub struct WatchContext {
     pub watch_path: String,
     pub only_check: bool,
+    pub file_context: FileCompilationContext,
 }
 
 fn watch_internal(context: WatchContext) {
-    let path = Path::new(".");
-    if path.is_absolute() {
-        // TODO
-        log::error!("Absolute path's are not supported");
-        return;
+    if context.only_check {
+        watch_with_handler(
+            &context.watch_path,
+            &WatchChecker {
+                locales: context.file_context.locales,
+            },
+        )
+    } else {
+        watch_with_handler(
+            &context.watch_path,
+            &WatchCompiler::new(context.file_context),
+        )
     }
+}
 
-    let handler: &dyn WatchHandler = if context.only_check {
-        &WatchChecker
-    } else {
-        &WatchCompiler
+/// Handle the given path and keep handling the files that change
+pub fn watch_with_handler(watch_path: &str, handler: &dyn WatchHandler) {
+    let root = match find_root(Path::new(".")) {
+        Some(root) => root,
+        None => {
+            log::error!("Missing rc file");
+            return;
+        }
+    };
+    let watched = match RumbasPath::create(Path::new(watch_path), &root) {
+        Some(watched) => watched,
+        None => {
+            log::error!(
+                "{:?} doesn't seem to belong to a rumbas project.",
+                watch_path
+            );
+            return;
+        }
     };
 
-    handler.handle_setup(&context.watch_path);
+    handler.handle_setup(watch_path);
 
-    log::info!("Watching {:?}", path.display());
+    log::info!("Watching {:?}", watched.display());
 
     // Create a channel to receive the events.
     let (tx, rx) = channel();
 
     // Create a watcher object, delivering debounced events.
     // The notification back-end is selected based on the platform.
-    let mut watcher = watcher(tx, Duration::from_secs(2)).unwrap();
+    let mut watcher = match watcher(tx, Duration::from_secs(2)) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            log::error!("Could not create a file watcher: {}", e);
+            return;
+        }
+    };
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    watcher.watch(path, RecursiveMode::Recursive).unwrap();
+    // The whole repository is monitored because the files in the watched path can depend on
+    // files elsewhere (e.g. default files, templates and custom part types), only the files
+    // in the watched path are handled.
+    if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+        log::error!("Could not watch {}: {}", root.display(), e);
+        return;
+    }
 
+    let repo_path = |p: &Path| {
+        RumbasPath::create(p, &root).filter(|p| {
+            !p.in_main_folder(crate::cli::compile::CACHE_FOLDER)
+                && !p.in_main_folder(crate::cli::compile::OUTPUT_FOLDER)
+        })
+    };
     loop {
         match rx.recv() {
-            Ok(event) => match event {
-                DebouncedEvent::Create(p) => handle_if_needed(&p, handler), // Shouldn't do anything?
-                DebouncedEvent::Write(p) => handle_if_needed(&p, handler),
-                DebouncedEvent::Chmod(p) => handle_if_needed(&p, handler),
-                DebouncedEvent::Remove(p) => handle_if_needed(&p, handler),
-                DebouncedEvent::Rename(_previous, _new) => (), // TODO do the rename in the dependencies?
-                _ => (),
-            },
+            Ok(event) => {
+                match event {
+                    DebouncedEvent::Create(p) => {
+                        if let Some(path) = repo_path(&p) {
+                            CACHE.invalidate_folder_of(&path);
+                            handler.handle_created(path, &watched)
+                        }
+                    }
+                    DebouncedEvent::Write(p) | DebouncedEvent::Chmod(p) => {
+                        if let Some(path) = repo_path(&p) {
+                            handler.recompile_dependant(path, &watched)
+                        }
+                    }
+                    DebouncedEvent::Remove(p) => {
+                        if let Some(path) = repo_path(&p) {
+                            CACHE.invalidate_folder_of(&path);
+                            handler.handle_removed(path, &watched)
+                        }
+                    }
+                    DebouncedEvent::Rename(previous, new) => {
+                        match (repo_path(&previous), repo_path(&new)) {
+                            (Some(previous), Some(new)) => {
+                                CACHE.invalidate_folder_of(&previous);
+                                CACHE.invalidate_folder_of(&new);
+                                handler.handle_renamed(previous, new, &watched)
+                            }
+                            (Some(previous), None) => {
+                                CACHE.invalidate_folder_of(&previous);
+                                handler.handle_removed(previous, &watched)
+                            }
+                            (None, Some(new)) => {
+                                CACHE.invalidate_folder_of(&new);
+                                handler.handle_created(new, &watched)
+                            }
+                            (None, None) => (),
+                        }
+                    }
+                    _ => continue,
+                }
+                handler.after_change();
+            }
             Err(e) => log::error!("watch error: {:?}", e),
         }
     }
 }
 
-fn handle_if_needed(path: &Path, handler: &dyn WatchHandler) {
-    if let Some(path) = within_repo(&path) {
-        if path.in_main_folder(crate::cli::compile::CACHE_FOLDER)
-            || path.in_main_folder(crate::cli::compile::OUTPUT_FOLDER)
-        {
-            // do nothing
-        } else {
-            handler.recompile_dependant(path)
-        }
-    }
+/// Whether the path is (in) the given file or folder
+fn is_in(path: &RumbasPath, folder: &RumbasPath) -> bool {
+    path.project().starts_with(folder.project())
+}
+
+/// Whether the path is an exam or a question (and not e.g. a template or a default file)
+fn is_exam_or_question(path: &RumbasPath) -> bool {
+    path.is_file()
+        && matches!(
+            RumbasRepoFileType::from(path),
+            RumbasRepoFileType::ExamFile | RumbasRepoFileType::QuestionFile
+        )
 }
 
-trait WatchHandler {
+pub trait WatchHandler {
     fn handle_setup(&self, path: &str);
     fn handle_file(&self, path: &RumbasPath);
-    fn recompile_dependant(&self, path: RumbasPath) {
-        let file_data = RumbasRepoFileData::from(path.clone());
-        let relative_path = file_data.dependency_path();
-
-        let file_to_remove: FileToLoad = file_data.into();
+    /// Called after the files that are affected by a change are handled
+    fn after_change(&self) {}
+    /// Handle the files in the watched path that depend on the changed file
+    fn recompile_dependant(&self, path: RumbasPath, watched: &RumbasPath) {
+        let file_to_remove: FileToLoad = RumbasRepoFileData::from(path.clone()).into();
         CACHE.delete_file(file_to_remove);
 
         DEPENDENCIES.log_debug();
 
         let dependants = DEPENDENCIES.get_dependants(path);
-        for dependant in dependants.iter() {
+        for dependant in dependants.iter().filter(|d| is_in(d, watched)) {
+            self.handle_file(dependant);
+        }
+    }
+    /// Handle a new file or folder, the new exams and questions in the watched path are handled
+    fn handle_created(&self, path: RumbasPath, watched: &RumbasPath) {
+        let is_watched = is_in(&path, watched);
+        let is_new = |file: &RumbasPath| !DEPENDENCIES.get_dependants(file.clone()).contains(file);
+        if path.is_dir() {
+            if is_watched
+                && (path.in_main_folder(rumbas::EXAMS_FOLDER)
+                    || path.in_main_folder(rumbas::QUESTIONS_FOLDER))
+            {
+                for file in crate::cli::check::find_all_files(path)
+                    .iter()
+                    .filter(|f| is_new(f))
+                {
+                    log::info!("Found new file {}", file.display());
+                    self.handle_file(file);
+                }
+            }
+        } else if is_watched && is_exam_or_question(&path) && is_new(&path) {
+            log::info!("Found new file {}", path.display());
+            self.handle_file(&path);
+        } else {
+            // A known file (e.g. a renamed exam) and the files that depend on it
+            self.recompile_dependant(path, watched)
+        }
+    }
+    /// Handle a removed file, the files that depended on it are handled again
+    fn handle_removed(&self, path: RumbasPath, watched: &RumbasPath) {
+        let file_to_remove: FileToLoad = RumbasRepoFileData::from(path.clone()).into();
+        CACHE.delete_file(file_to_remove);
+
+        for dependant in DEPENDENCIES
+            .remove(&path)
+            .iter()
+            .filter(|d| is_in(d, watched))
+        {
             self.handle_file(dependant);
         }
     }
+    /// Handle a renamed (or moved) file
+    fn handle_renamed(&self, previous: RumbasPath, new: RumbasPath, watched: &RumbasPath) {
+        let file_to_remove: FileToLoad = RumbasRepoFileData::from(previous.clone()).into();
+        CACHE.delete_file(file_to_remove);
+
+        DEPENDENCIES.rename(&previous, new.clone());
+        self.handle_created(new, watched)
+    }
 }
 
-pub struct WatchChecker;
+pub struct WatchChecker {
+    locales: Vec<String>,
+}
 impl WatchHandler for WatchChecker {
     fn handle_setup(&self, path: &str) {
         // TODO
-        crate::cli::check::check_internal(vec![path.to_string()]);
+        crate::cli::check::check_internal(vec![path.to_string()], None, &self.locales);
     }
     fn handle_file(&self, path: &RumbasPath) {
-        crate::cli::check::check_file(&path);
+        crate::cli::check::check_file(&path, &self.locales);
     }
 }
 
-pub struct WatchCompiler;
+pub struct WatchCompiler {
+    file_context: FileCompilationContext,
+}
 impl WatchCompiler {
-    fn file_context() -> FileCompilationContext {
-        FileCompilationContext {
-            use_scorm: false,
-            as_zip: false,
-            minify: false,
-            output_folder: Path::new(crate::cli::compile::OUTPUT_FOLDER).to_path_buf(),
-        }
+    pub fn new(file_context: FileCompilationContext) -> Self {
+        Self { file_context }
     }
 }
 impl WatchHandler for WatchCompiler {
@@ -126,11 +266,12 @@ impl WatchHandler for WatchCompiler {
         crate::cli::compile::compile_internal(
             CompilationContext {
                 compile_paths: vec![path.to_string()],
+                changed_since: None,
             },
-            Self::file_context(),
+            self.file_context.clone(),
         );
     }
     fn handle_file(&self, path: &RumbasPath) {
-        crate::cli::compile::compile_file(&Self::file_context(), &path);
+        crate::cli::compile::compile_file(&self.file_context, &path);
     }
 }
@@ -65,6 +65,13 @@ impl Exam {
             Exam::Diagnostic(n) => n.numbas_settings.clone(),
         }
     }
+
+    pub fn question_groups(&self) -> Vec<question_group::QuestionGroup> {
+        match self {
+            Exam::Normal(n) => n.question_groups.clone(),
+            Exam::Diagnostic(n) => n.question_groups.clone(),
+        }
+    }
 }
 impl ExamInput {
     pub fn combine_with_defaults(&mut self, path: &RumbasPath) {
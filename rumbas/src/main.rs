@@ -19,6 +19,8 @@ fn main() {
     let args = Cli::parse();
 
     let log_level = match (args.quiet, args.verbose) {
+        // The language server uses stdout to talk to the editor
+        _ if matches!(args.command, Command::Lsp) => log::LevelFilter::Off,
         (true, _) => log::LevelFilter::Off,
         (false, 1) => log::LevelFilter::Error,
         (false, 2) => log::LevelFilter::Warn,
@@ -26,7 +28,9 @@ fn main() {
         (false, _) => log::LevelFilter::Debug, // debug for 4 or more v's
     };
 
-    cli::logger::setup(log_level).expect("Working logger");
+    // Reports are written to stdout, so they should not be mixed with the log messages
+    let log_to_stderr = args.command.output_format() != cli::OutputFormat::Text;
+    cli::logger::setup(log_level, log_to_stderr).expect("Working logger");
 
     match args.command {
         Command::Import {
@@ -38,17 +42,75 @@ fn main() {
             scorm,
             zip,
             no_minification,
-        } => cli::compile(exam_or_question_paths, scorm, zip, no_minification),
-        Command::Watch { path, only_check } => cli::watch(path, only_check),
+            format,
+            changed_since,
+            locales,
+            native,
+        } => cli::compile(
+            exam_or_question_paths,
+            scorm,
+            zip,
+            no_minification,
+            format,
+            changed_since,
+            locales,
+            native,
+        ),
+        Command::Watch {
+            path,
+            only_check,
+            scorm,
+            zip,
+            no_minification,
+            locales,
+            native,
+        } => cli::watch(
+            path,
+            only_check,
+            scorm,
+            zip,
+            no_minification,
+            locales,
+            native,
+        ),
+        Command::Serve {
+            path,
+            port,
+            locales,
+            native,
+        } => cli::serve(path, port, locales, native),
         Command::Check {
             exam_or_question_paths,
-        } => cli::check(exam_or_question_paths),
+            variables_test_runs,
+            variables_test_threshold,
+            format,
+            locales,
+        } => cli::check(
+            exam_or_question_paths,
+            variables_test_runs.map(|runs| cli::VariablesTestCheck {
+                runs,
+                threshold: variables_test_threshold,
+            }),
+            format,
+            locales,
+        ),
         Command::UpdateRepo => cli::update_repo(),
         Command::Init => cli::init(),
         Command::Schema => cli::schema(),
+        Command::Lsp => cli::lsp(),
         Command::Fmt {
             exam_or_question_paths,
         } => cli::fmt(exam_or_question_paths),
+        Command::Graph { question_path } => cli::graph(question_path),
+        Command::Diff {
+            revision_a,
+            revision_b,
+            paths,
+        } => cli::diff(revision_a, revision_b, paths),
+        Command::Sample {
+            question_path,
+            seeds,
+        } => cli::sample(question_path, seeds),
         Command::EditorOutput {
             output_path,
             url_prefix,
@@ -104,6 +166,22 @@ enum Command {
         /// on't perform minification on the created js in the exam. Useful if you don't have uglifyjs or want to debug something.
         #[clap(value_parser, long)]
         no_minification: bool,
+        /// The format of the report that is written to stdout. The log messages are written to stderr for the other formats.
+        #[clap(value_enum, long, default_value_t = cli::OutputFormat::Text)]
+        format: cli::OutputFormat,
+        /// Only compile the exams and questions that changed since the given git revision, or that
+        /// depend on a changed file (default files, templates, included files, custom part types...)
+        #[clap(value_parser, long)]
+        changed_since: Option<String>,
+        /// Only compile this locale, can be used multiple times
+        #[clap(value_parser, long = "locale")]
+        locales: Vec<String>,
+        /// Package the exams with rumbas instead of the python compiler of Numbas (experimental).
+        ///
+        /// Python is not needed, but the files of Numbas are still used. The js and css are not minified.
+        /// It is not tested whether the packaged exams are the same as the exams of the python compiler.
+        #[clap(value_parser, long)]
+        native: bool,
     },
     /// Check a rumbas exam (or question)
     ///
@@ -117,6 +195,20 @@ enum Command {
         /// It is possible to specify multiple paths to folder/files.
         #[clap(required = true, multiple = true, value_parser)]
         exam_or_question_paths: Vec<String>,
+        /// Estimate how often the variables test of each question fails by generating its variables this amount of times.
+        ///
+        /// A warning is shown when the chance that no valid variables are found within max_runs is too high.
+        #[clap(value_parser, long)]
+        variables_test_runs: Option<usize>,
+        /// The highest acceptable chance that no valid variables are found within max_runs.
+        #[clap(value_parser, long, default_value_t = 0.001)]
+        variables_test_threshold: f64,
+        /// The format of the report that is written to stdout. The log messages are written to stderr for the other formats.
+        #[clap(value_enum, long, default_value_t = cli::OutputFormat::Text)]
+        format: cli::OutputFormat,
+        /// Only check this locale, can be used multiple times
+        #[clap(value_parser, long = "locale")]
+        locales: Vec<String>,
     },
     /// Format a rumbas exam (or question).
     ///
@@ -131,6 +223,43 @@ enum Command {
         #[clap(required = true, multiple = true, value_parser)]
         exam_or_question_paths: Vec<String>,
     },
+    /// Print the dependency graph of the variables of a rumbas question (or exam) in DOT format.
+    ///
+    /// An edge from `a` to `b` means that the definition of `a` uses `b`.
+    #[clap(arg_required_else_help = true)]
+    Graph {
+        /// The path to the question (or exam) file.
+        #[clap(value_parser)]
+        question_path: String,
+    },
+    /// Show the changes to the exams and questions between two git revisions.
+    ///
+    /// Both versions are loaded with their templates and defaults resolved, so the changes are shown per field (e.g. `parts.1.marks changed 1 → 2`).
+    #[clap(arg_required_else_help = true)]
+    Diff {
+        /// The old revision, e.g. a commit hash, branch or tag.
+        #[clap(value_parser)]
+        revision_a: String,
+        /// The new revision.
+        #[clap(value_parser)]
+        revision_b: String,
+        /// The paths to the exams, questions or folders to compare. All exams and questions are compared by default.
+        #[clap(value_parser)]
+        paths: Vec<String>,
+    },
+    /// Generate the variables of a rumbas question (or exam) without compiling it.
+    ///
+    /// The variables are generated like Numbas does, honouring the condition and max_runs of the variables test.
+    /// A table with the generated values is printed, together with how often the condition failed.
+    #[clap(arg_required_else_help = true)]
+    Sample {
+        /// The path to the question (or exam) file.
+        #[clap(value_parser)]
+        question_path: String,
+        /// The amount of seeds to generate the variables for.
+        #[clap(value_parser, long, default_value_t = 10)]
+        seeds: u64,
+    },
     /// Import a numbas .exam file
     ///    
     /// Resources have to be manually placed in the resources folder
@@ -150,6 +279,11 @@ enum Command {
     /// Creates files with the json schemas (beta).
     /// See https://github.com/m8rex/rumbas-examples/tree/main/.vscode for usage instructions
     Schema,
+    /// Start a language server that communicates over stdin and stdout
+    ///
+    /// It shows the problems found by `rumbas check`, completes fields and jme variables,
+    /// shows the documentation of fields and goes to the files that are used in `template` and `file:` values.
+    Lsp,
     /// Watch a path
     #[clap(arg_required_else_help = true)]
     Watch {
@@ -158,6 +292,42 @@ enum Command {
         /// Only check exams and questions that change due to file changes, but don't compile them with numbas.
         #[clap(short)]
         only_check: bool,
+        /// Include the files necessary to make a SCORM package
+        #[clap(value_parser, long, short)]
+        scorm: bool,
+        /// Create a zip file instead of a directory
+        #[clap(value_parser, long, short)]
+        zip: bool,
+        /// Don't perform minification on the created js in the exam. Useful if you don't have uglifyjs or want to recompile faster.
+        #[clap(value_parser, long)]
+        no_minification: bool,
+        /// Only compile (or check) this locale, can be used multiple times
+        #[clap(value_parser, long = "locale")]
+        locales: Vec<String>,
+        /// Package the exams with rumbas instead of the python compiler of Numbas (experimental).
+        ///
+        /// It is not tested whether the packaged exams are the same as the exams of the python compiler.
+        #[clap(value_parser, long)]
+        native: bool,
+    },
+    /// Serve the compiled exams on a local http server while watching a path
+    ///
+    /// The exams are recompiled like in `rumbas watch --no-minification` and the opened pages are reloaded after each recompilation.
+    #[clap(arg_required_else_help = true)]
+    Serve {
+        /// The path to watch
+        path: String,
+        /// The port of the http server
+        #[clap(long, short, default_value_t = 8000)]
+        port: u16,
+        /// Only compile (or check) this locale, can be used multiple times
+        #[clap(value_parser, long = "locale")]
+        locales: Vec<String>,
+        /// Package the exams with rumbas instead of the python compiler of Numbas (experimental).
+        ///
+        /// It is not tested whether the packaged exams are the same as the exams of the python compiler.
+        #[clap(value_parser, long)]
+        native: bool,
     },
     /// Generates a folder structure that can be hosted and used as an 'editor' in the numbas lti provider
     ///
@@ -175,4 +345,10 @@ impl Command {
     fn can_execute_in_old_version(&self) -> bool {
         matches!(self, Self::UpdateRepo | Self::Init)
     }
+    fn output_format(&self) -> cli::OutputFormat {
+        match self {
+            Self::Check { format, .. } | Self::Compile { format, .. } => *format,
+            _ => cli::OutputFormat::Text,
+        }
+    }
 }
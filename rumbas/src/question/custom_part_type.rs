@@ -10,6 +10,7 @@ use crate::support::translatable::JMETranslatableString;
 use crate::support::translatable::TranslatableString;
 use crate::support::yaml::{YamlError, YamlResult};
 use comparable::Comparable;
+use numbas::jme::ast::ExprValidationError;
 use rumbas_support::path::RumbasPath;
 use rumbas_support::preamble::*;
 use schemars::JsonSchema;
@@ -17,7 +18,7 @@ use serde::{Deserialize, Serialize};
 use std::convert::{Into, TryInto};
 use std::path::PathBuf;
 
-#[derive(Input, Overwrite, RumbasCheck, Examples)]
+#[derive(Input, Overwrite, Examples)]
 #[input(name = "CustomPartTypeDefinitionInput")]
 #[input(test)]
 #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq)]
@@ -34,6 +35,54 @@ pub struct CustomPartTypeDefinition {
     pub input_widget: CustomPartInputWidget, //TODO source
 }
 
+impl RumbasCheck for CustomPartTypeDefinition {
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let mut result = RumbasCheckResult::empty();
+        macro_rules! check_field {
+            ($field: ident) => {{
+                let mut previous_result = self.$field.check(locale);
+                previous_result.extend_path(stringify!($field).to_string());
+                previous_result
+            }};
+        }
+        result.union(&check_field!(type_name));
+        result.union(&check_field!(description));
+        // The default values of the settings are only suggestions for the questions that use
+        // the part type, so they are not resolved in the scope of a question
+        let mut settings_result = check_field!(settings);
+        settings_result.resolve_jme_identifiers(|_| true);
+        result.union(&settings_result);
+        result.union(&check_field!(can_be_gap));
+        result.union(&check_field!(can_be_step));
+        result.union(&check_field!(marking_notes));
+        result.union(&check_field!(help_url));
+        result.union(&check_field!(published));
+        result.union(&check_field!(extensions));
+        // The answer input can use the settings of the part type, the other identifiers have to be
+        // variables or functions of the question that uses the part type
+        let setting_names: Vec<_> = self
+            .settings
+            .iter()
+            .filter_map(|s| s.shared_data().name.to_string(locale))
+            .map(|n| n.to_lowercase())
+            .chain(std::iter::once("settings".to_string()))
+            .collect();
+        let mut input_widget_result = check_field!(input_widget);
+        input_widget_result.resolve_jme_identifiers(|i| match i {
+            ExprValidationError::UnknownVariable(v) => {
+                setting_names.contains(&v.name().to_lowercase())
+            }
+            ExprValidationError::UnknownFunction(_) => false,
+        });
+        result.union(&input_widget_result);
+        // The marking algorithm of a custom part type is not based on another one
+        let mut notes_result = self.marking_notes.check_required_notes();
+        notes_result.extend_path("marking_notes".to_string());
+        result.union(&notes_result);
+        result
+    }
+}
+
 impl ToNumbas<numbas::question::custom_part_type::CustomPartType> for CustomPartTypeDefinition {
     fn to_numbas(&self, _locale: &str) -> numbas::question::custom_part_type::CustomPartType {
         panic!(
@@ -111,6 +160,19 @@ pub enum CustomPartTypeSetting {
     Percentage(CustomPartTypeSettingPercentage),
 }
 
+impl CustomPartTypeSetting {
+    fn shared_data(&self) -> &CustomPartTypeSettingSharedData {
+        match self {
+            Self::CheckBox(c) => &c.shared_data,
+            Self::Code(c) => &c.shared_data,
+            Self::MathematicalExpression(c) => &c.shared_data,
+            Self::String(c) => &c.shared_data,
+            Self::DropDown(c) => &c.shared_data,
+            Self::Percentage(c) => &c.shared_data,
+        }
+    }
+}
+
 impl ToNumbas<numbas::question::custom_part_type::CustomPartTypeSetting> for CustomPartTypeSetting {
     fn to_numbas(&self, locale: &str) -> numbas::question::custom_part_type::CustomPartTypeSetting {
         match self {
@@ -2,11 +2,13 @@ use crate::support::to_numbas::ToNumbas;
 use crate::support::to_rumbas::ToRumbas;
 use crate::support::translatable::{JMETranslatableString, TranslatableString};
 use comparable::Comparable;
+use numbas::jme::ast::ExprValidationError;
+use numbas::jme::types::{JMEType, TypeEnvironment};
 use rumbas_support::preamble::*;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Input, Overwrite, RumbasCheck, Examples)]
+#[derive(Input, Overwrite, Examples)]
 #[input(name = "FunctionInput")]
 #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq, Eq)]
 pub struct Function {
@@ -17,6 +19,56 @@ pub struct Function {
     pub definition: FunctionDefinition,
 }
 
+impl RumbasCheck for Function {
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let mut result = RumbasCheckResult::empty();
+        let mut previous_result = self.parameters.check(locale);
+        previous_result.extend_path("parameters".to_string());
+        result.union(&previous_result);
+        let mut previous_result = self.output_type.check(locale);
+        previous_result.extend_path("output_type".to_string());
+        result.union(&previous_result);
+        let mut previous_result = self.definition.check(locale);
+        previous_result.extend_path("definition".to_string());
+        result.union(&previous_result);
+        // The parameters are variables within the definition
+        result.resolve_jme_identifiers(|i| match i {
+            ExprValidationError::UnknownVariable(v) => self
+                .parameters
+                .iter()
+                .any(|(name, _)| name.to_lowercase() == v.name().to_lowercase()),
+            _ => false,
+        });
+        result
+    }
+}
+
+impl Function {
+    /// The type of this function, None for the types that are unknown in jme
+    pub fn jme_type_signature(&self) -> numbas::jme::types::FunctionSignature {
+        numbas::jme::types::FunctionSignature {
+            parameters: self
+                .parameters
+                .iter()
+                .map(|(_, t)| JMEType::from_function_type(t))
+                .collect(),
+            output: JMEType::from_function_type(&self.output_type),
+        }
+    }
+
+    /// The types within the definition, where the parameters shadow the question variables
+    pub fn jme_type_environment(&self, env: &TypeEnvironment) -> TypeEnvironment {
+        let mut env = env.clone();
+        for (name, t) in self.parameters.iter() {
+            match JMEType::from_function_type(t) {
+                Some(t) => env.set_variable(name, t),
+                None => env.remove_variable(name),
+            }
+        }
+        env
+    }
+}
+
 impl ToNumbas<numbas::question::function::Function> for Function {
     fn to_numbas(&self, locale: &str) -> numbas::question::function::Function {
         numbas::question::function::Function {
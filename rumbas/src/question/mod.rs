@@ -8,7 +8,9 @@ pub mod navigation;
 pub mod part;
 pub mod preamble;
 pub mod resource;
+pub mod sample;
 pub mod variable;
+pub mod variable_graph;
 pub mod variable_test;
 
 use crate::exam::{FileReadError, ParseError, RecursiveTemplatesError};
@@ -28,6 +30,8 @@ use constants::CustomConstant;
 use extension::Extensions;
 use function::Function;
 use navigation::QuestionNavigation;
+use numbas::jme::ast::ExprValidationError;
+use numbas::jme::types::{JMEType, TypeEnvironment};
 use preamble::Preamble;
 use resource::ResourcePath;
 use rumbas_support::path::RumbasPath;
@@ -35,13 +39,14 @@ use rumbas_support::preamble::*;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::path::Path;
 use std::path::PathBuf;
 use variable::VariableRepresentation;
 use variable::UNGROUPED_GROUP;
 use variable_test::VariablesTest;
 
-#[derive(Input, Overwrite, RumbasCheck, Examples)]
+#[derive(Input, Overwrite, Examples)]
 #[input(name = "QuestionInput")]
 #[input(test)]
 #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq)]
@@ -55,6 +60,9 @@ pub struct Question {
     pub builtin_constants: BuiltinConstants,
     pub custom_constants: Vec<CustomConstant>,
     pub variables: BTreeMap<String, VariableRepresentation>,
+    /// The names of the variable groups, in the order of the numbas editor. Groups without
+    /// variables are kept and the groups of variables that are not listed are added after them.
+    pub variable_groups: Vec<String>,
     pub variables_test: VariablesTest,
     pub functions: BTreeMap<String, Function>,
     pub preamble: Preamble,
@@ -70,6 +78,193 @@ pub struct Question {
     pub rulesets: BTreeMap<String, JMERulesetItem>,
 }
 
+impl RumbasCheck for Question {
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let mut result = RumbasCheckResult::empty();
+        macro_rules! check_field {
+            ($field: ident) => {{
+                let mut previous_result = self.$field.check(locale);
+                previous_result.extend_path(stringify!($field).to_string());
+                previous_result
+            }};
+        }
+        result.union(&check_field!(statement));
+        result.union(&check_field!(advice));
+        result.union(&check_field!(parts));
+        result.union(&check_field!(builtin_constants));
+        result.union(&check_field!(custom_constants));
+        // Variables are not checked for identifiers or types (yet)
+        let mut variables_result = check_field!(variables);
+        variables_result.resolve_jme_identifiers(|_| true);
+        result.union(&variables_result);
+        result.union(&check_field!(variables_test));
+        // The definitions of the functions are type checked with their own parameters
+        let type_environment = self.jme_type_environment(locale);
+        let mut functions_result = RumbasCheckResult::empty();
+        for (i, function) in self.functions.values().enumerate() {
+            let mut previous_result = function.check(locale);
+            previous_result.check_jme_types(&function.jme_type_environment(&type_environment));
+            previous_result.extend_path(i.to_string());
+            functions_result.union(&previous_result);
+        }
+        functions_result.extend_path("functions".to_string());
+        result.union(&functions_result);
+        result.union(&check_field!(preamble));
+        result.union(&check_field!(navigation));
+        result.union(&check_field!(extensions));
+        result.union(&check_field!(diagnostic_topic_names));
+        result.union(&check_field!(resources));
+        // The identifiers of the custom part types that are not resolved by the part types
+        // themselves, should be variables or functions of the question
+        let mut custom_part_types_result = check_field!(custom_part_types);
+        custom_part_types_result.check_jme_types(&TypeEnvironment::new());
+        result.union(&custom_part_types_result);
+        result.union(&check_field!(rulesets));
+
+        for (name, definition) in self.jme_variable_definitions(locale) {
+            let mut previous_result = definition.check(locale);
+            previous_result.extend_path(name);
+            previous_result.extend_path("variables".to_string());
+            result.union(&previous_result);
+        }
+
+        let graph = variable_graph::VariableGraph::from_question(self, locale);
+        for cycle in graph.cycles() {
+            let mut previous_result = RumbasCheckResult::from_circular_variables(cycle);
+            previous_result.extend_path("variables".to_string());
+            result.union(&previous_result);
+        }
+        let used_variables: Vec<_> = result
+            .used_jme_identifiers()
+            .iter()
+            .filter_map(|i| match i.error() {
+                ExprValidationError::UnknownVariable(v) => Some(v.name().to_lowercase()),
+                ExprValidationError::UnknownFunction(_) => None,
+            })
+            .collect();
+        for name in self.variables.keys() {
+            if !used_variables.contains(&name.to_lowercase()) {
+                let mut previous_result = RumbasCheckResult::from_unused_variable(name.clone());
+                previous_result.extend_path("variables".to_string());
+                result.union(&previous_result);
+            }
+        }
+
+        let variable_names: Vec<_> = self
+            .variables
+            .keys()
+            .map(|k| k.to_lowercase())
+            .chain(self.custom_constants.iter().map(|c| c.name.to_lowercase()))
+            .collect();
+        let function_names: Vec<_> = self.functions.keys().map(|k| k.to_lowercase()).collect();
+        result.resolve_jme_identifiers(|i| match i {
+            ExprValidationError::UnknownVariable(v) => {
+                variable_names.contains(&v.name().to_lowercase())
+            }
+            ExprValidationError::UnknownFunction(f) => {
+                function_names.contains(&f.name().to_lowercase())
+            }
+        });
+        result.report_unknown_jme_identifiers();
+        // Rule names in content areas that are no builtin rules, should be rulesets of the question
+        let rulesets: Vec<_> = self.rulesets.keys().cloned().collect();
+        result.resolve_simplification_rulesets(&rulesets[..]);
+        result.check_jme_types(&type_environment);
+        result
+    }
+}
+
+impl Question {
+    /// The numbas variable groups, in the order of `variable_groups`
+    fn variable_groups(&self, locale: &str) -> Vec<numbas::question::variable::VariableGroup> {
+        let mut groups: Vec<_> = self
+            .variable_groups
+            .iter()
+            .map(|name| numbas::question::variable::VariableGroup {
+                name: name.clone(),
+                variables: Vec::new(),
+            })
+            .collect();
+        for (name, variable) in self.variables.iter() {
+            let group = variable.to_variable(locale).group;
+            if group.is_empty() || group == UNGROUPED_GROUP {
+                continue;
+            }
+            match groups.iter_mut().find(|g| g.name == group) {
+                Some(g) => g.variables.push(name.clone()),
+                None => groups.push(numbas::question::variable::VariableGroup {
+                    name: group,
+                    variables: vec![name.clone()],
+                }),
+            }
+        }
+        groups
+    }
+
+    /// The known types of the variables and the custom functions
+    ///
+    /// The types of variables that are defined by a jme expression are inferred until nothing
+    /// changes, so variables can depend on variables that are defined later.
+    pub fn jme_type_environment(&self, locale: &str) -> TypeEnvironment {
+        let mut env = TypeEnvironment::new();
+        for (name, function) in self.functions.iter() {
+            env.add_function(name, function.jme_type_signature());
+        }
+        for (name, variable) in self.variables.iter() {
+            let t = match variable.to_variable(locale).template_type {
+                variable::VariableTemplateType::Anything => None,
+                variable::VariableTemplateType::ListOfNumbers
+                | variable::VariableTemplateType::ListOfStrings => Some(JMEType::List),
+                variable::VariableTemplateType::LongString
+                | variable::VariableTemplateType::r#String => Some(JMEType::String),
+                variable::VariableTemplateType::Number
+                | variable::VariableTemplateType::RandomRange => Some(JMEType::Number),
+                variable::VariableTemplateType::Range => Some(JMEType::Range),
+            };
+            if let Some(t) = t {
+                env.set_variable(name, t);
+            }
+        }
+        let definitions = self.jme_variable_definitions(locale);
+        for _ in 0..definitions.len() {
+            let previous = env.clone();
+            for (name, definition) in definitions.iter() {
+                if let Some(t) = definition.ast().and_then(|ast| ast.infer_type(&previous)) {
+                    env.set_variable(name, t);
+                }
+            }
+            if env == previous {
+                break;
+            }
+        }
+        env
+    }
+
+    /// The parsed definitions of the variables that are defined by a jme expression
+    ///
+    /// Definitions that can't be parsed are skipped, they are reported when converting the question.
+    pub fn jme_variable_definitions(
+        &self,
+        locale: &str,
+    ) -> BTreeMap<String, numbas::jme::JMEString> {
+        self.variables
+            .iter()
+            .filter_map(|(name, variable)| {
+                let variable = variable.to_variable(locale);
+                if variable.template_type != variable::VariableTemplateType::Anything {
+                    return None;
+                }
+                let definition: Result<numbas::jme::JMEString, _> = variable
+                    .definition
+                    .get_content(locale)
+                    .unwrap_or_default()
+                    .try_into();
+                definition.ok().map(|d| (name.clone(), d))
+            })
+            .collect()
+    }
+}
+
 impl ToNumbas<numbas::question::Question> for Question {
     fn to_numbas(&self, _locale: &str) -> numbas::question::Question {
         //TODO?
@@ -104,10 +299,13 @@ impl ToNumbas<numbas::question::Question> for Question {
                 .variables
                 .clone()
                 .into_iter()
-                .filter(|(_k, v)| &v.to_variable(locale).group[..] == UNGROUPED_GROUP)
+                .filter(|(_k, v)| {
+                    let group = v.to_variable(locale).group;
+                    group.is_empty() || group == UNGROUPED_GROUP
+                })
                 .map(|(k, _)| k)
                 .collect(),
-            variable_groups: Vec::new(), // Don't add variable groups
+            variable_groups: self.variable_groups(locale),
             preamble: self.preamble.to_numbas(locale),
             rulesets: self.rulesets.to_numbas(locale),
             navigation: self.navigation.to_numbas(locale),
@@ -135,7 +333,29 @@ impl ToRumbas<Question> for numbas::question::Question {
             parts: self.parts.to_rumbas(),
             builtin_constants: self.builtin_constants.to_rumbas(),
             custom_constants: self.constants.to_rumbas(),
-            variables: self.variables.to_rumbas(),
+            variables: self
+                .variables
+                .iter()
+                .map(|(name, variable)| {
+                    // The variable groups are the source of truth in the numbas editor
+                    let mut variable = variable.clone();
+                    if let Some(group) = self
+                        .variable_groups
+                        .iter()
+                        .find(|g| g.variables.contains(name))
+                    {
+                        variable.group = group.name.clone();
+                    } else if variable.group.is_empty() || self.ungrouped_variables.contains(name) {
+                        variable.group = UNGROUPED_GROUP.to_string();
+                    }
+                    (name.clone(), variable.to_rumbas())
+                })
+                .collect(),
+            variable_groups: self
+                .variable_groups
+                .iter()
+                .map(|g| g.name.clone())
+                .collect(),
             variables_test: self.variables_test.to_rumbas(),
             functions: self.functions.to_rumbas(),
             preamble: self.preamble.to_rumbas(),
@@ -175,3 +395,75 @@ impl QuestionFileTypeInput {
         serde_yaml::to_string(self)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn variable_groups_round_trip() {
+        let numbas_question: numbas::question::Question = serde_json::from_str(
+            r#"{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {"allowregen": true, "showfrontpage": false, "preventleave": false},
+                "variables": {
+                    "a": {"name": "a", "group": "Sizes", "definition": "1", "description": "", "templateType": "anything"},
+                    "b": {"name": "b", "group": "Ungrouped variables", "definition": "a", "description": "", "templateType": "anything"},
+                    "c": {"name": "c", "group": "", "definition": "2", "description": "", "templateType": "anything"},
+                    "d": {"name": "d", "group": "Angles", "definition": "3", "description": "", "templateType": "anything"}
+                },
+                "ungrouped_variables": ["b"],
+                "variable_groups": [
+                    {"name": "Sizes", "variables": ["a", "c"]},
+                    {"name": "Empty", "variables": []},
+                    {"name": "Angles", "variables": ["d"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let question: Question = numbas_question.to_rumbas();
+        assert_eq!(question.variable_groups, vec!["Sizes", "Empty", "Angles"]);
+        let exported = question.to_numbas("en");
+        assert_eq!(exported.variable_groups, numbas_question.variable_groups);
+        assert_eq!(exported.ungrouped_variables, vec!["b".to_string()]);
+        assert_eq!(exported.variables["c"].group, "Sizes");
+    }
+
+    #[test]
+    fn unlisted_variable_groups() {
+        let mut question: Question = serde_json::from_str::<numbas::question::Question>(
+            r#"{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {"allowregen": true, "showfrontpage": false, "preventleave": false},
+                "variables": {
+                    "a": {"name": "a", "group": "Zeta", "definition": "1", "description": "", "templateType": "anything"},
+                    "b": {"name": "b", "group": "Alpha", "definition": "2", "description": "", "templateType": "anything"},
+                    "c": {"name": "c", "group": "Zeta", "definition": "3", "description": "", "templateType": "anything"}
+                },
+                "ungrouped_variables": [],
+                "variable_groups": []
+            }"#,
+        )
+        .unwrap()
+        .to_rumbas();
+        question.variable_groups = vec!["Empty".to_string(), "Alpha".to_string()];
+        let groups: Vec<_> = question
+            .to_numbas("en")
+            .variable_groups
+            .into_iter()
+            .map(|g| (g.name, g.variables))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("Empty".to_string(), vec![]),
+                ("Alpha".to_string(), vec!["b".to_string()]),
+                ("Zeta".to_string(), vec!["a".to_string(), "c".to_string()]),
+            ]
+        );
+    }
+}
@@ -118,7 +118,7 @@ macro_rules! create_answer_simplification {
             $(#[$inner:meta])*
             $name: ident: $numbas_name: ident: $partofall: expr
         ),*) => {
-        #[derive(Input, Overwrite, RumbasCheck, Examples)]
+        #[derive(Input, Overwrite, Examples)]
         #[input(name = $input)]
         #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq, Eq)]
         pub struct $struct {
@@ -245,6 +245,33 @@ create_answer_simplification! {
     expand_brackets: ExpandBrackets: false
 }
 
+impl RumbasCheck for JMEAnswerSimplification {
+    /// Warn about rules that keep rewriting each others results
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let rules: Vec<_> = self
+            .to_numbas(locale)
+            .into_iter()
+            .filter_map(|a| match a {
+                numbas::question::answer_simplification::AnswerSimplificationType::Rule(r) => {
+                    Some(r)
+                }
+                _ => None,
+            })
+            .collect();
+        let mut result = RumbasCheckResult::empty();
+        for (a, b) in numbas::jme::simplify::RuleSet::from(&rules[..])
+            .looping_rules()
+            .into_iter()
+        {
+            result.union(&RumbasCheckResult::from_looping_simplification_rules(vec![
+                a.to_string(),
+                b.to_string(),
+            ]));
+        }
+        result
+    }
+}
+
 macro_rules! create_answer_display_type {
     ($struct: ident: $input: literal: $variant: ident: $variant_struct: ident,
         $(
@@ -317,6 +344,21 @@ create_answer_display_type! {
     matrices_without_parentheses: MatricesWithoutParentheses
 }
 
+impl JMEAnswerDisplay {
+    /// The options to render expressions as LaTeX with these display options
+    pub fn latex_options(&self) -> numbas::jme::latex::LatexOptions {
+        numbas::jme::latex::LatexOptions {
+            broken_as_fractions: self.broken_as_fractions,
+            mixed_fractions: self.mixed_fractions,
+            flat_fractions: self.flat_fractions,
+            vector_as_row: self.vector_as_row,
+            always_show_multiplication_sign: self.always_show_multiplication_sign,
+            use_dot_as_multiplication_sign: self.use_dot_as_multiplication_sign,
+            matrices_without_parentheses: self.matrices_without_parentheses,
+        }
+    }
+}
+
 #[derive(Input, Overwrite, RumbasCheck, Examples)]
 #[input(name = "CheckingTypeDataFloatInput")]
 #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq)]
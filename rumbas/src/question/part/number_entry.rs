@@ -85,7 +85,7 @@ impl ToRumbas<QuestionPartNumberEntry>
     }
 }
 
-#[derive(Input, Overwrite, RumbasCheck, Examples)]
+#[derive(Input, Overwrite, Examples)]
 #[input(name = "NumberEntryAnswerInput")]
 #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq, Eq)]
 #[serde(untagged)]
@@ -94,6 +94,19 @@ pub enum NumberEntryAnswer {
     Range(NumberEntryAnswerRange),
 }
 
+impl RumbasCheck for NumberEntryAnswer {
+    /// The answers should be numbers
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let mut result = match self {
+            NumberEntryAnswer::Normal(answer) => answer.check(locale),
+            NumberEntryAnswer::Range(range) => range.check(locale),
+        };
+        result.extend_path("0".to_string());
+        result.expect_jme_type(numbas::jme::builtin_functions::ParameterType::Number);
+        result
+    }
+}
+
 // TODO, better (add toRumbas etc)
 #[derive(Input, Overwrite, RumbasCheck, Examples)]
 #[input(name = "NumberEntryAnswerRangeInput")]
@@ -8,17 +8,24 @@ use crate::question::part::multiple_choice::choose_one::QuestionPartChooseOne;
 use crate::question::part::multiple_choice::match_answers::QuestionPartMatchAnswersWithItems;
 use crate::question::part::number_entry::QuestionPartNumberEntry;
 use crate::question::part::pattern_match::QuestionPartPatternMatch;
+use crate::question::variable_graph::VariableGraph;
 use crate::support::noneable::Noneable;
 use crate::support::to_numbas::ToNumbas;
 use crate::support::to_rumbas::*;
 use crate::support::translatable::{ContentAreaTranslatableString, JMETranslatableString};
 use comparable::Comparable;
+use numbas::jme::ast::ExprValidationError;
+use numbas::jme::marking::{
+    missing_required_notes, MarkingError, MARKING_FUNCTIONS, MARKING_VARIABLES,
+};
+use numbas::jme::JMEString;
 use rumbas_support::preamble::*;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryInto;
 
-#[derive(Input, Overwrite, RumbasCheck, Examples)]
+#[derive(Input, Overwrite, Examples)]
 #[input(name = "QuestionPartInput")]
 #[derive(Serialize, Deserialize, Comparable, Debug, Clone, JsonSchema, PartialEq)]
 #[serde(untagged)]
@@ -27,6 +34,44 @@ pub enum QuestionPart {
     Custom(QuestionPartCustom),
 }
 
+impl RumbasCheck for QuestionPart {
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let (mut result, notes_path): (_, &[&str]) = match self {
+            QuestionPart::Builtin(b) => (b.check(locale), &["0", "0"]),
+            QuestionPart::Custom(c) => (c.check(locale), &["0"]),
+        };
+        result.extend_path("0".to_string());
+        let (notes, extend_base) = self.custom_marking_algorithm();
+        // The notes of the base algorithm are not known, the notes of an algorithm that extends
+        // it are only checked against the notes that it defines itself when it replaces it
+        if extend_base {
+            let mut path = notes_path.join(".");
+            path.push_str(".custom_marking_algorithm_notes");
+            result.resolve_base_marking_notes(&path);
+        }
+        // A custom marking algorithm that replaces the builtin one has to define its result
+        if !extend_base && !notes.0.is_empty() {
+            let mut previous_result = notes.check_required_notes();
+            previous_result.extend_path("custom_marking_algorithm_notes".to_string());
+            for part in notes_path.iter() {
+                previous_result.extend_path(part.to_string());
+            }
+            result.union(&previous_result);
+        }
+        result
+    }
+}
+
+impl QuestionPart {
+    /// The notes of the custom marking algorithm, and whether they extend the base algorithm
+    pub fn custom_marking_algorithm(&self) -> (&JMENotes, bool) {
+        match self {
+            QuestionPart::Builtin(b) => b.custom_marking_algorithm(),
+            QuestionPart::Custom(c) => c.custom_marking_algorithm(),
+        }
+    }
+}
+
 impl ToNumbas<numbas::question::part::QuestionPart> for QuestionPart {
     fn to_numbas(&self, locale: &str) -> numbas::question::part::QuestionPart {
         match self {
@@ -129,6 +174,24 @@ impl ToNumbas<numbas::question::part::QuestionPartBuiltin> for QuestionPartBuilt
     }
 }
 
+impl QuestionPartBuiltin {
+    /// The notes of the custom marking algorithm, and whether they extend the base algorithm
+    pub fn custom_marking_algorithm(&self) -> (&JMENotes, bool) {
+        match self {
+            QuestionPartBuiltin::JME(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::GapFill(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::ChooseOne(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::ChooseMultiple(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::MatchAnswersWithItems(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::NumberEntry(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::PatternMatch(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::Information(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::Extension(d) => d.custom_marking_algorithm(),
+            QuestionPartBuiltin::Matrix(d) => d.custom_marking_algorithm(),
+        }
+    }
+}
+
 impl ToRumbas<QuestionPartBuiltin> for numbas::question::part::QuestionPartBuiltin {
     fn to_rumbas(&self) -> QuestionPartBuiltin {
         match self {
@@ -183,12 +246,66 @@ impl QuestionPartBuiltinInput {
     }
 }
 
-#[derive(Input, Overwrite, RumbasCheck, Examples)]
+#[derive(Input, Overwrite, Examples)]
 #[input(name = "JMENotesInput")]
 #[derive(Debug, Clone, JsonSchema, Deserialize, Serialize, Comparable, PartialEq, Eq, Default)]
 #[serde(transparent)]
 pub struct JMENotes(pub Vec<JMENote>);
 
+impl RumbasCheck for JMENotes {
+    fn check(&self, locale: &str) -> RumbasCheckResult {
+        let mut result = self.0.check(locale);
+        result.extend_path("0".to_string());
+        // Notes can use the other notes and the marking environment
+        result.resolve_jme_identifiers(|i| match i {
+            ExprValidationError::UnknownVariable(v) => {
+                let name = v.name().to_lowercase();
+                MARKING_VARIABLES.contains(&&name[..])
+                    || self.0.iter().any(|n| n.name.to_lowercase() == name)
+            }
+            ExprValidationError::UnknownFunction(f) => {
+                MARKING_FUNCTIONS.contains(&&f.name().to_lowercase()[..])
+            }
+        });
+        // The calls of marking functions and the dependencies between the notes
+        let names: Vec<_> = self.0.iter().map(|n| n.name.to_lowercase()).collect();
+        let mut dependencies: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (i, note) in self.0.iter().enumerate() {
+            let expression: Result<JMEString, _> = note
+                .expression
+                .to_string(locale)
+                .unwrap_or_default()
+                .try_into();
+            if let Some(ast) = expression.ok().as_ref().and_then(|e| e.ast()) {
+                let mut previous_result =
+                    RumbasCheckResult::from_marking_errors(ast.marking_errors(&names));
+                previous_result.extend_path("expression".to_string());
+                previous_result.extend_path(i.to_string());
+                previous_result.extend_path("0".to_string());
+                result.union(&previous_result);
+                dependencies
+                    .entry(names[i].clone())
+                    .or_default()
+                    .extend(ast.note_dependencies(&names));
+            }
+        }
+        for cycle in VariableGraph::new(dependencies).cycles() {
+            result.union(&RumbasCheckResult::from_marking_errors(vec![
+                MarkingError::CircularNotes(cycle),
+            ]));
+        }
+        result
+    }
+}
+
+impl JMENotes {
+    /// Check that a marking algorithm that doesn't extend another one defines the required notes
+    pub fn check_required_notes(&self) -> RumbasCheckResult {
+        let names: Vec<_> = self.0.iter().map(|n| n.name.clone()).collect();
+        RumbasCheckResult::from_marking_errors(missing_required_notes(&names[..]))
+    }
+}
+
 impl ToNumbas<numbas::jme::JMENotesString> for JMENotes {
     fn to_numbas(&self, locale: &str) -> numbas::jme::JMENotesString {
         self.0
@@ -316,6 +433,13 @@ macro_rules! question_part_type {
 
             }
         }
+        impl $struct {
+            /// The notes of the custom marking algorithm, and whether they extend the base
+            /// algorithm
+            pub fn custom_marking_algorithm(&self) -> (&JMENotes, bool) {
+                (&self.custom_marking_algorithm_notes, self.extend_base_marking_algorithm)
+            }
+        }
         paste::paste! {
             impl [<$struct Input>] {
                 pub fn get_steps(&mut self) -> &mut Value<Vec<ValueType<<crate::question::part::question_part::QuestionPart as InputInverse>::Input>>> {
@@ -436,3 +560,105 @@ impl ToRumbas<VariableReplacementStrategy> for numbas::question::part::VariableR
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The problems in the custom marking algorithm of the given numbas part
+    fn marking_problems(part: &str, notes: &str, extend_base: bool) -> Vec<String> {
+        let mut part: serde_json::Value = serde_json::from_str(part).unwrap();
+        part["customMarkingAlgorithm"] = notes.into();
+        part["extendBaseMarkingAlgorithm"] = extend_base.into();
+        let part: numbas::question::part::QuestionPart = serde_json::from_value(part).unwrap();
+        let part: QuestionPart = part.to_rumbas();
+        let mut result = part.check("en");
+        result.report_unknown_jme_identifiers();
+        result
+            .diagnostics()
+            .into_iter()
+            .filter(|d| d.path.contains("custom_marking_algorithm_notes"))
+            .map(|d| d.message)
+            .collect()
+    }
+
+    /// A minimal numbas part of each builtin part type, with a note of its base algorithm
+    const BUILTIN_PARTS: [(&str, &str); 10] = [
+        (
+            r#"{"type": "jme", "marks": 1, "answer": "x", "checkingType": "absdiff", "checkingAccuracy": 0.001}"#,
+            "studentexpression",
+        ),
+        (
+            r#"{"type": "numberentry", "marks": 1, "minValue": "1", "maxValue": "1"}"#,
+            "validnumber",
+        ),
+        (
+            r#"{"type": "matrix", "marks": 1, "correctAnswer": "id(2)"}"#,
+            "studentmatrix",
+        ),
+        (
+            r#"{"type": "patternmatch", "marks": 1, "answer": "a"}"#,
+            "regex_match",
+        ),
+        (
+            r#"{"type": "1_n_2", "marks": 0, "choices": ["a", "b"], "matrix": ["1", "0"]}"#,
+            "marked_original_order",
+        ),
+        (
+            r#"{"type": "m_n_2", "marks": 0, "choices": ["a", "b"], "matrix": ["1", "0"]}"#,
+            "marked_original_order",
+        ),
+        (
+            r#"{"type": "m_n_x", "marks": 0, "choices": ["a"], "answers": ["b"], "matrix": [["1"]], "displayType": "radiogroup"}"#,
+            "marked_original_order",
+        ),
+        (
+            r#"{"type": "gapfill", "marks": 0, "gaps": []}"#,
+            "mark_gaps",
+        ),
+        (
+            r#"{"type": "information", "marks": 0}"#,
+            "interpreted_answer",
+        ),
+        (r#"{"type": "extension", "marks": 0}"#, "interpreted_answer"),
+    ];
+
+    #[test]
+    fn extended_marking_algorithms_use_base_notes() {
+        for (part, base_note) in BUILTIN_PARTS.iter() {
+            let notes = format!("extra:\napply({0});\ncorrectif({0} = {0})", base_note);
+            assert_eq!(
+                marking_problems(part, &notes, true),
+                Vec::<String>::new(),
+                "{}",
+                part
+            );
+        }
+    }
+
+    #[test]
+    fn replaced_marking_algorithms_only_use_their_own_notes() {
+        for (part, base_note) in BUILTIN_PARTS.iter() {
+            let notes = format!(
+                "mark:\napply({})\n\ninterpreted_answer:\nstudentanswer",
+                base_note
+            );
+            let problems = marking_problems(part, &notes, false);
+            assert!(
+                problems.contains(&format!(
+                    "apply is called with {}, but expects the name of a note",
+                    base_note
+                )),
+                "{}: {:?}",
+                part,
+                problems
+            );
+        }
+        let own_notes =
+            "validity:\ntrue\n\nmark:\napply(validity)\n\ninterpreted_answer:\nstudentanswer";
+        assert_eq!(
+            marking_problems(BUILTIN_PARTS[1].0, own_notes, false),
+            Vec::<String>::new()
+        );
+    }
+}
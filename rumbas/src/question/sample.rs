@@ -0,0 +1,293 @@
+//! Contains the offline generation of the variables of a question
+
+use crate::question::function::FunctionDefinition;
+use crate::question::variable::VariableTemplateType;
+use crate::question::Question;
+use numbas::jme::evaluate::{Environment, EvaluationError, Function, Value};
+use numbas::jme::JMEString;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+/// The result of generating the variables of a question for one seed
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariablesSample {
+    pub seed: u64,
+    /// The amount of times the variables were generated
+    pub runs: usize,
+    /// The amount of runs where `variables_test.condition` was not satisfied
+    pub failed_conditions: usize,
+    /// The generated values of the last run, or the error that occured while generating them
+    pub values: Result<BTreeMap<String, Value>, EvaluationError>,
+}
+
+impl VariablesSample {
+    /// Whether the last run satisfied the condition of the variables test
+    pub fn is_accepted(&self) -> bool {
+        self.values.is_ok() && self.failed_conditions < self.runs
+    }
+}
+
+/// An estimate of how often the condition of the variables test is satisfied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariablesTestEstimate {
+    /// The amount of times the variables were generated
+    pub runs: usize,
+    /// The amount of runs where the condition was satisfied
+    pub satisfied: usize,
+}
+
+impl VariablesTestEstimate {
+    /// The estimated chance that the condition is satisfied in one run
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            1.0
+        } else {
+            self.satisfied as f64 / self.runs as f64
+        }
+    }
+
+    /// The estimated chance that the condition is not satisfied within `max_runs` runs
+    pub fn failure_chance(&self, max_runs: usize) -> f64 {
+        (1.0 - self.success_rate()).powf(max_runs.max(1) as f64)
+    }
+}
+
+impl Question {
+    /// Estimate how often the condition of the variables test is satisfied
+    ///
+    /// The variables are generated `runs` times, seeded with `0..runs`. A run in which the
+    /// variables can't be generated counts as a run that doesn't satisfy the condition, the error
+    /// is only returned if no run succeeds. An error of a function that rumbas can't evaluate is
+    /// returned immediately, as it says nothing about the condition.
+    pub fn estimate_variables_test(
+        &self,
+        locale: &str,
+        runs: usize,
+    ) -> Result<VariablesTestEstimate, EvaluationError> {
+        let mut satisfied = 0;
+        let mut last_error = None;
+        for seed in 0..runs {
+            match self.run_variables_test(locale, seed as u64) {
+                Ok((_, true)) => satisfied += 1,
+                Ok((_, false)) => (),
+                Err(e) if e.is_unsupported() => return Err(e),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if satisfied == 0 => Err(e),
+            _ => Ok(VariablesTestEstimate { runs, satisfied }),
+        }
+    }
+
+    /// Generate the variables of this question, like Numbas does
+    ///
+    /// The variables are regenerated until `variables_test.condition` is satisfied, at most
+    /// `variables_test.max_runs` times. The same seed always gives the same values.
+    pub fn sample_variables(&self, locale: &str, seed: u64) -> VariablesSample {
+        let max_runs = self.variables_test.max_runs.max(1);
+        let mut failed_conditions = 0;
+        let mut run = 0;
+        loop {
+            run += 1;
+            let run_seed = seed.wrapping_mul(max_runs as u64).wrapping_add(run as u64);
+            let (values, satisfied) = match self.run_variables_test(locale, run_seed) {
+                Ok(result) => result,
+                Err(e) => {
+                    return VariablesSample {
+                        seed,
+                        runs: run,
+                        failed_conditions,
+                        values: Err(e),
+                    }
+                }
+            };
+            if !satisfied {
+                failed_conditions += 1;
+            }
+            if satisfied || run >= max_runs {
+                return VariablesSample {
+                    seed,
+                    runs: run,
+                    failed_conditions,
+                    values: Ok(values),
+                };
+            }
+        }
+    }
+
+    /// Create an environment with the functions, constants and variable definitions of this question
+    pub fn environment(&self, locale: &str, seed: u64) -> Result<Environment, EvaluationError> {
+        let mut env = Environment::new(seed);
+        for (name, function) in self.functions.iter() {
+            match &function.definition {
+                FunctionDefinition::JME(d) => {
+                    let definition: Result<JMEString, _> = d
+                        .definition
+                        .to_string(locale)
+                        .unwrap_or_default()
+                        .try_into();
+                    if let Some(definition) = definition.ok().and_then(|d| d.ast().cloned()) {
+                        env.add_function(
+                            name,
+                            Function {
+                                parameters: function
+                                    .parameters
+                                    .iter()
+                                    .map(|(p, _)| p.clone())
+                                    .collect(),
+                                definition,
+                            },
+                        );
+                    }
+                }
+                // Calling these results in an unknown function error
+                FunctionDefinition::Javascript(_) => (),
+            }
+        }
+        for constant in self.custom_constants.iter() {
+            if let Some(ast) = constant.value.ast() {
+                env.add_definition(&constant.name, ast.clone());
+            }
+        }
+        for (name, variable) in self.variables.iter() {
+            let variable = variable.to_variable(locale);
+            let definition = variable.definition.get_content(locale).unwrap_or_default();
+            match variable.template_type {
+                // The definition of a string template is the string itself
+                VariableTemplateType::r#String | VariableTemplateType::LongString => {
+                    env.set_variable(name, Value::String(definition))
+                }
+                _ => {
+                    let parsed: Result<JMEString, _> = definition.try_into();
+                    let parsed = parsed.map_err(|e| {
+                        EvaluationError::InVariable(
+                            name.clone(),
+                            Box::new(EvaluationError::InvalidOperation(e.to_string())),
+                        )
+                    })?;
+                    if let Some(ast) = parsed.ast() {
+                        env.add_definition(name, ast.clone());
+                    } else {
+                        env.set_variable(name, Value::Nothing);
+                    }
+                }
+            }
+        }
+        Ok(env)
+    }
+
+    /// Generate the variables once and check whether they satisfy the condition of the variables test
+    fn run_variables_test(
+        &self,
+        locale: &str,
+        seed: u64,
+    ) -> Result<(BTreeMap<String, Value>, bool), EvaluationError> {
+        let mut env = self.environment(locale, seed)?;
+        env.evaluate_definitions()?;
+        let values = self
+            .variables
+            .keys()
+            .filter_map(|name| env.variable(name).map(|v| (name.clone(), v.clone())))
+            .collect();
+        let satisfied = match self.variables_test.condition.ast() {
+            Some(condition) => env.evaluate(condition)?.as_bool().ok_or_else(|| {
+                EvaluationError::InvalidOperation(
+                    "the condition of the variables test is not a boolean".to_string(),
+                )
+            })?,
+            None => true,
+        };
+        Ok((values, satisfied))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::support::to_rumbas::ToRumbas;
+
+    #[test]
+    fn estimate_with_failing_seeds() {
+        // `b` can't be evaluated when `a` is 2 or 3 and the condition fails when `a` is 0
+        let numbas_question: numbas::question::Question = serde_json::from_str(
+            r#"{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {"allowregen": true, "showfrontpage": false, "preventleave": false},
+                "variables": {
+                    "a": {"name": "a", "group": "Ungrouped variables", "definition": "random(0..3)", "description": "", "templateType": "anything"},
+                    "b": {"name": "b", "group": "Ungrouped variables", "definition": "[10,20][a]", "description": "", "templateType": "anything"}
+                },
+                "variablesTest": {"condition": "a > 0", "maxRuns": 100}
+            }"#,
+        )
+        .unwrap();
+        let question: Question = numbas_question.to_rumbas();
+        let estimate = question.estimate_variables_test("en", 20).unwrap();
+        assert_eq!(estimate.runs, 20);
+        assert!(estimate.satisfied > 0 && estimate.satisfied < 20);
+    }
+
+    #[test]
+    fn estimate_with_unsupported_functions() {
+        let numbas_question: numbas::question::Question = serde_json::from_str(
+            r#"{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {"allowregen": true, "showfrontpage": false, "preventleave": false},
+                "variables": {
+                    "a": {"name": "a", "group": "Ungrouped variables", "definition": "unknown_function(1)", "description": "", "templateType": "anything"}
+                },
+                "variablesTest": {"condition": "a > 0", "maxRuns": 100}
+            }"#,
+        )
+        .unwrap();
+        let question: Question = numbas_question.to_rumbas();
+        let error = question.estimate_variables_test("en", 20).unwrap_err();
+        assert!(error.is_unsupported());
+    }
+
+    #[test]
+    fn string_templates_are_strings() {
+        let numbas_question: numbas::question::Question = serde_json::from_str(
+            r#"{
+                "name": "q",
+                "statement": "",
+                "advice": "",
+                "navigation": {"allowregen": true, "showfrontpage": false, "preventleave": false},
+                "variables": {
+                    "a": {"name": "a", "group": "Ungrouped variables", "definition": "1 + 2", "description": "", "templateType": "string"}
+                },
+                "variablesTest": {"condition": "", "maxRuns": 100}
+            }"#,
+        )
+        .unwrap();
+        let question: Question = numbas_question.to_rumbas();
+        let values = question.sample_variables("en", 0).values.unwrap();
+        assert_eq!(values.get("a"), Some(&Value::String("1 + 2".to_string())));
+    }
+
+    #[test]
+    fn failure_chance() {
+        let estimate = VariablesTestEstimate {
+            runs: 100,
+            satisfied: 50,
+        };
+        assert_eq!(estimate.success_rate(), 0.5);
+        assert_eq!(estimate.failure_chance(3), 0.125);
+        let never = VariablesTestEstimate {
+            runs: 100,
+            satisfied: 0,
+        };
+        assert_eq!(never.failure_chance(100), 1.0);
+        let always = VariablesTestEstimate {
+            runs: 100,
+            satisfied: 100,
+        };
+        assert_eq!(always.failure_chance(1), 0.0);
+        assert!(estimate.failure_chance(usize::MAX) < 1e-300);
+    }
+}
@@ -0,0 +1,229 @@
+//! Contains the dependency graph of the variables of a question
+
+use crate::question::function::FunctionDefinition;
+use crate::question::Question;
+use numbas::jme::ast::ExprValidationError;
+use numbas::jme::JMEString;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::TryInto;
+
+/// The dependency graph of the variables of a question
+///
+/// Each variable is mapped on the variables that are used in its definition, either directly
+/// or through the functions of the question.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VariableGraph {
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl VariableGraph {
+    pub fn new(dependencies: BTreeMap<String, BTreeSet<String>>) -> Self {
+        Self { dependencies }
+    }
+
+    pub fn from_question(question: &Question, locale: &str) -> Self {
+        let variable_names: BTreeMap<_, _> = question
+            .variables
+            .keys()
+            .map(|k| (k.to_lowercase(), k.clone()))
+            .collect();
+        let function_identifiers: BTreeMap<_, _> = question
+            .functions
+            .iter()
+            .filter_map(|(name, function)| match &function.definition {
+                FunctionDefinition::JME(d) => {
+                    let definition: Result<JMEString, _> = d
+                        .definition
+                        .to_string(locale)
+                        .unwrap_or_default()
+                        .try_into();
+                    let parameters: Vec<_> = function
+                        .parameters
+                        .iter()
+                        .map(|(p, _)| p.to_lowercase())
+                        .collect();
+                    let identifiers: Vec<_> = definition
+                        .ok()?
+                        .ast()?
+                        .unknown_identifiers()
+                        .into_iter()
+                        .filter(|i| match i {
+                            ExprValidationError::UnknownVariable(v) => {
+                                !parameters.contains(&v.name().to_lowercase())
+                            }
+                            ExprValidationError::UnknownFunction(_) => true,
+                        })
+                        .collect();
+                    Some((name.to_lowercase(), identifiers))
+                }
+                FunctionDefinition::Javascript(_) => None,
+            })
+            .collect();
+
+        let mut dependencies: BTreeMap<_, _> = question
+            .variables
+            .keys()
+            .map(|k| (k.clone(), BTreeSet::new()))
+            .collect();
+        for (name, definition) in question.jme_variable_definitions(locale) {
+            let variable_dependencies = dependencies.entry(name).or_default();
+            let mut identifiers = definition
+                .ast()
+                .map(|ast| ast.unknown_identifiers())
+                .unwrap_or_default();
+            let mut visited_functions = BTreeSet::new();
+            while let Some(identifier) = identifiers.pop() {
+                let identifier_name = identifier.name().to_lowercase();
+                match identifier {
+                    ExprValidationError::UnknownVariable(_) => {
+                        if let Some(variable) = variable_names.get(&identifier_name) {
+                            variable_dependencies.insert(variable.clone());
+                        }
+                    }
+                    ExprValidationError::UnknownFunction(_) => {
+                        if let Some(function_identifiers) =
+                            function_identifiers.get(&identifier_name)
+                        {
+                            if visited_functions.insert(identifier_name) {
+                                identifiers.extend(function_identifiers.iter().cloned());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Self::new(dependencies)
+    }
+
+    /// The variables that are used in the definition of the given variable
+    pub fn dependencies(&self, variable: &str) -> Option<&BTreeSet<String>> {
+        self.dependencies.get(variable)
+    }
+
+    /// Find the circular definitions
+    ///
+    /// Each cycle starts and ends with the same variable, e.g. `["a", "b", "a"]`.
+    /// Only one cycle is returned for each group of variables that depend on each other.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles = Vec::new();
+        let mut covered = BTreeSet::new();
+        for variable in self.dependencies.keys() {
+            if covered.contains(variable) {
+                continue;
+            }
+            if let Some(cycle) = self.shortest_cycle(variable) {
+                for other in self.reachable(variable) {
+                    if self.reachable(&other).contains(variable) {
+                        covered.insert(other);
+                    }
+                }
+                cycles.push(cycle);
+            }
+        }
+        cycles
+    }
+
+    fn reachable(&self, start: &str) -> BTreeSet<String> {
+        let mut reachable = BTreeSet::new();
+        let mut queue: VecDeque<_> = vec![start].into();
+        while let Some(current) = queue.pop_front() {
+            for dependency in self.dependencies.get(current).into_iter().flatten() {
+                if reachable.insert(dependency.clone()) {
+                    queue.push_back(dependency);
+                }
+            }
+        }
+        reachable
+    }
+
+    fn shortest_cycle(&self, start: &str) -> Option<Vec<String>> {
+        let mut parents: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut queue: VecDeque<&str> = vec![start].into();
+        while let Some(current) = queue.pop_front() {
+            for dependency in self.dependencies.get(current).into_iter().flatten() {
+                if dependency == start {
+                    let mut cycle = vec![start.to_string()];
+                    let mut node = current;
+                    while node != start {
+                        cycle.push(node.to_string());
+                        node = parents[node];
+                    }
+                    cycle.push(start.to_string());
+                    cycle.reverse();
+                    return Some(cycle);
+                }
+                if !parents.contains_key(&dependency[..]) {
+                    parents.insert(dependency, current);
+                    queue.push_back(dependency);
+                }
+            }
+        }
+        None
+    }
+
+    /// Create a graphviz (DOT) representation of the graph
+    ///
+    /// An edge `a -> b` means that the definition of `a` uses `b`.
+    pub fn to_dot(&self, name: &str) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut lines = vec![format!("digraph {} {{", quote(name))];
+        for (variable, dependencies) in self.dependencies.iter() {
+            lines.push(format!("    {};", quote(variable)));
+            for dependency in dependencies.iter() {
+                lines.push(format!("    {} -> {};", quote(variable), quote(dependency)));
+            }
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> VariableGraph {
+        VariableGraph::new(
+            edges
+                .iter()
+                .map(|(v, ds)| (v.to_string(), ds.iter().map(|d| d.to_string()).collect()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn no_cycles() {
+        let g = graph(&[("a", &["b", "c"]), ("b", &["c"]), ("c", &[])]);
+        assert!(g.cycles().is_empty());
+    }
+
+    #[test]
+    fn cycles() {
+        let g = graph(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a"]),
+            ("d", &["d"]),
+            ("e", &["a"]),
+        ]);
+        assert_eq!(
+            g.cycles(),
+            vec![
+                vec!["a", "b", "c", "a"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>(),
+                vec!["d".to_string(), "d".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let g = graph(&[("a", &["b"]), ("b", &[])]);
+        assert_eq!(
+            g.to_dot("q"),
+            "digraph \"q\" {\n    \"a\";\n    \"a\" -> \"b\";\n    \"b\";\n}"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Contains the description of the changes between two versions of a value
+//!
+//! Both versions are serialized and their serialized forms are compared, which results in one
+//! line per changed field, e.g. `parts.1.marks changed 1 → 2`.
+
+use serde::Serialize;
+use serde_yaml::Value;
+
+/// The maximal length of the description of an added or replaced value
+const MAX_SUMMARY_LENGTH: usize = 80;
+
+/// Describe the changes between two values, one line for each changed field
+pub fn describe_changes<T: Serialize>(old: &T, new: &T) -> Vec<String> {
+    let mut describer = ChangeDescriber::default();
+    match (serde_yaml::to_value(old), serde_yaml::to_value(new)) {
+        (Ok(old), Ok(new)) => describer.describe(&old, &new),
+        _ => describer.line("could not be compared".to_string()),
+    }
+    describer.lines
+}
+
+/// A short description of a value, e.g. of an added item
+fn summary(value: &Value) -> String {
+    let summary = serde_json::to_string(value).unwrap_or_else(|_| "?".to_string());
+    if summary.chars().count() > MAX_SUMMARY_LENGTH {
+        let truncated: String = summary.chars().take(MAX_SUMMARY_LENGTH).collect();
+        format!("{}…", truncated)
+    } else {
+        summary
+    }
+}
+
+/// The name of a key in a mapping
+fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        _ => summary(key),
+    }
+}
+
+/// Whether two mappings are different variants of an enum
+///
+/// Enums are serialized with their variant as the `type` field or as the only key.
+fn is_other_variant(old: &serde_yaml::Mapping, new: &serde_yaml::Mapping) -> bool {
+    let tag = Value::String("type".to_string());
+    match (old.get(&tag), new.get(&tag)) {
+        (Some(old_type), Some(new_type)) => old_type != new_type,
+        _ => {
+            old.len() == 1
+                && new.len() == 1
+                && old.iter().next().map(|(k, _)| k) != new.iter().next().map(|(k, _)| k)
+        }
+    }
+}
+
+#[derive(Default)]
+struct ChangeDescriber {
+    path: Vec<String>,
+    lines: Vec<String>,
+}
+
+impl ChangeDescriber {
+    fn line(&mut self, description: String) {
+        if self.path.is_empty() {
+            self.lines.push(description);
+        } else {
+            self.lines
+                .push(format!("{} {}", self.path.join("."), description));
+        }
+    }
+
+    fn line_at(&mut self, part: String, description: String) {
+        self.path.push(part);
+        self.line(description);
+        self.path.pop();
+    }
+
+    fn describe_at(&mut self, part: String, old: &Value, new: &Value) {
+        self.path.push(part);
+        self.describe(old, new);
+        self.path.pop();
+    }
+
+    fn describe(&mut self, old: &Value, new: &Value) {
+        if old == new {
+            return;
+        }
+        match (old, new) {
+            (Value::Mapping(old), Value::Mapping(new)) if !is_other_variant(old, new) => {
+                for (key, old_value) in old.iter() {
+                    match new.get(key) {
+                        Some(new_value) => self.describe_at(key_name(key), old_value, new_value),
+                        None => self.line_at(key_name(key), "removed".to_string()),
+                    }
+                }
+                for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(key)) {
+                    self.line_at(key_name(key), format!("added: {}", summary(new_value)));
+                }
+            }
+            (Value::Sequence(old), Value::Sequence(new)) => {
+                for (index, (old_item, new_item)) in old.iter().zip(new.iter()).enumerate() {
+                    self.describe_at(index.to_string(), old_item, new_item)
+                }
+                for index in new.len()..old.len() {
+                    self.line_at(index.to_string(), "removed".to_string());
+                }
+                for (index, new_item) in new.iter().enumerate().skip(old.len()) {
+                    self.line_at(index.to_string(), format!("added: {}", summary(new_item)));
+                }
+            }
+            _ => self.line(format!("changed {} → {}", summary(old), summary(new))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Part {
+        marks: usize,
+        prompt: Text,
+    }
+
+    #[derive(Serialize)]
+    struct Variable {
+        definition: String,
+        group: String,
+    }
+
+    /// A wrapper like JMEString
+    #[derive(Serialize)]
+    #[serde(transparent)]
+    struct Text {
+        s: String,
+    }
+
+    #[derive(Serialize)]
+    enum Navigation {
+        Sequential(Part),
+        Menu { allow_regenerate: bool },
+    }
+
+    #[derive(Serialize)]
+    struct Question {
+        parts: Vec<Part>,
+        variables: BTreeMap<String, Variable>,
+        navigation: Navigation,
+        max_runs: Option<usize>,
+    }
+
+    fn text(s: &str) -> Text {
+        Text { s: s.to_string() }
+    }
+
+    fn variable(definition: &str) -> Variable {
+        Variable {
+            definition: definition.to_string(),
+            group: "Ungrouped variables".to_string(),
+        }
+    }
+
+    fn question() -> Question {
+        Question {
+            parts: vec![
+                Part {
+                    marks: 1,
+                    prompt: text("a"),
+                },
+                Part {
+                    marks: 1,
+                    prompt: text("b"),
+                },
+            ],
+            variables: vec![
+                ("a".to_string(), variable("1")),
+                ("b".to_string(), variable("2")),
+            ]
+            .into_iter()
+            .collect(),
+            navigation: Navigation::Sequential(Part {
+                marks: 1,
+                prompt: text("c"),
+            }),
+            max_runs: None,
+        }
+    }
+
+    #[test]
+    fn unchanged() {
+        assert!(describe_changes(&question(), &question()).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let old = question();
+        let mut new = question();
+        new.parts[1].marks = 2;
+        new.parts.push(Part {
+            marks: 3,
+            prompt: text("d"),
+        });
+        new.variables.get_mut("a").unwrap().definition = "random(1..5)".to_string();
+        new.variables.remove("b");
+        new.navigation = Navigation::Menu {
+            allow_regenerate: true,
+        };
+        new.max_runs = Some(100);
+        assert_eq!(
+            describe_changes(&old, &new),
+            vec![
+                "parts.1.marks changed 1 → 2",
+                r#"parts.2 added: {"marks":3,"prompt":"d"}"#,
+                r#"variables.a.definition changed "1" → "random(1..5)""#,
+                "variables.b removed",
+                r#"navigation changed {"Sequential":{"marks":1,"prompt":"c"}} → {"Menu":{"allow_regenerate":true}}"#,
+                "max_runs changed null → 100",
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_changes() {
+        let old = question();
+        let mut new = question();
+        new.navigation = Navigation::Sequential(Part {
+            marks: 1,
+            prompt: text("d"),
+        });
+        assert_eq!(
+            describe_changes(&old, &new),
+            vec![r#"navigation.Sequential.prompt changed "c" → "d""#]
+        );
+    }
+}
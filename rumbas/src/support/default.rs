@@ -75,6 +75,10 @@ pub fn combine_exam_with_default_files(path: RumbasPath, exam: &mut ExamInput) {
 pub fn combine_question_with_default_files(path: RumbasPath, question: &mut QuestionInput) {
     let default_files = <DefaultFile<DefaultQuestionFileType>>::files(path);
     handle_question!(default_files, question);
+    // The order of the variable groups is optional, so older repositories stay valid
+    question
+        .variable_groups
+        .overwrite(&Value::Normal(Vec::new()));
 }
 
 /// Returns a vector of paths to default files for the given path
@@ -105,6 +109,21 @@ fn default_file_paths(path: RumbasPath) -> Vec<RumbasPath> {
     result
 }
 
+/// Returns the folder (within the repo) with the exams and questions that use the given default
+/// file, None if it is not in a defaults folder
+pub fn default_file_scope(path: &RumbasPath) -> Option<PathBuf> {
+    let defaults_folder = path
+        .project()
+        .ancestors()
+        .find(|a| a.file_name() == Some(std::ffi::OsStr::new(crate::DEFAULTS_FOLDER)))?;
+    Some(
+        defaults_folder
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf(),
+    )
+}
+
 // Create the needed enum for exams by specifying which files contain which data
 create_default_file_type_enums!(
     DefaultExamFileType: DefaultExamData,
@@ -76,6 +76,49 @@ impl DependencyManager {
         }
     }
 
+    /// Remove a file that no longer exists
+    ///
+    /// Returns the files that depended on it.
+    pub fn remove(&self, path: &RumbasPath) -> HashSet<RumbasPath> {
+        let mut map = self
+            .depended_on_by
+            .write()
+            .expect("Can write depended_on_by map");
+        let mut dependants = map
+            .remove(path)
+            .map(|val| val.into_inner().expect("unlock depended_on_by mutex"))
+            .unwrap_or_default();
+        dependants.remove(path);
+        // A removed file doesn't depend on other files anymore
+        for val in map.values() {
+            val.lock()
+                .expect("unlock loaded depended_on_by mutex")
+                .remove(path);
+        }
+        dependants
+    }
+
+    /// Move the dependencies of a file that is renamed (or moved) to its new path
+    pub fn rename(&self, from: &RumbasPath, to: RumbasPath) {
+        let mut map = self
+            .depended_on_by
+            .write()
+            .expect("Can write depended_on_by map");
+        if let Some(val) = map.remove(from) {
+            let mut dependants = val.into_inner().expect("unlock depended_on_by mutex");
+            if dependants.remove(from) {
+                dependants.insert(to.clone());
+            }
+            map.insert(to.clone(), Mutex::new(dependants));
+        }
+        for val in map.values() {
+            let mut dependants = val.lock().expect("unlock loaded depended_on_by mutex");
+            if dependants.remove(from) {
+                dependants.insert(to.clone());
+            }
+        }
+    }
+
     pub fn log_debug(&self) {
         let map = self
             .depended_on_by
@@ -84,3 +127,68 @@ impl DependencyManager {
         log::debug!("{:?}", map);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn rumbas_path(s: &str) -> RumbasPath {
+        RumbasPath::test_make(Path::new(s), Path::new("."))
+    }
+
+    fn paths(p: &[&str]) -> HashSet<RumbasPath> {
+        p.iter().map(|s| rumbas_path(s)).collect()
+    }
+
+    fn manager() -> DependencyManager {
+        let manager = DependencyManager::default();
+        manager.add_dependencies(
+            rumbas_path("exams/exam.yaml"),
+            paths(&["questions/question.yaml", "defaults/navigation.yaml"]),
+        );
+        manager.add_dependencies(
+            rumbas_path("questions/question.yaml"),
+            paths(&["defaults/navigation.yaml"]),
+        );
+        manager
+    }
+
+    #[test]
+    fn remove() {
+        let manager = manager();
+        assert_eq!(
+            manager.remove(&rumbas_path("questions/question.yaml")),
+            paths(&["exams/exam.yaml"])
+        );
+        assert_eq!(
+            manager.get_dependants(rumbas_path("questions/question.yaml")),
+            HashSet::new()
+        );
+        assert_eq!(
+            manager.get_dependants(rumbas_path("defaults/navigation.yaml")),
+            paths(&["exams/exam.yaml"])
+        );
+    }
+
+    #[test]
+    fn rename() {
+        let manager = manager();
+        manager.rename(
+            &rumbas_path("questions/question.yaml"),
+            rumbas_path("questions/renamed.yaml"),
+        );
+        assert_eq!(
+            manager.get_dependants(rumbas_path("questions/question.yaml")),
+            HashSet::new()
+        );
+        assert_eq!(
+            manager.get_dependants(rumbas_path("questions/renamed.yaml")),
+            paths(&["exams/exam.yaml", "questions/renamed.yaml"])
+        );
+        assert_eq!(
+            manager.get_dependants(rumbas_path("defaults/navigation.yaml")),
+            paths(&["exams/exam.yaml", "questions/renamed.yaml"])
+        );
+    }
+}
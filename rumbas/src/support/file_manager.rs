@@ -246,6 +246,20 @@ impl FileManager {
 }
 
 impl FileManager {
+    /// Forget the cached entries of the folder that contains the given path (and of the path
+    /// itself and its subfolders, if it is a folder), e.g. when it is created or removed
+    pub fn invalidate_folder_of(&self, path: &RumbasPath) {
+        let mut map = self.dir_cache.write().expect("Can write dir_cache map");
+        let parent = path.absolute().parent();
+        map.retain(|folder, _| {
+            let invalid = Some(folder.as_path()) == parent || folder.starts_with(path.absolute());
+            if invalid {
+                log::debug!("Deleting {} from the dir_cache.", folder.display());
+            }
+            !invalid
+        });
+    }
+
     pub fn delete_file(&self, file: FileToLoad) {
         let mut map = self.cache.write().expect("Can write cache map");
         if map.contains_key(&file) {
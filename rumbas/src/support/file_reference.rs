@@ -87,10 +87,10 @@ macro_rules! file_type {
                     let content = self.get_content(locale);
                     match content {
                         Some(c) => {
-                            let conversion_res: Result<$subtype, _> = c.try_into();
+                            let conversion_res: Result<$subtype, _> = c.clone().try_into();
                             match conversion_res {
                                 Ok(_) => RumbasCheckResult::empty(),
-                                Err(e) => $check_expr(e),
+                                Err(e) => $check_expr(e, &c),
                             }
                         }
                         None => RumbasCheckResult::from_missing_translation(Some(locale.to_string())),
@@ -331,7 +331,7 @@ file_type! {
     /// Specified by a string starting with [FILE_PREFIX].
     type FileString,
     subtype InputString,
-    rumbas_check |_e| RumbasCheckResult::empty() // never happens
+    rumbas_check |_e, _s: &str| RumbasCheckResult::empty() // never happens
 }
 
 file_type! {
@@ -340,7 +340,7 @@ file_type! {
     /// Specified by a string starting with [FILE_PREFIX].
     type JMEFileString,
     subtype JMEString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 }
 
 file_type! {
@@ -349,7 +349,7 @@ file_type! {
     /// Specified by a string starting with [FILE_PREFIX].
     type EmbracedJMEFileString,
     subtype EmbracedJMEString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 }
 
 file_type! {
@@ -358,5 +358,5 @@ file_type! {
     /// Specified by a string starting with [FILE_PREFIX].
     type ContentAreaFileString,
     subtype ContentAreaString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 }
@@ -0,0 +1,293 @@
+//! Contains the normalisation of the jme expressions in rumbas yaml files
+//!
+//! The definitions of the variables and the expressions of the marking notes are rewritten in
+//! their canonical form (see `numbas::jme::printer`). Each scalar is replaced at its position in
+//! the file, so the layout and the comments of the file are kept.
+
+use crate::question::variable::VariableStringRepresentation;
+use crate::support::file_reference::FILE_PREFIX;
+use crate::support::template::TEMPLATE_PREFIX;
+use crate::support::yaml::{YamlNode, YamlNodeValue, YamlPathPart};
+use numbas::jme::JMEString;
+use serde_yaml::Value;
+use std::convert::TryFrom;
+use std::ops::Range;
+use yaml_rust::scanner::TScalarStyle;
+
+/// The canonical form of a jme expression, None if it is already canonical
+///
+/// Expressions with comments, file references or template keys are left as is.
+fn canonical_jme(s: &str) -> Option<String> {
+    if s.contains("//")
+        || s.contains("/*")
+        || s.starts_with(&format!("{}:", FILE_PREFIX)[..])
+        || s.starts_with(&format!("{}:", TEMPLATE_PREFIX)[..])
+    {
+        return None;
+    }
+    let jme = JMEString::try_from(s.to_string()).ok()?;
+    let canonical = jme.ast()?.to_string();
+    if canonical == s {
+        None
+    } else {
+        Some(canonical)
+    }
+}
+
+/// Whether the string is a short variable definition that is interpreted as jme
+fn is_jme_variable(s: &str) -> bool {
+    matches!(
+        VariableStringRepresentation::try_from(s.to_string()),
+        Ok(VariableStringRepresentation::Anything(_))
+    )
+}
+
+/// A scalar of the yaml file and its canonical value
+type Replacement<'a> = (&'a YamlNode, String);
+
+fn collect_replacements(root: &YamlNode) -> Vec<Replacement<'_>> {
+    let mut replacements = Vec::new();
+    root.visit(&mut |path, _, node| {
+        // The key of the given ancestor, zero is the key of the node itself
+        let key = |ancestor: usize| match path.len().checked_sub(ancestor + 1).map(|i| &path[i]) {
+            Some(YamlPathPart::Key(k)) => Some(&k[..]),
+            _ => None,
+        };
+        let is_note = matches!(path.iter().rev().nth(1), Some(YamlPathPart::Item(_)))
+            && matches!(
+                key(2),
+                Some("custom_marking_algorithm_notes") | Some("marking_notes")
+            );
+        if key(1) == Some("variables") {
+            collect_variable_replacement(node, &mut replacements)
+        } else if key(0) == Some("expression") && is_note {
+            if let Some(canonical) = node.as_str().and_then(canonical_jme) {
+                replacements.push((node, canonical));
+            }
+        }
+    });
+    replacements
+}
+
+/// Add the replacement of the definition of a variable, in its short or its long form
+fn collect_variable_replacement<'a>(
+    variable: &'a YamlNode,
+    replacements: &mut Vec<Replacement<'a>>,
+) {
+    match &variable.value {
+        YamlNodeValue::Scalar(definition, _) if is_jme_variable(definition) => {
+            if let Some(canonical) = canonical_jme(definition) {
+                if is_jme_variable(&canonical) {
+                    replacements.push((variable, canonical));
+                }
+            }
+        }
+        YamlNodeValue::Mapping(_) => {
+            let is_anything =
+                variable.get("template_type").and_then(YamlNode::as_str) == Some("anything");
+            if let (true, Some(definition)) = (is_anything, variable.get("definition")) {
+                if let Some(canonical) = definition.as_str().and_then(canonical_jme) {
+                    replacements.push((definition, canonical));
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// The byte range and the text of a scalar that is written on one line, None if the scalar
+/// can't be replaced (e.g. a block scalar or a scalar that continues on the next line)
+fn written_scalar<'a>(yaml: &'a str, node: &YamlNode) -> Option<(Range<usize>, &'a str)> {
+    let value = match &node.value {
+        YamlNodeValue::Scalar(value, TScalarStyle::Plain)
+        | YamlNodeValue::Scalar(value, TScalarStyle::SingleQuoted)
+        | YamlNodeValue::Scalar(value, TScalarStyle::DoubleQuoted) => value,
+        _ => return None,
+    };
+    let start = yaml.char_indices().nth(node.start.index)?.0;
+    let line = yaml[start..].split('\n').next()?.trim_end_matches('\r');
+    let (written, _) = split_scalar(line)?;
+    match serde_yaml::from_str(written) {
+        Ok(Value::String(s)) if &s == value => Some((start..start + written.len(), written)),
+        _ => None,
+    }
+}
+
+/// Split a yaml scalar from the text that follows it on the same line
+fn split_scalar(s: &str) -> Option<(&str, &str)> {
+    let end = match s.chars().next()? {
+        '\'' => {
+            let mut chars = s.char_indices().skip(1).peekable();
+            loop {
+                match chars.next()? {
+                    (_, '\'') if chars.peek().map(|(_, c)| *c) == Some('\'') => {
+                        chars.next();
+                    }
+                    (i, '\'') => break i + 1,
+                    _ => (),
+                }
+            }
+        }
+        '"' => {
+            let mut chars = s.char_indices().skip(1);
+            loop {
+                match chars.next()? {
+                    (_, '\\') => {
+                        chars.next();
+                    }
+                    (i, '"') => break i + 1,
+                    _ => (),
+                }
+            }
+        }
+        _ => s.find(" #").unwrap_or(s.len()),
+    };
+    let value = s[..end].trim_end();
+    if value.is_empty() {
+        None
+    } else {
+        Some((value, &s[value.len()..]))
+    }
+}
+
+/// Write a string as a yaml scalar, in the same style as the original scalar if possible
+fn to_yaml_scalar(s: &str, original: &str) -> String {
+    let is_plain = |s: &str| matches!(serde_yaml::from_str(s), Ok(Value::String(p)) if p == s);
+    if original.starts_with('"') {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    } else if !original.starts_with('\'') && is_plain(s) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "''"))
+    }
+}
+
+/// Rewrite the variable definitions and marking notes of a yaml file in canonical jme
+///
+/// The yaml is returned unchanged if it can't be parsed.
+pub fn format_jme_in_yaml(yaml: &str) -> String {
+    let root = match YamlNode::parse(yaml) {
+        Some(root) => root,
+        None => return yaml.to_string(),
+    };
+    let mut replacements = collect_replacements(&root);
+    // Replace from the end of the file, so the positions of the other scalars stay the same
+    replacements.sort_by_key(|(node, _)| std::cmp::Reverse(node.start.index));
+    let mut result = yaml.to_string();
+    for (node, canonical) in replacements.into_iter() {
+        if let Some((range, written)) = written_scalar(&result, node) {
+            let scalar = to_yaml_scalar(&canonical, written);
+            result.replace_range(range, &scalar);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_variables_and_notes() {
+        let yaml = r#"type: normal
+# The variables
+variables:
+  a: random(1 .. 10)
+  b: "{a}*2x"
+  h: random([1,2,3])
+  c: 'a+b'
+  d: 1 .. 10#2
+  e:
+    definition: if(a>b,a,b)
+    template_type: string
+    description: ""
+    group: Ungrouped variables
+  f:
+    definition: if(a>b,a,b)
+    template_type: anything
+    description: ""
+    group: Ungrouped variables
+  g:
+    definition: x+1
+    template_type: string
+    description: ""
+    group: Ungrouped variables
+parts:
+  - type: jme
+    custom_marking_algorithm_notes:
+      - name: mark
+        description: The mark
+        expression: correctif(a=b) # the comment
+      - name: other
+        description: The mark
+        expression: '[1,2] // comment'
+"#;
+        let expected = r#"type: normal
+# The variables
+variables:
+  a: random(1 .. 10)
+  b: "a * 2 * x"
+  h: random([1, 2, 3])
+  c: 'a + b'
+  d: 1 .. 10#2
+  e:
+    definition: if(a>b,a,b)
+    template_type: string
+    description: ""
+    group: Ungrouped variables
+  f:
+    definition: if(a > b, a, b)
+    template_type: anything
+    description: ""
+    group: Ungrouped variables
+  g:
+    definition: x+1
+    template_type: string
+    description: ""
+    group: Ungrouped variables
+parts:
+  - type: jme
+    custom_marking_algorithm_notes:
+      - name: mark
+        description: The mark
+        expression: correctif(a = b) # the comment
+      - name: other
+        description: The mark
+        expression: '[1,2] // comment'
+"#;
+        assert_eq!(format_jme_in_yaml(yaml), expected);
+    }
+
+    #[test]
+    fn format_marking_notes_of_custom_part_types() {
+        let yaml = r#"name: part type
+marking_notes:
+  - name: mark
+    description: "set_credit(1,\"It's {a}\")"
+    expression: "set_credit(1,\"It's {a}\")"
+  - name: interpreted_answer
+    description: The answer
+    expression: |
+      studentAnswer+1
+"#;
+        let expected = r#"name: part type
+marking_notes:
+  - name: mark
+    description: "set_credit(1,\"It's {a}\")"
+    expression: "set_credit(1, \"It's {a}\")"
+  - name: interpreted_answer
+    description: The answer
+    expression: |
+      studentAnswer+1
+"#;
+        assert_eq!(format_jme_in_yaml(yaml), expected);
+    }
+
+    #[test]
+    fn keep_plain_scalars_valid() {
+        assert_eq!(to_yaml_scalar("a: 1", "x"), "'a: 1'");
+        assert_eq!(to_yaml_scalar("[a, b]", "[a,b]"), "'[a, b]'");
+        assert_eq!(to_yaml_scalar("-x", "- x"), "-x");
+        assert_eq!(to_yaml_scalar("it's", "'x'"), "'it''s'");
+    }
+}
@@ -0,0 +1,237 @@
+//! Contains the navigation of the json schemas of the rumbas files
+//!
+//! This is used by the language server to find the fields (and their documentation) that can be
+//! used at a position in a yaml file.
+
+use crate::support::yaml::YamlPathPart;
+use serde_json::Value;
+
+/// Parts of the names of the definitions of values that are (or contain) jme expressions
+const JME_DEFINITION_MARKERS: [&str; 2] = ["JME", "ContentArea"];
+
+/// A field of an object in a json schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaField {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// A json schema, as generated by schemars, that can be queried for the values at a path
+#[derive(Debug, Clone)]
+pub struct SchemaNavigator {
+    root: Value,
+}
+
+impl SchemaNavigator {
+    pub fn new(root: Value) -> Self {
+        Self { root }
+    }
+
+    fn definition(&self, reference: &str) -> Option<&Value> {
+        let name = reference.strip_prefix("#/definitions/")?;
+        self.root.get("definitions")?.get(name)
+    }
+
+    /// Collect the schema and all schemas that it refers to or combines
+    fn alternatives<'a>(
+        &'a self,
+        schema: &'a Value,
+        result: &mut Vec<&'a Value>,
+        references: &mut Vec<&'a str>,
+    ) {
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            if references.contains(&reference) {
+                return;
+            }
+            references.push(reference);
+            if let Some(definition) = self.definition(reference) {
+                self.alternatives(definition, result, references);
+            }
+        }
+        result.push(schema);
+        for key in ["anyOf", "oneOf", "allOf"] {
+            for subschema in schema
+                .get(key)
+                .and_then(|s| s.as_array())
+                .into_iter()
+                .flatten()
+            {
+                self.alternatives(subschema, result, references);
+            }
+        }
+        for key in ["then", "else"] {
+            if let Some(subschema) = schema.get(key) {
+                self.alternatives(subschema, result, references);
+            }
+        }
+    }
+
+    fn expand<'a>(&'a self, schema: &'a Value) -> (Vec<&'a Value>, Vec<&'a str>) {
+        let mut result = Vec::new();
+        let mut references = Vec::new();
+        self.alternatives(schema, &mut result, &mut references);
+        (result, references)
+    }
+
+    /// The schemas of the values at the given path
+    fn schemas_at(&self, path: &[YamlPathPart]) -> Vec<&Value> {
+        let mut current = vec![&self.root];
+        for part in path.iter() {
+            let mut next = Vec::new();
+            for schema in current.into_iter() {
+                for alternative in self.expand(schema).0 {
+                    match part {
+                        YamlPathPart::Key(key) => {
+                            if let Some(property) =
+                                alternative.get("properties").and_then(|p| p.get(key))
+                            {
+                                next.push(property)
+                            } else if let Some(additional) = alternative
+                                .get("additionalProperties")
+                                .filter(|a| a.is_object())
+                            {
+                                next.push(additional)
+                            }
+                        }
+                        YamlPathPart::Item(_) => match alternative.get("items") {
+                            Some(Value::Array(items)) => next.extend(items.iter()),
+                            Some(items) => next.push(items),
+                            None => (),
+                        },
+                    }
+                }
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// The fields that can be used in the object at the given path
+    pub fn fields_at(&self, path: &[YamlPathPart]) -> Vec<SchemaField> {
+        let mut result: Vec<SchemaField> = Vec::new();
+        for schema in self.schemas_at(path) {
+            for alternative in self.expand(schema).0 {
+                let properties = alternative.get("properties").and_then(|p| p.as_object());
+                for (name, property) in properties.into_iter().flatten() {
+                    if !result.iter().any(|f| &f.name == name) {
+                        result.push(SchemaField {
+                            name: name.clone(),
+                            description: self.description(property),
+                        });
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn description(&self, schema: &Value) -> Option<String> {
+        self.expand(schema)
+            .0
+            .into_iter()
+            .find_map(|s| s.get("description").and_then(|d| d.as_str()))
+            .map(|d| d.to_string())
+    }
+
+    /// The documentation of the value at the given path
+    pub fn description_at(&self, path: &[YamlPathPart]) -> Option<String> {
+        self.schemas_at(path)
+            .into_iter()
+            .find_map(|s| self.description(s))
+    }
+
+    /// Whether the value at the given path is a jme expression (or contains them)
+    pub fn is_jme_at(&self, path: &[YamlPathPart]) -> bool {
+        self.schemas_at(path).into_iter().any(|s| {
+            self.expand(s)
+                .1
+                .iter()
+                .any(|r| JME_DEFINITION_MARKERS.iter().any(|m| r.contains(m)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn navigator() -> SchemaNavigator {
+        SchemaNavigator::new(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "statement": {
+                    "description": "The statement",
+                    "allOf": [{"$ref": "#/definitions/ContentAreaTranslatableStringInput"}]
+                },
+                "parts": {"type": "array", "items": {"$ref": "#/definitions/Part"}},
+                "variables": {
+                    "type": "object",
+                    "additionalProperties": {"$ref": "#/definitions/VariableInput"}
+                }
+            },
+            "definitions": {
+                "ContentAreaTranslatableStringInput": {
+                    "description": "A translatable ContentArea string",
+                    "type": "string"
+                },
+                "Part": {
+                    "anyOf": [
+                        {"properties": {"type": {"enum": ["jme"]}, "answer": {"$ref": "#/definitions/JMETranslatableStringInput"}}},
+                        {"properties": {"type": {"enum": ["gapfill"]}, "gaps": {"type": "array", "items": {"$ref": "#/definitions/Part"}}}}
+                    ]
+                },
+                "JMETranslatableStringInput": {"type": "string"},
+                "VariableInput": {"properties": {"definition": {"description": "The definition", "type": "string"}}}
+            }
+        }))
+    }
+
+    fn keys(path: &[&str]) -> Vec<YamlPathPart> {
+        path.iter()
+            .map(|p| match p.parse() {
+                Ok(i) => YamlPathPart::Item(i),
+                Err(_) => YamlPathPart::Key(p.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fields() {
+        let names = |path: &[&str]| -> Vec<String> {
+            navigator()
+                .fields_at(&keys(path))
+                .into_iter()
+                .map(|f| f.name)
+                .collect()
+        };
+        assert_eq!(names(&[]), vec!["parts", "statement", "variables"]);
+        assert_eq!(names(&["parts", "0"]), vec!["answer", "type", "gaps"]);
+        assert_eq!(
+            names(&["parts", "0", "gaps", "1"]),
+            vec!["answer", "type", "gaps"]
+        );
+        assert_eq!(names(&["variables", "a"]), vec!["definition"]);
+    }
+
+    #[test]
+    fn descriptions() {
+        let navigator = navigator();
+        assert_eq!(
+            navigator.description_at(&keys(&["statement"])),
+            Some("The statement".to_string())
+        );
+        assert_eq!(
+            navigator.description_at(&keys(&["variables", "a", "definition"])),
+            Some("The definition".to_string())
+        );
+        assert_eq!(navigator.description_at(&keys(&["parts"])), None);
+    }
+
+    #[test]
+    fn jme() {
+        let navigator = navigator();
+        assert!(navigator.is_jme_at(&keys(&["statement"])));
+        assert!(navigator.is_jme_at(&keys(&["parts", "0", "answer"])));
+        assert!(!navigator.is_jme_at(&keys(&["variables", "a", "definition"])));
+    }
+}
@@ -4,12 +4,16 @@
 pub mod to_numbas;
 #[macro_use]
 pub mod to_rumbas;
+pub mod changes;
 pub mod default;
 pub mod dependency_manager;
 pub mod file_manager;
 pub mod file_reference;
 pub mod input_string;
+pub mod jme_format;
+pub mod json_schema;
 pub mod noneable;
+pub mod numbas_packager;
 pub mod rc;
 pub mod sanitize;
 pub mod template;
@@ -0,0 +1,647 @@
+//! Contains the native packager of numbas exams
+//!
+//! It assembles the output of `bin/numbas.py` of a local Numbas checkout, without python: the
+//! runtime, the theme (and the themes it inherits from), the extensions and resources of the
+//! exam, the generated settings and locale scripts, the rendered templates of the theme and the
+//! SCORM manifest. The javascript and css files are combined into `scripts.js` and `styles.css`.
+//! Whether the output is the same as the output of `bin/numbas.py` is not tested.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The folder of the Numbas checkout with the scripts and styles of the runtime
+pub const RUNTIME_FOLDER: &str = "runtime";
+const THEMES_FOLDER: &str = "themes";
+const THEME_INHERIT_FILE: &str = "inherit.txt";
+const THEME_FILES_FOLDER: &str = "files";
+const THEME_TEMPLATES_FOLDER: &str = "templates";
+const THEME_XSLT_FOLDER: &str = "xslt";
+const EXTENSIONS_FOLDER: &str = "extensions";
+const LOCALES_FOLDER: &str = "locales";
+/// The possible names of the folder with the SCORM files in the Numbas checkout
+const SCORM_FOLDERS: [&str; 2] = ["scormfiles", "scorm-files"];
+const SCORM_MANIFEST: &str = "imsmanifest.xml";
+/// The folder of the package with the resources of the exam and its questions
+const RESOURCES_FOLDER: &str = "resources/question-resources";
+/// The script that loads all other scripts, it has to be the first script in `scripts.js`
+const NUMBAS_LOADER: &str = "scripts/numbas.js";
+
+#[derive(Debug)]
+pub enum PackageError {
+    /// A file or folder of the Numbas checkout (or of a resource) that is missing
+    Missing(PathBuf),
+    Io(PathBuf, std::io::Error),
+    Json(serde_json::Error),
+    Template(String),
+    Zip(PathBuf, String),
+}
+
+impl Display for PackageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(p) => write!(f, "{} does not exist", p.display()),
+            Self::Io(p, e) => write!(f, "Failed reading or writing {}: {}", p.display(), e),
+            Self::Json(e) => write!(f, "Failed generating the exam json: {}", e),
+            Self::Template(e) => write!(f, "Failed rendering the theme templates: {}", e),
+            Self::Zip(p, e) => write!(f, "Failed creating the zip file {}: {}", p.display(), e),
+        }
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> PackageError + '_ {
+    move |e| PackageError::Io(path.to_path_buf(), e)
+}
+
+/// A file of the package
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackageFile {
+    /// A file that is copied from the Numbas checkout (or a resource)
+    Copy(PathBuf),
+    Generated(String),
+}
+
+impl PackageFile {
+    fn read(&self) -> Result<Vec<u8>, PackageError> {
+        match self {
+            Self::Copy(path) => std::fs::read(path).map_err(io_error(path)),
+            Self::Generated(content) => Ok(content.clone().into_bytes()),
+        }
+    }
+
+    fn read_to_string(&self) -> Result<String, PackageError> {
+        match self {
+            Self::Copy(path) => std::fs::read_to_string(path).map_err(io_error(path)),
+            Self::Generated(content) => Ok(content.clone()),
+        }
+    }
+}
+
+/// The files of a packaged exam, by their path (with `/` separators) in the output
+#[derive(Debug, Clone, Default)]
+pub struct Package {
+    pub files: BTreeMap<String, PackageFile>,
+}
+
+impl Package {
+    /// Write the package to the given folder
+    pub fn write_to_folder(&self, folder: &Path) -> Result<(), PackageError> {
+        for (name, file) in self.files.iter() {
+            let path = folder.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(io_error(parent))?;
+            }
+            std::fs::write(&path, file.read()?).map_err(io_error(&path))?;
+        }
+        Ok(())
+    }
+
+    /// Write the package to the given zip file
+    pub fn write_to_zip(&self, zip_path: &Path) -> Result<(), PackageError> {
+        let zip_error =
+            |e: zip::result::ZipError| PackageError::Zip(zip_path.to_path_buf(), e.to_string());
+        let file = std::fs::File::create(zip_path).map_err(io_error(zip_path))?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, file) in self.files.iter() {
+            zip.start_file(name, options).map_err(zip_error)?;
+            zip.write_all(&file.read()?).map_err(io_error(zip_path))?;
+        }
+        zip.finish().map_err(zip_error)?;
+        Ok(())
+    }
+}
+
+/// Packages numbas exams with the files of a local Numbas checkout
+#[derive(Debug, Clone)]
+pub struct NumbasPackager {
+    /// The root of the Numbas checkout
+    pub numbas_folder: PathBuf,
+    /// The name of a theme in the Numbas checkout or the path to a theme
+    pub theme: String,
+    /// The numbas locale, e.g. `en-GB`
+    pub locale: String,
+    pub scorm: bool,
+}
+
+impl NumbasPackager {
+    /// Create the package of an exam
+    pub fn package(&self, exam: &numbas::exam::Exam) -> Result<Package, PackageError> {
+        let exam = serde_json::to_value(exam).map_err(PackageError::Json)?;
+        self.package_json(&exam)
+    }
+
+    /// Create the package of an exam in its json representation
+    pub fn package_json(&self, exam: &serde_json::Value) -> Result<Package, PackageError> {
+        let theme_paths = self.theme_paths()?;
+        let mut package = Package::default();
+        let files = &mut package.files;
+
+        collect_folder(&self.numbas_folder.join(RUNTIME_FOLDER), "", files)?;
+        for theme_path in theme_paths.iter() {
+            collect_optional_folder(&theme_path.join(THEME_FILES_FOLDER), "", files)?;
+        }
+        for extension in string_list(&exam["extensions"]) {
+            // The extensions of an exam are absolute paths, a name is looked up in the Numbas
+            // checkout
+            let extension_path = self.numbas_folder.join(EXTENSIONS_FOLDER).join(&extension);
+            let extension_name = match extension_path.file_name() {
+                Some(name) if extension_path.is_dir() => name.to_string_lossy().to_string(),
+                _ => return Err(PackageError::Missing(extension_path)),
+            };
+            collect_folder(
+                &extension_path,
+                &format!("{}/{}", EXTENSIONS_FOLDER, extension_name),
+                files,
+            )?;
+        }
+        for resource in exam["resources"].as_array().into_iter().flatten() {
+            if let (Some(name), Some(path)) = (resource[0].as_str(), resource[1].as_str()) {
+                let path = Path::new(path);
+                let destination = format!("{}/{}", RESOURCES_FOLDER, name);
+                if path.is_dir() {
+                    collect_folder(path, &destination, files)?;
+                } else if path.is_file() {
+                    files.insert(destination, PackageFile::Copy(path.to_path_buf()));
+                } else {
+                    return Err(PackageError::Missing(path.to_path_buf()));
+                }
+            }
+        }
+
+        files.insert(
+            "settings.js".to_string(),
+            PackageFile::Generated(settings_script(&theme_paths, exam)?),
+        );
+        files.insert(
+            "locale.js".to_string(),
+            PackageFile::Generated(self.locale_script()?),
+        );
+        for (name, content) in self.render_templates(&theme_paths, exam)? {
+            files.insert(name, PackageFile::Generated(content));
+        }
+
+        // The SCORM scripts are combined with the other scripts
+        let scorm_manifest_template = if self.scorm {
+            Some(self.add_scorm_files(files)?)
+        } else {
+            None
+        };
+
+        combine_files(files, ".css", "styles.css", None)?;
+        combine_files(files, ".js", "scripts.js", Some(NUMBAS_LOADER))?;
+
+        if let Some(template) = scorm_manifest_template {
+            let name = exam["name"].as_str().unwrap_or_default();
+            let file_names: Vec<&str> = files.keys().map(|k| &k[..]).collect();
+            let manifest = scorm_manifest(&template, name, &file_names);
+            files.insert(SCORM_MANIFEST.to_string(), PackageFile::Generated(manifest));
+        }
+        Ok(package)
+    }
+
+    /// The folders of the theme and the themes it inherits from, the inherited themes come first
+    pub fn theme_paths(&self) -> Result<Vec<PathBuf>, PackageError> {
+        let mut themes = vec![self.theme.clone()];
+        let mut paths: Vec<PathBuf> = Vec::new();
+        while let Some(theme) = themes.pop() {
+            let path = if Path::new(&theme).is_dir() {
+                Path::new(&theme).to_path_buf()
+            } else {
+                self.numbas_folder.join(THEMES_FOLDER).join(&theme)
+            };
+            if !path.is_dir() {
+                return Err(PackageError::Missing(path));
+            }
+            if paths.contains(&path) {
+                continue;
+            }
+            let inherit_file = path.join(THEME_INHERIT_FILE);
+            if inherit_file.is_file() {
+                let inherited =
+                    std::fs::read_to_string(&inherit_file).map_err(io_error(&inherit_file))?;
+                themes.extend(
+                    inherited
+                        .lines()
+                        .map(|l| l.trim())
+                        .filter(|l| !l.is_empty())
+                        .map(|l| l.to_string()),
+                );
+            }
+            paths.push(path);
+        }
+        paths.reverse();
+        Ok(paths)
+    }
+
+    /// The script with the translations of all locales of numbas and the preferred locale
+    fn locale_script(&self) -> Result<String, PackageError> {
+        let locales_folder = self.numbas_folder.join(LOCALES_FOLDER);
+        let mut resources = serde_json::Map::new();
+        for (name, path) in folder_files(&locales_folder)? {
+            if let Some(locale) = name.strip_suffix(".json") {
+                let content = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+                let translation: serde_json::Value =
+                    serde_json::from_str(&content).map_err(PackageError::Json)?;
+                resources.insert(
+                    locale.to_string(),
+                    serde_json::json!({ "translation": translation }),
+                );
+            }
+        }
+        Ok(format!(
+            "Numbas.queueScript('localisation-resources',['i18next'],function() {{\nNumbas.locale = {{\n    preferred_locale: {},\n    resources: {}\n}}\n}});\n",
+            serde_json::to_string(&self.locale).map_err(PackageError::Json)?,
+            serde_json::to_string_pretty(&resources).map_err(PackageError::Json)?
+        ))
+    }
+
+    /// Render the templates of the themes (e.g. `index.html`), the templates of a theme replace
+    /// those of the themes it inherits from
+    fn render_templates(
+        &self,
+        theme_paths: &[PathBuf],
+        exam: &serde_json::Value,
+    ) -> Result<BTreeMap<String, String>, PackageError> {
+        let template_folders: Vec<PathBuf> = theme_paths
+            .iter()
+            .rev()
+            .map(|p| p.join(THEME_TEMPLATES_FOLDER))
+            .collect();
+        let mut names = Vec::new();
+        // A theme doesn't have to replace templates of the themes it inherits from
+        for folder in template_folders.iter().filter(|f| f.is_dir()) {
+            for (name, _) in folder_files(folder)? {
+                if !names.contains(&name) {
+                    names.push(name)
+                }
+            }
+        }
+
+        let mut env = minijinja::Environment::new();
+        let loader_folders = template_folders.clone();
+        env.set_loader(move |name| {
+            match loader_folders
+                .iter()
+                .map(|folder| folder.join(name))
+                .find(|path| path.is_file())
+            {
+                Some(path) => std::fs::read_to_string(path).map(Some).map_err(|e| {
+                    minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
+                }),
+                None => Ok(None),
+            }
+        });
+        let context = minijinja::context! {
+            exam => exam,
+            options => serde_json::json!({
+                "locale": self.locale,
+                "theme": self.theme,
+                "scorm": self.scorm,
+            }),
+        };
+        names
+            .into_iter()
+            .map(|name| {
+                let rendered = env
+                    .get_template(&name)
+                    .and_then(|template| template.render(&context))
+                    .map_err(|e| PackageError::Template(format!("{}: {}", name, e)))?;
+                Ok((name, rendered))
+            })
+            .collect()
+    }
+
+    /// Add the SCORM files, the template of the manifest (that lists all files of the package) is
+    /// returned
+    fn add_scorm_files(
+        &self,
+        files: &mut BTreeMap<String, PackageFile>,
+    ) -> Result<String, PackageError> {
+        let scorm_folder = SCORM_FOLDERS
+            .iter()
+            .map(|f| self.numbas_folder.join(f))
+            .find(|f| f.is_dir())
+            .ok_or_else(|| PackageError::Missing(self.numbas_folder.join(SCORM_FOLDERS[0])))?;
+        collect_folder(&scorm_folder, "", files)?;
+        files
+            .remove(SCORM_MANIFEST)
+            .ok_or_else(|| PackageError::Missing(scorm_folder.join(SCORM_MANIFEST)))?
+            .read_to_string()
+    }
+}
+
+/// The strings in a json list
+fn string_list(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str().map(|s| s.to_string()))
+        .collect()
+}
+
+/// The files in a folder (recursively) with their path relative to the folder
+pub fn folder_files(folder: &Path) -> Result<Vec<(String, PathBuf)>, PackageError> {
+    let mut result = Vec::new();
+    if !folder.is_dir() {
+        return Err(PackageError::Missing(folder.to_path_buf()));
+    }
+    let mut entries: Vec<_> = std::fs::read_dir(folder)
+        .map_err(io_error(folder))?
+        .collect::<Result<_, _>>()
+        .map_err(io_error(folder))?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(".git") {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            for (sub_name, sub_path) in folder_files(&path)? {
+                result.push((format!("{}/{}", name, sub_name), sub_path));
+            }
+        } else {
+            result.push((name, path));
+        }
+    }
+    Ok(result)
+}
+
+/// Add the files of a folder to the package, the files are placed in the destination folder
+fn collect_folder(
+    folder: &Path,
+    destination: &str,
+    files: &mut BTreeMap<String, PackageFile>,
+) -> Result<(), PackageError> {
+    for (name, path) in folder_files(folder)? {
+        let name = if destination.is_empty() {
+            name
+        } else {
+            format!("{}/{}", destination, name)
+        };
+        files.insert(name, PackageFile::Copy(path));
+    }
+    Ok(())
+}
+
+/// Add the files of a folder to the package if the folder exists, e.g. the files of a theme
+fn collect_optional_folder(
+    folder: &Path,
+    destination: &str,
+    files: &mut BTreeMap<String, PackageFile>,
+) -> Result<(), PackageError> {
+    if folder.is_dir() {
+        collect_folder(folder, destination, files)
+    } else {
+        Ok(())
+    }
+}
+
+/// The script with the xslt templates of the themes and the exam
+fn settings_script(
+    theme_paths: &[PathBuf],
+    exam: &serde_json::Value,
+) -> Result<String, PackageError> {
+    let mut templates = serde_json::Map::new();
+    for theme_path in theme_paths.iter() {
+        let xslt_folder = theme_path.join(THEME_XSLT_FOLDER);
+        if !xslt_folder.is_dir() {
+            continue;
+        }
+        for (name, path) in folder_files(&xslt_folder)? {
+            if let Some(name) = name.strip_suffix(".xslt") {
+                let content = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+                templates.insert(name.to_string(), serde_json::Value::String(content));
+            }
+        }
+    }
+    Ok(format!(
+        "Numbas.queueScript('settings',[],function() {{\nNumbas.rawxml = {{\n    templates: {},\n    examJSON: {}\n}};\n}});\n",
+        serde_json::to_string_pretty(&templates).map_err(PackageError::Json)?,
+        serde_json::to_string(exam).map_err(PackageError::Json)?
+    ))
+}
+
+/// Combine the files with the given extension into one file, the resources of the exam are not
+/// combined. The files are combined in alphabetical order, except for the given first file
+fn combine_files(
+    files: &mut BTreeMap<String, PackageFile>,
+    extension: &str,
+    combined_name: &str,
+    first: Option<&str>,
+) -> Result<(), PackageError> {
+    let mut names: Vec<String> = files
+        .keys()
+        .filter(|name| {
+            name.ends_with(extension) && !name.starts_with(&format!("{}/", RESOURCES_FOLDER))
+        })
+        .cloned()
+        .collect();
+    if let Some(first) = first {
+        if let Some(index) = names.iter().position(|name| name == first) {
+            let name = names.remove(index);
+            names.insert(0, name);
+        }
+    }
+    let mut contents = Vec::new();
+    for name in names.iter() {
+        if let Some(file) = files.remove(name) {
+            contents.push(file.read_to_string()?);
+        }
+    }
+    files.insert(
+        combined_name.to_string(),
+        PackageFile::Generated(contents.join("\n")),
+    );
+    Ok(())
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Fill in the identifier, the title and the files of the package in the SCORM manifest template
+fn scorm_manifest(template: &str, name: &str, files: &[&str]) -> String {
+    let mut manifest = template.to_string();
+    let identifier = format!(
+        "identifier=\"{}\"",
+        escape_xml(&format!("Numbas: {}", name))
+    );
+    if let Some(start) = manifest.find("<manifest") {
+        let end = manifest[start..]
+            .find('>')
+            .map(|e| start + e)
+            .unwrap_or(manifest.len());
+        if let Some(attribute) = manifest[start..end].find("identifier=\"") {
+            let attribute_start = start + attribute;
+            let value_start = attribute_start + "identifier=\"".len();
+            if let Some(value_end) = manifest[value_start..].find('"') {
+                manifest.replace_range(attribute_start..value_start + value_end + 1, &identifier);
+            }
+        } else {
+            manifest.insert_str(start + "<manifest".len(), &format!(" {}", identifier));
+        }
+    }
+    if let Some(start) = manifest.find("<title>") {
+        let content_start = start + "<title>".len();
+        if let Some(content_end) = manifest[content_start..].find("</title>") {
+            manifest.replace_range(
+                content_start..content_start + content_end,
+                &escape_xml(name),
+            );
+        }
+    }
+    let file_elements: String = files
+        .iter()
+        .map(|f| format!("<file href=\"{}\"/>\n", escape_xml(f)))
+        .collect();
+    if let Some(end) = manifest.find("</resource>") {
+        manifest.insert_str(end, &file_elements);
+    }
+    manifest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(root: &Path, name: &str, content: &str) {
+        let path = root.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// Create a minimal Numbas checkout in a temporary folder
+    fn numbas_folder() -> tempfile::TempDir {
+        let folder = tempfile::tempdir().unwrap();
+        let root = folder.path();
+        write(root, "runtime/scripts/numbas.js", "// loader");
+        write(root, "runtime/scripts/exam.js", "// exam");
+        write(
+            root,
+            "themes/default/files/resources/exam.css",
+            "/* default */",
+        );
+        write(root, "themes/default/templates/index.html", "default");
+        write(root, "themes/default/xslt/question.xslt", "<xsl/>");
+        write(root, "themes/child/inherit.txt", "default\n");
+        write(
+            root,
+            "themes/child/files/resources/child.css",
+            "/* child */",
+        );
+        write(
+            root,
+            "themes/child/templates/index.html",
+            "<title>{{ exam.name }}</title>{{ options.locale }}",
+        );
+        write(root, "extensions/stats/stats.js", "// stats");
+        write(root, "extensions/stats/icon.svg", "<svg/>");
+        write(root, "locales/en-GB.json", r#"{"page.loading": "Loading"}"#);
+        write(
+            root,
+            "scormfiles/imsmanifest.xml",
+            r#"<manifest identifier="Numbas" version="1"><organizations><organization><title>Numbas</title></organization></organizations><resources><resource identifier="r" href="index.html"></resource></resources></manifest>"#,
+        );
+        write(root, "scormfiles/SCORM_API_wrapper.js", "// scorm");
+        folder
+    }
+
+    fn packager(numbas_folder: &Path, scorm: bool) -> NumbasPackager {
+        NumbasPackager {
+            numbas_folder: numbas_folder.to_path_buf(),
+            theme: "child".to_string(),
+            locale: "en-GB".to_string(),
+            scorm,
+        }
+    }
+
+    /// An exam with the extension paths like `Extensions::to_paths`
+    fn exam(numbas_folder: &Path, extension: &str) -> serde_json::Value {
+        let extension = numbas_folder.join(EXTENSIONS_FOLDER).join(extension);
+        serde_json::json!({"name": "Exam <1>", "extensions": [extension], "resources": []})
+    }
+
+    fn content(package: &Package, name: &str) -> String {
+        package.files[name].read_to_string().unwrap()
+    }
+
+    #[test]
+    fn package() {
+        let folder = numbas_folder();
+        let package = packager(folder.path(), false)
+            .package_json(&exam(folder.path(), "stats"))
+            .unwrap();
+        assert_eq!(
+            package.files.keys().collect::<Vec<_>>(),
+            vec![
+                "extensions/stats/icon.svg",
+                "index.html",
+                "scripts.js",
+                "styles.css"
+            ]
+        );
+        assert_eq!(
+            content(&package, "index.html"),
+            "<title>Exam &lt;1&gt;</title>en-GB"
+        );
+        let scripts = content(&package, "scripts.js");
+        assert!(scripts.starts_with("// loader\n"));
+        for part in [
+            "// exam",
+            "// stats",
+            "preferred_locale: \"en-GB\"",
+            "\"page.loading\": \"Loading\"",
+            "\"question\": \"<xsl/>\"",
+            "examJSON: {",
+        ] {
+            assert!(scripts.contains(part), "{} not in scripts.js", part);
+        }
+        assert!(!scripts.contains("// scorm"));
+        assert_eq!(
+            content(&package, "styles.css"),
+            "/* child */\n/* default */"
+        );
+    }
+
+    #[test]
+    fn missing_extension() {
+        let folder = numbas_folder();
+        let result = packager(folder.path(), false).package_json(&exam(folder.path(), "unknown"));
+        assert!(
+            matches!(result, Err(PackageError::Missing(p)) if p.ends_with("extensions/unknown"))
+        );
+    }
+
+    #[test]
+    fn missing_runtime() {
+        let folder = numbas_folder();
+        std::fs::remove_dir_all(folder.path().join(RUNTIME_FOLDER)).unwrap();
+        let result = packager(folder.path(), false).package_json(&exam(folder.path(), "stats"));
+        assert!(matches!(result, Err(PackageError::Missing(p)) if p.ends_with(RUNTIME_FOLDER)));
+    }
+
+    #[test]
+    fn scorm() {
+        let folder = numbas_folder();
+        let package = packager(folder.path(), true)
+            .package_json(&exam(folder.path(), "stats"))
+            .unwrap();
+        assert!(content(&package, "scripts.js").contains("// scorm"));
+        assert_eq!(
+            content(&package, "imsmanifest.xml"),
+            r#"<manifest identifier="Numbas: Exam &lt;1&gt;" version="1"><organizations><organization><title>Exam &lt;1&gt;</title></organization></organizations><resources><resource identifier="r" href="index.html"><file href="extensions/stats/icon.svg"/>
+<file href="index.html"/>
+<file href="scripts.js"/>
+<file href="styles.css"/>
+</resource></resources></manifest>"#
+        );
+    }
+}
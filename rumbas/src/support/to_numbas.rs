@@ -5,11 +5,20 @@ pub type NumbasResult<T> = Result<T, RumbasCheckResult>;
 pub trait ToNumbas<NumbasType>: Clone + RumbasCheck {
     /// Method that safely converts a type to another (probably numbas) type
     fn to_numbas_safe(&self, locale: &str) -> NumbasResult<NumbasType> {
+        self.to_numbas_safe_with_warnings(locale).0
+    }
+    /// Method that safely converts a type like `to_numbas_safe`, the warnings of the check are
+    /// also returned when the conversion succeeds
+    fn to_numbas_safe_with_warnings(
+        &self,
+        locale: &str,
+    ) -> (NumbasResult<NumbasType>, RumbasCheckResult) {
         let check = self.check(locale);
+        let warnings = check.warnings();
         if check.is_empty() {
-            Ok(self.to_numbas(locale))
+            (Ok(self.to_numbas(locale)), warnings)
         } else {
-            Err(check)
+            (Err(check), warnings)
         }
     }
     /// Method that converts a type to another type
@@ -21,7 +21,7 @@ translatable_type! {
     /// - A map that maps locales on formattables strings and parts like "{func}" (between {}) to values.
     type TranslatableString,
     subtype String,
-    rumbas_check |_e| RumbasCheckResult::empty() // never happens
+    rumbas_check |_e, _s: &str| RumbasCheckResult::empty() // never happens
 
 }
 
@@ -34,7 +34,7 @@ translatable_type! {
     /// - A map that maps locales on formattables strings and parts like "{func}" (between {}) to values.
     type JMETranslatableString,
     subtype JMEString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 }
 
 translatable_type! {
@@ -46,7 +46,7 @@ translatable_type! {
     /// - A map that maps locales on formattables strings and parts like "{func}" (between {}) to values.
     type EmbracedJMETranslatableString,
     subtype EmbracedJMEString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 
 }
 
@@ -59,7 +59,7 @@ translatable_type! {
     /// - A map that maps locales on formattables strings and parts like "{func}" (between {}) to values.
     type JMENotesTranslatableString,
     subtype JMENotesString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 
 }
 translatable_type! {
@@ -71,7 +71,7 @@ translatable_type! {
     /// - A map that maps locales on formattables strings and parts like "{func}" (between {}) to values.
     type ContentAreaTranslatableString,
     subtype ContentAreaString,
-    rumbas_check |e| RumbasCheckResult::from_invalid_jme(&e)
+    rumbas_check |e, s: &str| RumbasCheckResult::from_invalid_jme(&e, s)
 
 }
 
@@ -474,10 +474,10 @@ macro_rules! translatable_type {
                     let content = self.to_string(locale);
                     match content {
                         Some(c) => {
-                            let conversion_res: Result<$subtype, _> = c.try_into();
+                            let conversion_res: Result<$subtype, _> = c.clone().try_into();
                             match conversion_res {
-                                Ok(_) => RumbasCheckResult::empty(),
-                                Err(e) => $check_expr(e),
+                                Ok(v) => v.check(locale),
+                                Err(e) => $check_expr(e, &c),
                             }
                         }
                         None => RumbasCheckResult::from_missing_translation(Some(locale.to_owned())),
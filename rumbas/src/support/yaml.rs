@@ -1,4 +1,7 @@
 use rumbas_support::path::RumbasPath;
+use rumbas_support::rumbas_check::RumbasCheckLocation;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, TScalarStyle};
 
 pub type YamlResult<T> = Result<T, YamlError>;
 
@@ -12,6 +15,14 @@ impl YamlError {
     pub fn from(error: serde_yaml::Error, file: RumbasPath) -> YamlError {
         YamlError { error, file }
     }
+    /// The position of the error in the file, if it is known
+    pub fn location(&self) -> Option<RumbasCheckLocation> {
+        self.error.location().map(|l| RumbasCheckLocation {
+            file: self.file.display().to_string(),
+            line: l.line(),
+            column: l.column(),
+        })
+    }
 }
 
 impl std::fmt::Display for YamlError {
@@ -35,3 +46,606 @@ impl std::fmt::Display for YamlError {
         }
     }
 }
+
+/// A position in a yaml file, lines and columns start at zero and count characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct YamlPosition {
+    pub line: usize,
+    pub column: usize,
+    /// The amount of characters before the position
+    pub index: usize,
+}
+
+impl From<Marker> for YamlPosition {
+    fn from(marker: Marker) -> Self {
+        YamlPosition {
+            line: marker.line() - 1,
+            column: marker.col(),
+            index: marker.index(),
+        }
+    }
+}
+
+/// A node of a yaml file, together with the position where it starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlNode {
+    pub value: YamlNodeValue,
+    pub start: YamlPosition,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum YamlNodeValue {
+    Scalar(String, TScalarStyle),
+    Sequence(Vec<YamlNode>),
+    /// The keys and values of a mapping, in the order of the file
+    Mapping(Vec<(YamlNode, YamlNode)>),
+    /// An alias of an anchored node, the node itself is only part of the anchor
+    Alias,
+}
+
+impl YamlNode {
+    /// Parse the first document of a yaml file, None if it is empty or not valid yaml
+    pub fn parse(text: &str) -> Option<YamlNode> {
+        let mut builder = YamlNodeBuilder::default();
+        Parser::new(text.chars()).load(&mut builder, false).ok()?;
+        builder.root
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            YamlNodeValue::Scalar(s, _) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value of the given key, if this node is a mapping
+    pub fn get(&self, key: &str) -> Option<&YamlNode> {
+        match &self.value {
+            YamlNodeValue::Mapping(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Call the given function for this node and all nodes within it, with their path
+    ///
+    /// The values of a mapping are visited together with their key.
+    pub fn visit<'a>(
+        &'a self,
+        f: &mut impl FnMut(&[YamlPathPart], Option<&'a YamlNode>, &'a YamlNode),
+    ) {
+        fn visit_inner<'a>(
+            node: &'a YamlNode,
+            key: Option<&'a YamlNode>,
+            path: &mut Vec<YamlPathPart>,
+            f: &mut impl FnMut(&[YamlPathPart], Option<&'a YamlNode>, &'a YamlNode),
+        ) {
+            f(path, key, node);
+            match &node.value {
+                YamlNodeValue::Sequence(items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        path.push(YamlPathPart::Item(index));
+                        visit_inner(item, None, path, f);
+                        path.pop();
+                    }
+                }
+                YamlNodeValue::Mapping(entries) => {
+                    for (key, value) in entries.iter() {
+                        if let Some(name) = key.as_str() {
+                            path.push(YamlPathPart::Key(name.to_string()));
+                            visit_inner(value, Some(key), path, f);
+                            path.pop();
+                        }
+                    }
+                }
+                YamlNodeValue::Scalar(_, _) | YamlNodeValue::Alias => (),
+            }
+        }
+        visit_inner(self, None, &mut Vec::new(), f)
+    }
+
+    /// The node that best matches the given field path of a check (e.g.
+    /// `question.parts.0.prompt`) and satisfies the given condition
+    ///
+    /// A node matches when its path in the yaml file is a subsequence of the field path, because
+    /// the field path can contain parts that are not in the file (e.g. for enum variants). The
+    /// node with the longest path wins, its key is returned as well. The root itself never
+    /// matches.
+    pub fn find_field(
+        &self,
+        field_path: &str,
+        condition: impl Fn(&YamlNode) -> bool,
+    ) -> Option<(Option<&YamlNode>, &YamlNode)> {
+        let field_parts: Vec<_> = field_path.split('.').collect();
+        let mut best = None;
+        self.visit(&mut |path, key, node| {
+            let mut remaining = field_parts.iter();
+            let is_subsequence = path
+                .iter()
+                .all(|part| remaining.any(|f| *f == part.to_string()));
+            let is_longer = best
+                .map(|(length, _, _)| path.len() > length)
+                .unwrap_or(true);
+            if !path.is_empty() && is_subsequence && is_longer && condition(node) {
+                best = Some((path.len(), key, node));
+            }
+        });
+        best.map(|(_, key, node)| (key, node))
+    }
+
+    /// The amount of characters of a scalar on one line, including its quotes
+    fn written_length(&self) -> usize {
+        match &self.value {
+            YamlNodeValue::Scalar(s, TScalarStyle::SingleQuoted)
+            | YamlNodeValue::Scalar(s, TScalarStyle::DoubleQuoted) => s.chars().count() + 2,
+            YamlNodeValue::Scalar(s, _) => s.chars().count(),
+            _ => 0,
+        }
+    }
+
+    /// Whether the given position is within this scalar, which is written on one line
+    fn contains(&self, line: usize, column: usize) -> bool {
+        self.start.line == line
+            && self.start.column <= column
+            && column <= self.start.column + self.written_length()
+    }
+
+    /// Add the path within this node to the content at the given position
+    ///
+    /// A position on a line after a key or item, of which the content is indented up to the
+    /// column of the key or the indicator of the item, is the start of a new key or item. Items
+    /// of a sequence can be indented as much as the key of the sequence, so it matters whether
+    /// the content is an item.
+    fn path_at(
+        &self,
+        position: (usize, usize),
+        indent: usize,
+        is_item: bool,
+        path: &mut Vec<YamlPathPart>,
+    ) {
+        let (line, column) = position;
+        let before =
+            |start: YamlPosition, column_start: usize| (start.line, column_start) <= (line, column);
+        match &self.value {
+            YamlNodeValue::Mapping(entries) => {
+                let entry = entries
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| before(key.start, key.start.column));
+                if let Some((key, value)) = entry {
+                    let name = match key.as_str() {
+                        Some(name) => name,
+                        None => return,
+                    };
+                    let in_key = key.contains(line, column);
+                    let is_sequence = matches!(value.value, YamlNodeValue::Sequence(_));
+                    let is_new_key = line > key.start.line
+                        && (indent < key.start.column
+                            || (indent == key.start.column && !(is_item && is_sequence)));
+                    if !in_key && !is_new_key {
+                        path.push(YamlPathPart::Key(name.to_string()));
+                        value.path_at(position, indent, is_item, path);
+                    }
+                }
+            }
+            YamlNodeValue::Sequence(items) => {
+                // The indicators of the items of a block sequence are in the column of its start
+                let indicator = |item: &YamlNode| item.start.column.min(self.start.column);
+                let item = items
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find(|(_, item)| before(item.start, indicator(item)));
+                if let Some((index, item)) = item {
+                    let is_new_item = line > item.start.line && indent <= indicator(item);
+                    if !is_new_item {
+                        path.push(YamlPathPart::Item(index));
+                        item.path_at(position, indent, is_item, path);
+                    }
+                }
+            }
+            YamlNodeValue::Scalar(_, _) | YamlNodeValue::Alias => (),
+        }
+    }
+}
+
+/// Parse a yaml file that is being edited at the given line
+///
+/// The line is left out if the file can't be parsed with it, e.g. because it contains a key
+/// that is being typed.
+fn parse_while_editing(text: &str, line: usize) -> Option<YamlNode> {
+    YamlNode::parse(text).or_else(|| {
+        let without_line: Vec<_> = text
+            .lines()
+            .enumerate()
+            .map(|(index, l)| {
+                if index == line {
+                    " ".repeat(l.chars().count())
+                } else {
+                    l.to_string()
+                }
+            })
+            .collect();
+        YamlNode::parse(&without_line.join("\n"))
+    })
+}
+
+/// A collection of which the end is not parsed yet
+enum PartialNode {
+    Sequence(YamlPosition, Vec<YamlNode>),
+    Mapping(YamlPosition, Vec<(YamlNode, YamlNode)>, Option<YamlNode>),
+}
+
+#[derive(Default)]
+struct YamlNodeBuilder {
+    stack: Vec<PartialNode>,
+    root: Option<YamlNode>,
+}
+
+impl YamlNodeBuilder {
+    fn add(&mut self, node: YamlNode) {
+        match self.stack.last_mut() {
+            Some(PartialNode::Sequence(_, items)) => items.push(node),
+            Some(PartialNode::Mapping(start, entries, pending_key)) => match pending_key.take() {
+                Some(key) => entries.push((key, node)),
+                None => {
+                    // The start of a block mapping is its first key
+                    *start = std::cmp::min(*start, node.start);
+                    *pending_key = Some(node);
+                }
+            },
+            None => {
+                if self.root.is_none() {
+                    self.root = Some(node)
+                }
+            }
+        }
+    }
+}
+
+impl MarkedEventReceiver for YamlNodeBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        let start = marker.into();
+        match event {
+            Event::Scalar(value, style, _, _) => self.add(YamlNode {
+                value: YamlNodeValue::Scalar(value, style),
+                start,
+            }),
+            Event::Alias(_) => self.add(YamlNode {
+                value: YamlNodeValue::Alias,
+                start,
+            }),
+            Event::SequenceStart(_) => self.stack.push(PartialNode::Sequence(start, Vec::new())),
+            Event::MappingStart(_) => {
+                self.stack
+                    .push(PartialNode::Mapping(start, Vec::new(), None))
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                let node = match self.stack.pop() {
+                    Some(PartialNode::Sequence(start, items)) => YamlNode {
+                        value: YamlNodeValue::Sequence(items),
+                        start,
+                    },
+                    Some(PartialNode::Mapping(start, entries, _)) => YamlNode {
+                        value: YamlNodeValue::Mapping(entries),
+                        start,
+                    },
+                    None => return,
+                };
+                self.add(node)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The line and column in a yaml file of a position within the string value of a field
+///
+/// The field is given by the path of a check (see `YamlNode::find_field`). The position is
+/// exact for block scalars and for scalars on one line, otherwise the start of the scalar is
+/// used. Lines and columns start at one.
+pub fn find_source_position(
+    text: &str,
+    field_path: &str,
+    source: &str,
+    (line, column): (usize, usize),
+) -> Option<(usize, usize)> {
+    let root = YamlNode::parse(text)?;
+    let (_, node) = root.find_field(field_path, |node| {
+        node.as_str().map(|s| s.contains(source)).unwrap_or(false)
+    })?;
+    let (value, style) = match &node.value {
+        YamlNodeValue::Scalar(value, style) => (value, *style),
+        _ => return None,
+    };
+    // The line and column (starting at zero) of the position within the value
+    let before = &value[..value.find(source)?];
+    let value_line = before.matches('\n').count() + line - 1;
+    let mut value_column = column - 1;
+    if line == 1 {
+        value_column += before.rsplit('\n').next().unwrap_or("").chars().count();
+    }
+    let start = node.start;
+    let position = match style {
+        TScalarStyle::Literal => (start.line + value_line, start.column + value_column),
+        TScalarStyle::Plain | TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted
+            if !value.contains('\n') =>
+        {
+            // The text of the scalar in the file, after the quote of a quoted scalar
+            let offset = if style == TScalarStyle::Plain { 0 } else { 1 };
+            let written: String = text
+                .lines()
+                .nth(start.line)?
+                .chars()
+                .skip(start.column + offset)
+                .take(value.chars().count())
+                .collect();
+            if &written == value {
+                (start.line, start.column + offset + value_column)
+            } else {
+                (start.line, start.column)
+            }
+        }
+        _ => (start.line, start.column),
+    };
+    Some((position.0 + 1, position.1 + 1))
+}
+
+/// A part of the path to a position in a yaml file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YamlPathPart {
+    Key(String),
+    /// An item of a sequence, with its index
+    Item(usize),
+}
+
+impl std::fmt::Display for YamlPathPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YamlPathPart::Key(k) => write!(f, "{}", k),
+            YamlPathPart::Item(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+/// The path to the content at the given position, lines and columns start at zero and count
+/// characters
+///
+/// When the position is in the value of a key, the path ends with that key; when the position
+/// is in a key, the path is the path of the mapping that contains the key.
+pub fn yaml_path_at(text: &str, line: usize, column: usize) -> Option<Vec<YamlPathPart>> {
+    let root = parse_while_editing(text, line)?;
+    // The indentation of the content before the position
+    let current = text.lines().nth(line).unwrap_or("");
+    let indent = current
+        .chars()
+        .position(|c| c != ' ')
+        .unwrap_or(column)
+        .min(column);
+    let is_item = current.chars().nth(indent) == Some('-');
+    let mut path = Vec::new();
+    root.path_at((line, column), indent, is_item, &mut path);
+    Some(path)
+}
+
+/// The path of the key at the given position, lines and columns start at zero and count
+/// characters
+pub fn yaml_key_at(text: &str, line: usize, column: usize) -> Option<Vec<YamlPathPart>> {
+    let root = YamlNode::parse(text)?;
+    let mut result = None;
+    root.visit(&mut |path, key, _| {
+        if key.map(|k| k.contains(line, column)).unwrap_or(false) {
+            result = Some(path.to_vec());
+        }
+    });
+    result
+}
+
+/// The scalar value (that is not a key) at the given position with its path, lines and columns
+/// start at zero and count characters
+pub fn yaml_value_at(
+    text: &str,
+    line: usize,
+    column: usize,
+) -> Option<(Vec<YamlPathPart>, String)> {
+    let root = YamlNode::parse(text)?;
+    let mut result = None;
+    root.visit(&mut |path, _, node| {
+        if let (Some(value), true) = (node.as_str(), node.contains(line, column)) {
+            result = Some((path.to_vec(), value.to_string()));
+        }
+    });
+    result
+}
+
+/// The keys in a yaml file with their line and column (starting at zero) and their path
+pub fn yaml_key_paths(text: &str) -> Vec<(usize, usize, Vec<YamlPathPart>)> {
+    let mut result = Vec::new();
+    if let Some(root) = YamlNode::parse(text) {
+        root.visit(&mut |path, key, _| {
+            if let Some(key) = key {
+                result.push((key.start.line, key.start.column, path.to_vec()));
+            }
+        });
+    }
+    result
+}
+
+/// The line and column (starting at one) of the key (or item) that best matches the given field
+/// path of a check, e.g. `question.parts.0.prompt` (see `YamlNode::find_field`)
+pub fn find_field_position(text: &str, field_path: &str) -> Option<(usize, usize)> {
+    let root = YamlNode::parse(text)?;
+    let (key, node) = root.find_field(field_path, |_| true)?;
+    let start = key.unwrap_or(node).start;
+    Some((start.line + 1, start.column + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source_positions() {
+        let yaml = "parts:\n  - type: number_entry\n    answer: 1 +* 2\n    prompt: \"1 +* 2\"\n";
+        assert_eq!(
+            find_source_position(yaml, "question.parts.0.0.answer", "1 +* 2", (1, 4)),
+            Some((3, 16))
+        );
+        assert_eq!(
+            find_source_position(yaml, "question.parts.0.0.prompt", "1 +* 2", (1, 4)),
+            Some((4, 17))
+        );
+        let yaml = "statement: |\n  <p>Test</p>\n  <p>Value {1 +* 2}</p>\nadvice: \"\"\n";
+        assert_eq!(
+            find_source_position(
+                yaml,
+                "question.statement",
+                "<p>Test</p>\n<p>Value {1 +* 2}</p>\n",
+                (2, 14)
+            ),
+            Some((3, 16))
+        );
+        assert_eq!(
+            find_source_position(yaml, "question.statement", "x +* 2", (1, 4)),
+            None
+        );
+        assert_eq!(
+            find_source_position(yaml, "question.advice", "<p>Test</p>", (1, 4)),
+            None
+        );
+        let yaml = "advice: >-\n  <p>Tést</p>\n  <p>{1 +* 2}</p>\n";
+        assert_eq!(
+            find_source_position(
+                yaml,
+                "question.advice",
+                "<p>Tést</p> <p>{1 +* 2}</p>",
+                (1, 16)
+            ),
+            Some((2, 3))
+        );
+    }
+
+    #[test]
+    fn yaml_nodes() {
+        let root = YamlNode::parse("a: 1\nb:\n  - x\n  - {c: [d]}\n").unwrap();
+        assert_eq!(
+            root.start,
+            YamlPosition {
+                line: 0,
+                column: 0,
+                index: 0
+            }
+        );
+        let mut paths = Vec::new();
+        root.visit(&mut |path, key, node| {
+            let path: Vec<_> = path.iter().map(|p| p.to_string()).collect();
+            paths.push((
+                path.join("."),
+                key.map(|k| k.start.line),
+                node.start.line,
+                node.start.column,
+            ));
+        });
+        assert_eq!(
+            paths,
+            vec![
+                ("".to_string(), None, 0, 0),
+                ("a".to_string(), Some(0), 0, 3),
+                ("b".to_string(), Some(1), 2, 2),
+                ("b.0".to_string(), None, 2, 4),
+                ("b.1".to_string(), None, 3, 4),
+                ("b.1.c".to_string(), Some(3), 3, 8),
+                ("b.1.c.0".to_string(), None, 3, 9),
+            ]
+        );
+        assert_eq!(YamlNode::parse("a: [1"), None);
+        assert_eq!(YamlNode::parse(""), None);
+    }
+
+    fn keys(path: &[&str]) -> Vec<YamlPathPart> {
+        path.iter()
+            .map(|p| match p.parse() {
+                Ok(i) => YamlPathPart::Item(i),
+                Err(_) => YamlPathPart::Key(p.to_string()),
+            })
+            .collect()
+    }
+
+    const QUESTION: &str = "statement: |\n  <p>Test</p>\nparts:\n  - type: jme\n    answer: x\n  - type: gapfill\n    gaps:\n    - type: number_entry\n      answer: 5\n      \nvariables:\n  a: 1\n";
+
+    #[test]
+    fn yaml_paths() {
+        let path_at = |line, column| yaml_path_at(QUESTION, line, column).unwrap();
+        assert_eq!(path_at(4, 4), keys(&["parts", "0"]));
+        assert_eq!(path_at(4, 12), keys(&["parts", "0", "answer"]));
+        assert_eq!(path_at(8, 6), keys(&["parts", "1", "gaps", "0"]));
+        assert_eq!(path_at(9, 6), keys(&["parts", "1", "gaps", "0"]));
+        assert_eq!(path_at(9, 4), keys(&["parts", "1"]));
+        assert_eq!(path_at(7, 6), keys(&["parts", "1", "gaps", "0"]));
+        assert_eq!(path_at(1, 4), keys(&["statement"]));
+        assert_eq!(path_at(11, 2), keys(&["variables"]));
+        assert_eq!(path_at(10, 0), keys(&[]));
+        // A key that is being typed
+        let editing = QUESTION.replace("      answer: 5", "      answ");
+        assert_eq!(
+            yaml_path_at(&editing, 8, 10),
+            Some(keys(&["parts", "1", "gaps", "0"]))
+        );
+    }
+
+    #[test]
+    fn yaml_keys() {
+        assert_eq!(
+            yaml_key_at(QUESTION, 8, 8),
+            Some(keys(&["parts", "1", "gaps", "0", "answer"]))
+        );
+        assert_eq!(
+            yaml_key_at(QUESTION, 5, 5),
+            Some(keys(&["parts", "1", "type"]))
+        );
+        assert_eq!(yaml_key_at(QUESTION, 5, 12), None);
+    }
+
+    #[test]
+    fn yaml_values() {
+        let yaml = "template: \"templates/q\"\nstatement: file:statement.html\nparts:\n  - file:part.yaml\n";
+        assert_eq!(
+            yaml_value_at(yaml, 0, 14),
+            Some((keys(&["template"]), "templates/q".to_string()))
+        );
+        assert_eq!(
+            yaml_value_at(yaml, 1, 11),
+            Some((keys(&["statement"]), "file:statement.html".to_string()))
+        );
+        assert_eq!(
+            yaml_value_at(yaml, 3, 5),
+            Some((keys(&["parts", "0"]), "file:part.yaml".to_string()))
+        );
+        assert_eq!(yaml_value_at(yaml, 1, 2), None);
+    }
+
+    #[test]
+    fn key_paths() {
+        let variables: Vec<_> = yaml_key_paths(QUESTION)
+            .into_iter()
+            .filter(|(_, _, path)| path.len() == 2 && path[0] == keys(&["variables"])[0])
+            .map(|(line, column, path)| (line, column, path[1].to_string()))
+            .collect();
+        assert_eq!(variables, vec![(11, 2, "a".to_string())]);
+    }
+
+    #[test]
+    fn field_positions() {
+        assert_eq!(
+            find_field_position(QUESTION, "question.parts.1.0.0.gaps.0.0.0.answer"),
+            Some((9, 7))
+        );
+        assert_eq!(
+            find_field_position(QUESTION, "question.variables.a"),
+            Some((12, 3))
+        );
+        assert_eq!(find_field_position(QUESTION, "question.advice"), None);
+    }
+}
@@ -473,6 +473,39 @@ impl InputCheckResult {
     pub fn error_messages(&self) -> Vec<String> {
         self.error_messages.clone()
     }
+    /// All problems, one for each missing field, invalid field or error message
+    pub fn diagnostics(&self) -> Vec<crate::rumbas_check::RumbasCheckDiagnostic> {
+        let diagnostic =
+            |kind, path: String, message: String| crate::rumbas_check::RumbasCheckDiagnostic {
+                kind,
+                warning: false,
+                path,
+                message,
+                location: None,
+            };
+        self.missing_values
+            .iter()
+            .map(|m| {
+                diagnostic(
+                    "missing_field",
+                    m.path.to_string(),
+                    "Missing field".to_string(),
+                )
+            })
+            .chain(self.invalid_yaml_values.iter().map(|i| {
+                let message = i
+                    .error
+                    .clone()
+                    .unwrap_or_else(|| "Invalid value".to_string());
+                diagnostic("invalid_field", i.path.to_string(), message)
+            }))
+            .chain(
+                self.error_messages
+                    .iter()
+                    .map(|e| diagnostic("error", String::new(), e.clone())),
+            )
+            .collect()
+    }
 }
 
 impl InputCheckResult {
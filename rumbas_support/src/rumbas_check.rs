@@ -84,9 +84,7 @@ impl_rumbas_check!(bool);
 
 impl_rumbas_check!(std::path::PathBuf);
 
-impl_rumbas_check!(numbas::jme::ContentAreaString);
-impl_rumbas_check!(numbas::jme::EmbracedJMEString);
-impl_rumbas_check!(numbas::jme::JMEString);
+impl_rumbas_check!(numbas::jme::JMENotesString);
 impl_rumbas_check!(numbas::question::part::match_answers::MatchAnswersWithChoicesLayout);
 impl_rumbas_check!(numbas::question::part::match_answers::MatchAnswersWithChoicesDisplayType);
 impl_rumbas_check!(numbas::question::part::match_answers::MultipleChoiceWarningType);
@@ -95,11 +93,95 @@ impl_rumbas_check!(numbas::support::answer_style::AnswerStyle);
 impl_rumbas_check!(numbas::support::primitive::Number);
 impl_rumbas_check!(numbas::question::function::FunctionType);
 
+impl RumbasCheck for numbas::jme::JMEString {
+    fn check(&self, _locale: &str) -> RumbasCheckResult {
+        let mut result = RumbasCheckResult::from_jme_identifiers(
+            self.ast()
+                .map(|ast| ast.unknown_identifiers())
+                .unwrap_or_default(),
+        );
+        result.union(&RumbasCheckResult::from_invalid_function_calls(
+            self.ast()
+                .map(|ast| ast.invalid_function_calls())
+                .unwrap_or_default(),
+        ));
+        result.union(&RumbasCheckResult::from_jme_expressions(
+            self.ast().into_iter().cloned().collect(),
+        ));
+        result
+    }
+}
+
+impl RumbasCheck for numbas::jme::EmbracedJMEString {
+    fn check(&self, _locale: &str) -> RumbasCheckResult {
+        let mut result = RumbasCheckResult::from_jme_identifiers(
+            self.asts()
+                .iter()
+                .flat_map(|ast| ast.unknown_identifiers())
+                .collect(),
+        );
+        result.union(&RumbasCheckResult::from_invalid_function_calls(
+            self.asts()
+                .iter()
+                .flat_map(|ast| ast.invalid_function_calls())
+                .collect(),
+        ));
+        result.union(&RumbasCheckResult::from_jme_expressions(
+            self.asts().to_vec(),
+        ));
+        result
+    }
+}
+
+impl RumbasCheck for numbas::jme::ContentAreaString {
+    fn check(&self, _locale: &str) -> RumbasCheckResult {
+        let mut result = RumbasCheckResult::from_jme_identifiers(
+            self.asts()
+                .iter()
+                .flat_map(|ast| ast.unknown_identifiers())
+                .collect(),
+        );
+        result.union(&RumbasCheckResult::from_invalid_function_calls(
+            self.asts()
+                .iter()
+                .flat_map(|ast| ast.invalid_function_calls())
+                .collect(),
+        ));
+        result.union(&RumbasCheckResult::from_jme_expressions(
+            self.asts().to_vec(),
+        ));
+        result.union(&RumbasCheckResult::from_simplification_rulesets(
+            self.unknown_simplification_rules(),
+        ));
+        result
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RumbasCheckResult {
     // When adding a field, do also add it to is_empty
     missing_translations: Vec<RumbasCheckMissingData>,
     invalid_jme_strings: Vec<RumbasCheckInvalidJMEStringData>,
+    unknown_jme_identifiers: Vec<RumbasCheckJMEIdentifierData>,
+    // Identifiers that still have to be resolved by a parent that knows the defined variables
+    // and functions, so these are not part of is_empty
+    used_jme_identifiers: Vec<RumbasCheckJMEIdentifierData>,
+    circular_jme_variables: Vec<RumbasCheckCircularVariablesData>,
+    unknown_simplification_rules: Vec<RumbasCheckSimplificationRuleData>,
+    // Rule names that are no builtin rules, so these should be rulesets that are defined by a
+    // parent, these are not part of is_empty
+    used_simplification_rulesets: Vec<RumbasCheckSimplificationRuleData>,
+    invalid_marking_notes: Vec<RumbasCheckMarkingData>,
+    // Expressions whose types still have to be checked by a parent that knows the types of the
+    // variables and functions, so these are not part of is_empty
+    jme_expressions: Vec<RumbasCheckJMEExpressionData>,
+    // Warnings, these are not part of is_empty. Type errors and invalid function calls are
+    // warnings because the signatures and the inference of the types don't know all conversions
+    // that Numbas does
+    invalid_jme_function_calls: Vec<RumbasCheckFunctionCallData>,
+    jme_type_errors: Vec<RumbasCheckJMETypeErrorData>,
+    unused_jme_variables: Vec<RumbasCheckMissingData>,
+    looping_simplification_rules: Vec<RumbasCheckLoopingRulesData>,
 }
 
 impl RumbasCheckResult {
@@ -108,27 +190,260 @@ impl RumbasCheckResult {
             missing_translations: vec![RumbasCheckMissingData {
                 path: RumbasCheckPath::with_last(os),
             }],
-            invalid_jme_strings: vec![],
+            ..Self::empty()
         }
     }
 
-    pub fn from_invalid_jme(e: &numbas::jme::parser::ConsumeError) -> RumbasCheckResult {
+    /// Create a result for the given source that can't be parsed
+    pub fn from_invalid_jme(
+        e: &numbas::jme::parser::ConsumeError,
+        source: &str,
+    ) -> RumbasCheckResult {
         RumbasCheckResult {
-            missing_translations: vec![],
             invalid_jme_strings: vec![RumbasCheckInvalidJMEStringData {
                 path: RumbasCheckPath::without_last(),
                 error: e.clone(),
+                source: source.to_string(),
+                location: None,
+            }],
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result containing identifiers that are used but not defined within a jme expression
+    pub fn from_jme_identifiers(
+        errors: Vec<numbas::jme::ast::ExprValidationError>,
+    ) -> RumbasCheckResult {
+        RumbasCheckResult {
+            used_jme_identifiers: errors
+                .into_iter()
+                .map(|error| RumbasCheckJMEIdentifierData {
+                    path: RumbasCheckPath::without_last(),
+                    error,
+                })
+                .collect(),
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result for variables whose definitions depend on each other
+    pub fn from_circular_variables(cycle: Vec<String>) -> RumbasCheckResult {
+        RumbasCheckResult {
+            circular_jme_variables: vec![RumbasCheckCircularVariablesData {
+                path: RumbasCheckPath::without_last(),
+                cycle,
+            }],
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result for calls of builtin functions with invalid arguments
+    pub fn from_invalid_function_calls(
+        errors: Vec<numbas::jme::ast::FunctionCallError>,
+    ) -> RumbasCheckResult {
+        RumbasCheckResult {
+            invalid_jme_function_calls: errors
+                .into_iter()
+                .map(|error| RumbasCheckFunctionCallData {
+                    path: RumbasCheckPath::without_last(),
+                    error,
+                })
+                .collect(),
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result containing simplification rules that should be defined as rulesets
+    pub fn from_simplification_rulesets(rules: Vec<String>) -> RumbasCheckResult {
+        RumbasCheckResult {
+            used_simplification_rulesets: rules
+                .into_iter()
+                .map(|rule| RumbasCheckSimplificationRuleData {
+                    path: RumbasCheckPath::without_last(),
+                    rule,
+                })
+                .collect(),
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result for problems in the notes of a marking algorithm
+    pub fn from_marking_errors(
+        errors: Vec<numbas::jme::marking::MarkingError>,
+    ) -> RumbasCheckResult {
+        RumbasCheckResult {
+            invalid_marking_notes: errors
+                .into_iter()
+                .map(|error| RumbasCheckMarkingData {
+                    path: RumbasCheckPath::without_last(),
+                    error,
+                })
+                .collect(),
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result containing jme expressions whose types still have to be checked
+    pub fn from_jme_expressions(expressions: Vec<numbas::jme::ast::Expr>) -> RumbasCheckResult {
+        RumbasCheckResult {
+            jme_expressions: expressions
+                .into_iter()
+                .map(|expression| RumbasCheckJMEExpressionData {
+                    path: RumbasCheckPath::without_last(),
+                    expression,
+                    expected_type: None,
+                })
+                .collect(),
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result that warns about a variable that is never used
+    pub fn from_unused_variable(name: String) -> RumbasCheckResult {
+        RumbasCheckResult {
+            unused_jme_variables: vec![RumbasCheckMissingData {
+                path: RumbasCheckPath::with_last(Some(name)),
+            }],
+            ..Self::empty()
+        }
+    }
+
+    /// Create a result that warns about simplification rules that can rewrite each other forever
+    pub fn from_looping_simplification_rules(rules: Vec<String>) -> RumbasCheckResult {
+        RumbasCheckResult {
+            looping_simplification_rules: vec![RumbasCheckLoopingRulesData {
+                path: RumbasCheckPath::without_last(),
+                rules,
             }],
+            ..Self::empty()
         }
     }
     pub fn empty() -> RumbasCheckResult {
         RumbasCheckResult {
             missing_translations: vec![],
             invalid_jme_strings: vec![],
+            unknown_jme_identifiers: vec![],
+            used_jme_identifiers: vec![],
+            circular_jme_variables: vec![],
+            invalid_jme_function_calls: vec![],
+            unknown_simplification_rules: vec![],
+            used_simplification_rulesets: vec![],
+            jme_type_errors: vec![],
+            invalid_marking_notes: vec![],
+            jme_expressions: vec![],
+            unused_jme_variables: vec![],
+            looping_simplification_rules: vec![],
         }
     }
     pub fn is_empty(&self) -> bool {
-        self.missing_translations.len() == 0 && self.invalid_jme_strings.len() == 0
+        self.missing_translations.len() == 0
+            && self.invalid_jme_strings.len() == 0
+            && self.unknown_jme_identifiers.len() == 0
+            && self.circular_jme_variables.len() == 0
+            && self.unknown_simplification_rules.len() == 0
+            && self.invalid_marking_notes.len() == 0
+    }
+    /// Whether there are warnings
+    pub fn has_warnings(&self) -> bool {
+        !self.unused_jme_variables.is_empty()
+            || !self.looping_simplification_rules.is_empty()
+            || !self.invalid_jme_function_calls.is_empty()
+            || !self.jme_type_errors.is_empty()
+    }
+    /// A result with only the warnings of this result
+    pub fn warnings(&self) -> RumbasCheckResult {
+        RumbasCheckResult {
+            unused_jme_variables: self.unused_jme_variables.clone(),
+            looping_simplification_rules: self.looping_simplification_rules.clone(),
+            invalid_jme_function_calls: self.invalid_jme_function_calls.clone(),
+            jme_type_errors: self.jme_type_errors.clone(),
+            ..Self::empty()
+        }
+    }
+    /// Remove the used jme identifiers that are known
+    pub fn resolve_jme_identifiers(
+        &mut self,
+        is_known: impl Fn(&numbas::jme::ast::ExprValidationError) -> bool,
+    ) {
+        self.used_jme_identifiers.retain(|i| !is_known(&i.error));
+    }
+    /// Allow the notes of a base marking algorithm in the marking notes at the given path
+    ///
+    /// The unknown variables and applied notes in these notes are assumed to be notes of the base
+    /// algorithm.
+    pub fn resolve_base_marking_notes(&mut self, notes_path: &str) {
+        let within = |path: &RumbasCheckPath| path.to_string().starts_with(notes_path);
+        self.used_jme_identifiers.retain(|i| {
+            !(within(&i.path)
+                && matches!(
+                    i.error,
+                    numbas::jme::ast::ExprValidationError::UnknownVariable(_)
+                ))
+        });
+        self.invalid_marking_notes.retain(|m| {
+            !(within(&m.path)
+                && matches!(
+                    m.error,
+                    numbas::jme::marking::MarkingError::UnknownAppliedNote(_)
+                ))
+        });
+    }
+    /// Mark all used jme identifiers that are not resolved as unknown
+    pub fn report_unknown_jme_identifiers(&mut self) {
+        self.unknown_jme_identifiers
+            .append(&mut self.used_jme_identifiers);
+    }
+    /// Report the used simplification rules that are not one of the given rulesets as unknown
+    pub fn resolve_simplification_rulesets(&mut self, rulesets: &[String]) {
+        let rulesets: Vec<_> = rulesets.iter().map(|r| r.to_lowercase()).collect();
+        for used in self.used_simplification_rulesets.drain(..) {
+            let name = used.rule.trim_start_matches('!').to_lowercase();
+            if !rulesets.contains(&name) {
+                self.unknown_simplification_rules.push(used);
+            }
+        }
+    }
+    /// Set the type that the value of the unchecked jme expressions should have
+    pub fn expect_jme_type(
+        &mut self,
+        expected_type: numbas::jme::builtin_functions::ParameterType,
+    ) {
+        for expression in self.jme_expressions.iter_mut() {
+            expression.expected_type = Some(expected_type);
+        }
+    }
+    /// Check the types of the unchecked jme expressions
+    pub fn check_jme_types(&mut self, env: &numbas::jme::types::TypeEnvironment) {
+        for expression in self.jme_expressions.drain(..) {
+            let errors = match expression.expected_type {
+                Some(expected_type) => expression
+                    .expression
+                    .type_errors_expecting(env, expected_type),
+                None => expression.expression.type_errors(env),
+            };
+            self.jme_type_errors.extend(errors.into_iter().map(|error| {
+                RumbasCheckJMETypeErrorData {
+                    path: expression.path.clone(),
+                    error,
+                }
+            }));
+        }
+    }
+    /// Set the locations in the files of the jme strings that can't be parsed
+    ///
+    /// The locate function receives the path of the field, its source and the line and column
+    /// of the error within that source.
+    pub fn locate_invalid_jme(
+        &mut self,
+        locate: impl Fn(&str, &str, (usize, usize)) -> Option<RumbasCheckLocation>,
+    ) {
+        for invalid in self.invalid_jme_strings.iter_mut() {
+            let path = invalid.path.to_string();
+            invalid.location = invalid
+                .error
+                .line_col()
+                .and_then(|line_col| locate(&path, &invalid.source, line_col));
+        }
     }
     pub fn extend_path(&mut self, s: String) {
         for missing_value in self.missing_translations.iter_mut() {
@@ -137,12 +452,65 @@ impl RumbasCheckResult {
         for invalid_value in self.invalid_jme_strings.iter_mut() {
             invalid_value.path.add(s.clone());
         }
+        for identifier in self.unknown_jme_identifiers.iter_mut() {
+            identifier.path.add(s.clone());
+        }
+        for identifier in self.used_jme_identifiers.iter_mut() {
+            identifier.path.add(s.clone());
+        }
+        for circular_value in self.circular_jme_variables.iter_mut() {
+            circular_value.path.add(s.clone());
+        }
+        for call in self.invalid_jme_function_calls.iter_mut() {
+            call.path.add(s.clone());
+        }
+        for rule in self.unknown_simplification_rules.iter_mut() {
+            rule.path.add(s.clone());
+        }
+        for rule in self.used_simplification_rulesets.iter_mut() {
+            rule.path.add(s.clone());
+        }
+        for type_error in self.jme_type_errors.iter_mut() {
+            type_error.path.add(s.clone());
+        }
+        for marking_error in self.invalid_marking_notes.iter_mut() {
+            marking_error.path.add(s.clone());
+        }
+        for expression in self.jme_expressions.iter_mut() {
+            expression.path.add(s.clone());
+        }
+        for unused_value in self.unused_jme_variables.iter_mut() {
+            unused_value.path.add(s.clone());
+        }
+        for looping_value in self.looping_simplification_rules.iter_mut() {
+            looping_value.path.add(s.clone());
+        }
     }
     pub fn union(&mut self, other: &Self) {
         self.missing_translations
             .extend(other.missing_translations.clone());
         self.invalid_jme_strings
             .extend(other.invalid_jme_strings.clone());
+        self.unknown_jme_identifiers
+            .extend(other.unknown_jme_identifiers.clone());
+        self.used_jme_identifiers
+            .extend(other.used_jme_identifiers.clone());
+        self.circular_jme_variables
+            .extend(other.circular_jme_variables.clone());
+        self.invalid_jme_function_calls
+            .extend(other.invalid_jme_function_calls.clone());
+        self.unknown_simplification_rules
+            .extend(other.unknown_simplification_rules.clone());
+        self.used_simplification_rulesets
+            .extend(other.used_simplification_rulesets.clone());
+        self.jme_type_errors.extend(other.jme_type_errors.clone());
+        self.invalid_marking_notes
+            .extend(other.invalid_marking_notes.clone());
+        self.jme_expressions.extend(other.jme_expressions.clone());
+        self.unused_jme_variables
+            .extend(other.unused_jme_variables.clone());
+        self.looping_simplification_rules
+            .extend(other.looping_simplification_rules.clone());
     }
     pub fn missing_translations(&self) -> Vec<RumbasCheckMissingData> {
         self.missing_translations.clone()
@@ -150,12 +518,120 @@ impl RumbasCheckResult {
     pub fn invalid_jme_fields(&self) -> Vec<RumbasCheckInvalidJMEStringData> {
         self.invalid_jme_strings.clone()
     }
+    pub fn unknown_jme_identifiers(&self) -> Vec<RumbasCheckJMEIdentifierData> {
+        self.unknown_jme_identifiers.clone()
+    }
+    /// The jme identifiers that are used but not (yet) resolved
+    pub fn used_jme_identifiers(&self) -> Vec<RumbasCheckJMEIdentifierData> {
+        self.used_jme_identifiers.clone()
+    }
+    pub fn circular_jme_variables(&self) -> Vec<RumbasCheckCircularVariablesData> {
+        self.circular_jme_variables.clone()
+    }
+    pub fn invalid_jme_function_calls(&self) -> Vec<RumbasCheckFunctionCallData> {
+        self.invalid_jme_function_calls.clone()
+    }
+    pub fn unknown_simplification_rules(&self) -> Vec<RumbasCheckSimplificationRuleData> {
+        self.unknown_simplification_rules.clone()
+    }
+    pub fn jme_type_errors(&self) -> Vec<RumbasCheckJMETypeErrorData> {
+        self.jme_type_errors.clone()
+    }
+    pub fn invalid_marking_notes(&self) -> Vec<RumbasCheckMarkingData> {
+        self.invalid_marking_notes.clone()
+    }
+    pub fn unused_jme_variables(&self) -> Vec<RumbasCheckMissingData> {
+        self.unused_jme_variables.clone()
+    }
+    pub fn looping_simplification_rules(&self) -> Vec<RumbasCheckLoopingRulesData> {
+        self.looping_simplification_rules.clone()
+    }
 }
 
 impl RumbasCheckResult {
+    /// All errors and warnings, one for each problem
+    pub fn diagnostics(&self) -> Vec<RumbasCheckDiagnostic> {
+        let mut result = Vec::new();
+        let mut add = |kind, warning, path: &RumbasCheckPath, message: String| {
+            result.push(RumbasCheckDiagnostic {
+                kind,
+                warning,
+                path: path.to_string(),
+                message,
+                location: None,
+            })
+        };
+        for d in self.missing_translations.iter() {
+            add(
+                "missing_translation",
+                false,
+                &d.path,
+                "Missing translation".to_string(),
+            );
+        }
+        for d in self.unknown_jme_identifiers.iter() {
+            add(
+                "unknown_jme_identifier",
+                false,
+                &d.path,
+                d.error.to_string(),
+            );
+        }
+        for d in self.circular_jme_variables.iter() {
+            let message = format!("Circular variable definition: {}", d.cycle.join(" -> "));
+            add("circular_jme_variables", false, &d.path, message);
+        }
+        for d in self.invalid_jme_function_calls.iter() {
+            add(
+                "invalid_jme_function_call",
+                true,
+                &d.path,
+                d.error.to_string(),
+            );
+        }
+        for d in self.unknown_simplification_rules.iter() {
+            let message = format!("Unknown simplification rule {}", d.rule);
+            add("unknown_simplification_rule", false, &d.path, message);
+        }
+        for d in self.jme_type_errors.iter() {
+            add("jme_type_error", true, &d.path, d.error.to_string());
+        }
+        for d in self.invalid_marking_notes.iter() {
+            add("invalid_marking_notes", false, &d.path, d.error.to_string());
+        }
+        for d in self.unused_jme_variables.iter() {
+            add(
+                "unused_jme_variable",
+                true,
+                &d.path,
+                "Unused variable".to_string(),
+            );
+        }
+        for d in self.looping_simplification_rules.iter() {
+            let message = format!(
+                "The simplification rules {} can loop forever",
+                d.rules.join(" and ")
+            );
+            add("looping_simplification_rules", true, &d.path, message);
+        }
+        for d in self.invalid_jme_strings.iter() {
+            result.push(RumbasCheckDiagnostic {
+                kind: "invalid_jme_string",
+                warning: false,
+                path: d.path.to_string(),
+                message: d.error.to_string(),
+                location: d.location.clone(),
+            });
+        }
+        result
+    }
     pub fn log(&self) {
         let missing_translations = self.missing_translations();
         let invalid_jme_fields = self.invalid_jme_fields();
+        let unknown_jme_identifiers = self.unknown_jme_identifiers();
+        let circular_jme_variables = self.circular_jme_variables();
+        let unknown_simplification_rules = self.unknown_simplification_rules();
+        let invalid_marking_notes = self.invalid_marking_notes();
         if !missing_translations.is_empty() {
             log::error!("Found {} missing translations:", missing_translations.len());
             for (idx, error) in missing_translations.iter().enumerate() {
@@ -171,6 +647,110 @@ impl RumbasCheckResult {
                 log::error!("{}\t{}", idx + 1, error.to_string());
             }
         }
+        if !unknown_jme_identifiers.is_empty() {
+            log::error!(
+                "Found {} unknown identifiers in jme expressions:",
+                unknown_jme_identifiers.len()
+            );
+            for (idx, error) in unknown_jme_identifiers.iter().enumerate() {
+                log::error!("{}\t{}", idx + 1, error.to_string());
+            }
+        }
+        if !circular_jme_variables.is_empty() {
+            log::error!(
+                "Found {} circular variable definitions:",
+                circular_jme_variables.len()
+            );
+            for (idx, error) in circular_jme_variables.iter().enumerate() {
+                log::error!("{}\t{}", idx + 1, error.to_string());
+            }
+        }
+        if !unknown_simplification_rules.is_empty() {
+            log::error!(
+                "Found {} unknown simplification rules:",
+                unknown_simplification_rules.len()
+            );
+            for (idx, error) in unknown_simplification_rules.iter().enumerate() {
+                log::error!("{}\t{}", idx + 1, error.to_string());
+            }
+        }
+        if !invalid_marking_notes.is_empty() {
+            log::error!(
+                "Found {} errors in marking algorithms:",
+                invalid_marking_notes.len()
+            );
+            for (idx, error) in invalid_marking_notes.iter().enumerate() {
+                log::error!("{}\t{}", idx + 1, error.to_string());
+            }
+        }
+    }
+    pub fn log_warnings(&self) {
+        let mut unused_jme_variables: Vec<String> = Vec::new();
+        // The same question can be checked for multiple locales
+        for unused in self.unused_jme_variables().iter().map(|u| u.to_string()) {
+            if !unused_jme_variables.contains(&unused) {
+                unused_jme_variables.push(unused);
+            }
+        }
+        if !unused_jme_variables.is_empty() {
+            log::warn!("Found {} unused variables:", unused_jme_variables.len());
+            for (idx, warning) in unused_jme_variables.iter().enumerate() {
+                log::warn!("{}\t{}", idx + 1, warning);
+            }
+        }
+        let mut looping_simplification_rules: Vec<String> = Vec::new();
+        for looping in self
+            .looping_simplification_rules()
+            .iter()
+            .map(|l| l.to_string())
+        {
+            if !looping_simplification_rules.contains(&looping) {
+                looping_simplification_rules.push(looping);
+            }
+        }
+        if !looping_simplification_rules.is_empty() {
+            log::warn!(
+                "Found {} answer simplifications that can loop forever:",
+                looping_simplification_rules.len()
+            );
+            for (idx, warning) in looping_simplification_rules.iter().enumerate() {
+                log::warn!("{}\t{}", idx + 1, warning);
+            }
+        }
+        let mut invalid_jme_function_calls: Vec<String> = Vec::new();
+        for call in self
+            .invalid_jme_function_calls()
+            .iter()
+            .map(|c| c.to_string())
+        {
+            if !invalid_jme_function_calls.contains(&call) {
+                invalid_jme_function_calls.push(call);
+            }
+        }
+        if !invalid_jme_function_calls.is_empty() {
+            log::warn!(
+                "Found {} invalid function calls in jme expressions:",
+                invalid_jme_function_calls.len()
+            );
+            for (idx, warning) in invalid_jme_function_calls.iter().enumerate() {
+                log::warn!("{}\t{}", idx + 1, warning);
+            }
+        }
+        let mut jme_type_errors: Vec<String> = Vec::new();
+        for error in self.jme_type_errors().iter().map(|e| e.to_string()) {
+            if !jme_type_errors.contains(&error) {
+                jme_type_errors.push(error);
+            }
+        }
+        if !jme_type_errors.is_empty() {
+            log::warn!(
+                "Found {} type errors in jme expressions:",
+                jme_type_errors.len()
+            );
+            for (idx, warning) in jme_type_errors.iter().enumerate() {
+                log::warn!("{}\t{}", idx + 1, warning);
+            }
+        }
     }
 }
 
@@ -225,11 +805,154 @@ impl std::fmt::Display for RumbasCheckMissingData {
 pub struct RumbasCheckInvalidJMEStringData {
     path: RumbasCheckPath,
     error: numbas::jme::parser::ConsumeError,
+    source: String,
+    location: Option<RumbasCheckLocation>,
+}
+
+impl RumbasCheckInvalidJMEStringData {
+    pub fn location(&self) -> Option<&RumbasCheckLocation> {
+        self.location.as_ref()
+    }
 }
 
 impl std::fmt::Display for RumbasCheckInvalidJMEStringData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        if let Some(ref location) = self.location {
+            write!(f, "{}: {}\n With error:\n{}", location, p, self.error)
+        } else {
+            write!(f, "{}\n With error:\n{}", p, self.error)
+        }
+    }
+}
+
+/// A single error or warning of a check, for tools that show the problems one by one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckDiagnostic {
+    /// The kind of problem, e.g. `missing_translation`
+    pub kind: &'static str,
+    pub warning: bool,
+    /// The path of the field with the problem, e.g. `question.parts.0.prompt`
+    pub path: String,
+    pub message: String,
+    /// The position in a file, only known for jme strings that can't be parsed
+    pub location: Option<RumbasCheckLocation>,
+}
+
+/// A position in a file, lines and columns start at one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for RumbasCheckLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckJMEIdentifierData {
+    path: RumbasCheckPath,
+    error: numbas::jme::ast::ExprValidationError,
+}
+
+impl RumbasCheckJMEIdentifierData {
+    pub fn error(&self) -> &numbas::jme::ast::ExprValidationError {
+        &self.error
+    }
+}
+
+impl std::fmt::Display for RumbasCheckJMEIdentifierData {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let p = self.path.to_string();
         write!(f, "{}\n With error:\n{}", p, self.error)
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckFunctionCallData {
+    path: RumbasCheckPath,
+    error: numbas::jme::ast::FunctionCallError,
+}
+
+impl std::fmt::Display for RumbasCheckFunctionCallData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        write!(f, "{}\n With error:\n{}", p, self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckSimplificationRuleData {
+    path: RumbasCheckPath,
+    rule: String,
+}
+
+impl std::fmt::Display for RumbasCheckSimplificationRuleData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        write!(f, "{}\n With rule: {}", p, self.rule)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckJMEExpressionData {
+    path: RumbasCheckPath,
+    expression: numbas::jme::ast::Expr,
+    expected_type: Option<numbas::jme::builtin_functions::ParameterType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckJMETypeErrorData {
+    path: RumbasCheckPath,
+    error: numbas::jme::types::TypeError,
+}
+
+impl std::fmt::Display for RumbasCheckJMETypeErrorData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        write!(f, "{}\n With error:\n{}", p, self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckMarkingData {
+    path: RumbasCheckPath,
+    error: numbas::jme::marking::MarkingError,
+}
+
+impl std::fmt::Display for RumbasCheckMarkingData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        write!(f, "{}\n With error:\n{}", p, self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckCircularVariablesData {
+    path: RumbasCheckPath,
+    cycle: Vec<String>,
+}
+
+impl std::fmt::Display for RumbasCheckCircularVariablesData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        write!(f, "{}\n With cycle:\n{}", p, self.cycle.join(" -> "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RumbasCheckLoopingRulesData {
+    path: RumbasCheckPath,
+    rules: Vec<String>,
+}
+
+impl std::fmt::Display for RumbasCheckLoopingRulesData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = self.path.to_string();
+        write!(f, "{}\n With rules: {}", p, self.rules.join(" and "))
+    }
+}